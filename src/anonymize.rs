@@ -0,0 +1,285 @@
+//! Pseudonymization for `format anonymize`.
+//!
+//! Unlike [`crate::redact`], which hashes identities and blanks free text, anonymizing keeps
+//! conversations readable: every user becomes `user-<n>` (the same pseudonym for their id, names,
+//! and mentions of them), personal data in message content is replaced per the configured
+//! rules, and attachments are removed. The pseudonyms can be recorded in a mapping file, which
+//! re-identifies everyone and must stay internal.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::atomic_file::write_atomic;
+use crate::config::AnonymizeRule;
+use crate::error::CliError;
+
+/// `format` of a mapping file.
+pub const MAPPING_FORMAT: &str = "anonymize-mapping";
+pub const MAPPING_VERSION: u64 = 1;
+
+/// Keys whose values are user ids.
+const ID_KEYS: &[&str] = &["user_id", "author_id", "owner_id"];
+
+/// Keys whose values are a user's names.
+const NAME_KEYS: &[&str] = &["username", "global_name", "nick", "display_name"];
+
+/// Objects (and lists of objects) describing a user, whose `id` is the user's.
+const PERSON_KEYS: &[&str] = &["author", "user", "member", "owner", "mentions"];
+
+/// Keys of a user object that identify them without being a name or id; removed.
+const PERSONAL_KEYS: &[&str] = &["avatar", "banner", "email", "avatar_decoration_data"];
+
+/// Original ids and names, each with its pseudonym.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Mapping {
+    pub format: String,
+    pub version: u64,
+    pub pseudonyms: BTreeMap<String, String>,
+    /// Distinct pseudonyms, counted on first use.
+    #[serde(skip)]
+    users: Option<usize>,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self {
+            format: MAPPING_FORMAT.to_string(),
+            version: MAPPING_VERSION,
+            pseudonyms: BTreeMap::new(),
+            users: None,
+        }
+    }
+}
+
+impl Mapping {
+    /// The pseudonym of `original`, assigning the next free one if it has none.
+    fn pseudonym(&mut self, original: &str) -> String {
+        if let Some(pseudonym) = self.pseudonyms.get(original) {
+            return pseudonym.clone();
+        }
+        let users = self.users() + 1;
+        self.users = Some(users);
+        let pseudonym = format!("user-{users}");
+        self.pseudonyms
+            .insert(original.to_string(), pseudonym.clone());
+        pseudonym
+    }
+
+    /// Distinct pseudonyms assigned so far.
+    pub fn users(&mut self) -> usize {
+        *self.users.get_or_insert_with(|| {
+            let mut pseudonyms: Vec<&String> = self.pseudonyms.values().collect();
+            pseudonyms.sort();
+            pseudonyms.dedup();
+            pseudonyms.len()
+        })
+    }
+}
+
+/// Read a mapping written by an earlier run, so its users keep their pseudonyms.
+pub fn read_mapping(path: &Path) -> Result<Mapping, CliError> {
+    let text = std::fs::read_to_string(path)?;
+    let mapping: Mapping = serde_json::from_str(&text)
+        .map_err(|e| CliError::Validation(format!("{}: {e}", path.display())))?;
+    if mapping.format != MAPPING_FORMAT || mapping.version != MAPPING_VERSION {
+        return Err(CliError::Validation(format!(
+            "{}: expected a {MAPPING_FORMAT} v{MAPPING_VERSION} file, found {} v{}",
+            path.display(),
+            mapping.format,
+            mapping.version
+        )));
+    }
+    Ok(mapping)
+}
+
+pub fn write_mapping(path: &Path, mapping: &Mapping) -> Result<(), CliError> {
+    let mut bytes = serde_json::to_vec_pretty(mapping)?;
+    bytes.push(b'\n');
+    write_atomic(path, &bytes)
+}
+
+/// Compiled content rules.
+pub struct Rules(Vec<(String, Regex, String)>);
+
+impl Rules {
+    pub fn compile(rules: &[AnonymizeRule]) -> Result<Self, CliError> {
+        rules
+            .iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.pattern).map_err(|e| {
+                    CliError::Config(format!("formats.anonymize {}: {e}", rule.name))
+                })?;
+                Ok((rule.name.clone(), regex, rule.replacement.clone()))
+            })
+            .collect::<Result<_, _>>()
+            .map(Rules)
+    }
+}
+
+/// What [`anonymize`] changed.
+#[derive(Debug, Default, Serialize)]
+pub struct Anonymized {
+    /// Users given a pseudonym, including those from an earlier mapping.
+    pub users: usize,
+    /// Id, name, and personal fields replaced or removed.
+    pub fields: usize,
+    /// Content matches replaced, by rule name.
+    pub matches: BTreeMap<String, usize>,
+    pub attachments: usize,
+}
+
+/// Anonymize `document` in place, extending `mapping` with any new pseudonyms.
+pub fn anonymize(document: &mut Value, rules: &Rules, mapping: &mut Mapping) -> Anonymized {
+    let mention = Regex::new(r"<@!?(\d+)>").expect("valid pattern");
+    let mut pass = Pass {
+        rules,
+        mention,
+        mapping,
+        stats: Anonymized::default(),
+    };
+    pass.walk(document, None);
+    pass.stats.users = pass.mapping.users();
+    pass.stats
+}
+
+struct Pass<'a> {
+    rules: &'a Rules,
+    mention: Regex,
+    mapping: &'a mut Mapping,
+    stats: Anonymized,
+}
+
+impl Pass<'_> {
+    fn walk(&mut self, value: &mut Value, parent: Option<&str>) {
+        match value {
+            Value::Object(map) => self.object(map, parent),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.walk(item, parent)),
+            _ => {}
+        }
+    }
+
+    fn object(&mut self, map: &mut Map<String, Value>, parent: Option<&str>) {
+        let person = parent.is_some_and(|p| PERSON_KEYS.contains(&p));
+        let own = person
+            .then(|| scalar(map.get("id")?))
+            .flatten()
+            .map(|id| self.mapping.pseudonym(&id));
+        if person {
+            let before = map.len();
+            map.retain(|key, _| !PERSONAL_KEYS.contains(&key.as_str()));
+            self.stats.fields += before - map.len();
+        }
+
+        for (key, value) in map.iter_mut() {
+            let key = key.as_str();
+            let Some(original) = scalar(value) else {
+                if key == "attachments"
+                    && let Value::Array(attachments) = value
+                {
+                    self.stats.attachments += attachments.len();
+                    attachments.clear();
+                } else {
+                    self.walk(value, Some(key));
+                }
+                continue;
+            };
+            let pseudonym = if person && key == "id" {
+                own.clone()
+            } else if NAME_KEYS.contains(&key) {
+                // A user's names get the pseudonym of their id, and keep it wherever they
+                // appear without one.
+                Some(match &own {
+                    Some(own) => {
+                        self.mapping
+                            .pseudonyms
+                            .insert(original.clone(), own.clone());
+                        own.clone()
+                    }
+                    None => self.mapping.pseudonym(&original),
+                })
+            } else if ID_KEYS.contains(&key) {
+                Some(self.mapping.pseudonym(&original))
+            } else {
+                None
+            };
+            if let Some(pseudonym) = pseudonym {
+                *value = pseudonym.into();
+                self.stats.fields += 1;
+            } else if key == "content" {
+                *value = self.content(&original).into();
+            }
+        }
+    }
+
+    /// `content` with mentions pointed at pseudonyms and every rule applied.
+    fn content(&mut self, content: &str) -> String {
+        let mapping = &mut *self.mapping;
+        let mut content = self
+            .mention
+            .replace_all(content, |caps: &regex::Captures| {
+                format!("<@{}>", mapping.pseudonym(&caps[1]))
+            })
+            .into_owned();
+        for (name, regex, replacement) in &self.rules.0 {
+            let matches = regex.find_iter(&content).count();
+            if matches > 0 {
+                *self.stats.matches.entry(name.clone()).or_default() += matches;
+                content = regex
+                    .replace_all(&content, replacement.as_str())
+                    .into_owned();
+            }
+        }
+        content
+    }
+}
+
+/// A string or number as a string; `None` for nulls and containers.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FormatsConfig;
+
+    #[test]
+    fn users_get_one_pseudonym_and_content_is_scrubbed() {
+        let author = serde_json::json!({ "id": "7", "username": "alice", "avatar": "a1" });
+        let mut document = serde_json::json!({
+            "guild": { "id": "1", "owner_id": "7" },
+            "channels": [{ "id": "20", "messages": [
+                { "id": "98", "author": author, "content": "hi <@!8>, mail alice@example.com",
+                  "attachments": [{ "id": "5", "url": "https://cdn.example/a" }] },
+                { "id": "99", "author": { "id": "8", "username": "bob" },
+                  "content": "call +49 30 1234 5678 in <#20>", "mentions": [author] },
+            ]}],
+        });
+        let rules = Rules::compile(&FormatsConfig::default().anonymize).unwrap();
+        let mut mapping = Mapping::default();
+        let stats = anonymize(&mut document, &rules, &mut mapping);
+
+        let messages = &document["channels"][0]["messages"];
+        assert_eq!(document["guild"]["owner_id"], "user-1");
+        assert_eq!(
+            messages[0]["author"],
+            serde_json::json!({ "id": "user-1", "username": "user-1" })
+        );
+        assert_eq!(messages[0]["content"], "hi <@user-2>, mail [email]");
+        assert_eq!(messages[0]["attachments"], serde_json::json!([]));
+        assert_eq!(messages[1]["author"]["username"], "user-2");
+        assert_eq!(messages[1]["content"], "call [phone] in <#20>");
+        assert_eq!(messages[1]["mentions"][0]["id"], "user-1");
+        assert_eq!((stats.users, stats.attachments), (2, 1));
+        assert_eq!(stats.matches["email"], 1);
+        assert_eq!(mapping.pseudonyms["alice"], "user-1");
+    }
+}
@@ -0,0 +1,164 @@
+//! Custom emoji, sticker, and scheduled event cover images for dumps.
+//!
+//! Every emoji and sticker in a dump carries its CDN `url`, as does every scheduled event with a
+//! cover image (Discord's cover `image` hash is replaced by the `url` it names). `discord export --with-assets` also
+//! embeds the image itself as `image`, a `data:` URI (the form Discord takes on emoji creation),
+//! so the dump still migrates the set after the source guild is gone. `discord import` uploads
+//! `image` when present and otherwise fetches `url`.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+
+use crate::discord::{Client, text};
+use crate::error::CliError;
+
+/// Dump sections whose items carry images: custom emoji, stickers, and scheduled events.
+pub const SECTIONS: &[&str] = &["emojis", "stickers", "scheduled_events"];
+
+/// Dump section of scheduled events, whose cover image is optional.
+const EVENTS: &str = "scheduled_events";
+
+/// Set the CDN `url` of every emoji, sticker, and event cover in `dump`, with `cdn` as the CDN
+/// base (e.g. `https://cdn.discordapp.com`).
+pub fn add_urls(dump: &mut Value, cdn: &str) {
+    let cdn = cdn.trim_end_matches('/');
+    for section in SECTIONS {
+        for item in dump[*section].as_array_mut().into_iter().flatten() {
+            let id = text(&item["id"]);
+            let url = if *section == EVENTS {
+                // The cover hash would read as an embedded image; keep only the URL it names.
+                let Some(hash) = item["image"].as_str().filter(|i| !i.starts_with("data:")) else {
+                    continue;
+                };
+                format!("{cdn}/guild-events/{id}/{hash}.png")
+            } else {
+                format!("{cdn}/{section}/{id}.{}", extension(item))
+            };
+            if let Value::Object(fields) = item {
+                fields.insert("url".to_string(), url.into());
+                if *section == EVENTS {
+                    fields.shift_remove("image");
+                }
+            }
+        }
+    }
+}
+
+/// Download every emoji, sticker, and event cover image of `dump` into its `image`, returning the ones the
+/// CDN no longer serves. `progress` advances once per image.
+pub async fn embed_all(
+    client: &Client,
+    dump: &mut Value,
+    progress: &indicatif::ProgressBar,
+) -> Result<Vec<String>, CliError> {
+    let mut missing = Vec::new();
+    for section in SECTIONS {
+        for item in dump[*section].as_array_mut().into_iter().flatten() {
+            progress.inc(1);
+            let Some(url) = item["url"].as_str() else {
+                continue;
+            };
+            match client.asset(url).await {
+                Ok(bytes) => item["image"] = data_uri(&bytes, extension(item)).into(),
+                Err(CliError::NotFound(_)) => {
+                    missing.push(format!("{} {}", kind(section), text(&item["name"])));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// The image of an emoji, sticker, or event as `(file name, bytes)`: the embedded `image`, or else a
+/// download of its `url`.
+pub async fn image(client: &Client, item: &Value) -> Result<(String, Vec<u8>), CliError> {
+    let name = format!("{}.{}", text(&item["id"]), extension(item));
+    if let Some(uri) = item["image"].as_str() {
+        let bytes = decode_data_uri(uri).ok_or_else(|| {
+            CliError::Validation(format!(
+                "{}: `image` is not a base64 data URI",
+                item["name"]
+            ))
+        })?;
+        return Ok((name, bytes));
+    }
+    match item["url"].as_str() {
+        Some(url) => Ok((name, client.asset(url).await?)),
+        None => Err(CliError::Validation(format!(
+            "{} has neither `image` nor `url`",
+            item["name"]
+        ))),
+    }
+}
+
+/// `bytes` as a `data:` URI whose media type follows the file extension.
+pub fn data_uri(bytes: &[u8], extension: &str) -> String {
+    let mime = match extension {
+        "gif" => "image/gif",
+        "json" => "application/json",
+        _ => "image/png",
+    };
+    format!("data:{mime};base64,{}", STANDARD.encode(bytes))
+}
+
+fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let (_, data) = uri.strip_prefix("data:")?.split_once(";base64,")?;
+    STANDARD.decode(data).ok()
+}
+
+/// File extension of an emoji (`gif` if animated) or sticker (by `format_type`: PNG, APNG,
+/// Lottie, GIF).
+fn extension(item: &Value) -> &'static str {
+    match (item["animated"].as_bool(), item["format_type"].as_u64()) {
+        (Some(true), _) | (_, Some(4)) => "gif",
+        (_, Some(3)) => "json",
+        _ => "png",
+    }
+}
+
+fn kind(section: &str) -> &'static str {
+    match section {
+        "emojis" => "emoji",
+        "stickers" => "sticker",
+        _ => "scheduled event",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_follow_the_image_format() {
+        let mut dump = serde_json::json!({
+            "emojis": [{ "id": "1", "name": "wave", "animated": true }],
+            "stickers": [{ "id": "2", "name": "cat", "format_type": 3 }],
+            "scheduled_events": [
+                { "id": "3", "name": "launch", "image": "abc" },
+                { "id": "4", "name": "no cover", "image": null },
+            ],
+        });
+        add_urls(&mut dump, "https://cdn.example/");
+        assert_eq!(dump["emojis"][0]["url"], "https://cdn.example/emojis/1.gif");
+        assert_eq!(
+            dump["stickers"][0]["url"],
+            "https://cdn.example/stickers/2.json"
+        );
+        let events = &dump["scheduled_events"];
+        assert_eq!(
+            events[0],
+            serde_json::json!({
+                "id": "3",
+                "name": "launch",
+                "url": "https://cdn.example/guild-events/3/abc.png",
+            })
+        );
+        assert!(events[1].get("url").is_none());
+
+        let uri = data_uri(b"\x89PNG", "png");
+        assert!(uri.starts_with("data:image/png;base64,"));
+        assert_eq!(decode_data_uri(&uri).as_deref(), Some(&b"\x89PNG"[..]));
+    }
+}
@@ -0,0 +1,145 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::CliError;
+
+/// Output file written to `<path>.tmp` and renamed into place only on [`commit`].
+///
+/// Dropping an uncommitted `AtomicFile` (error, Ctrl-C cancellation) removes the
+/// temp file, so an interrupted run never leaves a truncated file at `path`.
+///
+/// [`commit`]: AtomicFile::commit
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp: PathBuf,
+    file: Option<File>,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> Result<Self, CliError> {
+        let tmp = tmp_path(path);
+        let file = File::create(&tmp)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            tmp,
+            file: Some(file),
+        })
+    }
+
+    /// The temp file, for writers that need a path rather than the open handle.
+    pub fn tmp(&self) -> &Path {
+        &self.tmp
+    }
+
+    /// Flush to disk and atomically replace the destination.
+    ///
+    /// If the rename crosses filesystems (e.g. a bind-mounted output directory),
+    /// falls back to copying the temp file over the destination and removing it.
+    pub fn commit(mut self) -> Result<(), CliError> {
+        let Some(file) = self.file.take() else {
+            return Ok(());
+        };
+        file.sync_all()?;
+        drop(file);
+        match std::fs::rename(&self.tmp, &self.path) {
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                let copied = std::fs::copy(&self.tmp, &self.path);
+                let _ = std::fs::remove_file(&self.tmp);
+                copied?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&self.tmp);
+                Err(e.into())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(std::io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Write `bytes` to `path` atomically: readers see either the old file or the new one.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), CliError> {
+    write_atomic_with(path, |file| Ok(file.write_all(bytes)?))
+}
+
+/// Like [`write_atomic`], streaming the contents through `write`.
+///
+/// If `write` fails, the temp file is removed and `path` is left untouched.
+pub fn write_atomic_with<F>(path: &Path, write: F) -> Result<(), CliError>
+where
+    F: FnOnce(&mut AtomicFile) -> Result<(), CliError>,
+{
+    let mut file = AtomicFile::create(path)?;
+    write(&mut file)?;
+    file.commit()
+}
+
+/// `out.json` -> `out.json.tmp`, in the same directory.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(OsString::from(".tmp"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("guildsync-atomic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn write_replaces_file() {
+        let path = scratch("replace.json");
+        std::fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!tmp_path(&path).exists());
+    }
+
+    #[test]
+    fn failed_write_leaves_original_intact() {
+        let path = scratch("intact.json");
+        std::fs::write(&path, "original").unwrap();
+
+        let result = write_atomic_with(&path, |file| {
+            file.write_all(b"{\"partial\":")?;
+            Err(std::io::Error::other("simulated failure").into())
+        });
+
+        assert!(matches!(result, Err(CliError::Io(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "original");
+        assert!(!tmp_path(&path).exists());
+    }
+}
@@ -0,0 +1,233 @@
+//! Message attachment downloads for `discord export --with-attachments`.
+//!
+//! Files are stored content-addressed (`<sha256>.<ext>`) in a directory next to the dump, with
+//! an `index.json` mapping attachment ids to stored names. A download in progress lives in
+//! `.partial/<id>` and is resumed with a `Range` request, so an interrupted run neither leaves
+//! half-written files behind nor fetches completed ones again.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use reqwest::StatusCode;
+use reqwest::header::RANGE;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::task::JoinSet;
+
+use crate::atomic_file::write_atomic;
+use crate::discord::{list, text};
+use crate::error::CliError;
+use crate::retry::{self, RetryPolicy};
+
+const INDEX_FILE: &str = "index.json";
+const PARTIAL_DIR: &str = ".partial";
+
+/// Counts reported after [`download_all`].
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Stats {
+    pub downloaded: usize,
+    /// Attachments already stored by an earlier run.
+    pub cached: usize,
+    /// Attachments the CDN no longer serves (e.g. deleted messages).
+    pub missing: Vec<String>,
+}
+
+/// Default attachment directory for a dump written to `out`: `<out>.attachments`.
+pub fn default_dir(out: &Path) -> PathBuf {
+    let mut path = out.as_os_str().to_owned();
+    path.push(".attachments");
+    PathBuf::from(path)
+}
+
+/// Download the attachments of every message in `dump`'s channels into `dir`, at most
+/// `concurrency` at a time, and record each stored file on its attachment object as
+/// `"file"` (relative to the dump's directory).
+pub async fn download_all(
+    dump: &mut Value,
+    dir: &Path,
+    concurrency: usize,
+    policy: &RetryPolicy,
+    action: &'static str,
+    progress: &indicatif::ProgressBar,
+) -> Result<Stats, CliError> {
+    std::fs::create_dir_all(dir.join(PARTIAL_DIR))?;
+    let index_path = dir.join(INDEX_FILE);
+    let mut index: BTreeMap<String, String> = match std::fs::read(&index_path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut stats = Stats::default();
+    let mut pending = Vec::new();
+    for attachment in attachments(dump) {
+        let id = text(&attachment["id"]);
+        match index.get(&id) {
+            Some(name) if dir.join(name).exists() => stats.cached += 1,
+            _ => pending.push((
+                id,
+                attachment["url"].as_str().unwrap_or_default().to_string(),
+                extension(attachment),
+            )),
+        }
+    }
+    progress.set_length(pending.len() as u64);
+
+    let http = reqwest::Client::new();
+    let mut tasks = JoinSet::new();
+    let mut pending = pending.into_iter();
+    loop {
+        while tasks.len() < concurrency.max(1) {
+            let Some((id, url, ext)) = pending.next() else {
+                break;
+            };
+            let (http, dir, policy) = (http.clone(), dir.to_path_buf(), *policy);
+            tasks.spawn(async move {
+                let name =
+                    retry::with_backoff(&policy, action, || download(&http, &url, &dir, &id, &ext))
+                        .await?;
+                Ok::<_, CliError>((id, name))
+            });
+        }
+        let Some(joined) = tasks.join_next().await else {
+            break;
+        };
+        progress.inc(1);
+        let (id, name) = match joined.map_err(|e| CliError::Network(e.to_string()))? {
+            Ok(stored) => stored,
+            Err(CliError::NotFound(what)) => {
+                stats.missing.push(what);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        index.insert(id, name);
+        // Persist as we go so an interrupted run resumes from here.
+        write_atomic(&index_path, &serde_json::to_vec_pretty(&index)?)?;
+        stats.downloaded += 1;
+    }
+
+    let prefix = dir.file_name().map(PathBuf::from).unwrap_or_default();
+    for attachment in attachments_mut(dump) {
+        if let Some(name) = index.get(&text(&attachment["id"])) {
+            attachment["file"] = prefix.join(name).display().to_string().into();
+        }
+    }
+    Ok(stats)
+}
+
+/// The stored files recorded on `dump`'s attachments, relative to the dump's directory, each
+/// once.
+pub fn stored_files(dump: &Value) -> BTreeSet<String> {
+    attachments(dump)
+        .filter_map(|attachment| attachment["file"].as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Fetch `url` into `dir` under its content hash, resuming `.partial/<id>` if present.
+async fn download(
+    http: &reqwest::Client,
+    url: &str,
+    dir: &Path,
+    id: &str,
+    ext: &str,
+) -> Result<String, CliError> {
+    let net = |e: reqwest::Error| CliError::Network(format!("attachment {id}: {e}"));
+    let partial = dir.join(PARTIAL_DIR).join(id);
+    let have = std::fs::metadata(&partial).map_or(0, |m| m.len());
+
+    let mut request = http.get(url);
+    if have > 0 {
+        request = request.header(RANGE, format!("bytes={have}-"));
+    }
+    let mut response = request.send().await.map_err(net)?;
+    let status = response.status();
+    let append = match status {
+        StatusCode::PARTIAL_CONTENT => true,
+        StatusCode::RANGE_NOT_SATISFIABLE if have > 0 => {
+            // The partial file is already complete (or stale); start over to be sure.
+            std::fs::remove_file(&partial)?;
+            return Err(CliError::Network(format!(
+                "attachment {id}: restarting download"
+            )));
+        }
+        _ if status.is_success() => false,
+        _ if status.is_server_error() => {
+            return Err(CliError::Network(format!("attachment {id}: {status}")));
+        }
+        StatusCode::NOT_FOUND | StatusCode::GONE => {
+            return Err(CliError::NotFound(format!("attachment {id}: {url}")));
+        }
+        _ => return Err(CliError::Validation(format!("attachment {id}: {status}"))),
+    };
+    let expected = response
+        .content_length()
+        .map(|len| len + if append { have } else { 0 });
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(append)
+        .write(true)
+        .truncate(!append)
+        .open(&partial)?;
+    while let Some(chunk) = response.chunk().await.map_err(net)? {
+        file.write_all(&chunk)?;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    let bytes = std::fs::read(&partial)?;
+    if expected.is_some_and(|len| len != bytes.len() as u64) {
+        // Keep what arrived; the retry resumes from it.
+        return Err(CliError::Network(format!(
+            "attachment {id}: partial download ({} of {} bytes)",
+            bytes.len(),
+            expected.unwrap_or_default()
+        )));
+    }
+    let hash = format!("{:x}", Sha256::digest(&bytes));
+    let name = if ext.is_empty() {
+        hash
+    } else {
+        format!("{hash}.{ext}")
+    };
+    std::fs::rename(&partial, dir.join(&name))?;
+    Ok(name)
+}
+
+/// Sections whose items carry `messages`.
+const MESSAGE_SECTIONS: &[&str] = &["channels", "threads"];
+
+fn attachments(dump: &Value) -> impl Iterator<Item = &Value> {
+    MESSAGE_SECTIONS
+        .iter()
+        .flat_map(|section| list(&dump[*section]))
+        .flat_map(|channel| list(&channel["messages"]))
+        .flat_map(|message| list(&message["attachments"]))
+}
+
+fn attachments_mut(dump: &mut Value) -> impl Iterator<Item = &mut Value> {
+    dump.as_object_mut()
+        .into_iter()
+        .flat_map(|obj| obj.iter_mut())
+        .filter(|(key, _)| MESSAGE_SECTIONS.contains(&key.as_str()))
+        .flat_map(|(_, section)| list_mut(section))
+        .flat_map(|channel| list_mut(&mut channel["messages"]))
+        .flat_map(|message| list_mut(&mut message["attachments"]))
+}
+
+fn list_mut(value: &mut Value) -> impl Iterator<Item = &mut Value> {
+    value.as_array_mut().into_iter().flatten()
+}
+
+/// File extension from the attachment's `filename`, if it is a plain alphanumeric one.
+fn extension(attachment: &Value) -> String {
+    attachment["filename"]
+        .as_str()
+        .and_then(|name| Path::new(name).extension()?.to_str())
+        .filter(|ext| ext.len() <= 10 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
@@ -0,0 +1,109 @@
+//! Bot token and signing key storage in the platform keyring (macOS Keychain, Windows
+//! Credential Manager, or the Secret Service on Linux) for `guildsync auth`.
+
+use serde::Serialize;
+
+use crate::error::CliError;
+
+const SERVICE: &str = "guildsync";
+const TOKEN_ACCOUNT: &str = "discord-bot-token";
+const SIGNING_KEY_ACCOUNT: &str = "signing-key";
+
+/// Where [`crate::config::DiscordConfig::resolve_token_with_source`] found the token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenSource {
+    /// `--token` or `DISCORD_TOKEN`.
+    Flag,
+    /// The config's `token_env` variable.
+    Env,
+    Keyring,
+    /// The config's inline `token`.
+    Config,
+}
+
+impl std::fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TokenSource::Flag => "--token",
+            TokenSource::Env => "environment",
+            TokenSource::Keyring => "system keyring",
+            TokenSource::Config => "config file",
+        })
+    }
+}
+
+/// The token saved by `auth login`, if any.
+pub fn stored_token() -> Result<Option<String>, CliError> {
+    stored(TOKEN_ACCOUNT)
+}
+
+/// Save `token` in the keyring, replacing any previous one.
+pub fn store_token(token: &str) -> Result<(), CliError> {
+    outside_runtime(|| entry(TOKEN_ACCOUNT)?.set_password(token))
+        .map_err(|err| keyring_error(TOKEN_ACCOUNT, err))
+}
+
+/// Remove the saved token; `false` if there was none.
+pub fn delete_token() -> Result<bool, CliError> {
+    match outside_runtime(|| entry(TOKEN_ACCOUNT)?.delete_credential()) {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(err) => Err(keyring_error(TOKEN_ACCOUNT, err)),
+    }
+}
+
+/// The signing key saved by `auth keygen` (base64), if any.
+pub fn stored_signing_key() -> Result<Option<String>, CliError> {
+    stored(SIGNING_KEY_ACCOUNT)
+}
+
+/// Save a signing key in the keyring, replacing any previous one.
+pub fn store_signing_key(key: &str) -> Result<(), CliError> {
+    outside_runtime(|| entry(SIGNING_KEY_ACCOUNT)?.set_password(key))
+        .map_err(|err| keyring_error(SIGNING_KEY_ACCOUNT, err))
+}
+
+fn stored(account: &str) -> Result<Option<String>, CliError> {
+    match outside_runtime(|| entry(account)?.get_password()) {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(keyring_error(account, err)),
+    }
+}
+
+fn entry(account: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE, account)
+}
+
+/// Run a keyring call on a fresh thread: the Secret Service backend starts its own async
+/// runtime, which must not happen on a thread already driving ours.
+fn outside_runtime<T: Send>(
+    call: impl FnOnce() -> keyring::Result<T> + Send,
+) -> keyring::Result<T> {
+    std::thread::scope(|scope| {
+        scope.spawn(call).join().unwrap_or_else(|_| {
+            Err(keyring::Error::PlatformFailure(
+                "keyring thread panicked".into(),
+            ))
+        })
+    })
+}
+
+/// Environment variables to point users at when the keyring is unusable, by account.
+fn fallback(account: &str) -> &'static str {
+    match account {
+        SIGNING_KEY_ACCOUNT => "GUILDSYNC_SIGNING_KEY (or the config's formats.signing_key_env)",
+        _ => "DISCORD_TOKEN (or the config's token_env)",
+    }
+}
+
+fn keyring_error(account: &str, err: keyring::Error) -> CliError {
+    let hint = match err {
+        keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_) => {
+            format!("; set {} instead", fallback(account))
+        }
+        _ => String::new(),
+    };
+    CliError::Auth(format!("system keyring: {err}{hint}"))
+}
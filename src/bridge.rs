@@ -0,0 +1,114 @@
+//! Two-way bridge between a Discord channel and a tmux pane for `terminal bridge`.
+//!
+//! One direction is [`crate::relay`] (messages typed into the pane), the other
+//! [`crate::capture`] following the pane (output posted to the channel); both run until Ctrl-C.
+//! Two loops are cut: the bot's own messages are never relayed back, and lines the relay typed
+//! are recorded as [`Echoes`] so the capture does not post them again when they show up in the
+//! pane.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::capture::{self, Capture};
+use crate::discord::Client;
+use crate::error::CliError;
+use crate::gateway;
+use crate::relay::Relay;
+
+/// Typed lines remembered at most; older ones are forgotten.
+const ECHOES: usize = 64;
+
+/// Lines recently typed into the pane, shared by the two directions.
+#[derive(Clone, Debug, Default)]
+pub struct Echoes(Arc<Mutex<VecDeque<String>>>);
+
+impl Echoes {
+    /// Remember that `line` was typed.
+    pub fn record(&self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        let mut lines = self.lock();
+        if lines.len() == ECHOES {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+
+    /// Whether `output`, a line captured from the pane, shows a typed line (after a prompt,
+    /// say). Each typed line is matched once, so the same text printed later is posted.
+    pub fn take(&self, output: &str) -> bool {
+        let output = capture::strip_ansi(output);
+        let mut lines = self.lock();
+        match lines.iter().position(|line| output.contains(line.as_str())) {
+            Some(index) => {
+                lines.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<String>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Where the relay direction hears of new messages.
+#[derive(Copy, Clone, Debug)]
+pub enum Listen {
+    /// The gateway events of this guild.
+    Gateway(u64),
+    /// Polling the channel at this interval.
+    Poll(Duration),
+}
+
+/// Run `relay` and `capture` (every `interval`) together until `stop` completes or either
+/// fails.
+pub async fn run(
+    client: &Client,
+    relay: &mut Relay,
+    capture: &mut Capture,
+    listen: Listen,
+    interval: Duration,
+    stop: impl Future<Output = ()>,
+) -> Result<(), CliError> {
+    let relaying = async {
+        match listen {
+            Listen::Gateway(guild) => gateway::listen(client, guild, relay, std::future::pending())
+                .await
+                .map(drop),
+            Listen::Poll(every) => relay.poll(client, every, std::future::pending()).await,
+        }
+    };
+    let capturing = capture.follow(Some(client), interval, std::future::pending());
+    tokio::select! {
+        result = relaying => result,
+        result = capturing => result,
+        () = stop => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_lines_are_recognized_once() {
+        let echoes = Echoes::default();
+        echoes.record("[12:00] alice: ls ");
+        echoes.record("  ");
+        assert!(!echoes.take("total 0"));
+        assert!(echoes.take("\u{1b}[32m$\u{1b}[0m [12:00] alice: ls"));
+        assert!(!echoes.take("$ [12:00] alice: ls"));
+        for i in 0..=ECHOES {
+            echoes.record(&format!("line {i:03}"));
+        }
+        assert!(!echoes.take("line 000"));
+        assert!(echoes.take(&format!("line {ECHOES:03}")));
+    }
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+/// Metadata embedded by `build.rs`; fields are `"unknown"` when unavailable at build time.
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub commit: &'static str,
+    pub build_date: &'static str,
+    pub rustc: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    commit: env!("GUILDSYNC_COMMIT"),
+    build_date: env!("GUILDSYNC_BUILD_DATE"),
+    rustc: env!("GUILDSYNC_RUSTC"),
+};
+
+/// Multi-line text printed by `guildsync --version`.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\ncommit: ",
+    env!("GUILDSYNC_COMMIT"),
+    "\nbuilt: ",
+    env!("GUILDSYNC_BUILD_DATE"),
+    "\nrustc: ",
+    env!("GUILDSYNC_RUSTC"),
+);
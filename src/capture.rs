@@ -0,0 +1,290 @@
+//! Posting tmux pane output to Discord for `terminal capture`, the reverse of `terminal send`.
+//!
+//! A pane is captured once, or followed: it is captured again every interval and only lines
+//! completed since the previous capture are posted. Output goes out as code blocks of at most
+//! [`MESSAGE_LIMIT`] characters, paced by a pause between messages and capped per capture so a
+//! burst of output cannot flood the channel. Colors are kept as an `ansi` code block, which
+//! Discord renders, unless plain text is asked for.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::bridge::Echoes;
+use crate::discord::Client;
+use crate::error::CliError;
+use crate::tmux;
+
+/// Longest message Discord accepts, in characters.
+pub const MESSAGE_LIMIT: usize = 2000;
+
+/// `text` without ANSI escape sequences (colors, cursor movement, titles) or other control
+/// characters except tabs.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => match chars.next() {
+                // CSI: parameter and intermediate bytes up to a final byte.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\u{40}'..='\u{7e}').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC, DCS, SOS, PM, APC: a string up to BEL or ST (`ESC \`).
+                Some(']' | 'P' | 'X' | '^' | '_') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' || (c == '\u{1b}' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\t' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Lines of `current` that follow what `previous` (an earlier capture of the same pane)
+/// already had: the lines after the longest tail of `previous` that `current` starts with.
+/// Everything is new if nothing overlaps, as after `clear`.
+pub fn new_lines<'a>(previous: &[String], current: &'a [String]) -> &'a [String] {
+    let overlap = (0..previous.len())
+        .map(|scrolled| &previous[scrolled..])
+        .find(|tail| current.starts_with(tail))
+        .map_or(0, <[String]>::len);
+    &current[overlap..]
+}
+
+/// Messages holding `lines` as code blocks (tagged `lang`), at most `max` of them; when more
+/// would be needed, the earliest lines are left out. Returns the messages and how many lines
+/// were left out.
+pub fn code_blocks(lines: &[String], lang: &str, max: usize) -> (Vec<String>, usize) {
+    let fence = |body: &str| format!("```{lang}\n{body}\n```");
+    let budget = MESSAGE_LIMIT - fence("").chars().count();
+    let mut blocks: Vec<Vec<String>> = Vec::new();
+    let mut size = 0;
+    let mut kept = 0;
+    // Filled from the end, so the latest output is what gets posted.
+    'lines: for line in lines.iter().rev() {
+        for piece in split(&escape_fences(line.trim_end()), budget)
+            .into_iter()
+            .rev()
+        {
+            let len = piece.chars().count();
+            if let Some(block) = blocks.last_mut().filter(|_| size + 1 + len <= budget) {
+                block.push(piece);
+                size += 1 + len;
+            } else if blocks.len() == max {
+                break 'lines;
+            } else {
+                blocks.push(vec![piece]);
+                size = len;
+            }
+        }
+        kept += 1;
+    }
+    let messages = blocks
+        .into_iter()
+        .rev()
+        .map(|mut block| {
+            block.reverse();
+            fence(&block.join("\n"))
+        })
+        .collect();
+    (messages, lines.len() - kept)
+}
+
+/// `line` with a zero-width space between adjacent backticks, so it cannot close the block.
+fn escape_fences(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut previous = None;
+    for c in line.chars() {
+        if c == '`' && previous == Some('`') {
+            out.push('\u{200b}');
+        }
+        out.push(c);
+        previous = Some(c);
+    }
+    out
+}
+
+/// `line` in pieces of at most `width` characters.
+fn split(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(width).map(|c| c.iter().collect()).collect()
+}
+
+/// Counts reported when a capture stops.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// Messages posted (or printed with `--dry-run`).
+    pub messages: u64,
+    /// Lines of output they held.
+    pub lines: u64,
+    /// Lines left out because a capture needed more than the allowed messages.
+    pub omitted_lines: u64,
+    /// Lines left out because a [`crate::bridge`] relay typed them.
+    pub echoed_lines: u64,
+}
+
+/// Posts the output of one pane to one channel.
+pub struct Capture {
+    pub pane: String,
+    pub channel: String,
+    /// Convert colored output to plain text.
+    pub plain: bool,
+    /// Messages posted per capture at most.
+    pub max_messages: usize,
+    /// Pause between messages.
+    pub pause: Duration,
+    /// Lines typed by a relay, not posted when they show up in the pane.
+    pub echoes: Option<Echoes>,
+    pub stats: Stats,
+}
+
+impl Capture {
+    /// Post the visible pane, or its last `lines` lines (scrollback included). Without a
+    /// client (`--dry-run`) the messages are printed instead.
+    pub async fn once(
+        &mut self,
+        client: Option<&Client>,
+        lines: Option<usize>,
+    ) -> Result<(), CliError> {
+        let mut captured = self
+            .capture(if lines.is_some() { "-" } else { "0" }, "")
+            .await?;
+        if let Some(lines) = lines {
+            captured.drain(..captured.len().saturating_sub(lines));
+        }
+        self.post(client, &captured).await
+    }
+
+    /// Capture the pane every `interval` and post the lines completed since the last capture,
+    /// until `stop` completes. Output already there at the start is not posted.
+    pub async fn follow(
+        &mut self,
+        client: Option<&Client>,
+        interval: Duration,
+        stop: impl Future<Output = ()>,
+    ) -> Result<(), CliError> {
+        let mut previous = self.completed().await?;
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep(interval) => {}
+                () = &mut stop => return Ok(()),
+            }
+            let current = self.completed().await?;
+            let mut lines = new_lines(&previous, &current).to_vec();
+            if let Some(echoes) = &self.echoes {
+                let before = lines.len();
+                lines.retain(|line| !echoes.take(line));
+                self.stats.echoed_lines += (before - lines.len()) as u64;
+            }
+            self.post(client, &lines).await?;
+            previous = current;
+        }
+    }
+
+    /// The pane's history and visible lines above the cursor; the cursor's line may still
+    /// change (a prompt being typed, a progress bar).
+    async fn completed(&self) -> Result<Vec<String>, CliError> {
+        let cursor = tmux::cursor_line(&self.pane).await?;
+        self.capture("-", &(cursor - 1).to_string()).await
+    }
+
+    async fn capture(&self, start: &str, end: &str) -> Result<Vec<String>, CliError> {
+        let mut lines = tmux::capture_pane(&self.pane, start, end).await?;
+        if self.plain {
+            lines = lines.iter().map(|line| strip_ansi(line)).collect();
+        }
+        while lines
+            .last()
+            .is_some_and(|line| strip_ansi(line).trim().is_empty())
+        {
+            lines.pop();
+        }
+        Ok(lines)
+    }
+
+    async fn post(&mut self, client: Option<&Client>, lines: &[String]) -> Result<(), CliError> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let lang = if self.plain { "" } else { "ansi" };
+        let (messages, omitted) = code_blocks(lines, lang, self.max_messages);
+        if omitted > 0 {
+            tracing::warn!(
+                "{omitted} line(s) of output left out; only {} message(s) are posted per capture",
+                self.max_messages
+            );
+        }
+        let path = format!("/channels/{}/messages", self.channel);
+        for (i, content) in messages.iter().enumerate() {
+            match client {
+                Some(client) => {
+                    if i > 0 {
+                        tokio::time::sleep(self.pause).await;
+                    }
+                    let body = serde_json::json!({
+                        "content": content,
+                        "allowed_mentions": { "parse": [] },
+                    });
+                    client.post(&path, &body).await?;
+                }
+                None => println!("{content}"),
+            }
+            self.stats.messages += 1;
+        }
+        self.stats.lines += (lines.len() - omitted) as u64;
+        self.stats.omitted_lines += omitted as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn output_is_chunked_into_code_blocks_of_new_lines() {
+        assert_eq!(
+            strip_ansi("\u{1b}[1;31merror\u{1b}[0m: \u{1b}]0;title\u{7}done\r"),
+            "error: done"
+        );
+        assert_eq!(
+            new_lines(&lines("a\nb\nc"), &lines("b\nc\nd\ne")),
+            &lines("d\ne")[..]
+        );
+        assert_eq!(new_lines(&lines("a\nb"), &lines("x")), &lines("x")[..]);
+
+        let (messages, omitted) = code_blocks(&lines("ls\n```\nok"), "", 5);
+        assert_eq!(messages, ["```\nls\n`\u{200b}`\u{200b}`\nok\n```"]);
+        assert_eq!(omitted, 0);
+
+        let long: Vec<String> = (0..300).map(|i| format!("line {i:04}")).collect();
+        let (messages, omitted) = code_blocks(&long, "ansi", 5);
+        assert!(messages.len() == 2 && omitted == 0);
+        assert!(messages.iter().all(|m| m.chars().count() <= MESSAGE_LIMIT));
+        assert!(messages[1].ends_with("line 0299\n```"));
+        let (messages, omitted) = code_blocks(&long, "ansi", 1);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with(&format!("```ansi\nline {omitted:04}\n")));
+        assert!(messages[0].ends_with("line 0299\n```"));
+    }
+}
@@ -0,0 +1,437 @@
+//! Helm chart for `kube chart generate`: guildsync as a Deployment in one of three modes, with
+//! values for the token Secret, guild IDs, resource limits, and persistence.
+//!
+//! The templates are fixed; `values.yaml` starts from the current config (image, guilds, the
+//! config file itself), so a generated chart installs as-is and is edited from there.
+
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::atomic_file::write_atomic;
+use crate::error::CliError;
+
+/// What the chart's pod runs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// `discord watch`: append each guild's gateway events to the volume.
+    #[default]
+    Watch,
+    /// `terminal bridge`: relay a channel into a tmux session in the pod and post its output
+    /// back.
+    Bridge,
+    /// `watch-dir`: import upload files dropped into the volume's `inbox/` as they change.
+    Daemon,
+}
+
+/// What `values.yaml` starts from.
+#[derive(Debug)]
+pub struct Chart<'a> {
+    pub name: &'a str,
+    pub mode: Mode,
+    /// `repository[:tag]`.
+    pub image: &'a str,
+    pub guilds: &'a [u64],
+    /// Config file the pod runs with.
+    pub config: &'a str,
+}
+
+const CHART_YAML: &str = r#"apiVersion: v2
+name: {name}
+description: guildsync in watch, bridge, or daemon mode
+type: application
+version: 0.1.0
+appVersion: "{app_version}"
+"#;
+
+const VALUES_YAML: &str = r#"# Generated by `guildsync kube chart generate`.
+
+image:
+  repository: {repository}
+  tag: "{tag}"
+  pullPolicy: IfNotPresent
+
+# What the pod runs:
+#   watch  - `discord watch`: one container per guild appending gateway events to
+#            /data/events-<guild>.ndjson
+#   bridge - `terminal bridge`: relays `bridge.channel` into a tmux session in the pod and posts
+#            its output back (the image needs `sh` and `tmux`)
+#   daemon - `watch-dir`: imports upload files dropped into /data/inbox into the first guild
+#            (the image needs `sh`)
+mode: {mode}
+
+# Guild IDs (quoted: they do not fit YAML's float precision).
+guilds: {guilds}
+
+token:
+  # Existing Secret holding the bot token; when empty the chart creates one from `value`.
+  existingSecret: ""
+  key: token
+  value: ""
+
+bridge:
+  channel: ""
+  session: guildsync
+  # User IDs whose messages are relayed, quoted like guilds (empty: everyone in the channel).
+  allowUsers: []
+
+daemon:
+  maxChanges: 50
+
+resources:
+  requests:
+    cpu: 50m
+    memory: 64Mi
+  limits:
+    cpu: 500m
+    memory: 256Mi
+
+persistence:
+  enabled: true
+  # Use this PersistentVolumeClaim instead of creating one.
+  existingClaim: ""
+  storageClass: ""
+  accessMode: ReadWriteOnce
+  size: 1Gi
+
+nodeSelector: {}
+tolerations: []
+affinity: {}
+
+# Mounted at /etc/guildsync/config.toml.
+config: |
+{config}"#;
+
+const HELPERS_TPL: &str = r#"{{- define "guildsync.fullname" -}}
+{{- if contains .Chart.Name .Release.Name -}}
+{{- .Release.Name | trunc 63 | trimSuffix "-" -}}
+{{- else -}}
+{{- printf "%s-%s" .Release.Name .Chart.Name | trunc 63 | trimSuffix "-" -}}
+{{- end -}}
+{{- end -}}
+
+{{- define "guildsync.selectorLabels" -}}
+app.kubernetes.io/name: {{ .Chart.Name }}
+app.kubernetes.io/instance: {{ .Release.Name }}
+{{- end -}}
+
+{{- define "guildsync.labels" -}}
+{{ include "guildsync.selectorLabels" . }}
+app.kubernetes.io/version: {{ .Chart.AppVersion | quote }}
+app.kubernetes.io/managed-by: {{ .Release.Service }}
+app.kubernetes.io/component: {{ .Values.mode }}
+{{- end -}}
+
+{{- define "guildsync.secretName" -}}
+{{- .Values.token.existingSecret | default (printf "%s-token" (include "guildsync.fullname" .)) -}}
+{{- end -}}
+
+{{- define "guildsync.claimName" -}}
+{{- .Values.persistence.existingClaim | default (printf "%s-data" (include "guildsync.fullname" .)) -}}
+{{- end -}}
+
+{{- define "guildsync.image" -}}
+image: "{{ .Values.image.repository }}:{{ .Values.image.tag | default .Chart.AppVersion }}"
+imagePullPolicy: {{ .Values.image.pullPolicy }}
+{{- end -}}
+
+{{- define "guildsync.env" -}}
+- name: DISCORD_TOKEN
+  valueFrom:
+    secretKeyRef:
+      name: {{ include "guildsync.secretName" . }}
+      key: {{ .Values.token.key }}
+- name: GUILDSYNC_CONFIG
+  value: /etc/guildsync/config.toml
+{{- end -}}
+
+{{- define "guildsync.mounts" -}}
+volumeMounts:
+  - name: config
+    mountPath: /etc/guildsync
+    readOnly: true
+  - name: data
+    mountPath: /data
+resources:
+  {{- toYaml .Values.resources | nindent 2 }}
+{{- end -}}
+"#;
+
+const CONFIGMAP_YAML: &str = r#"apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: {{ include "guildsync.fullname" . }}-config
+  labels:
+    {{- include "guildsync.labels" . | nindent 4 }}
+data:
+  config.toml: |
+    {{- .Values.config | nindent 4 }}
+"#;
+
+const SECRET_YAML: &str = r#"{{- if not .Values.token.existingSecret }}
+apiVersion: v1
+kind: Secret
+metadata:
+  name: {{ include "guildsync.secretName" . }}
+  labels:
+    {{- include "guildsync.labels" . | nindent 4 }}
+type: Opaque
+stringData:
+  {{ .Values.token.key }}: {{ required "token.value or token.existingSecret is required" .Values.token.value | quote }}
+{{- end }}
+"#;
+
+const PVC_YAML: &str = r#"{{- if and .Values.persistence.enabled (not .Values.persistence.existingClaim) }}
+apiVersion: v1
+kind: PersistentVolumeClaim
+metadata:
+  name: {{ include "guildsync.claimName" . }}
+  labels:
+    {{- include "guildsync.labels" . | nindent 4 }}
+spec:
+  accessModes:
+    - {{ .Values.persistence.accessMode }}
+  {{- with .Values.persistence.storageClass }}
+  storageClassName: {{ . }}
+  {{- end }}
+  resources:
+    requests:
+      storage: {{ .Values.persistence.size }}
+{{- end }}
+"#;
+
+// One replica in every mode: two watchers would record every event twice, and two importers
+// would race on the same files.
+const DEPLOYMENT_YAML: &str = r#"{{- if not (has .Values.mode (list "watch" "bridge" "daemon")) }}
+{{- fail "mode must be watch, bridge, or daemon" }}
+{{- end }}
+{{- if and (ne .Values.mode "bridge") (not .Values.guilds) }}
+{{- fail "guilds needs at least one guild ID" }}
+{{- end }}
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: {{ include "guildsync.fullname" . }}
+  labels:
+    {{- include "guildsync.labels" . | nindent 4 }}
+spec:
+  replicas: 1
+  strategy:
+    type: Recreate
+  selector:
+    matchLabels:
+      {{- include "guildsync.selectorLabels" . | nindent 6 }}
+  template:
+    metadata:
+      labels:
+        {{- include "guildsync.labels" . | nindent 8 }}
+      annotations:
+        checksum/config: {{ .Values.config | sha256sum }}
+    spec:
+      containers:
+        {{- if eq .Values.mode "watch" }}
+        {{- range .Values.guilds }}
+        - name: watch-{{ . }}
+          args: ["discord", "watch", "--guild", {{ . | quote }}, "--out", "/data/events-{{ . }}.ndjson"]
+          {{- include "guildsync.image" $ | nindent 10 }}
+          env:
+            {{- include "guildsync.env" $ | nindent 12 }}
+          {{- include "guildsync.mounts" $ | nindent 10 }}
+        {{- end }}
+        {{- else if eq .Values.mode "bridge" }}
+        - name: bridge
+          command: ["sh", "-c"]
+          args:
+            - >-
+              tmux new-session -d -s "$SESSION" &&
+              exec guildsync terminal bridge --channel "$CHANNEL" --tmux "$SESSION"
+              {{- range .Values.bridge.allowUsers }} --allow-user {{ . | quote }}{{ end }}
+          {{- include "guildsync.image" . | nindent 10 }}
+          env:
+            {{- include "guildsync.env" . | nindent 12 }}
+            - name: SESSION
+              value: {{ .Values.bridge.session | quote }}
+            - name: CHANNEL
+              value: {{ required "bridge.channel is required in bridge mode" .Values.bridge.channel | quote }}
+          {{- include "guildsync.mounts" . | nindent 10 }}
+        {{- else }}
+        - name: daemon
+          command: ["sh", "-c"]
+          args:
+            - >-
+              mkdir -p /data/inbox &&
+              exec guildsync --yes watch-dir /data/inbox --guild "$GUILD" --max-changes "$MAX_CHANGES"
+          {{- include "guildsync.image" . | nindent 10 }}
+          env:
+            {{- include "guildsync.env" . | nindent 12 }}
+            - name: GUILD
+              value: {{ first .Values.guilds | quote }}
+            - name: MAX_CHANGES
+              value: {{ .Values.daemon.maxChanges | quote }}
+          {{- include "guildsync.mounts" . | nindent 10 }}
+        {{- end }}
+      volumes:
+        - name: config
+          configMap:
+            name: {{ include "guildsync.fullname" . }}-config
+        - name: data
+          {{- if .Values.persistence.enabled }}
+          persistentVolumeClaim:
+            claimName: {{ include "guildsync.claimName" . }}
+          {{- else }}
+          emptyDir: {}
+          {{- end }}
+      {{- with .Values.nodeSelector }}
+      nodeSelector:
+        {{- toYaml . | nindent 8 }}
+      {{- end }}
+      {{- with .Values.tolerations }}
+      tolerations:
+        {{- toYaml . | nindent 8 }}
+      {{- end }}
+      {{- with .Values.affinity }}
+      affinity:
+        {{- toYaml . | nindent 8 }}
+      {{- end }}
+"#;
+
+const NOTES_TXT: &str = r#"guildsync is running in {{ .Values.mode }} mode as deployment {{ include "guildsync.fullname" . }}.
+
+Logs:
+  kubectl logs -n {{ .Release.Namespace }} deploy/{{ include "guildsync.fullname" . }} --all-containers -f
+{{- if eq .Values.mode "daemon" }}
+
+Drop upload files into /data/inbox to import them into guild {{ first .Values.guilds }}:
+  kubectl cp upload.json {{ .Release.Namespace }}/<pod>:/data/inbox/upload.json
+{{- end }}
+"#;
+
+const HELMIGNORE: &str = ".git/\n*.swp\n*.bak\n*.tmp\n";
+
+/// Fail unless `name` is a valid chart name: lowercase letters, digits, and inner `-`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "chart name {name:?} must be lowercase letters, digits, or inner '-'"
+        ))
+    }
+}
+
+impl Chart<'_> {
+    /// The chart's files, relative to its directory.
+    pub fn files(&self) -> Vec<(PathBuf, String)> {
+        // The tag starts after the last ':' unless that is part of a registry's `host:port`.
+        let (repository, tag) = match self.image.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+            _ => (self.image, ""),
+        };
+        let guilds: Vec<String> = self.guilds.iter().map(|g| format!("\"{g}\"")).collect();
+        let config: String = self
+            .config
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    "\n".to_string()
+                } else {
+                    format!("  {line}\n")
+                }
+            })
+            .collect();
+        let values = VALUES_YAML
+            .replace("{repository}", repository)
+            .replace("{tag}", tag)
+            .replace(
+                "{mode}",
+                self.mode
+                    .to_possible_value()
+                    .expect("not skipped")
+                    .get_name(),
+            )
+            .replace("{guilds}", &format!("[{}]", guilds.join(", ")))
+            .replace("{config}", &config);
+        let chart = CHART_YAML
+            .replace("{name}", self.name)
+            .replace("{app_version}", env!("CARGO_PKG_VERSION"));
+        [
+            ("Chart.yaml", chart),
+            ("values.yaml", values),
+            (".helmignore", HELMIGNORE.to_string()),
+            ("templates/_helpers.tpl", HELPERS_TPL.to_string()),
+            ("templates/configmap.yaml", CONFIGMAP_YAML.to_string()),
+            ("templates/secret.yaml", SECRET_YAML.to_string()),
+            ("templates/pvc.yaml", PVC_YAML.to_string()),
+            ("templates/deployment.yaml", DEPLOYMENT_YAML.to_string()),
+            ("templates/NOTES.txt", NOTES_TXT.to_string()),
+        ]
+        .into_iter()
+        .map(|(path, text)| (PathBuf::from(path), text))
+        .collect()
+    }
+}
+
+/// Write `files` under `out`, which must not hold a chart already unless `force`.
+pub fn write(out: &Path, files: &[(PathBuf, String)], force: bool) -> Result<(), CliError> {
+    if out.join("Chart.yaml").exists() && !force {
+        return Err(CliError::Usage(format!(
+            "{} already holds a chart; pass --force to overwrite",
+            out.display()
+        )));
+    }
+    for (path, text) in files {
+        let path = out.join(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_atomic(&path, text.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_start_from_the_config() {
+        let chart = Chart {
+            name: "guildsync",
+            mode: Mode::Daemon,
+            image: "registry.local:5000/guildsync:1.2",
+            guilds: &[123, 456],
+            config: "[discord]\n\ntoken_env = \"BOT_TOKEN\"\n",
+        };
+        let files = chart.files();
+        let values = &files
+            .iter()
+            .find(|(p, _)| p == Path::new("values.yaml"))
+            .unwrap()
+            .1;
+        let values: serde_yaml::Value = serde_yaml::from_str(values).unwrap();
+        assert_eq!(
+            values["image"]["repository"].as_str(),
+            Some("registry.local:5000/guildsync")
+        );
+        assert_eq!(values["image"]["tag"].as_str(), Some("1.2"));
+        assert_eq!(values["mode"].as_str(), Some("daemon"));
+        assert_eq!(values["guilds"][1].as_str(), Some("456"));
+        assert_eq!(values["config"].as_str(), Some(chart.config));
+        assert!(
+            files
+                .iter()
+                .any(|(p, _)| p == Path::new("templates/deployment.yaml"))
+        );
+
+        assert!(validate_name("guild-sync").is_ok());
+        assert!(validate_name("Guild").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! State for incremental `discord export`: the newest message id seen per channel.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file::write_atomic;
+use crate::error::CliError;
+
+/// Checkpoint persisted next to an incrementally exported dump.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub guild: u64,
+    /// When the checkpoint was last written (RFC 3339).
+    #[serde(default)]
+    pub updated_at: String,
+    /// Channel id to the id of the newest exported message (the snowflake watermark).
+    #[serde(default)]
+    pub channels: BTreeMap<String, u64>,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint for `guild` from `path`; a missing file starts from scratch.
+    pub fn load(path: &Path, guild: u64) -> Result<Self, CliError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    guild,
+                    ..Self::default()
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let checkpoint: Self = serde_json::from_str(&text)
+            .map_err(|e| CliError::Config(format!("checkpoint {}: {e}", path.display())))?;
+        if checkpoint.guild != guild {
+            return Err(CliError::Usage(format!(
+                "checkpoint {} belongs to guild {}, not {guild}",
+                path.display(),
+                checkpoint.guild
+            )));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint atomically.
+    pub fn save(&self, path: &Path) -> Result<(), CliError> {
+        let mut bytes = serde_json::to_vec_pretty(self)?;
+        bytes.push(b'\n');
+        write_atomic(path, &bytes)
+    }
+}
+
+/// Default checkpoint path for a dump written to `out`: `<out>.state.json`.
+pub fn default_path(out: &Path) -> PathBuf {
+    let mut path = out.as_os_str().to_owned();
+    path.push(".state.json");
+    PathBuf::from(path)
+}
@@ -0,0 +1,335 @@
+//! Chunked dumps for `format split` and `format join`.
+//!
+//! A split dump is a directory holding a manifest, a base file, and chunk files. The base is the
+//! dump without any messages; every chunk is a partial dump (`"partial": true`) with stubs of the
+//! channels it covers carrying some of their messages, so chunks can be processed in parallel
+//! and each is still a valid file on its own (`format merge` applies one to the base). Joining
+//! appends the chunks' messages to the base in manifest order, which restores the original.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::atomic_file::write_atomic;
+use crate::config::FormatsConfig;
+use crate::discord::text;
+use crate::error::CliError;
+use crate::format::{self, GuildFormat};
+
+/// `format` of a manifest.
+pub const MANIFEST_FORMAT: &str = "dump-chunks";
+pub const MANIFEST_VERSION: u64 = 1;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const BASE_FILE: &str = "base.json";
+
+/// How `format split` divides messages into chunks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SplitBy {
+    /// One chunk per channel with messages (several if `--max-size` is exceeded).
+    Channel,
+    /// Chunks filled up to `--max-size`, regardless of channel.
+    Size,
+}
+
+/// The manifest of a split dump.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u64,
+    /// The file that was split.
+    pub source: String,
+    pub base: String,
+    pub chunks: Vec<Chunk>,
+}
+
+/// One chunk file in a [`Manifest`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    /// File name, relative to the manifest.
+    pub file: String,
+    /// Ids of the channels whose messages it carries.
+    pub channels: Vec<String>,
+    pub messages: usize,
+    pub bytes: u64,
+    /// SHA-256 of the file, checked when joining.
+    pub sha256: String,
+}
+
+/// Divide `dump` into a base without messages and the chunks carrying them, each at most
+/// `max_size` bytes when serialized compactly.
+pub fn split(
+    dump: &Value,
+    by: SplitBy,
+    max_size: Option<u64>,
+) -> Result<(Value, Vec<Value>), CliError> {
+    let header = serde_json::json!({
+        "format": dump["format"],
+        "version": dump["version"],
+        "partial": true,
+        "guild": { "id": dump["guild"]["id"] },
+        "channels": [],
+    });
+    let limit = max_size.unwrap_or(u64::MAX);
+    let overhead = size(&header);
+
+    let mut base = dump.clone();
+    let mut chunks = Vec::new();
+    let mut current = header.clone();
+    // Compact size of `current`, kept up to date rather than re-serialized.
+    let mut bytes = overhead;
+    for channel in base["channels"].as_array_mut().into_iter().flatten() {
+        let Some(Value::Array(messages)) = channel
+            .as_object_mut()
+            .and_then(|c| c.shift_remove("messages"))
+        else {
+            continue;
+        };
+        if messages.is_empty() {
+            continue;
+        }
+        if by == SplitBy::Channel && bytes > overhead {
+            chunks.push(std::mem::replace(&mut current, header.clone()));
+            bytes = overhead;
+        }
+        let stub = serde_json::json!({ "id": channel["id"], "messages": [] });
+        let stub_size = size(&stub);
+        // Messages of this channel in the current chunk.
+        let mut in_chunk = 0;
+        for message in messages {
+            let message_size = size(&message);
+            if overhead + stub_size + message_size > limit {
+                return Err(CliError::Validation(format!(
+                    "message {} in channel {} alone is larger than --max-size {limit}",
+                    text(&message["id"]),
+                    text(&channel["id"])
+                )));
+            }
+            // Separating commas: between messages of a stub and between stubs.
+            let added = |in_chunk: usize, bytes: u64| match in_chunk {
+                0 => stub_size + message_size + u64::from(bytes > overhead),
+                _ => message_size + 1,
+            };
+            if bytes + added(in_chunk, bytes) > limit {
+                chunks.push(std::mem::replace(&mut current, header.clone()));
+                (bytes, in_chunk) = (overhead, 0);
+            }
+            bytes += added(in_chunk, bytes);
+            let stubs = current["channels"]
+                .as_array_mut()
+                .expect("chunks list channels");
+            if in_chunk == 0 {
+                stubs.push(stub.clone());
+            }
+            if let Some(messages) = stubs.last_mut().and_then(|s| s["messages"].as_array_mut()) {
+                messages.push(message);
+            }
+            in_chunk += 1;
+        }
+    }
+    if bytes > overhead {
+        chunks.push(current);
+    }
+    Ok((base, chunks))
+}
+
+/// Append the messages of `chunks` to their channels in `base`, in order, reading each chunk
+/// only when its turn comes.
+pub fn join(
+    mut base: Value,
+    chunks: impl IntoIterator<Item = Result<Value, CliError>>,
+) -> Result<Value, CliError> {
+    let index: HashMap<String, usize> = base["channels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, channel)| (text(&channel["id"]), i))
+        .collect();
+    for chunk in chunks {
+        let mut chunk = chunk?;
+        for stub in chunk["channels"].as_array_mut().into_iter().flatten() {
+            let id = text(&stub["id"]);
+            let Some(&i) = index.get(&id) else {
+                return Err(CliError::Validation(format!(
+                    "chunk has messages for channel {id}, which the base does not have"
+                )));
+            };
+            let channel = &mut base["channels"][i];
+            if !channel["messages"].is_array() {
+                channel["messages"] = Value::Array(Vec::new());
+            }
+            if let (Some(messages), Value::Array(more)) =
+                (channel["messages"].as_array_mut(), stub["messages"].take())
+            {
+                messages.extend(more);
+            }
+        }
+    }
+    Ok(base)
+}
+
+/// Split the dump `source` into `dir`, which must not exist or be empty, and return the
+/// manifest written there.
+pub fn write_split(
+    source: &Path,
+    dir: &Path,
+    by: SplitBy,
+    max_size: Option<u64>,
+    formats: &FormatsConfig,
+) -> Result<Manifest, CliError> {
+    if std::fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(CliError::Usage(format!(
+            "{} is not empty; split into a new directory",
+            dir.display()
+        )));
+    }
+    let dump = format::read_document(source)?;
+    if format::validate_value(&dump, None, formats)?.format != GuildFormat::Dump {
+        return Err(CliError::Usage(
+            "only dumps carry messages to split; upload files are small already".to_string(),
+        ));
+    }
+    let (base, chunks) = split(&dump, by, max_size)?;
+    std::fs::create_dir_all(dir)?;
+    format::write_document(&dir.join(BASE_FILE), &base)?;
+    let mut manifest = Manifest {
+        format: MANIFEST_FORMAT.to_string(),
+        version: MANIFEST_VERSION,
+        source: source.display().to_string(),
+        base: BASE_FILE.to_string(),
+        chunks: Vec::new(),
+    };
+    for (i, chunk) in chunks.iter().enumerate() {
+        let file = format!("chunk-{:04}.json", i + 1);
+        let bytes = serde_json::to_vec(chunk)?;
+        write_atomic(&dir.join(&file), &bytes)?;
+        let stubs = chunk["channels"].as_array().into_iter().flatten();
+        manifest.chunks.push(Chunk {
+            file,
+            channels: stubs.clone().map(|stub| text(&stub["id"])).collect(),
+            messages: stubs
+                .map(|stub| stub["messages"].as_array().map_or(0, Vec::len))
+                .sum(),
+            bytes: bytes.len() as u64,
+            sha256: digest(&bytes),
+        });
+    }
+    let mut bytes = serde_json::to_vec_pretty(&manifest)?;
+    bytes.push(b'\n');
+    write_atomic(&dir.join(MANIFEST_FILE), &bytes)?;
+    Ok(manifest)
+}
+
+/// Reassemble the split dump in `dir`, checking every chunk against the manifest.
+pub fn read_split(dir: &Path) -> Result<(Manifest, Value), CliError> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.is_file() {
+        return Err(CliError::NotFound(format!(
+            "{}: no {MANIFEST_FILE}; not a split dump",
+            dir.display()
+        )));
+    }
+    let manifest: Manifest = serde_json::from_value(format::read_document(&path)?)
+        .map_err(|e| CliError::Validation(format!("{}: {e}", path.display())))?;
+    if manifest.format != MANIFEST_FORMAT || manifest.version != MANIFEST_VERSION {
+        return Err(CliError::Validation(format!(
+            "{}: expected a {MANIFEST_FORMAT} v{MANIFEST_VERSION} manifest, found {} v{}",
+            path.display(),
+            manifest.format,
+            manifest.version
+        )));
+    }
+    let base = format::read_document(&dir.join(&manifest.base))?;
+    let chunks = manifest.chunks.iter().map(|chunk| {
+        let bytes = std::fs::read(dir.join(&chunk.file))?;
+        if digest(&bytes) != chunk.sha256 {
+            return Err(CliError::Validation(format!(
+                "{}: checksum does not match the manifest; the chunk is damaged or was edited",
+                chunk.file
+            )));
+        }
+        format::parse_json(&String::from_utf8_lossy(&bytes))
+    });
+    let joined = join(base, chunks)?;
+    Ok((manifest, joined))
+}
+
+fn digest(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Parse a size like `50M`, `1G`, `512k`, or `1000` (bytes); units are powers of 1024.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (digits, unit) = text.split_at(split);
+    let number: u64 = digits
+        .parse()
+        .map_err(|_| format!("`{text}` is not a size (e.g. 50M)"))?;
+    let shift = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(format!("`{text}` has an unknown unit (use K, M, or G)")),
+    };
+    number
+        .checked_mul(1 << shift)
+        .filter(|size| *size > 0)
+        .ok_or_else(|| format!("`{text}` is not a usable size"))
+}
+
+fn size(value: &Value) -> u64 {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_stay_within_the_size_limit_and_join_back() {
+        let channel = |id: u64, count: u64| {
+            let messages: Vec<Value> = (0..count)
+                .map(
+                    |m| serde_json::json!({ "id": (id * 100 + m).to_string(), "content": "hello" }),
+                )
+                .collect();
+            serde_json::json!({ "id": id.to_string(), "type": 0, "messages": messages })
+        };
+        let dump = serde_json::json!({
+            "format": "dump",
+            "version": 1,
+            "guild": { "id": "1" },
+            "channels": [channel(1, 3), { "id": "2", "type": 0 }, channel(3, 20)],
+        });
+
+        let (base, chunks) = split(&dump, SplitBy::Channel, None).unwrap();
+        assert!(base["channels"][0].get("messages").is_none());
+        assert_eq!(chunks.len(), 2);
+
+        let (base, chunks) = split(&dump, SplitBy::Size, Some(300)).unwrap();
+        assert!(chunks.len() > 2);
+        assert!(chunks.iter().all(|chunk| size(chunk) <= 300));
+        // Channel 1's messages share the first chunk with the start of channel 3's.
+        assert_eq!(chunks[0]["channels"].as_array().unwrap().len(), 2);
+        assert_eq!(join(base, chunks.into_iter().map(Ok)).unwrap(), dump);
+
+        assert!(split(&dump, SplitBy::Size, Some(100)).is_err());
+        assert_eq!(parse_size("50M"), Ok(50 << 20));
+        assert_eq!(parse_size("2KiB"), Ok(2048));
+        assert!(parse_size("5X").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::{Path, PathBuf};
+
+use clap_complete::Shell;
+
+use crate::atomic_file::write_atomic;
+use crate::config::expand_tilde;
+use crate::error::CliError;
+
+/// Conventional per-user completion file for `shell`, if it has one that is loaded automatically.
+pub fn install_path(shell: Shell, bin: &str) -> Option<PathBuf> {
+    let data_home = || env_dir("XDG_DATA_HOME", "~/.local/share");
+    let config_home = || env_dir("XDG_CONFIG_HOME", "~/.config");
+    match shell {
+        Shell::Bash => Some(data_home().join("bash-completion/completions").join(bin)),
+        Shell::Zsh => Some(expand_tilde(Path::new("~/.zsh/completions")).join(format!("_{bin}"))),
+        Shell::Fish => Some(
+            config_home()
+                .join("fish/completions")
+                .join(format!("{bin}.fish")),
+        ),
+        _ => None,
+    }
+}
+
+/// Write `script` to `path`, creating parent directories; an existing file needs `force`.
+pub fn install(path: &Path, script: &[u8], force: bool) -> Result<(), CliError> {
+    if path.exists() && !force {
+        return Err(CliError::Usage(format!(
+            "{} already exists; pass --force to overwrite",
+            path.display()
+        )));
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    write_atomic(path, script)
+}
+
+fn env_dir(var: &str, fallback: &str) -> PathBuf {
+    std::env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| expand_tilde(Path::new(fallback)))
+}
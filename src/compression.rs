@@ -0,0 +1,157 @@
+//! Transparent compression for dump/upload files.
+//!
+//! Readers detect gzip and zstd by their magic bytes; writers compress as the destination's
+//! extension (`.gz`, `.zst`) implies unless a [`Codec`] is given explicitly. Readers also decrypt
+//! age files first (see [`crate::encryption`]).
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::encryption;
+use crate::error::CliError;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// zstd level for writing; 0 picks the library default.
+const ZSTD_LEVEL: i32 = 0;
+
+/// How a file is compressed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// The codec a destination's extension implies: `.gz` or `.zst`, otherwise none.
+    pub fn for_path(path: &Path) -> Self {
+        if has_extension(path, "gz") {
+            Codec::Gzip
+        } else if has_extension(path, "zst") {
+            Codec::Zstd
+        } else {
+            Codec::None
+        }
+    }
+
+    /// The codec of a file starting with `head`. Compressed files always carry their magic
+    /// bytes, so the extension is not consulted: `--compress none` may write a plain `.gz`.
+    fn detect(head: &[u8]) -> Self {
+        if head.starts_with(&GZIP_MAGIC) {
+            Codec::Gzip
+        } else if head.starts_with(&ZSTD_MAGIC) {
+            Codec::Zstd
+        } else {
+            Codec::None
+        }
+    }
+}
+
+/// Read a file as UTF-8, decompressing it if it is gzip or zstd.
+pub fn read_to_string(path: &Path) -> Result<String, CliError> {
+    let mut text = String::new();
+    open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Open a file for reading line by line, decompressing it like [`read_to_string`] does.
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>, CliError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if encryption::is_encrypted(reader.fill_buf()?) {
+        let plain = BufReader::new(encryption::decrypt(path, reader)?);
+        return decompress(plain);
+    }
+    decompress(reader)
+}
+
+fn decompress<R: BufRead + 'static>(mut reader: R) -> Result<Box<dyn BufRead>, CliError> {
+    Ok(match Codec::detect(reader.fill_buf()?) {
+        Codec::None => Box::new(reader),
+        Codec::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Codec::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+/// Run `write` against `out`, compressing what it writes with `codec`.
+pub fn stream_to<W: Write>(
+    codec: Codec,
+    out: W,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), CliError>,
+) -> Result<(), CliError> {
+    let mut out = std::io::BufWriter::new(out);
+    match codec {
+        Codec::None => write(&mut out)?,
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(&mut out, Compression::default());
+            write(&mut encoder)?;
+            encoder.finish()?;
+        }
+        Codec::Zstd => {
+            let mut encoder = zstd::Encoder::new(&mut out, ZSTD_LEVEL)?;
+            write(&mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Whether `path` names a file of the given kind (`ndjson`, `json`, ...), compressed or not.
+pub fn is_kind(path: &Path, ext: &str) -> bool {
+    let stem = match Codec::for_path(path) {
+        Codec::None => path,
+        Codec::Gzip | Codec::Zstd => Path::new(path.file_stem().unwrap_or_default()),
+    };
+    has_extension(stem, ext)
+}
+
+/// Compress `bytes` with `codec`.
+pub fn encode(codec: Codec, bytes: Vec<u8>) -> Result<Vec<u8>, CliError> {
+    match codec {
+        Codec::None => Ok(bytes),
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&bytes)?;
+            Ok(encoder.finish()?)
+        }
+        Codec::Zstd => Ok(zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL)?),
+    }
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_codecs_round_trip_and_are_detected_by_magic_bytes() {
+        let dir =
+            std::env::temp_dir().join(format!("guildsync-compression-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = r#"{"format":"dump"}"#.repeat(100);
+        for codec in [Codec::Gzip, Codec::Zstd] {
+            // No extension: only the magic bytes tell.
+            let path = dir.join(format!("{codec:?}"));
+            std::fs::write(&path, encode(codec, text.clone().into_bytes()).unwrap()).unwrap();
+            assert!(std::fs::metadata(&path).unwrap().len() < 100);
+            assert_eq!(read_to_string(&path).unwrap(), text);
+        }
+        assert_eq!(Codec::for_path(Path::new("guild.json.zst")), Codec::Zstd);
+        assert!(is_kind(Path::new("guild.ndjson.zst"), "ndjson"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,828 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{self, TokenSource};
+use crate::compression::Codec;
+use crate::discord;
+use crate::encryption;
+use crate::error::CliError;
+use crate::format::GuildFormat;
+use crate::kube;
+use crate::layout::{self, Layout};
+use crate::lint;
+use crate::relay;
+use crate::runner::RunnerConfig;
+use crate::signing;
+use crate::tmux;
+
+/// Placeholder printed in place of secret values.
+pub const REDACTED: &str = "<redacted>";
+
+/// Settings loaded from the config file. Missing sections fall back to defaults.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// File the config was loaded from; `None` when running on built-in defaults.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+
+    pub discord: DiscordConfig,
+    pub formats: FormatsConfig,
+    pub terminal: TerminalConfig,
+    pub kube: KubeConfig,
+    pub ssh: SshConfig,
+    pub retry: RetryConfig,
+    pub hooks: HooksConfig,
+}
+
+/// `[discord]` section.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DiscordConfig {
+    /// Environment variable holding the bot token.
+    pub token_env: String,
+    /// Inline bot token. Discouraged; prefer `guildsync auth login` or `token_env`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Base URL of the Discord HTTP API.
+    pub api_base: String,
+    /// Base URL of the Discord CDN, which serves emoji and sticker images.
+    pub cdn_base: String,
+    /// Attachment downloads in flight at once during `export --with-attachments`.
+    pub attachment_concurrency: usize,
+    /// Pause between messages posted by `import --replay-messages`, in milliseconds.
+    pub replay_interval_ms: u64,
+    /// Guilds exported at once by `discord export --all`.
+    pub export_concurrency: usize,
+    /// Guilds exported by `discord export --all` (`[[discord.guilds]]` tables).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub guilds: Vec<GuildExport>,
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            token_env: "DISCORD_TOKEN".to_string(),
+            token: None,
+            api_base: "https://discord.com/api/v10".to_string(),
+            cdn_base: "https://cdn.discordapp.com".to_string(),
+            attachment_concurrency: 4,
+            replay_interval_ms: 2000,
+            export_concurrency: 2,
+            guilds: Vec::new(),
+        }
+    }
+}
+
+impl DiscordConfig {
+    /// Resolve the bot token: explicit value (flag or `DISCORD_TOKEN`), then the
+    /// `token_env` variable, then the keyring (`auth login`), then the inline `token`.
+    pub fn resolve_token(&self, explicit: Option<&str>) -> Result<String, CliError> {
+        self.resolve_token_with_source(explicit)
+            .map(|(token, _)| token)
+    }
+
+    /// [`DiscordConfig::resolve_token`], also reporting where the token came from.
+    ///
+    /// An unavailable keyring (e.g. no Secret Service on a headless host) is skipped.
+    pub fn resolve_token_with_source(
+        &self,
+        explicit: Option<&str>,
+    ) -> Result<(String, TokenSource), CliError> {
+        let non_empty = |token: &String| !token.is_empty();
+        let keyring = || {
+            auth::stored_token()
+                .inspect_err(|err| tracing::debug!("skipping keyring: {err}"))
+                .ok()
+                .flatten()
+        };
+        explicit
+            .map(str::to_string)
+            .filter(non_empty)
+            .map(|t| (t, TokenSource::Flag))
+            .or_else(|| {
+                let token = std::env::var(&self.token_env).ok().filter(non_empty)?;
+                Some((token, TokenSource::Env))
+            })
+            .or_else(|| Some((keyring().filter(non_empty)?, TokenSource::Keyring)))
+            .or_else(|| Some((self.token.clone().filter(non_empty)?, TokenSource::Config)))
+            .ok_or_else(|| {
+                CliError::Auth(format!(
+                    "no Discord token; run `guildsync auth login`, pass --token, or set {}",
+                    self.token_env
+                ))
+            })
+    }
+}
+
+/// One `[[discord.guilds]]` entry: a guild and the `discord export` flags to export it with.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GuildExport {
+    /// Discord guild ID.
+    pub id: u64,
+    /// Output path of the dump (`--out`).
+    pub out: PathBuf,
+    #[serde(default = "default_guild_format")]
+    pub format: GuildFormat,
+    #[serde(default)]
+    pub incremental: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// Checkpoint file for `incremental` (default `<out>.state.json`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<PathBuf>,
+    #[serde(default)]
+    pub with_attachments: bool,
+    #[serde(default)]
+    pub with_assets: bool,
+    /// Write `<out>.manifest.json` with checksums of the dump and its attachments.
+    #[serde(default)]
+    pub manifest: bool,
+    /// age recipients (`age1…`) to encrypt the dump to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encrypt_to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<u64>,
+    /// Message ID, date, or RFC 3339 time, as for `--after`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Message ID, date, or RFC 3339 time, as for `--before`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// Compression of the output (default: as its extension implies).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compress: Option<Codec>,
+}
+
+fn default_guild_format() -> GuildFormat {
+    GuildFormat::Dump
+}
+
+impl GuildExport {
+    /// How the output is compressed: `compress`, or as the extension of `out` implies.
+    pub fn codec(&self) -> Codec {
+        self.compress.unwrap_or_else(|| Codec::for_path(&self.out))
+    }
+
+    /// The `after` and `before` bounds as snowflakes, or which of them does not parse.
+    pub fn bounds(&self) -> Result<(Option<u64>, Option<u64>), String> {
+        let parse = |field: &str, bound: &Option<String>| {
+            bound
+                .as_deref()
+                .map(discord::parse_bound)
+                .transpose()
+                .map_err(|e| format!("discord.guilds {}: {field}: {e}", self.id))
+        };
+        Ok((parse("after", &self.after)?, parse("before", &self.before)?))
+    }
+}
+
+/// `[formats]` section.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FormatsConfig {
+    pub dump_version: u32,
+    pub upload_version: u32,
+    pub strict: bool,
+    /// Patterns `format anonymize` scrubs from message content (`[[formats.anonymize]]`
+    /// tables); configuring any replaces the built-in email and phone number rules.
+    pub anonymize: Vec<AnonymizeRule>,
+    /// Levels of `format lint` rules by rule name (`[formats.lint]`), overriding their defaults.
+    pub lint: BTreeMap<String, lint::Level>,
+    /// Environment variable holding the `format sign` key, checked before the keyring.
+    pub signing_key_env: String,
+    /// Base64 public keys whose signatures `format verify-signature` accepts.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<String>,
+    /// age identity file that encrypted dumps are decrypted with (`--identity`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<PathBuf>,
+}
+
+impl Default for FormatsConfig {
+    fn default() -> Self {
+        Self {
+            dump_version: 1,
+            upload_version: 1,
+            strict: true,
+            anonymize: vec![
+                AnonymizeRule {
+                    name: "email".to_string(),
+                    pattern: r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}".to_string(),
+                    replacement: "[email]".to_string(),
+                },
+                // At least two separators, so snowflakes in channel and role mentions survive.
+                AnonymizeRule {
+                    name: "phone".to_string(),
+                    pattern: r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)|\d{2,4})[ .-]\d{3,4}[ .-]\d{3,5}"
+                        .to_string(),
+                    replacement: "[phone]".to_string(),
+                },
+            ],
+            lint: BTreeMap::new(),
+            signing_key_env: "GUILDSYNC_SIGNING_KEY".to_string(),
+            trusted_keys: Vec::new(),
+            identity_file: None,
+        }
+    }
+}
+
+/// One `[[formats.anonymize]]` table: a regular expression and what matches become.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AnonymizeRule {
+    /// Name reported in the per-rule match counts.
+    pub name: String,
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// `[terminal]` section.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+    pub tmux_default_session: String,
+    /// Program for the agent pane (`opencode`, `codex`, ...).
+    pub agent_command: String,
+    /// Program for the interpreter pane; the user's shell if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpreter_command: Option<String>,
+    /// Line format of `terminal send`.
+    pub send_template: String,
+    /// Pause between the messages `terminal capture` posts.
+    pub capture_interval_ms: u64,
+    /// Users whose messages `terminal send` and `terminal bridge` relay, besides their
+    /// `--allow-user` flags.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bridge_allowed_users: Vec<u64>,
+    /// Running code blocks of bridged messages.
+    pub runner: RunnerConfig,
+    /// Named tmux layouts for `terminal layout apply`; `opencode` replaces the built-in one.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub layouts: BTreeMap<String, Layout>,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            tmux_default_session: "opencode".to_string(),
+            agent_command: "opencode".to_string(),
+            interpreter_command: None,
+            send_template: relay::DEFAULT_TEMPLATE.to_string(),
+            capture_interval_ms: 1000,
+            bridge_allowed_users: Vec::new(),
+            runner: RunnerConfig::default(),
+            layouts: BTreeMap::new(),
+        }
+    }
+}
+
+impl TerminalConfig {
+    /// The layout called `name`: from `layouts`, or the built-in `opencode` one.
+    pub fn layout(&self, name: &str) -> Result<Layout, CliError> {
+        match self.layouts.get(name) {
+            Some(layout) => Ok(layout.clone()),
+            None if name == layout::OPENCODE => Ok(layout::opencode()),
+            None => Err(CliError::NotFound(format!(
+                "no layout {name} in [terminal.layouts]; see `terminal layout list`"
+            ))),
+        }
+    }
+}
+
+/// `[kube.local]` and `[kube.remote]` sections.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KubeConfig {
+    pub local: KubeLocalConfig,
+    pub remote: KubeRemoteConfig,
+    pub deploy: KubeDeployConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KubeLocalConfig {
+    /// Backend that runs the cluster; `--backend` overrides it.
+    #[serde(alias = "backend")]
+    pub provider: KubeProvider,
+    /// Cluster name; `--name` overrides it.
+    pub name: String,
+    /// Node image the cluster is created with (`kindest/node:v1.30.0`, `rancher/k3s:...`, or
+    /// minikube's `--base-image`); the backend's default if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_image: Option<String>,
+    /// kubeconfig file the cluster's context is written (or merged) into; otherwise the last
+    /// `--kubeconfig` file, or the backend's default (`$KUBECONFIG`, `~/.kube/config`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubeconfig: Option<PathBuf>,
+}
+
+impl Default for KubeLocalConfig {
+    fn default() -> Self {
+        Self {
+            provider: KubeProvider::default(),
+            name: kube::LOCAL_CLUSTER.to_string(),
+            node_image: None,
+            kubeconfig: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KubeProvider {
+    #[default]
+    Kind,
+    K3d,
+    Minikube,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KubeRemoteConfig {
+    pub contexts: Vec<String>,
+    /// Container image used by the built-in `kube remote test` Job.
+    pub image: String,
+}
+
+impl Default for KubeRemoteConfig {
+    fn default() -> Self {
+        Self {
+            contexts: Vec::new(),
+            image: "ghcr.io/realagiorganization/guildsync:latest".to_string(),
+        }
+    }
+}
+
+/// `[kube.deploy]` section: what `kube remote deploy` renders; flags override each field.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KubeDeployConfig {
+    /// Name of the Deployment; the ConfigMap and Secret are `<name>-config` and `<name>-token`.
+    pub name: String,
+    pub namespace: String,
+    /// Watcher image (default: `kube.remote.image`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Guild the watcher follows (default: the only `[[discord.guilds]]` entry).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guild: Option<u64>,
+    /// Directory of templates replacing the built-in ones by file name, plus manifests of its
+    /// own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub templates: Option<PathBuf>,
+    /// Extra template variables, or overrides of the built-in ones.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Default for KubeDeployConfig {
+    fn default() -> Self {
+        Self {
+            name: "guildsync".to_string(),
+            namespace: "default".to_string(),
+            image: None,
+            guild: None,
+            templates: None,
+            vars: BTreeMap::new(),
+        }
+    }
+}
+
+/// `[ssh]` section.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SshConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<PathBuf>,
+    pub known_hosts_mode: KnownHostsMode,
+    /// OpenSSH client config to read host aliases from and pass to `ssh -F` [default:
+    /// `~/.ssh/config`, which `ssh` reads by itself].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_file: Option<PathBuf>,
+    /// Hosts `ssh exec` runs on at once when given several.
+    pub concurrency: usize,
+    /// Named lists of hosts for `ssh exec --group` (`[ssh.groups]`).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        Self {
+            user: None,
+            identity_file: None,
+            known_hosts_mode: KnownHostsMode::default(),
+            config_file: None,
+            concurrency: 8,
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KnownHostsMode {
+    #[default]
+    Strict,
+    AcceptNew,
+    Off,
+}
+
+/// `[retry]` section: defaults for `--max-retries` / `--retry-base-ms`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub base_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_ms: 500,
+        }
+    }
+}
+
+/// `[hooks]` section: shell commands run around every action.
+///
+/// Templates may use `{action}` (e.g. `discord.export`) and `{status}` (`started` for the
+/// pre-hook; `ok` or `error` for the post-hook).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_hook: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_hook: Option<String>,
+    /// Abort the action when the pre-hook fails; otherwise only warn.
+    pub pre_hook_required: bool,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_hook: None,
+            post_hook: None,
+            pre_hook_required: true,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from an explicit path, or from the default location if it exists.
+    ///
+    /// An explicit path must exist; a missing default file yields the built-in defaults.
+    pub fn load(explicit: Option<&Path>) -> Result<Self, CliError> {
+        match explicit {
+            Some(path) => Self::from_path(path),
+            None => match default_path() {
+                Some(path) if path.is_file() => Self::from_path(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+
+    fn from_path(path: &Path) -> Result<Self, CliError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CliError::Config(format!("{}: {e}", path.display())))?;
+        let mut config = match ConfigFormat::from_path(path) {
+            Some(format) => format.parse(&text).map_err(|e| {
+                CliError::Config(format!("{}: invalid {format}: {e}", path.display()))
+            })?,
+            None => {
+                let mut attempts = Vec::new();
+                ConfigFormat::ALL
+                    .iter()
+                    .find_map(|format| match format.parse(&text) {
+                        Ok(config) => Some(config),
+                        Err(e) => {
+                            attempts.push(format!("{format}: {e}"));
+                            None
+                        }
+                    })
+                    .ok_or_else(|| {
+                        CliError::Config(format!(
+                            "{}: unrecognized config format ({})",
+                            path.display(),
+                            attempts.join("; ")
+                        ))
+                    })?
+            }
+        };
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Copy of the config with secret values replaced by [`REDACTED`].
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if config.discord.token.is_some() {
+            config.discord.token = Some(REDACTED.to_string());
+        }
+        config
+    }
+
+    /// Check semantic constraints that parsing alone does not catch.
+    ///
+    /// All problems are reported together in a single [`CliError::Config`].
+    pub fn validate(&self) -> Result<(), CliError> {
+        let Some(source) = &self.source else {
+            return Err(CliError::Config(match default_path() {
+                Some(path) => format!("no config file found at {}", path.display()),
+                None => "no config file found".to_string(),
+            }));
+        };
+
+        let mut problems = Vec::new();
+        if self.discord.token_env.is_empty() {
+            problems.push("discord.token_env must not be empty".to_string());
+        }
+        if let Err(err) = tmux::validate_session_name(&self.terminal.tmux_default_session) {
+            problems.push(format!("terminal.tmux_default_session: {err}"));
+        }
+        if self.terminal.agent_command.trim().is_empty() {
+            problems.push("terminal.agent_command must not be empty".to_string());
+        }
+        if let Err(err) = relay::Template::parse(&self.terminal.send_template) {
+            problems.push(format!("terminal.send_template: {err}"));
+        }
+        if let Err(err) = self.terminal.runner.validate() {
+            problems.push(err);
+        }
+        for (name, layout) in &self.terminal.layouts {
+            problems.extend(layout.problems(name));
+        }
+        if let Err(err) = kube::validate_cluster_name(&self.kube.local.name) {
+            problems.push(format!("kube.local.name: {err}"));
+        }
+        if let Some(identity) = &self.ssh.identity_file {
+            let path = expand_tilde(identity);
+            if !path.is_file() {
+                problems.push(format!(
+                    "ssh.identity_file: {} does not exist",
+                    path.display()
+                ));
+            }
+        }
+        if let Some(file) = &self.ssh.config_file {
+            let path = expand_tilde(file);
+            if !path.is_file() {
+                problems.push(format!(
+                    "ssh.config_file: {} does not exist",
+                    path.display()
+                ));
+            }
+        }
+        if self.ssh.concurrency == 0 {
+            problems.push("ssh.concurrency must be at least 1".to_string());
+        }
+        for (name, hosts) in &self.ssh.groups {
+            if hosts.is_empty() || hosts.iter().any(|host| host.trim().is_empty()) {
+                problems.push(format!(
+                    "ssh.groups.{name} must list at least one host, none empty"
+                ));
+            }
+        }
+        let mut outs = std::collections::HashSet::new();
+        for guild in &self.discord.guilds {
+            if let Err(err) = guild.bounds() {
+                problems.push(err);
+            }
+            if let Err(err) = encryption::recipients(&guild.encrypt_to, "discord.guilds encrypt_to")
+            {
+                problems.push(err);
+            }
+            if !outs.insert(&guild.out) {
+                problems.push(format!(
+                    "discord.guilds: {} is the output of more than one guild",
+                    guild.out.display()
+                ));
+            }
+        }
+        if self.discord.export_concurrency == 0 {
+            problems.push("discord.export_concurrency must be at least 1".to_string());
+        }
+        if self.formats.dump_version == 0 || self.formats.upload_version == 0 {
+            problems.push("formats: versions start at 1".to_string());
+        }
+        for rule in &self.formats.anonymize {
+            if let Err(err) = regex::Regex::new(&rule.pattern) {
+                problems.push(format!("formats.anonymize {}: {err}", rule.name));
+            }
+        }
+        for name in lint::unknown_rules(&self.formats.lint) {
+            problems.push(format!("formats.lint: unknown rule `{name}`"));
+        }
+        if self.formats.signing_key_env.is_empty() {
+            problems.push("formats.signing_key_env must not be empty".to_string());
+        }
+        if let Some(identity) = &self.formats.identity_file {
+            let path = expand_tilde(identity);
+            if !path.is_file() {
+                problems.push(format!(
+                    "formats.identity_file: {} does not exist",
+                    path.display()
+                ));
+            }
+        }
+        for key in &self.formats.trusted_keys {
+            if let Err(err) = signing::public_key(key) {
+                problems.push(format!("formats.trusted_keys {key}: {err}"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CliError::Config(format!(
+                "{}: {}",
+                source.display(),
+                problems.join("; ")
+            )))
+        }
+    }
+}
+
+/// Serialization formats accepted for config files, chosen by extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Order in which formats are tried when the extension is not recognized.
+    pub const ALL: [ConfigFormat; 3] = [ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json];
+
+    /// Format implied by the file extension, if recognized.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+
+    fn parse(self, text: &str) -> Result<Config, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Json => "json",
+        })
+    }
+}
+
+/// `$XDG_CONFIG_HOME/guildsync/config.toml`, falling back to `~/.config/guildsync/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("guildsync").join("config.toml"))
+}
+
+/// Expand a leading `~/` to `$HOME`.
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+[discord]
+token_env = "BOT_TOKEN"
+
+[[discord.guilds]]
+id = 123
+out = "main.json"
+incremental = true
+after = "2024-01-01"
+
+[formats]
+strict = false
+
+[kube.local]
+provider = "k3d"
+
+[kube.remote]
+contexts = ["dev", "staging"]
+
+[ssh]
+user = "stc"
+known_hosts_mode = "accept-new"
+
+[retry]
+max_retries = 5
+"#;
+
+    const YAML: &str = r#"
+discord:
+  token_env: BOT_TOKEN
+  guilds:
+    - id: 123
+      out: main.json
+      incremental: true
+      after: "2024-01-01"
+formats:
+  strict: false
+kube:
+  local:
+    provider: k3d
+  remote:
+    contexts: [dev, staging]
+ssh:
+  user: stc
+  known_hosts_mode: accept-new
+retry:
+  max_retries: 5
+"#;
+
+    const JSON: &str = r#"{
+  "discord": {
+    "token_env": "BOT_TOKEN",
+    "guilds": [{ "id": 123, "out": "main.json", "incremental": true, "after": "2024-01-01" }]
+  },
+  "formats": { "strict": false },
+  "kube": { "local": { "provider": "k3d" }, "remote": { "contexts": ["dev", "staging"] } },
+  "ssh": { "user": "stc", "known_hosts_mode": "accept-new" },
+  "retry": { "max_retries": 5 }
+}"#;
+
+    fn load(name: &str, text: &str) -> Result<Config, CliError> {
+        let dir = std::env::temp_dir().join(format!("guildsync-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, text).unwrap();
+        let config = Config::load(Some(&path));
+        std::fs::remove_file(&path).unwrap();
+        config.map(|c| Config { source: None, ..c })
+    }
+
+    #[test]
+    fn same_config_from_toml_yaml_and_json() {
+        let toml = load("config.toml", TOML).unwrap();
+        let yaml = load("config.yaml", YAML).unwrap();
+        let yml = load("config.yml", YAML).unwrap();
+        let json = load("config.json", JSON).unwrap();
+
+        assert_eq!(toml, yaml);
+        assert_eq!(toml, yml);
+        assert_eq!(toml, json);
+        assert_eq!(toml.kube.local.provider, KubeProvider::K3d);
+        assert_eq!(toml.retry.max_retries, 5);
+        assert_eq!(toml.retry.base_ms, RetryConfig::default().base_ms);
+        let guild = &toml.discord.guilds[0];
+        assert_eq!(guild.format, GuildFormat::Dump);
+        assert_eq!(
+            guild.bounds().unwrap(),
+            (Some(discord::parse_bound("2024-01-01").unwrap()), None)
+        );
+    }
+
+    #[test]
+    fn unknown_extension_tries_each_format() {
+        assert_eq!(
+            load("config.conf", JSON).unwrap(),
+            load("config.json", JSON).unwrap()
+        );
+        assert_eq!(
+            load("config", YAML).unwrap(),
+            load("config.yaml", YAML).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_failure_names_the_format() {
+        let err = load("config.yaml", "retry: [").unwrap_err().to_string();
+        assert!(err.contains("invalid yaml"), "{err}");
+    }
+}
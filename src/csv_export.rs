@@ -0,0 +1,286 @@
+//! CSV export of a dump's members, messages, or roles for `format export-csv`, for moderators
+//! who work in spreadsheets.
+//!
+//! Each entity has a fixed set of columns; `--columns` picks some of them in any order. Lists
+//! (a member's roles) are joined with `; `, times are normalized to UTC RFC 3339, and missing
+//! values are empty cells.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::atomic_file::AtomicFile;
+use crate::discord::{list, text};
+use crate::error::CliError;
+use crate::timestamp;
+
+/// What each row of the CSV describes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Entity {
+    Members,
+    Messages,
+    Roles,
+}
+
+impl Entity {
+    /// Every column of the entity, in default order.
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            Entity::Members => &[
+                "id",
+                "username",
+                "global_name",
+                "nick",
+                "joined_at",
+                "bot",
+                "roles",
+                "role_ids",
+            ],
+            Entity::Messages => &[
+                "id",
+                "channel_id",
+                "channel",
+                "author_id",
+                "author",
+                "timestamp",
+                "edited_timestamp",
+                "content",
+                "attachments",
+                "reply_to",
+                "pinned",
+            ],
+            Entity::Roles => &[
+                "id",
+                "name",
+                "color",
+                "position",
+                "permissions",
+                "hoist",
+                "mentionable",
+                "managed",
+                "members",
+            ],
+        }
+    }
+
+    /// `requested` columns checked against the entity's, or all of them if none are.
+    pub fn select(self, requested: &[String]) -> Result<Vec<&'static str>, String> {
+        let known = self.columns();
+        if requested.is_empty() {
+            return Ok(known.to_vec());
+        }
+        requested
+            .iter()
+            .map(|column| {
+                known
+                    .iter()
+                    .find(|known| **known == column.trim())
+                    .copied()
+                    .ok_or_else(|| {
+                        format!(
+                            "unknown column {column:?}; known columns: {}",
+                            known.join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// When cells are quoted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Quote {
+    /// Only cells containing the delimiter, a quote, or a line break.
+    #[default]
+    Necessary,
+    Always,
+}
+
+/// How the CSV is written.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub columns: Vec<&'static str>,
+    pub delimiter: u8,
+    pub quote: Quote,
+    /// Start with a UTF-8 byte order mark, which Excel needs to read non-ASCII text.
+    pub bom: bool,
+}
+
+/// Write the `entity` rows of `document` to `out` as CSV, atomically. `members` (from `discord
+/// members`) are needed for the members entity and fill the roles' `members` column. Returns
+/// the number of rows.
+pub fn export(
+    document: &Value,
+    members: &[Value],
+    entity: Entity,
+    options: &Options,
+    out: &Path,
+) -> Result<usize, CliError> {
+    let mut file = AtomicFile::create(out)?;
+    if options.bom {
+        file.write_all(b"\xEF\xBB\xBF")?;
+    }
+    let rows = write(document, members, entity, options, &mut file)?;
+    file.commit()?;
+    Ok(rows)
+}
+
+fn write(
+    document: &Value,
+    members: &[Value],
+    entity: Entity,
+    options: &Options,
+    out: impl Write,
+) -> Result<usize, CliError> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .quote_style(match options.quote {
+            Quote::Necessary => csv::QuoteStyle::Necessary,
+            Quote::Always => csv::QuoteStyle::Always,
+        })
+        .from_writer(out);
+    writer.write_record(&options.columns).map_err(csv_error)?;
+    let mut rows = 0;
+    let mut row = |cell: &dyn Fn(&str) -> String| -> Result<(), CliError> {
+        let record: Vec<String> = options.columns.iter().map(|c| cell(c)).collect();
+        writer.write_record(&record).map_err(csv_error)?;
+        rows += 1;
+        Ok(())
+    };
+
+    let role_names: HashMap<String, String> = list(&document["roles"])
+        .map(|role| (text(&role["id"]), text(&role["name"])))
+        .collect();
+    match entity {
+        Entity::Members => {
+            for member in members {
+                row(&|column| match column {
+                    "joined_at" => time(&member[column]),
+                    "roles" => join(list(&member["roles"]).map(|id| {
+                        let id = text(id);
+                        role_names.get(&id).cloned().unwrap_or(id)
+                    })),
+                    "role_ids" => join(list(&member["roles"]).map(text)),
+                    _ => text(&member[column]),
+                })?;
+            }
+        }
+        Entity::Messages => {
+            for channel in ["channels", "threads"]
+                .iter()
+                .flat_map(|section| list(&document[*section]))
+            {
+                for message in list(&channel["messages"]) {
+                    let author = &message["author"];
+                    row(&|column| match column {
+                        "channel_id" => text(&channel["id"]),
+                        "channel" => text(&channel["name"]),
+                        "author_id" => text(&author["id"]),
+                        "author" => text(
+                            [&author["global_name"], &author["username"]]
+                                .into_iter()
+                                .find(|name| !name.is_null())
+                                .unwrap_or(&Value::Null),
+                        ),
+                        "timestamp" | "edited_timestamp" => time(&message[column]),
+                        "attachments" => list(&message["attachments"]).count().to_string(),
+                        "reply_to" => text(&message["message_reference"]["message_id"]),
+                        _ => text(&message[column]),
+                    })?;
+                }
+            }
+        }
+        Entity::Roles => {
+            let mut holders: HashMap<String, usize> = HashMap::new();
+            for id in members.iter().flat_map(|m| list(&m["roles"])) {
+                *holders.entry(text(id)).or_default() += 1;
+            }
+            for role in list(&document["roles"]) {
+                row(&|column| match column {
+                    "color" => role["color"]
+                        .as_u64()
+                        .map(|color| format!("#{color:06x}"))
+                        .unwrap_or_default(),
+                    "members" if members.is_empty() => String::new(),
+                    "members" => holders
+                        .get(&text(&role["id"]))
+                        .copied()
+                        .unwrap_or(0)
+                        .to_string(),
+                    _ => text(&role[column]),
+                })?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+fn csv_error(err: csv::Error) -> CliError {
+    CliError::Io(err.into())
+}
+
+fn time(value: &Value) -> String {
+    value
+        .as_str()
+        .map(|t| timestamp::normalize(t).unwrap_or_else(|| t.to_string()))
+        .unwrap_or_default()
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_selected_columns_with_quoting() {
+        let document = serde_json::json!({
+            "roles": [{ "id": "5", "name": "Mods", "color": 255 }],
+            "channels": [{ "id": "20", "name": "general", "messages": [
+                { "id": "1", "author": { "id": "7", "username": "alice" },
+                  "timestamp": "2025-01-31T23:30:00-01:00", "content": "hi, \"all\"\nbye" },
+            ]}],
+        });
+        let members = [serde_json::json!({ "id": "7", "username": "alice", "roles": ["5", "6"] })];
+        let csv = |entity: Entity, columns: &[&str], quote| {
+            let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+            let options = Options {
+                columns: entity.select(&columns).unwrap(),
+                delimiter: b',',
+                quote,
+                bom: false,
+            };
+            let mut out = Vec::new();
+            write(&document, &members, entity, &options, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            csv(
+                Entity::Messages,
+                &["author", "timestamp", "content"],
+                Quote::Necessary
+            ),
+            "author,timestamp,content\nalice,2025-02-01T00:30:00Z,\"hi, \"\"all\"\"\nbye\"\n"
+        );
+        assert_eq!(
+            csv(Entity::Members, &["username", "roles"], Quote::Always),
+            "\"username\",\"roles\"\n\"alice\",\"Mods; 6\"\n"
+        );
+        assert_eq!(
+            csv(
+                Entity::Roles,
+                &["name", "color", "members"],
+                Quote::Necessary
+            ),
+            "name,color,members\nMods,#0000ff,1\n"
+        );
+        assert!(Entity::Roles.select(&["nope".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,350 @@
+//! Manifests for `kube remote deploy`: a Deployment running `discord watch` (the gateway
+//! watcher), a Secret holding its bot token, and a ConfigMap holding the config file.
+//!
+//! They are rendered from built-in YAML templates with `{var}` placeholders. A template
+//! directory replaces built-ins by file name and adds manifests of its own; `[kube.deploy.vars]`
+//! and `--set` add variables or override the built-in ones. Values are substituted as quoted
+//! strings, so any text (the whole config file, a token) stays a single valid YAML scalar.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::CliError;
+
+/// Field manager the server-side apply records ownership under.
+pub const FIELD_MANAGER: &str = "guildsync";
+
+/// Where the container finds the config file.
+const CONFIG_DIR: &str = "/etc/guildsync";
+
+const CONFIGMAP: &str = r#"apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: {configmap}
+  namespace: {namespace}
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/instance: {name}
+data:
+  config.toml: {config}
+"#;
+
+const SECRET: &str = r#"apiVersion: v1
+kind: Secret
+metadata:
+  name: {secret}
+  namespace: {namespace}
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/instance: {name}
+type: Opaque
+stringData:
+  {token_env}: {token}
+"#;
+
+// One replica: two watchers would append every event twice.
+const DEPLOYMENT: &str = r#"apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: {name}
+  namespace: {namespace}
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/instance: {name}
+    app.kubernetes.io/component: gateway-watcher
+spec:
+  replicas: 1
+  strategy:
+    type: Recreate
+  selector:
+    matchLabels:
+      app.kubernetes.io/instance: {name}
+      app.kubernetes.io/component: gateway-watcher
+  template:
+    metadata:
+      labels:
+        app.kubernetes.io/name: guildsync
+        app.kubernetes.io/instance: {name}
+        app.kubernetes.io/component: gateway-watcher
+      annotations:
+        guildsync/checksum: {checksum}
+    spec:
+      containers:
+        - name: watcher
+          image: {image}
+          args: ["--config", {config_path}, "discord", "watch", "--guild", {guild}, "--out", "/data/events.ndjson"]
+          env:
+            - name: {token_env}
+              valueFrom:
+                secretKeyRef:
+                  name: {secret}
+                  key: {token_env}
+          volumeMounts:
+            - name: config
+              mountPath: {config_dir}
+              readOnly: true
+            - name: data
+              mountPath: /data
+      volumes:
+        - name: config
+          configMap:
+            name: {configmap}
+        - name: data
+          emptyDir: {}
+"#;
+
+/// Built-in templates by file name, in apply order.
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("configmap.yaml", CONFIGMAP),
+    ("secret.yaml", SECRET),
+    ("deployment.yaml", DEPLOYMENT),
+];
+
+/// What the built-in templates are filled in from.
+#[derive(Debug)]
+pub struct Release<'a> {
+    pub name: &'a str,
+    pub namespace: &'a str,
+    pub image: &'a str,
+    pub guild: u64,
+    /// Text of the config file the watcher runs with.
+    pub config: &'a str,
+    /// Variable the watcher reads its token from (`discord.token_env`).
+    pub token_env: &'a str,
+    pub token: &'a str,
+}
+
+impl Release<'_> {
+    /// Template variables with `overrides` applied. `configmap` and `secret` follow an
+    /// overridden `name` unless overridden themselves; `checksum` changes with the config and
+    /// token, so updating either restarts the watcher.
+    pub fn vars(&self, overrides: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.config);
+        hasher.update([0]);
+        hasher.update(self.token);
+        let checksum = format!("{:x}", hasher.finalize());
+        let mut vars: BTreeMap<String, String> = [
+            ("name", self.name.to_string()),
+            ("namespace", self.namespace.to_string()),
+            ("image", self.image.to_string()),
+            ("guild", self.guild.to_string()),
+            ("config", self.config.to_string()),
+            ("config_dir", CONFIG_DIR.to_string()),
+            ("config_path", format!("{CONFIG_DIR}/config.toml")),
+            ("token_env", self.token_env.to_string()),
+            ("token", self.token.to_string()),
+            ("checksum", checksum[..16].to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .chain(overrides.clone())
+        .collect();
+        let name = vars["name"].clone();
+        for (key, suffix) in [("configmap", "config"), ("secret", "token")] {
+            vars.entry(key.to_string())
+                .or_insert_with(|| format!("{name}-{suffix}"));
+        }
+        vars
+    }
+}
+
+/// One rendered object.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    /// Template file it came from.
+    pub file: String,
+    pub kind: String,
+    pub name: String,
+    /// The object as YAML.
+    #[serde(skip)]
+    pub yaml: String,
+}
+
+/// Parse a `--set KEY=VALUE` flag.
+pub fn parse_var(pair: &str) -> Result<(String, String), String> {
+    let (key, value) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{pair}`"))?;
+    if !placeholder().is_match(&format!("{{{key}}}")) {
+        return Err(format!("`{key}` is not a valid template variable name"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Render the built-in templates, or the files of `templates` replacing them, followed by the
+/// directory's other `*.yaml` files in name order.
+pub fn render(
+    templates: Option<&Path>,
+    vars: &BTreeMap<String, String>,
+) -> Result<Vec<Manifest>, CliError> {
+    let mut sources: Vec<(String, String)> = TEMPLATES
+        .iter()
+        .map(|(file, text)| (file.to_string(), text.to_string()))
+        .collect();
+    if let Some(dir) = templates {
+        let mut extra = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            if !(file.ends_with(".yaml") || file.ends_with(".yml")) || file.starts_with('.') {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path)?;
+            match sources.iter_mut().find(|(name, _)| *name == file) {
+                Some(source) => source.1 = text,
+                None => extra.push((file.into_owned(), text)),
+            }
+        }
+        extra.sort();
+        sources.extend(extra);
+    }
+
+    let mut manifests = Vec::new();
+    for (file, text) in sources {
+        manifests.extend(parse(&file, &substitute(&text, vars))?);
+    }
+    Ok(manifests)
+}
+
+/// The token Secret alone, rendered from the built-in template: `kube secrets sync` updates the
+/// same object `kube remote deploy` creates, holding `token` under `key`.
+pub fn token_secret(
+    name: &str,
+    namespace: &str,
+    secret: &str,
+    key: &str,
+    token: &str,
+) -> Result<Manifest, CliError> {
+    let vars = [
+        ("name", name),
+        ("namespace", namespace),
+        ("secret", secret),
+        ("token_env", key),
+        ("token", token),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+    let mut manifests = parse("secret.yaml", &substitute(SECRET, &vars))?;
+    Ok(manifests.remove(0))
+}
+
+/// `text` with `secret`, plain or base64-encoded as in a Secret's `data`, replaced by `***`.
+pub fn mask(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        return text.to_string();
+    }
+    text.replace(secret, "***")
+        .replace(&STANDARD.encode(secret), "***")
+}
+
+/// The objects of one rendered template file.
+pub fn parse(file: &str, rendered: &str) -> Result<Vec<Manifest>, CliError> {
+    let mut manifests = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(rendered) {
+        let invalid = |reason: String| CliError::Validation(format!("{file}: {reason}"));
+        let object =
+            serde_yaml::Value::deserialize(document).map_err(|e| invalid(e.to_string()))?;
+        if object.is_null() {
+            continue;
+        }
+        let field = |value: &serde_yaml::Value| value.as_str().map(str::to_string);
+        let (Some(kind), Some(name)) = (field(&object["kind"]), field(&object["metadata"]["name"]))
+        else {
+            return Err(invalid(
+                "every document needs a kind and metadata.name".to_string(),
+            ));
+        };
+        manifests.push(Manifest {
+            file: file.to_string(),
+            kind,
+            name,
+            yaml: serde_yaml::to_string(&object).map_err(|e| invalid(e.to_string()))?,
+        });
+    }
+    Ok(manifests)
+}
+
+/// The manifests as one multi-document YAML stream, as `kubectl -f -` reads it.
+pub fn stream(manifests: &[Manifest]) -> String {
+    manifests
+        .iter()
+        .map(|m| format!("---\n{}", m.yaml))
+        .collect()
+}
+
+/// `template` with each `{var}` naming a variable replaced by its quoted value; other braces
+/// (flow mappings like `{}`) are left alone.
+pub fn substitute(template: &str, vars: &BTreeMap<String, String>) -> String {
+    placeholder()
+        .replace_all(template, |caps: &regex::Captures<'_>| {
+            match vars.get(&caps[1]) {
+                // A JSON string is a valid double-quoted YAML scalar.
+                Some(value) => serde_json::Value::from(value.as_str()).to_string(),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn placeholder() -> Regex {
+    Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("valid regex")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_built_ins_with_quoted_values() {
+        let release = Release {
+            name: "guildsync",
+            namespace: "bots",
+            image: "example/guildsync:1",
+            guild: 42,
+            config: "[discord]\ntoken_env = \"BOT_TOKEN\"\n",
+            token_env: "BOT_TOKEN",
+            token: "t: {x}",
+        };
+        let overrides = [("image", "example/guildsync:2"), ("name", "watcher")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let vars = release.vars(&overrides);
+        let manifests = render(None, &vars).unwrap();
+        let kinds: Vec<_> = manifests.iter().map(|m| m.kind.as_str()).collect();
+        assert_eq!(kinds, ["ConfigMap", "Secret", "Deployment"]);
+        assert_eq!(manifests[1].name, "watcher-token");
+
+        let object = |i: usize| serde_yaml::from_str::<serde_yaml::Value>(&manifests[i].yaml);
+        let config = object(0).unwrap();
+        assert_eq!(config["data"]["config.toml"].as_str(), Some(release.config));
+        assert_eq!(
+            object(1).unwrap()["stringData"]["BOT_TOKEN"].as_str(),
+            Some("t: {x}")
+        );
+        let deployment = object(2).unwrap();
+        let container = &deployment["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(container["image"].as_str(), Some("example/guildsync:2"));
+        assert_eq!(container["args"][5].as_str(), Some("42"));
+        assert!(deployment["spec"]["template"]["spec"]["volumes"][1]["emptyDir"].is_mapping());
+        assert!(stream(&manifests).starts_with("---\napiVersion: v1\n"));
+
+        let secret = token_secret("guildsync", "bots", "gs-token", "BOT_TOKEN", "abc").unwrap();
+        assert_eq!(
+            (secret.kind.as_str(), secret.name.as_str()),
+            ("Secret", "gs-token")
+        );
+        assert_eq!(mask("token abc, data YWJj", "abc"), "token ***, data ***");
+
+        assert!(parse_var("replicas=2").is_ok());
+        assert!(parse_var("bad-key=1").is_err());
+    }
+}
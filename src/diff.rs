@@ -0,0 +1,493 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::config::FormatsConfig;
+use crate::error::CliError;
+use crate::format::{self, LIST_KEYS, RUNTIME_OBJECT_KEYS};
+use crate::permissions::{self, PermissionChange};
+
+/// Arguments of `format diff`, shared by the CLI and the MCP tool.
+#[derive(clap::Args, Debug, Deserialize)]
+pub struct DiffArgs {
+    /// Old dump file.
+    #[arg(value_name = "OLD")]
+    pub old: PathBuf,
+
+    /// New dump file.
+    #[arg(value_name = "NEW")]
+    pub new: PathBuf,
+}
+
+/// Item fields that diffs do not compare: message history, embedded images and CDN
+/// URLs, and live counters (see [`RUNTIME_OBJECT_KEYS`]).
+const UNCOMPARED_FIELDS: &[&str] = &["messages", "image", "url"];
+
+/// Semantic difference between two guild documents, counted by the import preflight.
+#[derive(Debug, Default, Serialize)]
+pub struct GuildDiff {
+    /// `guild` settings that were added, removed, or changed.
+    pub guild: Vec<String>,
+    /// Per list section (`roles`, `channels`), item ids by kind of change.
+    pub sections: BTreeMap<String, SectionDiff>,
+}
+
+/// Item ids added, removed, or changed within one list section.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SectionDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl SectionDiff {
+    fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+}
+
+impl GuildDiff {
+    /// Total number of changed settings and items.
+    pub fn change_count(&self) -> usize {
+        self.guild.len() + self.sections.values().map(SectionDiff::len).sum::<usize>()
+    }
+
+    /// One-line human summary, e.g. `guild: 1 setting(s); channels: +2 -0 ~1`.
+    pub fn summary(&self) -> String {
+        std::iter::once(format!("guild: {} setting(s)", self.guild.len()))
+            .chain(self.sections.iter().map(|(name, s)| {
+                format!(
+                    "{name}: +{} -{} ~{}",
+                    s.added.len(),
+                    s.removed.len(),
+                    s.changed.len()
+                )
+            }))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Compare `old` against `new`: what applying `new` would change, ignoring the
+/// [`UNCOMPARED_FIELDS`] and live counters of list items.
+pub fn diff(old: &Value, new: &Value) -> GuildDiff {
+    let empty = Map::new();
+    let old_guild = old["guild"].as_object().unwrap_or(&empty);
+    let new_guild = new["guild"].as_object().unwrap_or(&empty);
+    let mut guild: Vec<String> = old_guild
+        .keys()
+        .chain(new_guild.keys())
+        .filter(|key| old_guild.get(*key) != new_guild.get(*key))
+        .cloned()
+        .collect();
+    guild.sort();
+    guild.dedup();
+
+    let sections = LIST_KEYS
+        .iter()
+        .map(|key| (key.to_string(), diff_section(&old[*key], &new[*key])))
+        .collect();
+    GuildDiff { guild, sections }
+}
+
+/// Field-level difference between two guild documents, as reported by `discord diff` and
+/// `format diff`.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Changed `guild` settings with both values.
+    pub settings: Vec<SettingChange>,
+    /// Per list section (`roles`, `channels`, ...), the items added, removed, or changed.
+    pub sections: BTreeMap<String, SectionReport>,
+    /// Per channel or thread with message changes, by its id (`format diff` only).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub messages: BTreeMap<String, MessageReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingChange {
+    pub key: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SectionReport {
+    pub added: Vec<ItemChange>,
+    pub removed: Vec<ItemChange>,
+    pub changed: Vec<ItemChange>,
+}
+
+/// One added, removed, or changed item.
+#[derive(Debug, Serialize)]
+pub struct ItemChange {
+    pub id: String,
+    pub name: String,
+    /// Top-level fields that differ (changed items only).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// Permissions granted or revoked: role `permissions` and channel overwrites.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<PermissionChange>,
+}
+
+/// Messages added, removed, or edited in one channel or thread.
+#[derive(Debug, Default, Serialize)]
+pub struct MessageReport {
+    pub name: String,
+    /// Ids of added and removed messages.
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Messages in both files whose fields differ.
+    pub edited: Vec<EditedMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EditedMessage {
+    pub id: String,
+    pub fields: Vec<String>,
+}
+
+impl MessageReport {
+    fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.edited.len()
+    }
+}
+
+impl Report {
+    /// Total number of changed settings, items, and messages.
+    pub fn change_count(&self) -> usize {
+        self.settings.len()
+            + self
+                .sections
+                .values()
+                .map(|s| s.added.len() + s.removed.len() + s.changed.len())
+                .sum::<usize>()
+            + self
+                .messages
+                .values()
+                .map(MessageReport::len)
+                .sum::<usize>()
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for setting in &self.settings {
+            writeln!(
+                f,
+                "~ guild {}: {} -> {}",
+                setting.key, setting.old, setting.new
+            )?;
+        }
+        for (section, report) in &self.sections {
+            let kind = section.trim_end_matches('s');
+            let groups = [
+                ('+', &report.added),
+                ('-', &report.removed),
+                ('~', &report.changed),
+            ];
+            for (sign, items) in groups {
+                for item in items {
+                    write!(f, "{sign} {kind} {} ({})", item.name, item.id)?;
+                    if !item.fields.is_empty() {
+                        write!(f, ": {}", item.fields.join(", "))?;
+                    }
+                    writeln!(f)?;
+                    for change in &item.permissions {
+                        writeln!(f, "    {change}")?;
+                    }
+                }
+            }
+        }
+        // Added and removed messages are counted; their ids are in `--json` output.
+        for (id, report) in &self.messages {
+            writeln!(
+                f,
+                "~ messages in {} ({id}): +{} -{} ~{}",
+                report.name,
+                report.added.len(),
+                report.removed.len(),
+                report.edited.len()
+            )?;
+            for message in &report.edited {
+                writeln!(
+                    f,
+                    "    ~ message {}: {}",
+                    message.id,
+                    message.fields.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare `old` against `new` field by field: what changed in settings and in each list
+/// section, with the permission flags each role and channel gains or loses.
+pub fn report(old: &Value, new: &Value) -> Report {
+    let empty = Map::new();
+    let old_guild = old["guild"].as_object().unwrap_or(&empty);
+    let new_guild = new["guild"].as_object().unwrap_or(&empty);
+    let settings = diff(old, new)
+        .guild
+        .into_iter()
+        .map(|key| SettingChange {
+            old: old_guild.get(&key).cloned().unwrap_or(Value::Null),
+            new: new_guild.get(&key).cloned().unwrap_or(Value::Null),
+            key,
+        })
+        .collect();
+
+    let roles: Vec<&Value> = [new, old]
+        .into_iter()
+        .flat_map(|doc| doc["roles"].as_array().into_iter().flatten())
+        .collect();
+    let permissions = |section: &str, before: Option<&Value>, after: &Value| match section {
+        "roles" => permissions::role_changes(before, after),
+        "channels" => permissions::overwrite_changes(before, after, &roles),
+        _ => Vec::new(),
+    };
+    let sections = LIST_KEYS
+        .iter()
+        .map(|section| {
+            let (before, after) = (by_id(&old[*section]), by_id(&new[*section]));
+            let mut report = SectionReport::default();
+            for (id, item) in &after {
+                match before.get(id) {
+                    None => report.added.push(ItemChange {
+                        permissions: permissions(section, None, item),
+                        ..item_change(id, item, Vec::new())
+                    }),
+                    Some(previous) => {
+                        let fields = changed_fields(previous, item);
+                        if !fields.is_empty() {
+                            report.changed.push(ItemChange {
+                                permissions: permissions(section, Some(previous), item),
+                                ..item_change(id, item, fields)
+                            });
+                        }
+                    }
+                }
+            }
+            for (id, item) in before.iter().filter(|(id, _)| !after.contains_key(*id)) {
+                report.removed.push(item_change(id, item, Vec::new()));
+            }
+            (section.to_string(), report)
+        })
+        .collect();
+    Report {
+        settings,
+        sections,
+        messages: BTreeMap::new(),
+    }
+}
+
+/// Read and validate the files of `args` and compare them, messages included.
+pub fn compare_files(args: &DiffArgs, formats: &FormatsConfig) -> Result<Report, CliError> {
+    let (old, new) = (
+        format::read_document(&args.old)?,
+        format::read_document(&args.new)?,
+    );
+    format::validate_value(&old, None, formats)?;
+    format::validate_value(&new, None, formats)?;
+    let mut report = report(&old, &new);
+    report.messages = message_report(&old, &new);
+    Ok(report)
+}
+
+/// Compare the messages of every channel and thread in `old` and `new`, by message id. A
+/// channel missing from one side counts as having no messages there.
+pub fn message_report(old: &Value, new: &Value) -> BTreeMap<String, MessageReport> {
+    let (before, after) = (containers(old), containers(new));
+    let mut ids: Vec<&String> = before.keys().chain(after.keys()).collect();
+    ids.sort();
+    ids.dedup();
+    let mut reports = BTreeMap::new();
+    for id in ids {
+        let (previous, current) = (before.get(id).copied(), after.get(id).copied());
+        let (old_messages, new_messages) = (messages(previous), messages(current));
+        let mut report = MessageReport::default();
+        for (message_id, message) in &new_messages {
+            match old_messages.get(message_id) {
+                None => report.added.push(message_id.clone()),
+                Some(earlier) => {
+                    let fields = changed_fields(earlier, message);
+                    if !fields.is_empty() {
+                        report.edited.push(EditedMessage {
+                            id: message_id.clone(),
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+        report.removed = old_messages
+            .keys()
+            .filter(|message_id| !new_messages.contains_key(*message_id))
+            .cloned()
+            .collect();
+        if let Some(channel) = current.or(previous)
+            && report.len() > 0
+        {
+            report.name = item_change(id, channel, Vec::new()).name;
+            reports.insert(id.clone(), report);
+        }
+    }
+    reports
+}
+
+/// Channels and threads, the items that carry messages, by id.
+fn containers(doc: &Value) -> BTreeMap<String, &Value> {
+    ["channels", "threads"]
+        .iter()
+        .flat_map(|section| by_id(&doc[*section]))
+        .collect()
+}
+
+fn messages(channel: Option<&Value>) -> BTreeMap<String, &Value> {
+    channel.map_or_else(BTreeMap::new, |c| by_id(&c["messages"]))
+}
+
+fn item_change(id: &str, item: &Value, fields: Vec<String>) -> ItemChange {
+    let name = &item["name"];
+    ItemChange {
+        id: id.to_string(),
+        name: name
+            .as_str()
+            .map_or_else(|| name.to_string(), str::to_string),
+        fields,
+        permissions: Vec::new(),
+    }
+}
+
+fn changed_fields(old: &Value, new: &Value) -> Vec<String> {
+    let empty = Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+    let mut fields: Vec<String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|key| {
+            !RUNTIME_OBJECT_KEYS.contains(&key.as_str())
+                && !UNCOMPARED_FIELDS.contains(&key.as_str())
+                && old.get(*key) != new.get(*key)
+        })
+        .cloned()
+        .collect();
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+fn diff_section(old: &Value, new: &Value) -> SectionDiff {
+    let old = by_id(old);
+    let new = by_id(new);
+    let mut section = SectionDiff::default();
+    for (id, item) in &new {
+        match old.get(id) {
+            None => section.added.push(id.clone()),
+            Some(previous) if !changed_fields(previous, item).is_empty() => {
+                section.changed.push(id.clone());
+            }
+            Some(_) => {}
+        }
+    }
+    section.removed = old
+        .keys()
+        .filter(|id| !new.contains_key(*id))
+        .cloned()
+        .collect();
+    section
+}
+
+/// Index list items by `id`, falling back to `name` for items without one.
+fn by_id(list: &Value) -> BTreeMap<String, &Value> {
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let key = item.get("id").or_else(|| item.get("name"))?;
+            let key = key.as_str().map_or_else(|| key.to_string(), str::to_string);
+            Some((key, item))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_settings_and_items() {
+        let old = serde_json::json!({
+            "guild": { "name": "a", "icon": null },
+            "roles": [{ "id": "1", "name": "admin" }, { "id": "2", "name": "mod" }],
+            "channels": [{ "id": 10, "name": "general" }],
+        });
+        let new = serde_json::json!({
+            "guild": { "name": "b", "icon": null, "banner": "x" },
+            "roles": [{ "id": "1", "name": "owner" }],
+            "channels": [{ "id": 10, "name": "general" }, { "id": 11, "name": "dev" }],
+        });
+        let diff = diff(&old, &new);
+        assert_eq!(diff.guild, ["banner", "name"]);
+        assert_eq!(diff.sections["roles"].changed, ["1"]);
+        assert_eq!(diff.sections["roles"].removed, ["2"]);
+        assert_eq!(diff.sections["channels"].added, ["11"]);
+        assert_eq!(diff.change_count(), 5);
+    }
+
+    #[test]
+    fn reports_fields_and_permissions() {
+        let old = serde_json::json!({
+            "guild": { "name": "a" },
+            "roles": [{ "id": "1", "name": "mod", "permissions": "2" }],
+            "channels": [{ "id": "5", "name": "general", "last_message_id": "9" }],
+        });
+        let new = serde_json::json!({
+            "guild": { "name": "b" },
+            "roles": [{ "id": "1", "name": "mod", "permissions": "6", "color": 3 }],
+            "channels": [{ "id": "5", "name": "general", "last_message_id": "10" }],
+        });
+        let report = report(&old, &new);
+        assert_eq!(report.change_count(), 2);
+        assert_eq!(
+            report.to_string(),
+            "~ guild name: \"a\" -> \"b\"\n\
+             ~ role mod (1): color, permissions\n    permissions: +BAN_MEMBERS\n"
+        );
+    }
+
+    #[test]
+    fn compares_messages_of_channels_and_threads() {
+        let old = serde_json::json!({
+            "channels": [{ "id": "20", "name": "general", "messages": [
+                { "id": "98", "content": "hi" }, { "id": "99", "content": "bye" },
+            ]}],
+            "threads": [{ "id": "30", "name": "help", "messages": [{ "id": "300" }] }],
+        });
+        let new = serde_json::json!({
+            "channels": [{ "id": "20", "name": "general", "messages": [
+                { "id": "98", "content": "hi!" }, { "id": "100", "content": "new" },
+            ]}],
+            "threads": [{ "id": "30", "name": "help", "messages": [{ "id": "300" }] }],
+        });
+        let messages = message_report(&old, &new);
+        assert_eq!(messages.keys().collect::<Vec<_>>(), ["20"]);
+        let general = &messages["20"];
+        assert_eq!(general.added, ["100"]);
+        assert_eq!(general.removed, ["99"]);
+        assert_eq!(general.edited[0].fields, ["content"]);
+
+        let mut report = report(&old, &new);
+        report.messages = messages;
+        assert_eq!(report.change_count(), 3);
+        assert!(
+            report
+                .to_string()
+                .ends_with("~ messages in general (20): +1 -1 ~1\n    ~ message 98: content\n")
+        );
+    }
+}
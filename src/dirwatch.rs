@@ -0,0 +1,137 @@
+//! Directory polling for `watch-dir`: which dump/upload files in a directory are new or changed,
+//! once they have stopped changing for a debounce window.
+//!
+//! Files are compared by size and modification time at each poll, which also works on network
+//! and container-mounted filesystems that do not deliver change events. Files present when the
+//! watch starts count as seen; only later changes are reported.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::compression;
+use crate::error::CliError;
+
+/// What a poll compares.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// A polled directory.
+#[derive(Debug)]
+pub struct DirWatch {
+    dir: PathBuf,
+    debounce: Duration,
+    /// Stamps of files as last reported (or found at the start).
+    seen: HashMap<PathBuf, Stamp>,
+    /// Files changed since, with their latest stamp and when it was first observed.
+    pending: HashMap<PathBuf, (Stamp, Instant)>,
+}
+
+impl DirWatch {
+    /// Watch `dir`; the files it holds now are not reported unless they change.
+    pub fn new(dir: &Path, debounce: Duration) -> Result<Self, CliError> {
+        let mut watch = Self {
+            dir: dir.to_path_buf(),
+            debounce,
+            seen: HashMap::new(),
+            pending: HashMap::new(),
+        };
+        watch.seen = watch.scan()?;
+        Ok(watch)
+    }
+
+    /// Files new or changed since they were last reported that have kept the same size and
+    /// modification time for the debounce window, as of `now`, in name order.
+    pub fn poll(&mut self, now: Instant) -> Result<Vec<PathBuf>, CliError> {
+        let current = self.scan()?;
+        self.seen.retain(|path, _| current.contains_key(path));
+        self.pending.retain(|path, _| current.contains_key(path));
+        let mut ready = Vec::new();
+        for (path, stamp) in current {
+            if self.seen.get(&path) == Some(&stamp) {
+                self.pending.remove(&path);
+                continue;
+            }
+            match self.pending.get(&path) {
+                Some((pending, since)) if *pending == stamp => {
+                    if now.duration_since(*since) >= self.debounce {
+                        self.pending.remove(&path);
+                        self.seen.insert(path.clone(), stamp);
+                        ready.push(path);
+                    }
+                }
+                _ => {
+                    self.pending.insert(path, (stamp, now));
+                }
+            }
+        }
+        ready.sort();
+        Ok(ready)
+    }
+
+    fn scan(&self) -> Result<HashMap<PathBuf, Stamp>, CliError> {
+        let mut files = HashMap::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if !is_candidate(&path) {
+                continue;
+            }
+            // A file removed between listing and reading is simply gone.
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                let stamp = Stamp {
+                    len: metadata.len(),
+                    modified: metadata.modified().ok(),
+                };
+                files.insert(path, stamp);
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Whether `path` names a JSON or NDJSON document (possibly compressed), leaving out hidden
+/// files, temporary files of atomic writes, and the journals imports write next to their input.
+pub fn is_candidate(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    !name.starts_with('.')
+        && !name.contains(".journal-")
+        && (compression::is_kind(path, "json") || compression::is_kind(path, "ndjson"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_changed_files_once_they_settle() {
+        let dir = std::env::temp_dir().join(format!("guildsync-dirwatch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("old.json"), "{}").unwrap();
+        let second = Duration::from_secs(1);
+        let mut watch = DirWatch::new(&dir, second).unwrap();
+        let start = Instant::now();
+        assert!(watch.poll(start).unwrap().is_empty());
+
+        std::fs::write(dir.join("new.json.gz"), "x").unwrap();
+        std::fs::write(dir.join("new.json.journal-20250101T000000Z.json"), "{}").unwrap();
+        std::fs::write(dir.join("notes.txt"), "x").unwrap();
+        assert!(watch.poll(start).unwrap().is_empty());
+        // Growing restarts the window.
+        std::fs::write(dir.join("new.json.gz"), "xy").unwrap();
+        assert!(watch.poll(start + second).unwrap().is_empty());
+        assert!(watch.poll(start + second + second / 2).unwrap().is_empty());
+        assert_eq!(
+            watch.poll(start + second * 2).unwrap(),
+            [dir.join("new.json.gz")]
+        );
+        assert!(watch.poll(start + second * 5).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
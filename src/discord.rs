@@ -0,0 +1,836 @@
+//! Discord REST client: bot-token auth, rate limiting, retries, and mapping of HTTP failures
+//! onto [`CliError`]. Every Discord API call goes through [`Client`].
+
+use std::time::Duration;
+
+use reqwest::header::{AUTHORIZATION, HeaderMap, RETRY_AFTER, USER_AGENT};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::build_info::BUILD_INFO;
+use crate::error::CliError;
+use crate::format::GuildFormat;
+use crate::ratelimit::{self, RateLimiter};
+use crate::redact::NameRedaction;
+use crate::retry::{self, RetryPolicy};
+use crate::timestamp;
+
+/// Dump sections fetched, in order, by [`fetch_dump`], with their API paths.
+pub const DUMP_SECTIONS: &[(&str, &str)] = &[
+    ("guild", "/guilds/{guild}"),
+    ("roles", "/guilds/{guild}/roles"),
+    ("channels", "/guilds/{guild}/channels"),
+    ("emojis", "/guilds/{guild}/emojis"),
+    ("stickers", "/guilds/{guild}/stickers"),
+    ("scheduled_events", "/guilds/{guild}/scheduled-events"),
+];
+
+/// Timeout of a single attempt; failed attempts are retried per the client's policy.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Authenticated client for the Discord HTTP API.
+///
+/// Requests wait out known rate limits (see [`ratelimit`]) and are retried on `429` and
+/// server or connection errors with the jittered backoff of [`retry::with_backoff`]. A `POST`
+/// creates something and may have been applied when its response is lost, so it is retried
+/// on `429` only.
+pub struct Client {
+    http: reqwest::Client,
+    base: String,
+    token: String,
+    limiter: RateLimiter,
+    policy: RetryPolicy,
+    /// Label for retry log lines, e.g. `discord.export`.
+    action: &'static str,
+}
+
+impl Client {
+    /// Client for `base` (e.g. `https://discord.com/api/v10`) using a bot `token`. An empty
+    /// token sends no `Authorization` header, for webhook URLs, which carry their own.
+    pub fn new(
+        base: &str,
+        token: String,
+        policy: RetryPolicy,
+        action: &'static str,
+    ) -> Result<Self, CliError> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| CliError::Network(e.to_string()))?;
+        Ok(Self {
+            http,
+            base: base.trim_end_matches('/').to_string(),
+            token,
+            limiter: RateLimiter::default(),
+            policy,
+            action,
+        })
+    }
+
+    /// The bot token, for the gateway's Identify.
+    pub(crate) fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// The retry policy requests are made with.
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+    }
+
+    /// `GET` a JSON resource, e.g. `/guilds/123/roles`.
+    pub async fn get(&self, path: &str) -> Result<Value, CliError> {
+        self.request(Method::GET, path, None).await
+    }
+
+    /// `POST` a JSON body, returning the created resource.
+    pub async fn post(&self, path: &str, body: &Value) -> Result<Value, CliError> {
+        self.request(Method::POST, path, Some(Body::Json(body)))
+            .await
+    }
+
+    /// `PATCH` a JSON body, returning the updated resource.
+    pub async fn patch(&self, path: &str, body: &Value) -> Result<Value, CliError> {
+        self.request(Method::PATCH, path, Some(Body::Json(body)))
+            .await
+    }
+
+    /// `PUT` without a body, e.g. to sync a guild template.
+    pub async fn put(&self, path: &str) -> Result<Value, CliError> {
+        self.request(Method::PUT, path, None).await
+    }
+
+    /// `DELETE` a resource.
+    pub async fn delete(&self, path: &str) -> Result<(), CliError> {
+        self.request(Method::DELETE, path, None).await.map(drop)
+    }
+
+    /// `POST` a multipart form of the string `fields` plus `bytes` as its `file` part, e.g. to
+    /// create a sticker.
+    pub async fn post_file(
+        &self,
+        path: &str,
+        fields: &Value,
+        file_name: &str,
+        bytes: &[u8],
+    ) -> Result<Value, CliError> {
+        let file = Body::File {
+            fields,
+            file_name,
+            bytes,
+        };
+        self.request(Method::POST, path, Some(file)).await
+    }
+
+    /// Download a CDN asset (an emoji, sticker, or event cover image) without sending the bot token.
+    pub async fn asset(&self, url: &str) -> Result<Vec<u8>, CliError> {
+        retry::with_backoff(&self.policy, self.action, || async {
+            let net = |e: reqwest::Error| CliError::Network(format!("GET {url}: {e}"));
+            let response = self.http.get(url).send().await.map_err(net)?;
+            let status = response.status();
+            match status {
+                _ if status.is_success() => Ok(response.bytes().await.map_err(net)?.to_vec()),
+                StatusCode::NOT_FOUND => Err(CliError::NotFound(format!("GET {url}"))),
+                StatusCode::TOO_MANY_REQUESTS => Err(CliError::RateLimited {
+                    retry_after: retry_after(response.headers()),
+                }),
+                _ if status.is_server_error() => {
+                    Err(CliError::Network(format!("GET {url}: {status}")))
+                }
+                _ => Err(CliError::Validation(format!("GET {url}: {status}"))),
+            }
+        })
+        .await
+    }
+
+    /// Send a request, with retries, and decode the JSON response (`null` for `204 No Content`).
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+    ) -> Result<Value, CliError> {
+        // Discord applies nothing it answers with `429`; any other failure of a `POST` may
+        // have created the resource, and sending it again would create a duplicate.
+        let idempotent = method != Method::POST;
+        let retryable = |err: &CliError| match err {
+            CliError::RateLimited { .. } => true,
+            _ => idempotent && err.is_retryable(),
+        };
+        retry::with_backoff_if(&self.policy, self.action, retryable, || {
+            self.attempt(method.clone(), path, body)
+        })
+        .await
+    }
+
+    /// One attempt of [`Client::request`], within the route's rate limit.
+    async fn attempt(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+    ) -> Result<Value, CliError> {
+        let permit = self
+            .limiter
+            .acquire(&ratelimit::route(method.as_str(), path))
+            .await;
+        let url = format!("{}{path}", self.base);
+        // Webhook tokens let anyone post; keep them out of logs and errors.
+        let path = &mask_webhook_token(path);
+        tracing::debug!("{method} {}{path}", self.base);
+        let mut request = self.http.request(method.clone(), &url).header(
+                USER_AGENT,
+                format!(
+                    "DiscordBot (https://github.com/realagiorganization/terminal-translate-discord-guild, {})",
+                    BUILD_INFO.version
+                ),
+            );
+        if !self.token.is_empty() {
+            request = request.header(AUTHORIZATION, format!("Bot {}", self.token));
+        }
+        match body {
+            Some(Body::Json(body)) => request = request.json(body),
+            Some(Body::File {
+                fields,
+                file_name,
+                bytes,
+            }) => {
+                let mut form = Form::new();
+                for (key, value) in fields.as_object().into_iter().flatten() {
+                    let value = value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), str::to_string);
+                    form = form.text(key.clone(), value);
+                }
+                let part = Part::bytes(bytes.to_vec()).file_name(file_name.to_string());
+                request = request.multipart(form.part("file", part));
+            }
+            None => {}
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| CliError::Network(format!("{method} {path}: {e}")))?;
+        permit.update(response.headers());
+
+        let status = response.status();
+        if status == StatusCode::NO_CONTENT {
+            return Ok(Value::Null);
+        }
+        if status.is_success() {
+            return response
+                .json()
+                .await
+                .map_err(|e| CliError::Network(format!("{method} {path}: {e}")));
+        }
+        let retry_after = retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                CliError::Auth(format!("{method} {path}: {status}: {}", api_message(&body)))
+            }
+            StatusCode::NOT_FOUND => CliError::NotFound(format!("{method} {path}")),
+            StatusCode::TOO_MANY_REQUESTS => CliError::RateLimited { retry_after },
+            _ if status.is_server_error() => {
+                CliError::Network(format!("{method} {path}: {status}"))
+            }
+            _ => CliError::Validation(format!("{method} {path}: {status}: {}", api_message(&body))),
+        })
+    }
+}
+
+/// Request body of [`Client::request`].
+#[derive(Clone, Copy)]
+enum Body<'a> {
+    Json(&'a Value),
+    /// A multipart form; see [`Client::post_file`].
+    File {
+        fields: &'a Value,
+        file_name: &'a str,
+        bytes: &'a [u8],
+    },
+}
+
+/// `path` with the token of a `/webhooks/{id}/{token}` route replaced by `***`.
+fn mask_webhook_token(path: &str) -> String {
+    let Some(at) = path.find("/webhooks/") else {
+        return path.to_string();
+    };
+    let rest = &path[at + "/webhooks/".len()..];
+    let Some(slash) = rest.find('/') else {
+        return path.to_string();
+    };
+    let token = &rest[slash + 1..];
+    let end = token.find(['/', '?']).unwrap_or(token.len());
+    if end == 0 {
+        return path.to_string();
+    }
+    let start = path.len() - token.len();
+    format!("{}***{}", &path[..start], &token[end..])
+}
+
+/// The `Retry-After` header, in (possibly fractional) seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let secs: f64 = headers.get(RETRY_AFTER)?.to_str().ok()?.parse().ok()?;
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// Discord's `{"message": ...}` error text, or the raw body.
+fn api_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// Fetch a guild's settings, roles, channels (categories included), custom emoji, stickers,
+/// and scheduled events as a dump document.
+///
+/// `progress` advances once per section.
+pub async fn fetch_dump(
+    client: &Client,
+    guild: u64,
+    version: u32,
+    progress: &indicatif::ProgressBar,
+) -> Result<Value, CliError> {
+    let mut dump = serde_json::json!({
+        "format": GuildFormat::Dump,
+        "version": version,
+        "exported_at": timestamp::now_rfc3339(),
+        "exporter": format!("guildsync {}", BUILD_INFO.version),
+    });
+    for (section, path) in DUMP_SECTIONS {
+        progress.set_message(*section);
+        let path = path.replace("{guild}", &guild.to_string());
+        dump[*section] = client.get(&path).await?;
+        progress.inc(1);
+    }
+    // The guild object embeds roles, emojis, and stickers; they live at the top level.
+    if let Value::Object(guild) = &mut dump["guild"] {
+        for section in ["roles", "emojis", "stickers"] {
+            guild.shift_remove(section);
+        }
+    }
+    Ok(dump)
+}
+
+/// Discord's epoch (2015-01-01T00:00:00Z) in Unix milliseconds; snowflakes count from it.
+const DISCORD_EPOCH_MS: u64 = 1_420_070_400_000;
+
+/// Parse a message bound for `--after`/`--before`: a snowflake, a `YYYY-MM-DD` date, or an
+/// RFC 3339 UTC time (converted to the first snowflake of that instant).
+pub fn parse_bound(text: &str) -> Result<u64, String> {
+    if let Ok(id) = text.parse() {
+        return Ok(id);
+    }
+    let secs = timestamp::parse(text).ok_or_else(|| {
+        format!("expected a snowflake, YYYY-MM-DD, or RFC 3339 UTC time: `{text}`")
+    })?;
+    Ok((secs * 1000).saturating_sub(DISCORD_EPOCH_MS) << 22)
+}
+
+/// Scope of a filtered `discord export`, recorded as the dump's `filters` so an import of it
+/// leaves the rest of the guild alone.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExportFilters {
+    /// Channel ids to export.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    /// Category ids whose channels (and the categories themselves) are exported.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    /// Only messages by these user ids.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    /// Only messages newer than this snowflake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Only messages older than this snowflake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+}
+
+impl ExportFilters {
+    pub fn is_empty(&self) -> bool {
+        !self.scopes_channels() && !self.scopes_messages()
+    }
+
+    /// Whether only some channels are covered.
+    pub fn scopes_channels(&self) -> bool {
+        !self.channels.is_empty() || !self.categories.is_empty()
+    }
+
+    /// Whether the filters select messages (which implies exporting them).
+    pub fn scopes_messages(&self) -> bool {
+        !self.users.is_empty() || self.after.is_some() || self.before.is_some()
+    }
+
+    /// Whether `channel` is within the channel scope.
+    pub fn includes_channel(&self, channel: &Value) -> bool {
+        if !self.scopes_channels() {
+            return true;
+        }
+        let listed =
+            |ids: &[String], id: &Value| id.as_str().is_some_and(|id| ids.iter().any(|i| i == id));
+        listed(&self.channels, &channel["id"])
+            || listed(&self.categories, &channel["id"])
+            || listed(&self.categories, &channel["parent_id"])
+    }
+
+    /// Drop channels outside the scope from `dump`, keeping the categories of kept channels
+    /// so the hierarchy stays intact, and threads whose channel was dropped.
+    pub fn retain_channels(&self, dump: &mut Value) {
+        let Some(channels) = dump["channels"].as_array_mut() else {
+            return;
+        };
+        let parents: Vec<Value> = channels
+            .iter()
+            .filter(|c| self.includes_channel(c))
+            .map(|c| c["parent_id"].clone())
+            .collect();
+        channels.retain(|c| self.includes_channel(c) || parents.contains(&c["id"]));
+        let kept: Vec<Value> = channels.iter().map(|c| c["id"].clone()).collect();
+        if let Some(threads) = dump["threads"].as_array_mut() {
+            threads.retain(|t| kept.contains(&t["parent_id"]));
+        }
+    }
+
+    /// Whether `message` passes the user filter.
+    pub fn keeps_message(&self, message: &Value) -> bool {
+        self.users.is_empty()
+            || message["author"]["id"]
+                .as_str()
+                .is_some_and(|id| self.users.iter().any(|u| u == id))
+    }
+
+    /// `after` as a snowflake.
+    pub fn after_id(&self) -> Option<u64> {
+        self.after.as_deref()?.parse().ok()
+    }
+
+    /// `before` as a snowflake.
+    pub fn before_id(&self) -> Option<u64> {
+        self.before.as_deref()?.parse().ok()
+    }
+}
+
+/// `format` tag of an audit log export.
+pub const AUDIT_LOG_FORMAT: &str = "audit-log";
+
+/// Schema version of audit log exports, bumped on incompatible changes.
+pub const AUDIT_LOG_VERSION: u32 = 1;
+
+/// Page size of the audit log endpoint.
+const AUDIT_LOG_PAGE: usize = 100;
+
+/// Audit log filters passed through to Discord.
+#[derive(Debug, Default, Serialize)]
+pub struct AuditLogFilters {
+    /// Audit log event type, e.g. `20` for member kicks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_type: Option<u16>,
+    /// Only entries made by this user id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+}
+
+/// Page through `guild`'s audit log, newest entry first, into a versioned document:
+/// `entries` plus the `users`, `webhooks`, `integrations`, ... they reference, each listed once.
+///
+/// `progress` advances once per entry.
+pub async fn fetch_audit_log(
+    client: &Client,
+    guild: u64,
+    filters: &AuditLogFilters,
+    progress: &indicatif::ProgressBar,
+) -> Result<Value, CliError> {
+    let mut query = format!("limit={AUDIT_LOG_PAGE}");
+    if let Some(action_type) = filters.action_type {
+        query.push_str(&format!("&action_type={action_type}"));
+    }
+    if let Some(actor) = &filters.actor {
+        query.push_str(&format!("&user_id={actor}"));
+    }
+
+    let mut entries: Vec<Value> = Vec::new();
+    let mut related = serde_json::Map::new();
+    loop {
+        let mut path = format!("/guilds/{guild}/audit-logs?{query}");
+        if let Some(oldest) = entries.last() {
+            path.push_str(&format!("&before={}", snowflake(oldest)));
+        }
+        let Value::Object(page) = client.get(&path).await? else {
+            break;
+        };
+        let mut batch = Vec::new();
+        for (key, items) in page {
+            let Value::Array(items) = items else { continue };
+            if key == "audit_log_entries" {
+                batch = items;
+                continue;
+            }
+            let Value::Array(list) = related
+                .entry(key)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            else {
+                continue;
+            };
+            for item in items {
+                if !list.iter().any(|seen| seen["id"] == item["id"]) {
+                    list.push(item);
+                }
+            }
+        }
+        let full = batch.len() == AUDIT_LOG_PAGE;
+        progress.inc_length(batch.len() as u64);
+        progress.inc(batch.len() as u64);
+        batch.sort_by_key(|entry| std::cmp::Reverse(snowflake(entry)));
+        entries.extend(batch);
+        if !full {
+            break;
+        }
+    }
+
+    let mut document = serde_json::json!({
+        "format": AUDIT_LOG_FORMAT,
+        "version": AUDIT_LOG_VERSION,
+        "exported_at": timestamp::now_rfc3339(),
+        "exporter": format!("guildsync {}", BUILD_INFO.version),
+        "guild": { "id": guild.to_string() },
+        "filters": filters,
+        "entries": entries,
+    });
+    if let Value::Object(document) = &mut document {
+        document.extend(related);
+    }
+    Ok(document)
+}
+
+/// Save `guild`'s current structure as its guild template named `name`, returning the
+/// template. A guild has at most one template, so an existing one is synced to the guild and
+/// renamed instead of a second one being created.
+pub async fn save_template(
+    client: &Client,
+    guild: u64,
+    name: &str,
+    description: Option<&str>,
+) -> Result<Value, CliError> {
+    let path = format!("/guilds/{guild}/templates");
+    let body = serde_json::json!({ "name": name, "description": description });
+    let existing = client.get(&path).await?;
+    match existing.as_array().and_then(|templates| templates.first()) {
+        Some(template) => {
+            let code = template["code"].as_str().unwrap_or_default();
+            client.put(&format!("{path}/{code}")).await?;
+            client.patch(&format!("{path}/{code}"), &body).await
+        }
+        None => client.post(&path, &body).await,
+    }
+}
+
+/// `format` tag of a member list export.
+pub const MEMBERS_FORMAT: &str = "members";
+
+/// Schema version of member list exports, bumped on incompatible changes.
+pub const MEMBERS_VERSION: u32 = 1;
+
+/// Page size of the member list endpoint (its maximum).
+const MEMBERS_PAGE: usize = 1000;
+
+/// Page through `guild`'s members in id order into a versioned document. Each member is
+/// reduced to its id, names, roles, and join date, with names hashed or dropped per `redaction`.
+///
+/// `progress` advances once per member.
+pub async fn fetch_members(
+    client: &Client,
+    guild: u64,
+    redaction: Option<NameRedaction>,
+    progress: &indicatif::ProgressBar,
+) -> Result<Value, CliError> {
+    let mut members: Vec<Value> = Vec::new();
+    let mut after = 0;
+    loop {
+        let path = format!("/guilds/{guild}/members?limit={MEMBERS_PAGE}&after={after}");
+        let page = match client.get(&path).await {
+            Ok(Value::Array(page)) => page,
+            Ok(_) => Vec::new(),
+            Err(CliError::Auth(msg)) => {
+                return Err(CliError::Auth(format!(
+                    "{msg}; listing members needs the bot's Server Members intent"
+                )));
+            }
+            Err(err) => return Err(err),
+        };
+        let full = page.len() == MEMBERS_PAGE;
+        progress.inc_length(page.len() as u64);
+        progress.inc(page.len() as u64);
+        for member in page {
+            let mut record = serde_json::json!({
+                "id": member["user"]["id"],
+                "username": member["user"]["username"],
+                "global_name": member["user"]["global_name"],
+                "nick": member["nick"],
+                "roles": member["roles"],
+                "joined_at": member["joined_at"],
+            });
+            if member["user"]["bot"] == true {
+                record["bot"] = true.into();
+            }
+            if let Some(redaction) = redaction {
+                redaction.apply(&mut record);
+            }
+            after = after.max(snowflake(&record));
+            members.push(record);
+        }
+        if !full {
+            break;
+        }
+    }
+
+    let mut document = serde_json::json!({
+        "format": MEMBERS_FORMAT,
+        "version": MEMBERS_VERSION,
+        "exported_at": timestamp::now_rfc3339(),
+        "exporter": format!("guildsync {}", BUILD_INFO.version),
+        "guild": { "id": guild.to_string() },
+        "members": members,
+    });
+    if let Some(redaction) = redaction {
+        document["redaction"] = serde_json::to_value(redaction)?;
+    }
+    Ok(document)
+}
+
+/// Channel types that can have threads: text, announcement, forum, and media.
+const THREAD_PARENT_TYPES: &[u64] = &[0, 5, 15, 16];
+
+/// Channel type of a text channel, the only one with private threads.
+const TEXT_TYPE: u64 = 0;
+
+/// Fetch the guild's active threads plus the archived threads of each of `channels`, sorted by
+/// id. Forum posts are threads whose `applied_tags` refer to the forum's `available_tags`.
+///
+/// Returns the threads and the ids of channels whose archived threads the bot may not list
+/// (private archived threads need Manage Threads). `progress` advances once per channel.
+pub async fn fetch_threads(
+    client: &Client,
+    guild: u64,
+    channels: &[Value],
+    progress: &indicatif::ProgressBar,
+) -> Result<(Vec<Value>, Vec<String>), CliError> {
+    let active = client
+        .get(&format!("/guilds/{guild}/threads/active"))
+        .await?;
+    let mut threads: Vec<Value> = match active {
+        Value::Object(mut body) => match body.shift_remove("threads") {
+            Some(Value::Array(threads)) => threads,
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+
+    let mut denied = Vec::new();
+    for channel in channels {
+        progress.inc(1);
+        let Some(kind) = channel["type"]
+            .as_u64()
+            .filter(|t| THREAD_PARENT_TYPES.contains(t))
+        else {
+            continue;
+        };
+        let id = channel["id"].as_str().unwrap_or_default();
+        progress.set_message(channel["name"].as_str().unwrap_or_default().to_string());
+        let scopes: &[&str] = if kind == TEXT_TYPE {
+            &["public", "private"]
+        } else {
+            &["public"]
+        };
+        for scope in scopes {
+            match fetch_archived(client, id, scope).await {
+                Ok(archived) => threads.extend(archived),
+                Err(CliError::Auth(_)) => {
+                    denied.push(id.to_string());
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    for thread in &mut threads {
+        // The bot's own membership, not part of the guild's state.
+        if let Value::Object(thread) = thread {
+            thread.shift_remove("member");
+        }
+    }
+    threads.sort_by_key(snowflake);
+    threads.dedup_by_key(|thread| snowflake(thread));
+    Ok((threads, denied))
+}
+
+/// Every archived thread of one `scope` (`public` or `private`) in `channel`, newest first.
+async fn fetch_archived(
+    client: &Client,
+    channel: &str,
+    scope: &str,
+) -> Result<Vec<Value>, CliError> {
+    let mut threads = Vec::new();
+    let mut before: Option<String> = None;
+    loop {
+        let mut path = format!("/channels/{channel}/threads/archived/{scope}?limit=100");
+        if let Some(before) = &before {
+            // ISO 8601 timestamps carry a `+00:00` offset, which must not read as a space.
+            path.push_str(&format!("&before={}", before.replace('+', "%2B")));
+        }
+        let page = client.get(&path).await?;
+        let batch = page["threads"].as_array().cloned().unwrap_or_default();
+        // Pages are keyed by archive time; continue from the oldest one seen.
+        before = batch
+            .last()
+            .and_then(|t| t["thread_metadata"]["archive_timestamp"].as_str())
+            .map(str::to_string);
+        threads.extend(batch);
+        if page["has_more"] != true || before.is_none() {
+            break;
+        }
+    }
+    Ok(threads)
+}
+
+/// Channel types that carry messages: text, voice (text-in-voice), announcement, stage, and
+/// the three thread types.
+pub const MESSAGE_CHANNEL_TYPES: &[u64] = &[0, 2, 5, 13, 10, 11, 12];
+
+/// Page size of the channel messages endpoint.
+const MESSAGES_PAGE: usize = 100;
+
+/// Fetch every message in `channel` newer than the snowflake `after` (and older than `before`,
+/// if given), oldest first.
+pub async fn fetch_messages(
+    client: &Client,
+    channel: &str,
+    after: u64,
+    before: Option<u64>,
+) -> Result<Vec<Value>, CliError> {
+    let mut messages = Vec::new();
+    let mut after = after;
+    loop {
+        let path = format!("/channels/{channel}/messages?after={after}&limit={MESSAGES_PAGE}");
+        let page = client.get(&path).await?;
+        let mut page = match page {
+            Value::Array(page) => page,
+            _ => Vec::new(),
+        };
+        // The API does not promise an order within a page; sort by snowflake.
+        page.sort_by_key(snowflake);
+        let full = page.len() == MESSAGES_PAGE;
+        match page.last().map(snowflake) {
+            Some(newest) if newest > after => after = newest,
+            _ => break,
+        }
+        let past_end = before.is_some_and(|before| after >= before);
+        messages.extend(
+            page.into_iter()
+                .filter(|m| before.is_none_or(|before| snowflake(m) < before)),
+        );
+        if !full || past_end {
+            break;
+        }
+    }
+    Ok(messages)
+}
+
+/// An object's `id` as a snowflake (0 if absent or malformed).
+pub fn snowflake(item: &Value) -> u64 {
+    match &item["id"] {
+        Value::String(id) => id.parse().unwrap_or(0),
+        id => id.as_u64().unwrap_or(0),
+    }
+}
+
+/// Channel `type` of a category.
+pub const CATEGORY_TYPE: u64 = 4;
+
+/// A field as plain text: strings as they are, null (or absent) as empty, and anything else,
+/// such as a numeric snowflake, as JSON.
+pub fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The items of an array field; none if it is absent or not an array.
+pub fn list(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn webhook_tokens_are_masked() {
+        assert_eq!(
+            mask_webhook_token("/webhooks/123/abc-DEF?wait=true"),
+            "/webhooks/123/***?wait=true"
+        );
+        assert_eq!(
+            mask_webhook_token("/webhooks/123/abc/messages/9"),
+            "/webhooks/123/***/messages/9"
+        );
+        assert_eq!(mask_webhook_token("/webhooks/123"), "/webhooks/123");
+        assert_eq!(
+            mask_webhook_token("/channels/1/webhooks"),
+            "/channels/1/webhooks"
+        );
+    }
+
+    #[test]
+    fn fields_read_as_text_and_lists() {
+        let item = serde_json::json!({ "id": 5, "name": "general", "tags": ["a"], "topic": null });
+        assert_eq!(text(&item["id"]), "5");
+        assert_eq!(text(&item["name"]), "general");
+        assert_eq!(text(&item["topic"]), "");
+        assert_eq!(text(&item["missing"]), "");
+        assert_eq!(list(&item["tags"]).count(), 1);
+        assert_eq!(list(&item["name"]).count(), 0);
+        assert_eq!(snowflake(&item), 5);
+    }
+
+    #[tokio::test]
+    async fn post_is_not_retried_on_server_errors() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicU32::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.read(&mut [0; 4096]).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base: Duration::from_millis(1),
+        };
+        let client = Client::new(&base, "t".to_string(), policy, "test").unwrap();
+
+        let err = client.post("/channels/1/messages", &Value::Null).await;
+        assert!(matches!(err, Err(CliError::Network(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        assert!(client.get("/channels/1").await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+}
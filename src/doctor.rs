@@ -0,0 +1,121 @@
+//! Environment readiness checks for `guildsync doctor`.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::config::{self, Config, KubeProvider};
+
+/// How long a single `<tool> --version` probe may take.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    /// A failing critical check makes `doctor` exit non-zero.
+    pub critical: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, critical: bool, result: Result<String, String>) -> Self {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Self {
+            name: name.into(),
+            ok,
+            critical,
+            detail,
+        }
+    }
+}
+
+/// Run every check. `config_path` is re-loaded here so load errors are reported, not fatal.
+pub async fn run_checks(config_path: Option<&Path>) -> Vec<Check> {
+    let loaded = Config::load(config_path);
+    let mut checks = vec![Check::new(
+        "config",
+        true,
+        match &loaded {
+            Ok(config) => Ok(match &config.source {
+                Some(path) => format!("loaded {}", path.display()),
+                None => "no config file; using defaults".to_string(),
+            }),
+            Err(err) => Err(err.to_string()),
+        },
+    )];
+    let config = loaded.unwrap_or_default();
+
+    checks.push(Check::new(
+        "discord token",
+        true,
+        config
+            .discord
+            .resolve_token_with_source(None)
+            .map(|(_, source)| format!("set ({source})"))
+            .map_err(|e| e.to_string()),
+    ));
+
+    let provider = match config.kube.local.provider {
+        KubeProvider::Kind => ("kind", &["version"][..]),
+        KubeProvider::K3d => ("k3d", &["version"][..]),
+        KubeProvider::Minikube => ("minikube", &["version", "--short"][..]),
+    };
+    let tools: [(&str, &[&str]); 4] = [
+        ("tmux", &["-V"]),
+        ("kubectl", &["version", "--client"]),
+        provider,
+        ("ssh", &["-V"]),
+    ];
+    for (tool, args) in tools {
+        checks.push(Check::new(tool, false, probe(tool, args).await));
+    }
+
+    checks.push(Check::new("kubeconfig", false, kubeconfig_readable()));
+    checks
+}
+
+/// First line of `<tool> <args>` output (stdout, or stderr for tools like `ssh -V`).
+async fn probe(tool: &str, args: &[&str]) -> Result<String, String> {
+    let output = tokio::process::Command::new(tool)
+        .args(args)
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(PROBE_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err("not found on PATH".to_string());
+        }
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(_) => return Err(format!("timed out after {}s", PROBE_TIMEOUT.as_secs())),
+    };
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    let line = String::from_utf8_lossy(&text)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if output.status.success() {
+        Ok(line)
+    } else {
+        Err(format!("{}: {line}", output.status))
+    }
+}
+
+fn kubeconfig_readable() -> Result<String, String> {
+    let path = std::env::var_os("KUBECONFIG")
+        .and_then(|v| std::env::split_paths(&v).next())
+        .unwrap_or_else(|| config::expand_tilde(&PathBuf::from("~/.kube/config")));
+    std::fs::File::open(&path)
+        .map(|_| path.display().to_string())
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
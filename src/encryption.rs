@@ -0,0 +1,112 @@
+//! Encryption at rest for dumps, as [age](https://age-encryption.org) files.
+//!
+//! `discord export --encrypt-to` seals the dump (after compression) to one or more X25519
+//! recipients (`age1…`). Readers detect age files by their header, like compressed ones, and
+//! decrypt them with the identity file given by `--identity` or `[formats] identity_file`, so every
+//! command that reads a dump accepts an encrypted one. Files made with the `age` tool work too.
+
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+pub use age::x25519::Recipient;
+
+use crate::error::CliError;
+
+/// Start of every binary age file.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+static IDENTITY_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Decrypt files read from now on with the identities in `path`. Only the first call counts.
+pub fn use_identity_file(path: PathBuf) {
+    let _ = IDENTITY_FILE.set(path);
+}
+
+/// Whether a file starting with `head` is an age file.
+pub fn is_encrypted(head: &[u8]) -> bool {
+    head.starts_with(AGE_MAGIC)
+}
+
+/// Parse `age1…` recipients, naming `source` (a flag or config key) in errors.
+pub fn recipients(keys: &[String], source: &str) -> Result<Vec<Recipient>, String> {
+    keys.iter()
+        .map(|key| {
+            key.trim()
+                .parse()
+                .map_err(|e| format!("{source} {key}: {e}"))
+        })
+        .collect()
+}
+
+/// Run `write` against `out`, encrypting what it writes to `recipients`.
+pub fn seal<W: Write>(
+    recipients: &[Recipient],
+    out: W,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), CliError>,
+) -> Result<(), CliError> {
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|e| CliError::Usage(format!("recipients: {e}")))?;
+    let mut sealed = encryptor.wrap_output(out)?;
+    write(&mut sealed)?;
+    sealed.finish()?;
+    Ok(())
+}
+
+/// The plaintext of the age file `path`, whose contents `reader` yields.
+pub fn decrypt<R: BufRead>(path: &Path, reader: R) -> Result<impl Read + use<R>, CliError> {
+    let Some(identity_file) = IDENTITY_FILE.get() else {
+        return Err(CliError::Auth(format!(
+            "{} is encrypted; pass --identity <PATH> or set [formats] identity_file",
+            path.display()
+        )));
+    };
+    let identities = age::IdentityFile::from_file(identity_file.display().to_string())
+        .map_err(|e| CliError::Config(format!("identity file {}: {e}", identity_file.display())))?
+        .into_identities()
+        .map_err(|e| CliError::Config(format!("identity file {}: {e}", identity_file.display())))?;
+    let decryptor = age::Decryptor::new_buffered(reader)
+        .map_err(|e| CliError::Validation(format!("{}: {e}", path.display())))?;
+    decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref() as &dyn age::Identity))
+        .map_err(|e| match e {
+            age::DecryptError::NoMatchingKeys => CliError::Auth(format!(
+                "{}: no identity in {} can decrypt it",
+                path.display(),
+                identity_file.display()
+            )),
+            e => CliError::Validation(format!("{}: {e}", path.display())),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use age::secrecy::ExposeSecret;
+
+    use super::*;
+
+    #[test]
+    fn sealed_output_decrypts_with_the_identity() {
+        let identity = age::x25519::Identity::generate();
+        let dir = std::env::temp_dir().join(format!("guildsync-age-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = dir.join("key.txt");
+        std::fs::write(&key, identity.to_string().expose_secret()).unwrap();
+        use_identity_file(key);
+
+        let to = recipients(&[identity.to_public().to_string()], "--encrypt-to").unwrap();
+        let mut sealed = Vec::new();
+        seal(&to, &mut sealed, |out| Ok(out.write_all(b"{}\n")?)).unwrap();
+        assert!(is_encrypted(&sealed));
+
+        let mut plain = String::new();
+        decrypt(Path::new("guild.json"), sealed.as_slice())
+            .unwrap()
+            .read_to_string(&mut plain)
+            .unwrap();
+        assert_eq!(plain, "{}\n");
+        assert!(recipients(&["age1nope".to_string()], "--encrypt-to").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use crate::schema::Violation;
+
+/// Process exit codes, following `sysexits.h` where a category fits.
+///
+/// These are a stable interface: scripts may branch on them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExitCode {
+    #[default]
+    Ok,
+    /// Catch-all for failures without a more specific category.
+    Failure,
+    /// Bad flags or arguments (`EX_USAGE`).
+    Usage,
+    /// Input file is malformed (`EX_DATAERR`).
+    DataErr,
+    /// A referenced resource does not exist (`EX_NOINPUT`).
+    NoInput,
+    /// A remote service is unreachable (`EX_UNAVAILABLE`).
+    Unavailable,
+    /// Local I/O failed (`EX_IOERR`).
+    IoErr,
+    /// Temporary failure; retrying later may succeed (`EX_TEMPFAIL`).
+    TempFail,
+    /// Credentials missing or rejected (`EX_NOPERM`).
+    NoPerm,
+    /// Config file missing or invalid (`EX_CONFIG`).
+    Config,
+    /// An operation exceeded its deadline (same code as `timeout(1)`).
+    Timeout,
+    /// Interrupted by Ctrl-C (128 + SIGINT).
+    Cancelled,
+    /// Exit status of a remote command, passed through as-is (`ssh exec`).
+    Remote(u8),
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Ok => 0,
+            ExitCode::Failure => 1,
+            ExitCode::Usage => 64,
+            ExitCode::DataErr => 65,
+            ExitCode::NoInput => 66,
+            ExitCode::Unavailable => 69,
+            ExitCode::IoErr => 74,
+            ExitCode::TempFail => 75,
+            ExitCode::NoPerm => 77,
+            ExitCode::Config => 78,
+            ExitCode::Timeout => 124,
+            ExitCode::Cancelled => 130,
+            ExitCode::Remote(code) => code.into(),
+        }
+    }
+}
+
+/// Errors surfaced by CLI actions.
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    /// Invalid combination of arguments detected after parsing.
+    #[error("usage: {0}")]
+    Usage(String),
+
+    /// The config file could not be read or parsed.
+    #[error("config: {0}")]
+    Config(String),
+
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// A JSON syntax error with its position in the input.
+    #[error("json: {msg} at line {line}, column {column}\n{snippet}")]
+    JsonAt {
+        line: usize,
+        column: usize,
+        msg: String,
+        snippet: String,
+    },
+
+    /// The file parsed but does not match the dump/upload format.
+    #[error("invalid: {0}")]
+    Validation(String),
+
+    /// The file breaks its format's schema, at each of these places.
+    #[error("invalid: {}", violation_list(.0))]
+    Violations(Vec<Violation>),
+
+    /// Two files that must share a `format` do not.
+    #[error("invalid: format mismatch: base is {base}, delta is {delta}")]
+    FormatMismatch { base: String, delta: String },
+
+    /// An import would change more than `--max-changes` settings and items.
+    #[error(
+        "refusing to import: {count} changes exceed --max-changes {limit} (pass --yes to override)"
+    )]
+    TooManyChanges { count: usize, limit: usize },
+
+    /// An operation did not finish within its deadline.
+    #[error("timeout: {0}")]
+    Timeout(String),
+
+    /// A Kubernetes operation (kubectl, cluster provider) failed.
+    #[error("kube: {0}")]
+    Kube(String),
+
+    /// A configured pre-hook failed, aborting the action.
+    #[error("hook: {0}")]
+    Hook(String),
+
+    /// The user interrupted the run (Ctrl-C).
+    #[error("cancelled")]
+    Cancelled,
+
+    /// Credentials were rejected (HTTP 401/403).
+    #[error("unauthorized: {0}")]
+    Auth(String),
+
+    /// The remote resource does not exist (HTTP 404).
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    /// A transient network failure (connection reset, 5xx, timeout).
+    #[error("network: {0}")]
+    Network(String),
+
+    /// The remote asked us to slow down (HTTP 429), optionally with a `Retry-After` hint.
+    #[error("rate limited")]
+    RateLimited { retry_after: Option<Duration> },
+}
+
+impl CliError {
+    /// The process exit code for this error; the single source of truth used by `main`.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            CliError::Usage(_) => ExitCode::Usage,
+            CliError::Config(_) => ExitCode::Config,
+            CliError::Io(_) => ExitCode::IoErr,
+            CliError::Json(_)
+            | CliError::JsonAt { .. }
+            | CliError::Validation(_)
+            | CliError::Violations(_)
+            | CliError::FormatMismatch { .. }
+            | CliError::TooManyChanges { .. } => ExitCode::DataErr,
+            CliError::Timeout(_) => ExitCode::Timeout,
+            CliError::Kube(_) => ExitCode::Unavailable,
+            CliError::Hook(_) => ExitCode::Failure,
+            CliError::Cancelled => ExitCode::Cancelled,
+            CliError::Auth(_) => ExitCode::NoPerm,
+            CliError::NotFound(_) => ExitCode::NoInput,
+            CliError::Network(_) => ExitCode::Unavailable,
+            CliError::RateLimited { .. } => ExitCode::TempFail,
+        }
+    }
+
+    /// Whether retrying the same idempotent request could succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, CliError::Network(_) | CliError::RateLimited { .. })
+    }
+
+    /// Structured fields merged into the `--json` error envelope.
+    pub fn data(&self) -> Option<serde_json::Value> {
+        match self {
+            CliError::JsonAt {
+                line,
+                column,
+                msg,
+                snippet,
+            } => Some(serde_json::json!({
+                "line": line,
+                "column": column,
+                "error": msg,
+                "snippet": snippet,
+            })),
+            CliError::Violations(violations) => {
+                let mut data = serde_json::json!({ "violations": violations });
+                // Every absent required key at once, and the pinned version against the file's.
+                let missing: Vec<&serde_json::Value> = violations
+                    .iter()
+                    .filter(|v| v.code == "missing-field")
+                    .filter_map(|v| v.data.get("field"))
+                    .collect();
+                if !missing.is_empty() {
+                    data["missing"] = serde_json::json!(missing);
+                }
+                if let Some(mismatch) = violations.iter().find(|v| v.code == "version-mismatch") {
+                    data["expected"] = mismatch.data["expected"].clone();
+                    data["found"] = mismatch.data["found"].clone();
+                }
+                Some(data)
+            }
+            CliError::TooManyChanges { count, limit } => {
+                Some(serde_json::json!({ "count": count, "limit": limit }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Server-provided delay before the next attempt, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CliError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// One violation inline, or several on their own lines.
+fn violation_list(violations: &[Violation]) -> String {
+    match violations {
+        [violation] => violation.to_string(),
+        _ => {
+            let mut text = format!("{} violations", violations.len());
+            for violation in violations {
+                text.push_str(&format!("\n  {violation}"));
+            }
+            text
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_stable() {
+        let cases = [
+            (ExitCode::Ok, 0),
+            (ExitCode::Failure, 1),
+            (ExitCode::Usage, 64),
+            (ExitCode::DataErr, 65),
+            (ExitCode::NoInput, 66),
+            (ExitCode::Unavailable, 69),
+            (ExitCode::IoErr, 74),
+            (ExitCode::TempFail, 75),
+            (ExitCode::NoPerm, 77),
+            (ExitCode::Config, 78),
+            (ExitCode::Timeout, 124),
+            (ExitCode::Cancelled, 130),
+            (ExitCode::Remote(3), 3),
+        ];
+        for (exit, code) in cases {
+            assert_eq!(exit.code(), code, "{exit:?}");
+        }
+    }
+
+    #[test]
+    fn errors_map_to_exit_codes() {
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let cases = [
+            (CliError::Usage(String::new()), 64),
+            (CliError::Config(String::new()), 78),
+            (CliError::Io(std::io::Error::other("x")), 74),
+            (CliError::Json(json), 65),
+            (
+                CliError::JsonAt {
+                    line: 1,
+                    column: 1,
+                    msg: String::new(),
+                    snippet: String::new(),
+                },
+                65,
+            ),
+            (CliError::Validation(String::new()), 65),
+            (CliError::Violations(vec![]), 65),
+            (
+                CliError::FormatMismatch {
+                    base: String::new(),
+                    delta: String::new(),
+                },
+                65,
+            ),
+            (CliError::TooManyChanges { count: 2, limit: 1 }, 65),
+            (CliError::Timeout(String::new()), 124),
+            (CliError::Kube(String::new()), 69),
+            (CliError::Hook(String::new()), 1),
+            (CliError::Cancelled, 130),
+            (CliError::Auth(String::new()), 77),
+            (CliError::NotFound(String::new()), 66),
+            (CliError::Network(String::new()), 69),
+            (CliError::RateLimited { retry_after: None }, 75),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.exit_code().code(), code, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn violations_carry_missing_fields_and_versions() {
+        use crate::schema::Violation;
+
+        let err = CliError::Violations(vec![
+            Violation::new("/guild", "missing-field", "missing required field `guild`")
+                .with("field", "guild"),
+            Violation::new("/team", "missing-field", "missing required field `team`")
+                .with("field", "team"),
+            Violation::new(
+                "/version",
+                "version-mismatch",
+                "expected version 2, found 1",
+            )
+            .with("expected", 2)
+            .with("found", 1),
+        ]);
+        let data = err.data().unwrap();
+        assert_eq!(data["missing"], serde_json::json!(["guild", "team"]));
+        assert_eq!((&data["expected"], &data["found"]), (&2.into(), &1.into()));
+        assert_eq!(data["violations"][2]["expected"], 2);
+        assert_eq!(data["violations"][0]["field"], "guild");
+        assert_eq!(err.exit_code().code(), 65);
+
+        let data = CliError::Violations(vec![]).data().unwrap();
+        assert!(data.get("missing").is_none() && data.get("expected").is_none());
+    }
+}
@@ -0,0 +1,1103 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::atomic_file::{AtomicFile, write_atomic};
+use crate::compression::{self, Codec};
+use crate::config::FormatsConfig;
+use crate::discord::CATEGORY_TYPE;
+use crate::encryption::{self, Recipient};
+use crate::error::CliError;
+use crate::ndjson;
+use crate::schema::{self, Violation};
+use crate::timestamp;
+
+/// The two on-disk guild formats: a `dump` snapshot and an `upload` plan.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuildFormat {
+    Dump,
+    Upload,
+}
+
+impl std::fmt::Display for GuildFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GuildFormat::Dump => "dump",
+            GuildFormat::Upload => "upload",
+        })
+    }
+}
+
+/// Parts of a guild that `discord import --only` can restore independently.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSection {
+    Roles,
+    /// Non-category channels.
+    Channels,
+    /// Category channels (`type` 4).
+    Categories,
+    /// Role permissions and channel permission overwrites.
+    Permissions,
+    /// Custom emoji.
+    Emojis,
+    Stickers,
+    /// Scheduled events.
+    Events,
+}
+
+impl std::fmt::Display for ImportSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ImportSection::Roles => "roles",
+            ImportSection::Channels => "channels",
+            ImportSection::Categories => "categories",
+            ImportSection::Permissions => "permissions",
+            ImportSection::Emojis => "emojis",
+            ImportSection::Stickers => "stickers",
+            ImportSection::Events => "events",
+        })
+    }
+}
+
+/// Which side wins when `format merge` finds the same field with different values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Prefer {
+    Base,
+    Delta,
+    /// The side whose item has the later `edited_timestamp` (or `timestamp`); the delta for
+    /// items without one.
+    Newer,
+}
+
+/// What `format merge` did.
+#[derive(Debug, Default, Serialize)]
+pub struct MergeStats {
+    /// Items (by id) or keys present only in the delta.
+    pub added: usize,
+    /// Fields whose base and delta values differed.
+    pub conflicts: usize,
+    /// Each of those fields, with the side kept.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub resolved: Vec<Conflict>,
+}
+
+/// A field that differed between the base and the delta.
+#[derive(Debug, Serialize)]
+pub struct Conflict {
+    /// JSON pointer of the field in the merged document.
+    pub pointer: String,
+    /// `base` or `delta`.
+    pub kept: Prefer,
+}
+
+/// Arguments of `format validate`, shared by the CLI and the MCP tool.
+#[derive(clap::Args, Debug, Deserialize)]
+pub struct ValidateArgs {
+    /// Input file path.
+    #[arg(long, value_name = "PATH")]
+    pub r#in: PathBuf,
+
+    /// Expected format.
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    pub format: Option<GuildFormat>,
+
+    /// Additional top-level key that must be present (repeatable).
+    #[arg(long, value_name = "KEY")]
+    #[serde(default)]
+    pub required: Vec<String>,
+
+    /// Fail unless the file's `version` is exactly N.
+    #[arg(long, value_name = "N")]
+    #[serde(default)]
+    pub expect_version: Option<u64>,
+}
+
+/// Arguments of `format convert`, shared by the CLI and the MCP tool.
+#[derive(clap::Args, Debug, Deserialize)]
+pub struct ConvertArgs {
+    /// Input file path.
+    #[arg(long, value_name = "PATH")]
+    pub r#in: PathBuf,
+
+    /// Format to convert to.
+    #[arg(long, value_enum)]
+    pub to: GuildFormat,
+
+    /// Output path for the converted file.
+    #[arg(long, value_name = "PATH")]
+    pub out: PathBuf,
+}
+
+/// What `format convert` did.
+#[derive(Debug, Serialize)]
+pub struct Converted {
+    pub from: GuildFormat,
+    pub to: GuildFormat,
+    pub version: u64,
+    /// Archived messages left out, which upload files do not carry.
+    pub dropped_messages: usize,
+    /// Export-only fields left out (`exported_at`, `last_message_id`, member counts, ...).
+    pub dropped_fields: usize,
+}
+
+/// Top-level keys every dump/upload file must carry.
+pub const REQUIRED_KEYS: &[&str] = &["format", "version", "guild"];
+
+/// Top-level keys of id-bearing lists (roles, channels, ...).
+pub const LIST_KEYS: &[&str] = &[
+    "roles",
+    "channels",
+    "threads",
+    "emojis",
+    "stickers",
+    "scheduled_events",
+];
+
+/// Channel types of forum and media channels, which carry `available_tags`.
+const FORUM_TYPES: &[u64] = &[15, 16];
+
+/// Top-level dump keys that only describe the export run, not the guild.
+const RUNTIME_KEYS: &[&str] = &["exported_at", "exporter"];
+
+/// Per-object keys that reflect live state and cannot be applied to a guild.
+pub const RUNTIME_OBJECT_KEYS: &[&str] = &[
+    "last_message_id",
+    "member_count",
+    "approximate_member_count",
+    "approximate_presence_count",
+    "message_count",
+    "total_message_sent",
+    "user_count",
+];
+
+/// Summary of a file that passed validation.
+#[derive(Debug, Serialize)]
+pub struct Validated {
+    pub format: GuildFormat,
+    pub version: u64,
+    /// The version asserted with `--expect-version`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_version: Option<u64>,
+}
+
+/// Read the file named by `args` and check it is a well-formed dump/upload file carrying every
+/// `required` key and, if asked, exactly the expected version. Every problem found is reported
+/// at once, as [`CliError::Violations`].
+pub fn validate_format(
+    args: &ValidateArgs,
+    formats: &FormatsConfig,
+) -> Result<Validated, CliError> {
+    // NDJSON files are checked record by record rather than loaded whole.
+    let ndjson = ndjson::is_ndjson(&args.r#in);
+    let value = if ndjson {
+        ndjson::header(&args.r#in)?
+    } else {
+        read_document(&args.r#in)?
+    };
+    let checked = if ndjson {
+        ndjson::validate(&args.r#in, args.format, formats)
+    } else {
+        validate_value(&value, args.format, formats).map(|validated| (validated, Value::Null))
+    };
+    let (validated, mut violations) = match checked {
+        Ok((validated, skeleton)) => {
+            let mut missing = missing_keys(&value, &args.required);
+            if ndjson {
+                // Sections only appear in the skeleton, not the header.
+                missing.retain(|v| skeleton.get(&v.pointer[1..]).is_none());
+            }
+            (Some(validated), missing)
+        }
+        Err(CliError::Violations(violations)) => {
+            let mut all = violations;
+            all.extend(missing_keys(&value, &args.required));
+            (None, all)
+        }
+        Err(err) => return Err(err),
+    };
+    if let Some(expected) = args.expect_version
+        && let Some(found) = value["version"].as_u64()
+        && found != expected
+    {
+        violations.push(
+            Violation::new(
+                "/version",
+                "version-mismatch",
+                format!("expected version {expected} (--expect-version), found {found}"),
+            )
+            .with("expected", expected)
+            .with("found", found),
+        );
+    }
+    if !violations.is_empty() || validated.is_none() {
+        violations.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+        violations.dedup();
+        return Err(CliError::Violations(violations));
+    }
+    Ok(Validated {
+        expected_version: args.expect_version,
+        ..validated.expect("checked above")
+    })
+}
+
+/// A `missing-field` violation for every key from `keys` absent from the top-level object.
+fn missing_keys<K: AsRef<str>>(value: &Value, keys: &[K]) -> Vec<Violation> {
+    keys.iter()
+        .map(AsRef::as_ref)
+        .filter(|key| value.get(key).is_none())
+        .map(|key| {
+            Violation::new(
+                format!("/{key}"),
+                "missing-field",
+                format!("missing required field `{key}`"),
+            )
+            .with("field", key)
+        })
+        .collect()
+}
+
+/// Read and parse a (possibly gzip-compressed) JSON or NDJSON document.
+pub fn read_document(path: &Path) -> Result<Value, CliError> {
+    if ndjson::is_ndjson(path) {
+        return ndjson::read(path);
+    }
+    parse_json(&compression::read_to_string(path)?)
+}
+
+/// Pretty-print `value` to `path` atomically, compressing it for `.gz` and `.zst` destinations.
+/// `.ndjson` destinations get NDJSON records instead.
+pub fn write_document(path: &Path, value: &Value) -> Result<(), CliError> {
+    write_compressed(path, value, Codec::for_path(path))
+}
+
+/// [`write_document`] with the compression given rather than implied by the extension.
+pub fn write_compressed(path: &Path, value: &Value, codec: Codec) -> Result<(), CliError> {
+    if ndjson::is_ndjson(path) {
+        return ndjson::write(path, value, codec);
+    }
+    let mut bytes = serde_json::to_vec_pretty(value)?;
+    bytes.push(b'\n');
+    write_atomic(path, &compression::encode(codec, bytes)?)
+}
+
+/// [`write_compressed`], then encrypted to `recipients` if there are any. Nothing unencrypted
+/// reaches the disk.
+pub fn write_sealed(
+    path: &Path,
+    value: &Value,
+    codec: Codec,
+    recipients: &[Recipient],
+) -> Result<(), CliError> {
+    if recipients.is_empty() {
+        return write_compressed(path, value, codec);
+    }
+    let mut file = AtomicFile::create(path)?;
+    encryption::seal(recipients, &mut file, |out| {
+        if ndjson::is_ndjson(path) {
+            return ndjson::write_to(out, value, codec);
+        }
+        compression::stream_to(codec, out, |out| {
+            serde_json::to_writer_pretty(&mut *out, value)?;
+            Ok(out.write_all(b"\n")?)
+        })
+    })?;
+    file.commit()
+}
+
+/// Parse JSON, reporting syntax errors with line, column, and the offending text.
+pub fn parse_json(text: &str) -> Result<Value, CliError> {
+    serde_json::from_str(text).map_err(|e| json_error_at(text, &e))
+}
+
+/// Check an already-parsed document against the schema of its `format` and `version`, reporting
+/// every violation at once.
+pub fn validate_value(
+    value: &Value,
+    expected: Option<GuildFormat>,
+    formats: &FormatsConfig,
+) -> Result<Validated, CliError> {
+    let (validated, schema) = check_header(value, expected, formats)?;
+    let mut violations = schema::check(&schema, value);
+    // Only once the shapes are right do the references between sections mean anything.
+    if violations.is_empty() {
+        violations = thread_references(value);
+    }
+    if !violations.is_empty() {
+        return Err(CliError::Violations(violations));
+    }
+    Ok(validated)
+}
+
+/// Check the top-level `format` and `version` of a document and return the schema the rest of
+/// it must follow. Fails with every header problem at once if there is no schema to check
+/// against.
+pub(crate) fn check_header(
+    value: &Value,
+    expected: Option<GuildFormat>,
+    formats: &FormatsConfig,
+) -> Result<(Validated, Value), CliError> {
+    let Some(obj) = value.as_object() else {
+        return Err(CliError::Violations(vec![Violation::new(
+            "",
+            "not-an-object",
+            "top level must be a JSON object",
+        )]));
+    };
+    let mut violations = Vec::new();
+
+    let format = match obj.get("format") {
+        None => None,
+        Some(format) => match GuildFormat::deserialize(format) {
+            Err(_) => {
+                violations.push(Violation::new(
+                    "/format",
+                    "unknown-format",
+                    "`format` must be \"dump\" or \"upload\"",
+                ));
+                None
+            }
+            Ok(format) if expected.is_some_and(|expected| expected != format) => {
+                violations.push(Violation::new(
+                    "/format",
+                    "format-mismatch",
+                    format!(
+                        "expected format {}, found {format}",
+                        expected.expect("checked above")
+                    ),
+                ));
+                None
+            }
+            Ok(format) => Some(format),
+        },
+    };
+
+    let version = match obj.get("version") {
+        None => None,
+        Some(version) => match version.as_u64().filter(|v| *v > 0) {
+            None => {
+                violations.push(Violation::new(
+                    "/version",
+                    "invalid-version",
+                    "`version` must be a positive integer",
+                ));
+                None
+            }
+            Some(version) => Some(version),
+        },
+    };
+
+    let mut schema = None;
+    if let (Some(format), Some(version)) = (format, version) {
+        let supported = u64::from(match format {
+            GuildFormat::Dump => formats.dump_version,
+            GuildFormat::Upload => formats.upload_version,
+        });
+        if version > supported || (formats.strict && version != supported) {
+            violations.push(Violation::new(
+                "/version",
+                "unsupported-version",
+                format!("unsupported {format} version {version} (supported: {supported})"),
+            ));
+        } else if let Some(found) = schema::schema(format, version) {
+            schema = Some((format, version, found));
+        } else {
+            violations.push(Violation::new(
+                "/version",
+                "unsupported-version",
+                format!(
+                    "no schema for {format} version {version} (known versions: {})",
+                    schema::versions()
+                ),
+            ));
+        }
+    }
+
+    match schema {
+        // The schema reports any other missing keys itself.
+        Some((format, version, schema)) if violations.is_empty() => {
+            let validated = Validated {
+                format,
+                version,
+                expected_version: None,
+            };
+            Ok((validated, schema))
+        }
+        _ => {
+            violations.extend(missing_keys(value, REQUIRED_KEYS));
+            violations.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+            Err(CliError::Violations(violations))
+        }
+    }
+}
+
+/// Every thread must hang off a channel of the file and carry only tags its forum defines.
+fn thread_references(document: &Value) -> Vec<Violation> {
+    list(document, "threads")
+        .enumerate()
+        .flat_map(|(i, thread)| {
+            let parent = list(document, "channels").find(|c| c["id"] == thread["parent_id"]);
+            thread_violations(i, thread, parent)
+        })
+        .collect()
+}
+
+/// Reference violations of thread `i`, whose parent channel in the file is `parent`.
+pub(crate) fn thread_violations(
+    i: usize,
+    thread: &Value,
+    parent: Option<&Value>,
+) -> Vec<Violation> {
+    let parent_id = &thread["parent_id"];
+    let Some(parent) = parent else {
+        return vec![Violation::new(
+            format!("/threads/{i}/parent_id"),
+            "thread-parent",
+            format!("{parent_id} is not a channel in the file"),
+        )];
+    };
+    let is_forum = parent["type"]
+        .as_u64()
+        .is_some_and(|t| FORUM_TYPES.contains(&t));
+    let defined = |tag: &Value| list(parent, "available_tags").any(|t| t["id"] == *tag);
+    list(thread, "applied_tags")
+        .enumerate()
+        .filter(|(_, tag)| !is_forum || !defined(tag))
+        .map(|(j, tag)| {
+            Violation::new(
+                format!("/threads/{i}/applied_tags/{j}"),
+                "thread-tag",
+                format!("tag {tag} is not defined by forum {parent_id}"),
+            )
+        })
+        .collect()
+}
+
+/// Turn a dump into an upload document: drop runtime-only fields and retag it.
+pub fn to_upload(mut dump: Value, version: u32) -> Value {
+    strip_export_fields(&mut dump);
+    retag(&mut dump, GuildFormat::Upload, version);
+    dump
+}
+
+/// Read the file named by `args`, convert it to the other format, and write it to `args.out`.
+///
+/// A dump loses its archived messages and export-only fields on the way to an upload file; an
+/// upload file is a dump without those, so converting it back loses nothing.
+pub fn convert(args: &ConvertArgs, formats: &FormatsConfig) -> Result<Converted, CliError> {
+    let mut document = read_document(&args.r#in)?;
+    let from = validate_value(&document, None, formats)?.format;
+    if from == args.to {
+        return Err(CliError::Usage(format!(
+            "{} is already in the {from} format",
+            args.r#in.display()
+        )));
+    }
+    let (mut dropped_messages, mut dropped_fields) = (0, 0);
+    let version = match args.to {
+        GuildFormat::Upload => {
+            for channel in document["channels"].as_array_mut().into_iter().flatten() {
+                if let Some(messages) = channel
+                    .as_object_mut()
+                    .and_then(|c| c.shift_remove("messages"))
+                {
+                    dropped_messages += messages.as_array().map_or(0, Vec::len);
+                }
+            }
+            dropped_fields = strip_export_fields(&mut document);
+            formats.upload_version
+        }
+        GuildFormat::Dump => formats.dump_version,
+    };
+    retag(&mut document, args.to, version);
+    let converted = validate_value(&document, Some(args.to), formats)?;
+    write_document(&args.out, &document)?;
+    Ok(Converted {
+        from,
+        to: args.to,
+        version: converted.version,
+        dropped_messages,
+        dropped_fields,
+    })
+}
+
+/// Drop the fields that only describe the export run or live state, returning how many there
+/// were.
+fn strip_export_fields(document: &mut Value) -> usize {
+    let mut count = 0;
+    if let Value::Object(obj) = document {
+        for key in RUNTIME_KEYS {
+            count += usize::from(obj.shift_remove(*key).is_some());
+        }
+    }
+    count + strip_runtime_keys(document)
+}
+
+fn retag(document: &mut Value, format: GuildFormat, version: u32) {
+    if let Value::Object(obj) = document {
+        obj.insert("format".to_string(), format.to_string().into());
+        obj.insert("version".to_string(), version.into());
+    }
+}
+
+/// Rewrite `value` into a canonical form: object keys sorted recursively and arrays of
+/// id-bearing objects (roles, channels, overwrites, ...) ordered by snowflake.
+pub fn canonicalize(value: &mut Value) {
+    match value {
+        Value::Object(obj) => {
+            obj.sort_keys();
+            obj.values_mut().for_each(canonicalize);
+        }
+        Value::Array(items) => {
+            items.iter_mut().for_each(canonicalize);
+            if items.iter().all(|item| item.get("id").is_some()) {
+                items.sort_by_cached_key(|item| snowflake_key(&item["id"]));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// [`canonicalize`] `value` and rewrite its timestamps in UTC with one layout (see
+/// [`timestamp::normalize`]), so that exports diff only where the guild changed. Returns the
+/// number of timestamps rewritten.
+pub fn normalize(value: &mut Value) -> usize {
+    canonicalize(value);
+    normalize_timestamps(value)
+}
+
+/// Whether `key` holds a time: `timestamp`, `edited_timestamp`, `exported_at`,
+/// `scheduled_start_time`, `premium_since`, `communication_disabled_until`, ...
+fn is_time_key(key: &str) -> bool {
+    key == "timestamp"
+        || ["_timestamp", "_at", "_time", "_since", "_until"]
+            .iter()
+            .any(|suffix| key.ends_with(suffix))
+}
+
+fn normalize_timestamps(value: &mut Value) -> usize {
+    match value {
+        Value::Object(obj) => obj
+            .iter_mut()
+            .map(|(key, value)| match value {
+                Value::String(text) if is_time_key(key) => {
+                    match timestamp::normalize(text).filter(|n| n != text) {
+                        Some(normalized) => {
+                            *text = normalized;
+                            1
+                        }
+                        None => 0,
+                    }
+                }
+                value => normalize_timestamps(value),
+            })
+            .sum(),
+        Value::Array(items) => items.iter_mut().map(normalize_timestamps).sum(),
+        _ => 0,
+    }
+}
+
+/// Sort key for an id: snowflakes in numeric order, after any non-numeric ids (by text).
+fn snowflake_key(id: &Value) -> (Option<u64>, String) {
+    match id {
+        Value::String(text) => (text.parse().ok(), text.clone()),
+        Value::Number(n) => (n.as_u64(), n.to_string()),
+        other => (None, other.to_string()),
+    }
+}
+
+/// Restrict a document to `sections`, returning the scoped copy and the requested sections
+/// that had nothing in it.
+///
+/// Guild settings are dropped (only `guild.id` is kept), so a scoped import never touches them.
+/// With only `permissions`, roles and channels are reduced to their permission fields.
+pub fn select_sections(
+    document: &Value,
+    sections: &[ImportSection],
+) -> (Value, Vec<ImportSection>) {
+    let wants = |section| sections.contains(&section);
+    let mut scoped = document.clone();
+    if let Value::Object(obj) = &mut scoped {
+        obj.insert(
+            "guild".to_string(),
+            serde_json::json!({ "id": document["guild"]["id"] }),
+        );
+    }
+
+    let roles: Vec<Value> = list(document, "roles")
+        .filter_map(|role| {
+            if wants(ImportSection::Roles) {
+                Some(role.clone())
+            } else if wants(ImportSection::Permissions) && role.get("permissions").is_some() {
+                Some(pick(role, &["id", "name", "permissions"]))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let channels: Vec<Value> = list(document, "channels")
+        .filter_map(|channel| {
+            let category = channel["type"].as_u64() == Some(CATEGORY_TYPE);
+            if wants(ImportSection::Categories) && category
+                || wants(ImportSection::Channels) && !category
+            {
+                Some(channel.clone())
+            } else if wants(ImportSection::Permissions)
+                && channel.get("permission_overwrites").is_some()
+            {
+                Some(pick(channel, &["id", "name", "permission_overwrites"]))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let missing = sections
+        .iter()
+        .copied()
+        .filter(|section| {
+            let mut channels = list(document, "channels");
+            match section {
+                ImportSection::Roles => list(document, "roles").next().is_none(),
+                ImportSection::Channels => {
+                    !channels.any(|c| c["type"].as_u64() != Some(CATEGORY_TYPE))
+                }
+                ImportSection::Categories => {
+                    !channels.any(|c| c["type"].as_u64() == Some(CATEGORY_TYPE))
+                }
+                ImportSection::Permissions => {
+                    !list(document, "roles").any(|r| r.get("permissions").is_some())
+                        && !channels.any(|c| c.get("permission_overwrites").is_some())
+                }
+                ImportSection::Emojis => list(document, "emojis").next().is_none(),
+                ImportSection::Stickers => list(document, "stickers").next().is_none(),
+                ImportSection::Events => list(document, "scheduled_events").next().is_none(),
+            }
+        })
+        .collect();
+
+    scoped["roles"] = roles.into();
+    scoped["channels"] = channels.into();
+    for (section, key) in [
+        (ImportSection::Emojis, "emojis"),
+        (ImportSection::Stickers, "stickers"),
+        (ImportSection::Events, "scheduled_events"),
+    ] {
+        if !wants(section) {
+            scoped[key] = Value::Array(Vec::new());
+        }
+    }
+    (scoped, missing)
+}
+
+/// Merge a partial `delta` dump into a full `base` dump.
+///
+/// Objects merge key by key and arrays of id-bearing objects merge by id (new items are
+/// appended, and `messages` are kept in snowflake order); any other differing value is a conflict
+/// resolved by `prefer`. The result is a full dump: the `partial` marker is removed.
+pub fn merge(base: &Value, delta: &Value, prefer: Prefer) -> Result<(Value, MergeStats), CliError> {
+    if base["format"] != delta["format"] {
+        return Err(CliError::FormatMismatch {
+            base: base["format"].as_str().unwrap_or_default().to_string(),
+            delta: delta["format"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+    let mut merged = base.clone();
+    let mut delta = delta.clone();
+    for doc in [&mut merged, &mut delta] {
+        if let Value::Object(obj) = doc {
+            obj.shift_remove("partial");
+        }
+    }
+    let mut stats = MergeStats::default();
+    let kept = match prefer {
+        Prefer::Newer => Prefer::Delta,
+        side => side,
+    };
+    let by_time = prefer == Prefer::Newer;
+    merge_into(&mut merged, &delta, kept, by_time, "", &mut stats);
+    Ok((merged, stats))
+}
+
+/// Merge `delta` into `base`, keeping the `kept` side's value on conflicts. With `by_time`, each
+/// id-bearing item with a timestamp on both sides decides that for itself and what it contains.
+fn merge_into(
+    base: &mut Value,
+    delta: &Value,
+    kept: Prefer,
+    by_time: bool,
+    pointer: &str,
+    stats: &mut MergeStats,
+) {
+    match (base, delta) {
+        (Value::Object(base), Value::Object(delta)) => {
+            for (key, value) in delta {
+                match base.get_mut(key) {
+                    Some(existing) => {
+                        let pointer = format!("{pointer}/{}", escape_pointer(key));
+                        merge_into(existing, value, kept, by_time, &pointer, stats);
+                    }
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                        stats.added += 1;
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(delta))
+            if base
+                .iter()
+                .chain(delta)
+                .all(|item| item.get("id").is_some()) =>
+        {
+            let known: HashSet<String> = base.iter().map(|item| item["id"].to_string()).collect();
+            let new: Vec<&Value> = delta
+                .iter()
+                .filter(|item| !known.contains(&item["id"].to_string()))
+                .collect();
+            stats.added += new.len();
+            base.extend(new.into_iter().cloned());
+            // Overlapping exports may add older messages; keep history in order.
+            if pointer.ends_with("/messages") && base.len() > known.len() {
+                base.sort_by_cached_key(|item| snowflake_key(&item["id"]));
+            }
+            let index: HashMap<String, usize> = base
+                .iter()
+                .enumerate()
+                .map(|(i, item)| (item["id"].to_string(), i))
+                .collect();
+            for item in delta
+                .iter()
+                .filter(|item| known.contains(&item["id"].to_string()))
+            {
+                let i = index[&item["id"].to_string()];
+                let kept = if by_time {
+                    newer(&base[i], item).unwrap_or(kept)
+                } else {
+                    kept
+                };
+                let pointer = format!("{pointer}/{i}");
+                merge_into(&mut base[i], item, kept, by_time, &pointer, stats);
+            }
+        }
+        (base, delta) => {
+            if base != delta {
+                stats.conflicts += 1;
+                stats.resolved.push(Conflict {
+                    pointer: pointer.to_string(),
+                    kept,
+                });
+                if kept == Prefer::Delta {
+                    *base = delta.clone();
+                }
+            }
+        }
+    }
+}
+
+/// The side whose item was written or last edited later, if both carry a timestamp and they
+/// differ.
+fn newer(base: &Value, delta: &Value) -> Option<Prefer> {
+    let stamp = |item: &Value| {
+        let text = item
+            .get("edited_timestamp")
+            .and_then(Value::as_str)
+            .or_else(|| item.get("timestamp").and_then(Value::as_str))?;
+        timestamp::instant(text)
+    };
+    match stamp(base)?.cmp(&stamp(delta)?) {
+        std::cmp::Ordering::Greater => Some(Prefer::Base),
+        std::cmp::Ordering::Less => Some(Prefer::Delta),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// `key` as a JSON pointer token.
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn list<'a>(document: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    document[key].as_array().into_iter().flatten()
+}
+
+/// Copy of `object` with only `keys`.
+fn pick(object: &Value, keys: &[&str]) -> Value {
+    keys.iter()
+        .filter_map(|key| Some((key.to_string(), object.get(key)?.clone())))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn strip_runtime_keys(value: &mut Value) -> usize {
+    match value {
+        Value::Object(obj) => {
+            let before = obj.len();
+            obj.retain(|key, _| !RUNTIME_OBJECT_KEYS.contains(&key.as_str()));
+            before - obj.len() + obj.values_mut().map(strip_runtime_keys).sum::<usize>()
+        }
+        Value::Array(items) => items.iter_mut().map(strip_runtime_keys).sum(),
+        _ => 0,
+    }
+}
+
+fn json_error_at(text: &str, err: &serde_json::Error) -> CliError {
+    let (line, column) = (err.line(), err.column());
+    let full = err.to_string();
+    let msg = full
+        .strip_suffix(&format!(" at line {line} column {column}"))
+        .unwrap_or(&full)
+        .to_string();
+    let snippet = text
+        .lines()
+        .nth(line.saturating_sub(1))
+        .map(|text| excerpt(text, column))
+        .unwrap_or_default();
+    CliError::JsonAt {
+        line,
+        column,
+        msg,
+        snippet,
+    }
+}
+
+/// Up to ~60 characters of `line` around `column` (1-based), with a caret under it.
+fn excerpt(line: &str, column: usize) -> String {
+    const CONTEXT: usize = 30;
+    let chars: Vec<char> = line.chars().collect();
+    let at = column.saturating_sub(1).min(chars.len());
+    let start = at.saturating_sub(CONTEXT);
+    let end = (at + CONTEXT).min(chars.len());
+    let text: String = chars[start..end].iter().collect();
+    format!("{text}\n{:>width$}", "^", width = at - start + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_appends_by_id_and_resolves_conflicts() {
+        let base = serde_json::json!({
+            "format": "dump",
+            "channels": [{ "id": "1", "name": "general", "messages": [{ "id": "10" }] }],
+        });
+        let delta = serde_json::json!({
+            "format": "dump",
+            "partial": true,
+            "channels": [
+                { "id": "1", "name": "lobby", "messages": [{ "id": "11" }] },
+                { "id": "2", "name": "dev" },
+            ],
+        });
+
+        let (merged, stats) = merge(&base, &delta, Prefer::Delta).unwrap();
+        assert_eq!(merged["channels"][0]["name"], "lobby");
+        assert_eq!(
+            merged["channels"][0]["messages"].as_array().unwrap().len(),
+            2
+        );
+        assert_eq!(merged["channels"][1]["id"], "2");
+        assert!(merged.get("partial").is_none());
+        assert_eq!((stats.added, stats.conflicts), (2, 1));
+
+        let (merged, _) = merge(&base, &delta, Prefer::Base).unwrap();
+        assert_eq!(merged["channels"][0]["name"], "general");
+
+        let upload = serde_json::json!({ "format": "upload" });
+        assert!(matches!(
+            merge(&base, &upload, Prefer::Delta),
+            Err(CliError::FormatMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn merge_keeps_newer_edits_and_orders_messages() {
+        let message = |id: &str, content: &str, edited: &str| {
+            serde_json::json!({ "id": id, "content": content, "timestamp": "2025-01-01T00:00:00+00:00",
+                                "edited_timestamp": edited })
+        };
+        let older = serde_json::json!({
+            "format": "dump",
+            "channels": [{ "id": "1", "messages": [
+                message("10", "v2", "2025-01-03T00:00:00+00:00"),
+                message("11", "v1", "2025-01-01T00:00:00+00:00"),
+            ]}],
+        });
+        let newer = serde_json::json!({
+            "format": "dump",
+            "channels": [{ "id": "1", "messages": [
+                message("9", "first", "2025-01-01T00:00:00+00:00"),
+                message("10", "v1", "2025-01-02T00:00:00+00:00"),
+                message("11", "v2", "2025-01-04T00:00:00+00:00"),
+            ]}],
+        });
+
+        let (merged, stats) = merge(&older, &newer, Prefer::Newer).unwrap();
+        let messages = &merged["channels"][0]["messages"];
+        let contents: Vec<&str> = (0..3)
+            .map(|i| messages[i]["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["first", "v2", "v2"]);
+        let kept: Vec<(&str, Prefer)> = stats
+            .resolved
+            .iter()
+            .map(|c| (c.pointer.as_str(), c.kept))
+            .filter(|(pointer, _)| pointer.ends_with("/content"))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("/channels/0/messages/1/content", Prefer::Base),
+                ("/channels/0/messages/2/content", Prefer::Delta),
+            ]
+        );
+    }
+
+    #[test]
+    fn header_problems_are_reported_together_with_codes() {
+        let document = serde_json::json!({ "format": "nope", "version": 0 });
+        let Err(CliError::Violations(violations)) =
+            validate_value(&document, None, &FormatsConfig::default())
+        else {
+            panic!("expected violations");
+        };
+        let found: Vec<(&str, &str)> = violations
+            .iter()
+            .map(|v| (v.pointer.as_str(), v.code.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("/format", "unknown-format"),
+                ("/guild", "missing-field"),
+                ("/version", "invalid-version"),
+            ]
+        );
+    }
+
+    #[test]
+    fn required_keys_and_expected_versions_are_reported_with_their_data() {
+        let dir = std::env::temp_dir().join(format!("guildsync-expect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.json");
+        let document =
+            serde_json::json!({ "format": "dump", "version": 1, "guild": { "id": "1" } });
+        write_document(&path, &document).unwrap();
+        let args = ValidateArgs {
+            r#in: path,
+            format: None,
+            required: vec!["team".to_string(), "guild".to_string()],
+            expect_version: Some(2),
+        };
+        let err = validate_format(&args, &FormatsConfig::default()).unwrap_err();
+        let data = err.data().unwrap();
+        assert_eq!(data["missing"], serde_json::json!(["team"]));
+        assert_eq!((&data["expected"], &data["found"]), (&2.into(), &1.into()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn threads_must_match_their_forum() {
+        let mut dump = serde_json::json!({
+            "format": "dump",
+            "version": 1,
+            "guild": { "id": "1" },
+            "channels": [{ "id": "20", "type": 15, "available_tags": [{ "id": "7", "name": "bug" }] }],
+            "threads": [{ "id": "30", "type": 11, "parent_id": "20", "applied_tags": ["7"] }],
+        });
+        let formats = FormatsConfig::default();
+        assert!(validate_value(&dump, None, &formats).is_ok());
+
+        dump["threads"][0]["applied_tags"] = serde_json::json!(["8"]);
+        assert!(validate_value(&dump, None, &formats).is_err());
+        dump["threads"][0]["applied_tags"] = serde_json::json!([]);
+        dump["threads"][0]["parent_id"] = "21".into();
+        assert!(validate_value(&dump, None, &formats).is_err());
+    }
+
+    #[test]
+    fn external_events_need_a_location_and_an_end() {
+        let mut dump = serde_json::json!({
+            "format": "dump",
+            "version": 1,
+            "guild": { "id": "1" },
+            "scheduled_events": [{
+                "id": "40",
+                "name": "meetup",
+                "entity_type": 3,
+                "scheduled_start_time": "2025-03-01T18:00:00+00:00",
+                "scheduled_end_time": "2025-03-01T20:00:00+00:00",
+                "entity_metadata": { "location": "Berlin" },
+                "recurrence_rule": { "frequency": 2, "interval": 1, "by_weekday": [5] },
+            }],
+        });
+        let formats = FormatsConfig::default();
+        assert!(validate_value(&dump, None, &formats).is_ok());
+
+        dump["scheduled_events"][0]["scheduled_end_time"] = Value::Null;
+        assert!(validate_value(&dump, None, &formats).is_err());
+        dump["scheduled_events"][0]["entity_type"] = 2.into();
+        assert!(validate_value(&dump, None, &formats).is_err());
+        dump["scheduled_events"][0]["channel_id"] = "20".into();
+        assert!(validate_value(&dump, None, &formats).is_ok());
+    }
+
+    #[test]
+    fn dumps_convert_to_upload_files_and_back() {
+        let dir = std::env::temp_dir().join(format!("guildsync-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (dump, upload, back) = (
+            dir.join("dump.json"),
+            dir.join("upload.json"),
+            dir.join("back.json"),
+        );
+        let document = serde_json::json!({
+            "format": "dump",
+            "version": 1,
+            "exported_at": "2025-03-01T18:00:00Z",
+            "guild": { "id": "1", "approximate_member_count": 12 },
+            "channels": [{ "id": "20", "type": 0, "last_message_id": "99",
+                           "messages": [{ "id": "98" }, { "id": "99" }] }],
+        });
+        write_document(&dump, &document).unwrap();
+        let formats = FormatsConfig::default();
+        let convert_to = |r#in: &Path, to, out: &Path| {
+            let (r#in, out) = (r#in.to_path_buf(), out.to_path_buf());
+            convert(&ConvertArgs { r#in, to, out }, &formats)
+        };
+
+        let converted = convert_to(&dump, GuildFormat::Upload, &upload).unwrap();
+        assert_eq!(
+            (converted.dropped_messages, converted.dropped_fields),
+            (2, 3)
+        );
+        let converted = convert_to(&upload, GuildFormat::Dump, &back).unwrap();
+        assert_eq!(
+            (converted.dropped_messages, converted.dropped_fields),
+            (0, 0)
+        );
+        let (uploaded, restored) = (
+            read_document(&upload).unwrap(),
+            read_document(&back).unwrap(),
+        );
+        assert_eq!(uploaded["format"], "upload");
+        assert_eq!(restored["format"], "dump");
+        assert_eq!(uploaded["channels"], restored["channels"]);
+        assert_eq!(
+            restored["channels"][0],
+            serde_json::json!({ "id": "20", "type": 0 })
+        );
+        assert!(matches!(
+            convert_to(&dump, GuildFormat::Dump, &back),
+            Err(CliError::Usage(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
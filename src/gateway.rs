@@ -0,0 +1,365 @@
+//! Discord gateway listener for `discord watch` and `terminal send`.
+//!
+//! The bot connects to the gateway, identifies with the guild, member, and message intents, and
+//! hands the message and member events of one guild to a [`Sink`] as they arrive: `discord
+//! watch` appends them to an NDJSON file. Dropped
+//! connections are resumed (or, when Discord invalidates the session, identified again) so a
+//! long-running watch only stops on Ctrl-C or a fatal error.
+
+use std::path::Path;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::discord::{Client, text};
+use crate::error::CliError;
+use crate::retry;
+use crate::timestamp;
+
+/// Gateway intents: `GUILDS`, `GUILD_MEMBERS`, `GUILD_MESSAGES`, and `MESSAGE_CONTENT`. The
+/// second and last are privileged and must be enabled for the bot in the developer portal.
+const INTENTS: u64 = 1 | 1 << 1 | 1 << 9 | 1 << 15;
+
+/// Dispatch events appended to the output.
+const WATCHED_EVENTS: &[&str] = &[
+    "MESSAGE_CREATE",
+    "MESSAGE_UPDATE",
+    "MESSAGE_DELETE",
+    "MESSAGE_DELETE_BULK",
+    "GUILD_MEMBER_ADD",
+    "GUILD_MEMBER_UPDATE",
+    "GUILD_MEMBER_REMOVE",
+];
+
+/// Gateway opcodes, per the Discord API documentation.
+const DISPATCH: u64 = 0;
+const HEARTBEAT: u64 = 1;
+const IDENTIFY: u64 = 2;
+const RESUME: u64 = 6;
+const RECONNECT: u64 = 7;
+const INVALID_SESSION: u64 = 9;
+const HELLO: u64 = 10;
+const HEARTBEAT_ACK: u64 = 11;
+
+/// Close codes after which reconnecting cannot help.
+const AUTHENTICATION_FAILED: u16 = 4004;
+const DISALLOWED_INTENTS: u16 = 4014;
+
+/// Close codes after which the session cannot be resumed: invalid sequence and session timeout.
+const SESSION_LOST: &[u16] = &[4007, 4009];
+
+/// Counts reported when [`listen`] stops.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// Events the sink took (lines appended, for `discord watch`).
+    pub events: u64,
+    /// Connections made after the first.
+    pub reconnects: u64,
+}
+
+/// Session to resume after a dropped connection.
+struct Session {
+    id: String,
+    url: String,
+    seq: Option<u64>,
+}
+
+/// Why one connection ended.
+enum Ended {
+    /// `stop` completed.
+    Stopped,
+    /// The connection dropped or Discord asked for a reconnect; `resume` says whether the
+    /// session survives.
+    Dropped { reason: String, resume: bool },
+}
+
+/// Receives the watched events of a guild, as `{ "seq", "type", "received_at", "data" }` records.
+pub trait Sink {
+    /// Handle one event record; `false` if it was skipped (not counted in [`Stats::events`]).
+    fn deliver(&mut self, record: &Value) -> impl Future<Output = Result<bool, CliError>>;
+}
+
+/// Appends each record to an NDJSON file.
+struct FileSink(tokio::fs::File);
+
+impl Sink for FileSink {
+    async fn deliver(&mut self, record: &Value) -> Result<bool, CliError> {
+        self.0.write_all(format!("{record}\n").as_bytes()).await?;
+        self.0.flush().await?;
+        Ok(true)
+    }
+}
+
+/// Append the watched events of `guild` to `out` (created if missing) until `stop` completes.
+pub async fn watch(
+    client: &Client,
+    guild: u64,
+    out: &Path,
+    stop: impl Future<Output = ()>,
+) -> Result<Stats, CliError> {
+    let file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(out)
+        .await?;
+    listen(client, guild, &mut FileSink(file), stop).await
+}
+
+/// Hand the watched events of `guild` to `sink` until `stop` completes.
+///
+/// Reconnects back off per the client's retry policy; once that many connections in a row fail
+/// before the gateway accepts the session, the last failure is returned.
+pub async fn listen<S: Sink>(
+    client: &Client,
+    guild: u64,
+    sink: &mut S,
+    stop: impl Future<Output = ()>,
+) -> Result<Stats, CliError> {
+    let gateway = client.get("/gateway/bot").await?;
+    let Some(base) = gateway["url"].as_str() else {
+        return Err(CliError::Network(
+            "GET /gateway/bot: response has no `url`".to_string(),
+        ));
+    };
+    let policy = client.policy();
+    let mut stats = Stats::default();
+    let mut session: Option<Session> = None;
+    let mut failures = 0;
+    tokio::pin!(stop);
+    loop {
+        let url = session.as_ref().map_or(base, |s| s.url.as_str());
+        let url = format!("{}/?v=10&encoding=json", url.trim_end_matches('/'));
+        let connected = tokio::select! {
+            ended = connection(client, guild, &url, &mut session, sink, &mut stats, &mut failures) => ended,
+            () = &mut stop => Ok(Ended::Stopped),
+        };
+        let (reason, resume) = match connected {
+            Ok(Ended::Stopped) => break,
+            Ok(Ended::Dropped { reason, resume }) => (reason, resume),
+            Err(err) if err.is_retryable() => (err.to_string(), true),
+            Err(err) => return Err(err),
+        };
+        if !resume {
+            session = None;
+        }
+        if failures >= policy.max_retries.max(1) {
+            return Err(CliError::Network(format!("gateway: {reason}")));
+        }
+        let delay = policy.backoff(failures);
+        failures += 1;
+        tracing::warn!("gateway: {reason}; reconnecting in {}ms", delay.as_millis());
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = &mut stop => break,
+        }
+        stats.reconnects += 1;
+    }
+    Ok(stats)
+}
+
+/// Run one gateway connection: identify (or resume `session`), heartbeat, and deliver events
+/// until it ends. `failures` is reset once the gateway accepts the session.
+async fn connection<S: Sink>(
+    client: &Client,
+    guild: u64,
+    url: &str,
+    session: &mut Option<Session>,
+    sink: &mut S,
+    stats: &mut Stats,
+    failures: &mut u32,
+) -> Result<Ended, CliError> {
+    let network =
+        |e: tokio_tungstenite::tungstenite::Error| CliError::Network(format!("gateway: {e}"));
+    let (mut socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(network)?;
+
+    let hello = match next_payload(&mut socket).await? {
+        Ok(payload) if payload["op"] == HELLO => payload,
+        Ok(_) => return Ok(dropped("no Hello from the gateway", true)),
+        Err(ended) => return Ok(ended),
+    };
+    let interval =
+        Duration::from_millis(hello["d"]["heartbeat_interval"].as_u64().unwrap_or(41_250));
+    let greeting = match session {
+        Some(s) => serde_json::json!({ "op": RESUME, "d": {
+            "token": client.token(), "session_id": s.id, "seq": s.seq,
+        }}),
+        None => identify(client.token()),
+    };
+    socket
+        .send(Message::text(greeting.to_string()))
+        .await
+        .map_err(network)?;
+
+    // The first heartbeat is jittered so that many clients do not beat in step.
+    let mut beat = tokio::time::interval_at(
+        tokio::time::Instant::now() + retry::jitter(interval),
+        interval,
+    );
+    let mut acknowledged = true;
+    loop {
+        let payload = tokio::select! {
+            _ = beat.tick() => {
+                if !acknowledged {
+                    return Ok(dropped("heartbeat not acknowledged", true));
+                }
+                acknowledged = false;
+                socket.send(heartbeat(session.as_ref())).await.map_err(network)?;
+                continue;
+            }
+            payload = next_payload(&mut socket) => payload?,
+        };
+        let payload = match payload {
+            Ok(payload) => payload,
+            Err(ended) => return Ok(ended),
+        };
+        match payload["op"].as_u64() {
+            Some(HEARTBEAT_ACK) => acknowledged = true,
+            // The gateway may ask for a heartbeat out of turn.
+            Some(HEARTBEAT) => {
+                socket
+                    .send(heartbeat(session.as_ref()))
+                    .await
+                    .map_err(network)?;
+            }
+            Some(RECONNECT) => return Ok(dropped("gateway asked to reconnect", true)),
+            Some(INVALID_SESSION) => {
+                let resume = payload["d"] == true && session.is_some();
+                return Ok(dropped("session invalidated", resume));
+            }
+            Some(DISPATCH) => {
+                let seq = payload["s"].as_u64();
+                match payload["t"].as_str() {
+                    Some("READY") => {
+                        let ready = &payload["d"];
+                        *session = Some(Session {
+                            id: text(&ready["session_id"]),
+                            url: ready["resume_gateway_url"]
+                                .as_str()
+                                .unwrap_or(url)
+                                .to_string(),
+                            seq,
+                        });
+                        *failures = 0;
+                        tracing::info!(
+                            "gateway: connected as {}",
+                            text(&ready["user"]["username"])
+                        );
+                    }
+                    Some("RESUMED") => {
+                        *failures = 0;
+                        tracing::info!("gateway: session resumed");
+                    }
+                    _ => {
+                        if let Some(record) = record(&payload, guild)
+                            && sink.deliver(&record).await?
+                        {
+                            stats.events += 1;
+                        }
+                    }
+                }
+                if let (Some(s), Some(seq)) = (session.as_mut(), seq) {
+                    s.seq = Some(seq);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Next JSON payload from the gateway, or how the connection ended if it closed. Close codes
+/// that reconnecting cannot fix are errors.
+async fn next_payload<S>(socket: &mut S) -> Result<Result<Value, Ended>, CliError>
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    while let Some(message) = socket.next().await {
+        let message = message.map_err(|e| CliError::Network(format!("gateway: {e}")))?;
+        match message {
+            Message::Text(text) => return Ok(Ok(serde_json::from_str(text.as_str())?)),
+            Message::Close(frame) => {
+                let code = frame.as_ref().map(|f| u16::from(f.code));
+                return match code {
+                    Some(AUTHENTICATION_FAILED) => Err(CliError::Auth(
+                        "gateway: authentication failed; check the bot token".to_string(),
+                    )),
+                    Some(DISALLOWED_INTENTS) => Err(CliError::Auth(
+                        "gateway: disallowed intents; enable the Server Members and Message \
+                         Content intents for the bot in the developer portal"
+                            .to_string(),
+                    )),
+                    Some(code) if SESSION_LOST.contains(&code) => {
+                        Ok(Err(dropped(&format!("session lost ({code})"), false)))
+                    }
+                    Some(code) => Ok(Err(dropped(&format!("connection closed ({code})"), true))),
+                    None => Ok(Err(dropped("connection closed", true))),
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok(Err(dropped("connection closed", true)))
+}
+
+/// The Identify payload for a bot `token`.
+fn identify(token: &str) -> Value {
+    serde_json::json!({ "op": IDENTIFY, "d": {
+        "token": token,
+        "intents": INTENTS,
+        "properties": {
+            "os": std::env::consts::OS,
+            "browser": "guildsync",
+            "device": "guildsync",
+        },
+    }})
+}
+
+/// A heartbeat carrying the last sequence number `session` received.
+fn heartbeat(session: Option<&Session>) -> Message {
+    let seq = session.and_then(|s| s.seq);
+    Message::text(serde_json::json!({ "op": HEARTBEAT, "d": seq }).to_string())
+}
+
+/// The record of a dispatch `payload`, or `None` if it is not a watched event of `guild`.
+fn record(payload: &Value, guild: u64) -> Option<Value> {
+    let event = payload["t"].as_str()?;
+    if !WATCHED_EVENTS.contains(&event) || text(&payload["d"]["guild_id"]) != guild.to_string() {
+        return None;
+    }
+    Some(serde_json::json!({
+        "seq": payload["s"],
+        "type": event,
+        "received_at": timestamp::now_rfc3339(),
+        "data": payload["d"],
+    }))
+}
+
+fn dropped(reason: &str, resume: bool) -> Ended {
+    Ended::Dropped {
+        reason: reason.to_string(),
+        resume,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_watched_events_of_the_guild_are_delivered() {
+        let dispatch = |event: &str, guild: &str| serde_json::json!({ "op": 0, "s": 42, "t": event, "d": { "guild_id": guild, "id": "9" } });
+        let created = record(&dispatch("MESSAGE_CREATE", "1"), 1).unwrap();
+        assert_eq!(created["seq"], 42);
+        assert_eq!(created["type"], "MESSAGE_CREATE");
+        assert_eq!(created["data"]["id"], "9");
+
+        assert!(record(&dispatch("MESSAGE_CREATE", "2"), 1).is_none());
+        assert!(record(&dispatch("TYPING_START", "1"), 1).is_none());
+        assert_eq!(identify("t")["d"]["intents"], 33283);
+    }
+}
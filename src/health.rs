@@ -0,0 +1,531 @@
+//! `kube remote status`: whether the guildsync workloads of a namespace are healthy, degraded,
+//! or down, judged from their Deployments, CronJobs, and Jobs, their pods' readiness and
+//! restarts, recent warning events, and optionally an HTTP health endpoint behind a Service.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::CliError;
+use crate::retry::RetryPolicy;
+use crate::{kube, timestamp};
+
+/// How far back restarts and warning events count against the workloads.
+pub const RECENT_SECS: u64 = 3600;
+
+/// Warning events listed at most.
+const MAX_EVENTS: usize = 10;
+
+/// Overall or per-object health, ordered from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    Healthy,
+    Degraded,
+    Down,
+}
+
+impl std::fmt::Display for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Health::Healthy => "healthy",
+            Health::Degraded => "degraded",
+            Health::Down => "down",
+        })
+    }
+}
+
+/// A Deployment, CronJob, or standalone Job.
+#[derive(Debug, Serialize)]
+pub struct Workload {
+    pub kind: String,
+    pub name: String,
+    pub health: Health,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PodHealth {
+    pub name: String,
+    pub phase: String,
+    pub ready: bool,
+    pub restarts: u64,
+    /// Why a container is waiting (`CrashLoopBackOff`) or why it last terminated.
+    pub reason: Option<String>,
+    /// When a container last terminated, if it restarted.
+    pub last_restart: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Event {
+    pub time: String,
+    /// `Kind/name` of the object it is about.
+    pub object: String,
+    pub reason: String,
+    pub message: String,
+    pub count: u64,
+}
+
+/// Result of asking the health endpoint.
+#[derive(Debug, Serialize)]
+pub struct Probe {
+    pub target: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RemoteStatus {
+    pub health: Health,
+    /// Why it is not healthy.
+    pub reasons: Vec<String>,
+    pub workloads: Vec<Workload>,
+    pub pods: Vec<PodHealth>,
+    /// Warning events of the last hour about the workloads or their pods, newest first.
+    pub events: Vec<Event>,
+    pub probe: Option<Probe>,
+}
+
+impl RemoteStatus {
+    /// Human-readable report: workloads, pods, recent events, and the probe.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for w in &self.workloads {
+            out.push_str(&format!(
+                "{}/{}\t{}\t{}\n",
+                w.kind, w.name, w.health, w.detail
+            ));
+        }
+        for pod in &self.pods {
+            let mut line = format!(
+                "pod {}\t{}\t{}\trestarts {}",
+                pod.name,
+                pod.phase,
+                if pod.ready { "ready" } else { "not ready" },
+                pod.restarts
+            );
+            match (&pod.last_restart, &pod.reason) {
+                (Some(at), Some(reason)) => line.push_str(&format!(" (last {at}: {reason})")),
+                (None, Some(reason)) => line.push_str(&format!(" ({reason})")),
+                (Some(at), None) => line.push_str(&format!(" (last {at})")),
+                (None, None) => {}
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        if !self.events.is_empty() {
+            out.push_str("warning events (last hour):\n");
+            for e in &self.events {
+                out.push_str(&format!(
+                    "  {} {} {}: {} (x{})\n",
+                    e.time, e.object, e.reason, e.message, e.count
+                ));
+            }
+        }
+        if let Some(probe) = &self.probe {
+            let verdict = if probe.ok { "ok" } else { "failing" };
+            out.push_str(&format!(
+                "health endpoint {}: {verdict} ({})\n",
+                probe.target, probe.detail
+            ));
+        }
+        out
+    }
+}
+
+/// An HTTP health endpoint served behind a Service, reached through the API server's proxy.
+#[derive(Debug)]
+pub struct ProbeTarget<'a> {
+    pub service: &'a str,
+    pub port: u16,
+    /// Path including the leading `/`, like `/healthz`.
+    pub path: &'a str,
+}
+
+/// Fetch and [`assess`] the workloads matching `selector` in `namespace`, asking `probe` too if
+/// given. `None` if nothing matches.
+pub async fn remote_status(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: &str,
+    selector: &str,
+    probe: Option<&ProbeTarget<'_>>,
+    policy: &RetryPolicy,
+) -> Result<Option<RemoteStatus>, CliError> {
+    let objects: Value = serde_json::from_str(
+        &kube::kubectl_read(
+            policy,
+            kubeconfigs,
+            Some(context),
+            &[
+                "-n",
+                namespace,
+                "get",
+                "deployments,cronjobs,jobs,pods",
+                "-l",
+                selector,
+                "-o",
+                "json",
+            ],
+        )
+        .await?,
+    )?;
+    if objects["items"].as_array().is_none_or(Vec::is_empty) {
+        return Ok(None);
+    }
+    let events: Value = serde_json::from_str(
+        &kube::kubectl_read(
+            policy,
+            kubeconfigs,
+            Some(context),
+            &["-n", namespace, "get", "events", "-o", "json"],
+        )
+        .await?,
+    )?;
+    let probe = match probe {
+        Some(target) => {
+            let url = format!(
+                "/api/v1/namespaces/{namespace}/services/{}:{}/proxy{}",
+                target.service, target.port, target.path
+            );
+            let args = ["--request-timeout=10s", "get", "--raw", &url];
+            let output = kube::kubectl_output(kubeconfigs, Some(context), &args, None).await?;
+            let ok = output.status.success();
+            let detail = if ok {
+                summary_line(&output.stdout, "empty response")
+            } else {
+                summary_line(&output.stderr, "request failed")
+            };
+            Some(Probe {
+                target: format!("service/{}:{}{}", target.service, target.port, target.path),
+                ok,
+                detail,
+            })
+        }
+        None => None,
+    };
+    Ok(Some(assess(&objects, &events, probe, timestamp::now())))
+}
+
+/// Judge the objects of a `kubectl get deployments,cronjobs,jobs,pods -o json` listing, the
+/// namespace's `kubectl get events -o json`, and the probe, as of `now` (Unix seconds).
+///
+/// Down: a Deployment has no ready replica. Degraded: a Deployment is short of replicas or
+/// stuck rolling out, a scheduled or standalone export Job failed, a CronJob is suspended, a
+/// pod restarted or logged a warning event in the last hour, or the probe failed.
+pub fn assess(objects: &Value, events: &Value, probe: Option<Probe>, now: u64) -> RemoteStatus {
+    let items: Vec<&Value> = objects["items"].as_array().into_iter().flatten().collect();
+    let of_kind = |kind: &str| -> Vec<&Value> {
+        items
+            .iter()
+            .copied()
+            .filter(|item| item["kind"] == kind)
+            .collect()
+    };
+    let recent = |time: &str| {
+        timestamp::instant(time).is_some_and(|(secs, _)| now.saturating_sub(secs) <= RECENT_SECS)
+    };
+    let mut reasons = Vec::new();
+    let mut workloads = Vec::new();
+
+    for deployment in of_kind("Deployment") {
+        let count = |value: &Value| value.as_u64().unwrap_or(0);
+        let desired = deployment["spec"]["replicas"].as_u64().unwrap_or(1);
+        let ready = count(&deployment["status"]["readyReplicas"]);
+        let stalled = deployment["status"]["conditions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|c| c["type"] == "Progressing" && c["reason"] == "ProgressDeadlineExceeded");
+        let health = if desired > 0 && ready == 0 {
+            Health::Down
+        } else if ready < desired || stalled {
+            Health::Degraded
+        } else {
+            Health::Healthy
+        };
+        let mut detail = format!("{ready}/{desired} ready");
+        if stalled {
+            detail.push_str(", rollout stalled");
+        }
+        workloads.push(workload(deployment, health, detail));
+    }
+
+    let jobs = of_kind("Job");
+    let owner = |job: &Value| -> Option<String> {
+        job["metadata"]["ownerReferences"]
+            .as_array()?
+            .iter()
+            .find(|r| r["kind"] == "CronJob")
+            .and_then(|r| r["name"].as_str())
+            .map(str::to_string)
+    };
+    for cronjob in of_kind("CronJob") {
+        let name = cronjob["metadata"]["name"].as_str().unwrap_or_default();
+        let mut runs: Vec<&Value> = jobs
+            .iter()
+            .copied()
+            .filter(|job| owner(job).as_deref() == Some(name))
+            .collect();
+        runs.sort_by_key(|job| created(job));
+        let last_success = cronjob["status"]["lastSuccessfulTime"].as_str();
+        let (health, mut detail) = match runs.last().map(|job| job_state(job)) {
+            Some(JobState::Failed) => (Health::Degraded, "last run failed".to_string()),
+            Some(JobState::Running) => (Health::Healthy, "running".to_string()),
+            Some(JobState::Complete) | None => (Health::Healthy, String::new()),
+        };
+        let mut health = health;
+        if cronjob["spec"]["suspend"] == true {
+            health = health.max(Health::Degraded);
+            detail = join(&detail, "suspended");
+        }
+        detail = join(
+            &detail,
+            &match last_success {
+                Some(time) => format!("last success {time}"),
+                None => "no successful run yet".to_string(),
+            },
+        );
+        workloads.push(workload(cronjob, health, detail));
+    }
+    for job in jobs.iter().filter(|job| owner(job).is_none()) {
+        let (health, detail) = match job_state(job) {
+            JobState::Failed => (Health::Degraded, "failed"),
+            JobState::Running => (Health::Healthy, "running"),
+            JobState::Complete => (Health::Healthy, "complete"),
+        };
+        workloads.push(workload(job, health, detail.to_string()));
+    }
+    for w in &workloads {
+        if w.health > Health::Healthy {
+            reasons.push(format!("{}/{}: {}", w.kind, w.name, w.detail));
+        }
+    }
+
+    let mut pods = Vec::new();
+    for pod in of_kind("Pod") {
+        let statuses: Vec<&Value> = pod["status"]["containerStatuses"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .collect();
+        let restarts = statuses
+            .iter()
+            .map(|c| c["restartCount"].as_u64().unwrap_or(0))
+            .sum();
+        let last_restart = statuses
+            .iter()
+            .filter_map(|c| c["lastState"]["terminated"]["finishedAt"].as_str())
+            .max_by_key(|time| timestamp::instant(time))
+            .map(str::to_string);
+        let reason = statuses
+            .iter()
+            .find_map(|c| c["state"]["waiting"]["reason"].as_str())
+            .or_else(|| {
+                statuses
+                    .iter()
+                    .find_map(|c| c["lastState"]["terminated"]["reason"].as_str())
+            })
+            .map(str::to_string);
+        let ready = !statuses.is_empty() && statuses.iter().all(|c| c["ready"] == true);
+        let pod = PodHealth {
+            name: pod["metadata"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            phase: pod["status"]["phase"]
+                .as_str()
+                .unwrap_or("Unknown")
+                .to_string(),
+            ready,
+            restarts,
+            reason,
+            last_restart,
+        };
+        if pod.last_restart.as_deref().is_some_and(recent) {
+            reasons.push(format!(
+                "pod {} restarted at {}",
+                pod.name,
+                pod.last_restart.as_deref().unwrap_or_default()
+            ));
+        }
+        pods.push(pod);
+    }
+
+    let names: Vec<&str> = items
+        .iter()
+        .filter_map(|item| item["metadata"]["name"].as_str())
+        .collect();
+    let mut warnings: Vec<Event> = events["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|e| e["type"] == "Warning")
+        .filter(|e| {
+            e["involvedObject"]["name"]
+                .as_str()
+                .is_some_and(|name| names.contains(&name))
+        })
+        .filter_map(|e| {
+            let time = ["lastTimestamp", "eventTime"]
+                .iter()
+                .find_map(|key| e[key].as_str())
+                .or(e["metadata"]["creationTimestamp"].as_str())?;
+            recent(time).then(|| Event {
+                time: time.to_string(),
+                object: format!(
+                    "{}/{}",
+                    e["involvedObject"]["kind"].as_str().unwrap_or_default(),
+                    e["involvedObject"]["name"].as_str().unwrap_or_default()
+                ),
+                reason: e["reason"].as_str().unwrap_or_default().to_string(),
+                message: e["message"].as_str().unwrap_or_default().trim().to_string(),
+                count: e["count"].as_u64().unwrap_or(1),
+            })
+        })
+        .collect();
+    warnings.sort_by_key(|e| std::cmp::Reverse(timestamp::instant(&e.time)));
+    if !warnings.is_empty() {
+        reasons.push(format!(
+            "{} warning event(s) in the last hour",
+            warnings.len()
+        ));
+    }
+    warnings.truncate(MAX_EVENTS);
+
+    if let Some(probe) = probe.as_ref().filter(|p| !p.ok) {
+        reasons.push(format!(
+            "health endpoint {}: {}",
+            probe.target, probe.detail
+        ));
+    }
+
+    let worst = workloads
+        .iter()
+        .map(|w| w.health)
+        .max()
+        .unwrap_or(Health::Healthy);
+    let health = if worst == Health::Down {
+        Health::Down
+    } else if reasons.is_empty() {
+        Health::Healthy
+    } else {
+        Health::Degraded
+    };
+    RemoteStatus {
+        health,
+        reasons,
+        workloads,
+        pods,
+        events: warnings,
+        probe,
+    }
+}
+
+enum JobState {
+    Running,
+    Complete,
+    Failed,
+}
+
+fn job_state(job: &Value) -> JobState {
+    let condition = |kind: &str| {
+        job["status"]["conditions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|c| c["type"] == kind && c["status"] == "True")
+    };
+    if condition("Failed") {
+        JobState::Failed
+    } else if condition("Complete") {
+        JobState::Complete
+    } else {
+        JobState::Running
+    }
+}
+
+fn created(object: &Value) -> Option<(u64, u32)> {
+    timestamp::instant(object["metadata"]["creationTimestamp"].as_str()?)
+}
+
+fn workload(object: &Value, health: Health, detail: String) -> Workload {
+    Workload {
+        kind: object["kind"].as_str().unwrap_or_default().to_string(),
+        name: object["metadata"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        health,
+        detail,
+    }
+}
+
+/// Last line of `output` (kubectl puts the reason last), shortened for a report.
+fn summary_line(output: &[u8], fallback: &str) -> String {
+    let text = String::from_utf8_lossy(output);
+    let line = text.trim().lines().last().unwrap_or(fallback);
+    line.chars().take(120).collect()
+}
+
+fn join(a: &str, b: &str) -> String {
+    if a.is_empty() {
+        b.to_string()
+    } else {
+        format!("{a}, {b}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn judges_workloads_restarts_and_events() {
+        let now = timestamp::parse("2025-06-01T12:00:00Z").unwrap();
+        let objects = serde_json::json!({ "items": [
+            { "kind": "Deployment", "metadata": { "name": "guildsync" },
+              "spec": { "replicas": 1 }, "status": { "readyReplicas": 1 } },
+            { "kind": "CronJob", "metadata": { "name": "export" },
+              "spec": {}, "status": { "lastSuccessfulTime": "2025-05-31T03:00:00Z" } },
+            { "kind": "Job", "metadata": { "name": "export-1",
+                "creationTimestamp": "2025-06-01T03:00:00Z",
+                "ownerReferences": [{ "kind": "CronJob", "name": "export" }] },
+              "status": { "conditions": [{ "type": "Failed", "status": "True" }] } },
+            { "kind": "Pod", "metadata": { "name": "guildsync-abc" },
+              "status": { "phase": "Running", "containerStatuses": [{
+                "ready": true, "restartCount": 2, "state": { "running": {} },
+                "lastState": { "terminated": { "reason": "Error",
+                  "finishedAt": "2025-06-01T11:30:00Z" } } }] } },
+        ]});
+        let events = serde_json::json!({ "items": [
+            { "type": "Warning", "reason": "BackOff", "message": "Back-off restarting",
+              "count": 3, "lastTimestamp": "2025-06-01T11:31:00Z",
+              "involvedObject": { "kind": "Pod", "name": "guildsync-abc" } },
+            { "type": "Warning", "reason": "Old", "lastTimestamp": "2025-05-01T00:00:00Z",
+              "involvedObject": { "kind": "Pod", "name": "guildsync-abc" } },
+            { "type": "Warning", "reason": "Other", "lastTimestamp": "2025-06-01T11:59:00Z",
+              "involvedObject": { "kind": "Pod", "name": "unrelated" } },
+        ]});
+        let status = assess(&objects, &events, None, now);
+        assert_eq!(status.health, Health::Degraded);
+        assert_eq!(status.workloads[0].health, Health::Healthy);
+        assert_eq!(status.workloads[1].health, Health::Degraded);
+        assert_eq!(status.pods[0].restarts, 2);
+        assert_eq!(status.events.len(), 1);
+        assert_eq!(status.reasons.len(), 3);
+
+        let down = serde_json::json!({ "items": [
+            { "kind": "Deployment", "metadata": { "name": "guildsync" },
+              "spec": { "replicas": 1 }, "status": {} },
+        ]});
+        let status = assess(&down, &serde_json::json!({}), None, now);
+        assert_eq!(status.health, Health::Down);
+        assert!(
+            status
+                .report()
+                .starts_with("Deployment/guildsync\tdown\t0/1 ready\n")
+        );
+    }
+}
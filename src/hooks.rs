@@ -0,0 +1,32 @@
+use crate::error::CliError;
+
+/// Run a hook `template` through `sh -c` with `{action}` and `{status}` filled in.
+///
+/// Output is logged at `debug`; a non-zero exit becomes [`CliError::Hook`].
+pub async fn run(template: &str, action: &str, status: &str) -> Result<(), CliError> {
+    let command = template
+        .replace("{action}", action)
+        .replace("{status}", status);
+    tracing::debug!("hook: {command}");
+    let output = tokio::process::Command::new("sh")
+        .args(["-c", &command])
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| CliError::Hook(format!("{command}: {e}")))?;
+    for line in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+    {
+        tracing::debug!("hook: {line}");
+    }
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(CliError::Hook(format!(
+            "`{command}` exited with {}",
+            output.status
+        )))
+    }
+}
@@ -0,0 +1,656 @@
+//! `discord import` as plan/apply: diff the file against the live guild, then replay the
+//! resulting create/update/delete steps through the Discord API.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::assets;
+use crate::diff;
+use crate::discord::{CATEGORY_TYPE, Client, text};
+use crate::error::CliError;
+use crate::journal::{Entry, Journal};
+use crate::permissions::{self, PermissionChange};
+
+/// Guild settings that the API lets a bot change.
+const GUILD_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "icon",
+    "banner",
+    "splash",
+    "verification_level",
+    "default_message_notifications",
+    "explicit_content_filter",
+    "afk_channel_id",
+    "afk_timeout",
+    "system_channel_id",
+    "system_channel_flags",
+    "rules_channel_id",
+    "public_updates_channel_id",
+    "safety_alerts_channel_id",
+    "preferred_locale",
+    "premium_progress_bar_enabled",
+];
+
+/// Role fields sent on create/update.
+const ROLE_FIELDS: &[&str] = &[
+    "name",
+    "permissions",
+    "color",
+    "hoist",
+    "mentionable",
+    "icon",
+    "unicode_emoji",
+];
+
+/// Channel fields sent on create/update (`type` only on create).
+const CHANNEL_FIELDS: &[&str] = &[
+    "name",
+    "type",
+    "topic",
+    "position",
+    "parent_id",
+    "nsfw",
+    "bitrate",
+    "user_limit",
+    "rate_limit_per_user",
+    "permission_overwrites",
+    "default_auto_archive_duration",
+];
+
+/// Emoji fields sent on create/update (the image is added on create).
+const EMOJI_FIELDS: &[&str] = &["name", "roles"];
+
+/// Sticker fields sent on create/update (the image is uploaded as a file on create).
+const STICKER_FIELDS: &[&str] = &["name", "description", "tags"];
+
+/// Scheduled event fields sent on create/update (a cover image is added on create).
+const EVENT_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "scheduled_start_time",
+    "scheduled_end_time",
+    "privacy_level",
+    "entity_type",
+    "channel_id",
+    "entity_metadata",
+    "recurrence_rule",
+];
+
+/// Scheduled event `status` of an event that has not started; others cannot be created.
+const SCHEDULED_STATUS: u64 = 1;
+
+/// Emoji fields journaled on delete: those sent on create plus what locates the image.
+const EMOJI_RECREATE_FIELDS: &[&str] = &["name", "roles", "url", "animated"];
+
+/// Sticker fields journaled on delete, like [`EMOJI_RECREATE_FIELDS`].
+const STICKER_RECREATE_FIELDS: &[&str] = &["name", "description", "tags", "url", "format_type"];
+
+/// Scheduled event fields journaled on delete, like [`EMOJI_RECREATE_FIELDS`].
+const EVENT_RECREATE_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "scheduled_start_time",
+    "scheduled_end_time",
+    "privacy_level",
+    "entity_type",
+    "channel_id",
+    "entity_metadata",
+    "recurrence_rule",
+    "url",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Guild,
+    Role,
+    Category,
+    Channel,
+    Emoji,
+    Sticker,
+    Event,
+}
+
+/// One API call of an import plan.
+#[derive(Debug, Serialize)]
+pub struct Step {
+    pub op: Op,
+    pub kind: Kind,
+    /// Id in the import file (for creates, replaced by Discord with a fresh one).
+    pub id: String,
+    pub name: String,
+    /// Request body for creates and updates.
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub payload: Value,
+    /// Role permissions and channel overwrites the step grants or revokes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<PermissionChange>,
+    /// Live values an update overwrites, or the fields needed to recreate a deleted object;
+    /// recorded in the import journal.
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub previous: Value,
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = match self.op {
+            Op::Create => '+',
+            Op::Update => '~',
+            Op::Delete => '-',
+        };
+        let kind = match self.kind {
+            Kind::Guild => "guild",
+            Kind::Role => "role",
+            Kind::Category => "category",
+            Kind::Channel => "channel",
+            Kind::Emoji => "emoji",
+            Kind::Sticker => "sticker",
+            Kind::Event => "event",
+        };
+        write!(f, "{sign} {kind} {} ({})", self.name, self.id)
+    }
+}
+
+/// Steps that turn `current` (the live guild) into `desired` (the import file), ordered so
+/// that parents exist before children and children go before parents on deletion.
+pub fn plan(current: &Value, desired: &Value) -> Vec<Step> {
+    let desired = &adopt_everyone(current, desired);
+    // Messages, embedded images, and CDN `url`s are not compared, so they never cause updates.
+    let changes = diff::diff(current, desired);
+    let mut steps = Vec::new();
+
+    let section = |name: &str| changes.sections.get(name).cloned().unwrap_or_default();
+    let (roles, channels) = (section("roles"), section("channels"));
+    let is_category = |item: &Value| item["type"].as_u64() == Some(CATEGORY_TYPE);
+    // Overwrite targets are named after the file's roles, or the live ones it deletes.
+    let all_roles: Vec<&Value> = [desired, current]
+        .into_iter()
+        .flat_map(|doc| doc["roles"].as_array().into_iter().flatten())
+        .collect();
+
+    // Roles that Discord manages (bot and integration roles) cannot be created or deleted.
+    let managed = |item: &Value| item["managed"] == true;
+    for id in &roles.added {
+        if let Some(role) = find(desired, "roles", id).filter(|r| !managed(r)) {
+            let mut create = step(Op::Create, Kind::Role, role, ROLE_FIELDS);
+            create.permissions = permissions::role_changes(None, role);
+            steps.push(create);
+        }
+    }
+    for id in &roles.changed {
+        if let Some(role) = find(desired, "roles", id) {
+            let mut update = step(Op::Update, Kind::Role, role, ROLE_FIELDS);
+            update.permissions = permissions::role_changes(find(current, "roles", id), role);
+            steps.push(update);
+        }
+    }
+
+    for categories in [true, false] {
+        let kind = if categories {
+            Kind::Category
+        } else {
+            Kind::Channel
+        };
+        for id in &channels.added {
+            if let Some(channel) =
+                find(desired, "channels", id).filter(|c| is_category(c) == categories)
+            {
+                let mut create = step(Op::Create, kind, channel, CHANNEL_FIELDS);
+                create.permissions = permissions::overwrite_changes(None, channel, &all_roles);
+                steps.push(create);
+            }
+        }
+        for id in &channels.changed {
+            if let Some(channel) =
+                find(desired, "channels", id).filter(|c| is_category(c) == categories)
+            {
+                let mut update = step(Op::Update, kind, channel, CHANNEL_FIELDS);
+                update.permissions = permissions::overwrite_changes(
+                    find(current, "channels", id),
+                    channel,
+                    &all_roles,
+                );
+                if let Value::Object(payload) = &mut update.payload {
+                    payload.shift_remove("type");
+                }
+                steps.push(update);
+            }
+        }
+    }
+
+    let extras = [
+        ("emojis", Kind::Emoji, EMOJI_FIELDS),
+        ("stickers", Kind::Sticker, STICKER_FIELDS),
+        ("scheduled_events", Kind::Event, EVENT_FIELDS),
+    ];
+    // Files exported before emoji, sticker, or event support leave the guild's set alone.
+    let extras = extras
+        .iter()
+        .filter(|(name, ..)| desired.get(*name).is_some());
+    // Events that started, ended, or were canceled are history; Discord only creates new ones.
+    let creatable = |item: &Value| {
+        !managed(item)
+            && item["status"]
+                .as_u64()
+                .is_none_or(|s| s == SCHEDULED_STATUS)
+    };
+    for &(name, kind, fields) in extras.clone() {
+        let changes = section(name);
+        for id in &changes.added {
+            if let Some(item) = find(desired, name, id).filter(|i| creatable(i)) {
+                steps.push(step(Op::Create, kind, item, fields));
+            }
+        }
+        for id in &changes.changed {
+            if let Some(item) = find(desired, name, id) {
+                steps.push(step(Op::Update, kind, item, fields));
+            }
+        }
+    }
+    for &(name, kind, _) in extras {
+        for id in &section(name).removed {
+            if let Some(item) = find(current, name, id).filter(|i| !managed(i)) {
+                steps.push(step(Op::Delete, kind, item, &[]));
+            }
+        }
+    }
+
+    for categories in [false, true] {
+        let kind = if categories {
+            Kind::Category
+        } else {
+            Kind::Channel
+        };
+        for id in &channels.removed {
+            if let Some(channel) =
+                find(current, "channels", id).filter(|c| is_category(c) == categories)
+            {
+                steps.push(step(Op::Delete, kind, channel, &[]));
+            }
+        }
+    }
+    for id in &roles.removed {
+        if let Some(role) = find(current, "roles", id).filter(|r| !managed(r)) {
+            steps.push(step(Op::Delete, Kind::Role, role, &[]));
+        }
+    }
+
+    // Last, so settings like `afk_channel_id` can point at channels created above.
+    let settings: Map<String, Value> = changes
+        .guild
+        .iter()
+        .filter(|key| GUILD_FIELDS.contains(&key.as_str()))
+        .map(|key| (key.clone(), desired["guild"][key].clone()))
+        .collect();
+    if !settings.is_empty() {
+        steps.push(Step {
+            op: Op::Update,
+            kind: Kind::Guild,
+            id: text(&current["guild"]["id"]),
+            name: text(&desired["guild"]["name"]),
+            payload: settings.into(),
+            permissions: Vec::new(),
+            previous: Value::Null,
+        });
+    }
+    for step in &mut steps {
+        step.previous = previous(current, step);
+    }
+    steps
+}
+
+/// [`Step::previous`] for `step`, from the live guild `current`.
+fn previous(current: &Value, step: &Step) -> Value {
+    let (item, fields) = match step.kind {
+        Kind::Guild => (Some(&current["guild"]), GUILD_FIELDS),
+        Kind::Role => (find(current, "roles", &step.id), ROLE_FIELDS),
+        Kind::Category | Kind::Channel => (find(current, "channels", &step.id), CHANNEL_FIELDS),
+        Kind::Emoji => (find(current, "emojis", &step.id), EMOJI_RECREATE_FIELDS),
+        Kind::Sticker => (find(current, "stickers", &step.id), STICKER_RECREATE_FIELDS),
+        Kind::Event => (
+            find(current, "scheduled_events", &step.id),
+            EVENT_RECREATE_FIELDS,
+        ),
+    };
+    let Some(item) = item else {
+        return Value::Null;
+    };
+    let values: Map<String, Value> = match step.op {
+        Op::Create => return Value::Null,
+        Op::Update => step
+            .payload
+            .as_object()
+            .into_iter()
+            .flat_map(|payload| payload.keys())
+            .map(|key| (key.clone(), item.get(key).cloned().unwrap_or(Value::Null)))
+            .collect(),
+        Op::Delete => fields
+            .iter()
+            .filter_map(|key| Some((key.to_string(), item.get(*key)?.clone())))
+            .collect(),
+    };
+    values.into()
+}
+
+/// Human-readable plan: one line per step, each followed by its indented permission changes.
+pub fn listing(steps: &[Step]) -> String {
+    let mut listing = String::new();
+    for step in steps {
+        listing.push_str(&format!("{step}\n"));
+        for change in &step.permissions {
+            listing.push_str(&format!("    {change}\n"));
+        }
+    }
+    listing
+}
+
+/// Execute `steps` against `guild`, advancing `progress` once per step, and return the ids
+/// Discord assigned to created objects by their id in the file.
+///
+/// Ids of created roles and channels are tracked so later steps (child channels,
+/// permission overwrites, emoji roles, event channels, guild settings) refer to the new objects.
+/// Emoji, sticker, and event cover images come from `desired`, the import file the steps were
+/// planned from.
+///
+/// Each applied step is recorded in `journal`, with the image of a deleted emoji, sticker, or
+/// event saved first so that it can be recreated. Deleting an object that is already gone counts as
+/// done.
+pub async fn apply(
+    client: &Client,
+    guild: u64,
+    desired: &Value,
+    steps: &[Step],
+    mut journal: Option<&mut Journal>,
+    progress: &indicatif::ProgressBar,
+) -> Result<HashMap<String, String>, CliError> {
+    // Gather images first, so a missing one fails the import before anything changes.
+    let mut images = HashMap::new();
+    for (i, step) in steps.iter().enumerate() {
+        let section = match (step.op, step.kind) {
+            (Op::Create, Kind::Emoji) => "emojis",
+            (Op::Create, Kind::Sticker) => "stickers",
+            (Op::Create, Kind::Event) => "scheduled_events",
+            _ => continue,
+        };
+        let item = find(desired, section, &step.id).unwrap_or(&Value::Null);
+        if step.kind == Kind::Event && !has_image(item) {
+            continue;
+        }
+        let image = assets::image(client, item).await.map_err(|err| match err {
+            CliError::NotFound(what) => CliError::NotFound(format!("{step}: no image at {what}")),
+            err => err,
+        })?;
+        images.insert(i, image);
+    }
+
+    let mut ids: HashMap<String, String> = HashMap::new();
+    for (done, step) in steps.iter().enumerate() {
+        progress.set_message(step.to_string());
+        let payload = remap(&step.payload, &ids);
+        let mut previous = step.previous.clone();
+        let path = match (step.op, step.kind) {
+            (_, Kind::Guild) => format!("/guilds/{guild}"),
+            (Op::Create, Kind::Role) => format!("/guilds/{guild}/roles"),
+            (_, Kind::Role) => format!("/guilds/{guild}/roles/{}", step.id),
+            (Op::Create, Kind::Emoji) => format!("/guilds/{guild}/emojis"),
+            (_, Kind::Emoji) => format!("/guilds/{guild}/emojis/{}", step.id),
+            (Op::Create, Kind::Sticker) => format!("/guilds/{guild}/stickers"),
+            (_, Kind::Sticker) => format!("/guilds/{guild}/stickers/{}", step.id),
+            (Op::Create, Kind::Event) => format!("/guilds/{guild}/scheduled-events"),
+            (_, Kind::Event) => format!("/guilds/{guild}/scheduled-events/{}", step.id),
+            (Op::Create, _) => format!("/guilds/{guild}/channels"),
+            (_, _) => format!("/channels/{}", step.id),
+        };
+        if journal.is_some()
+            && step.op == Op::Delete
+            && (matches!(step.kind, Kind::Emoji | Kind::Sticker)
+                || step.kind == Kind::Event && has_image(&previous))
+        {
+            match assets::image(client, &previous).await {
+                Ok((file_name, bytes)) => {
+                    let extension = file_name.rsplit('.').next().unwrap_or_default();
+                    previous["image"] = assets::data_uri(&bytes, extension).into();
+                }
+                Err(err) => tracing::warn!("{step}: image not journaled: {err}"),
+            }
+        }
+        let image = images.remove(&done);
+        let result = match (step.op, image) {
+            (Op::Create, Some((file_name, bytes))) if step.kind == Kind::Sticker => {
+                client.post_file(&path, &payload, &file_name, &bytes).await
+            }
+            (Op::Create, Some((file_name, bytes))) => {
+                let extension = file_name.rsplit('.').next().unwrap_or_default();
+                let mut body = payload.clone();
+                body["image"] = assets::data_uri(&bytes, extension).into();
+                client.post(&path, &body).await
+            }
+            (Op::Create, None) => client.post(&path, &payload).await,
+            (Op::Update, _) => client.patch(&path, &payload).await,
+            (Op::Delete, _) => match client.delete(&path).await {
+                Err(CliError::NotFound(_)) => {
+                    tracing::warn!("{step}: already gone");
+                    progress.inc(1);
+                    continue;
+                }
+                result => result.map(|()| Value::Null),
+            },
+        }
+        .inspect_err(|_| {
+            tracing::error!("{step} failed after {done} of {} steps", steps.len());
+        })?;
+        let id = match step.op {
+            Op::Create => {
+                let id = text(&result["id"]);
+                ids.insert(step.id.clone(), id.clone());
+                id
+            }
+            _ => step.id.clone(),
+        };
+        if let Some(journal) = journal.as_deref_mut() {
+            journal.record(Entry {
+                op: step.op,
+                kind: step.kind,
+                id,
+                name: step.name.clone(),
+                applied: payload,
+                previous,
+            })?;
+        }
+        progress.inc(1);
+    }
+    Ok(ids)
+}
+
+/// Give the file's `@everyone` role the live guild's id (they always differ across guilds).
+fn adopt_everyone(current: &Value, desired: &Value) -> Value {
+    let mut desired = desired.clone();
+    let live_id = current["guild"]["id"].clone();
+    if let Some(roles) = desired["roles"].as_array_mut() {
+        for role in roles.iter_mut().filter(|r| r["name"] == "@everyone") {
+            role["id"] = live_id.clone();
+        }
+    }
+    desired
+}
+
+/// Whether a scheduled event has a cover image to upload (emoji and stickers always do).
+fn has_image(item: &Value) -> bool {
+    item["image"].is_string() || item["url"].is_string()
+}
+
+fn find<'a>(document: &'a Value, section: &str, id: &str) -> Option<&'a Value> {
+    document[section]
+        .as_array()?
+        .iter()
+        .find(|item| text(&item["id"]) == id)
+}
+
+fn step(op: Op, kind: Kind, item: &Value, fields: &[&str]) -> Step {
+    let payload: Map<String, Value> = fields
+        .iter()
+        .filter_map(|key| Some((key.to_string(), item.get(*key)?.clone())))
+        .collect();
+    Step {
+        op,
+        kind,
+        id: text(&item["id"]),
+        name: text(&item["name"]),
+        payload: if op == Op::Delete {
+            Value::Null
+        } else {
+            payload.into()
+        },
+        permissions: Vec::new(),
+        previous: Value::Null,
+    }
+}
+
+/// Replace ids from the file with the ids Discord assigned to created objects, in `id` and
+/// `*_id` fields (e.g. `parent_id`, overwrite targets, `afk_channel_id`, an event's `channel_id`) and in an emoji's
+/// `roles` list.
+fn remap(value: &Value, ids: &HashMap<String, String>) -> Value {
+    match value {
+        Value::Array(items) => items.iter().map(|v| remap(v, ids)).collect(),
+        Value::Object(obj) => obj
+            .iter()
+            .map(|(key, v)| {
+                let v = match (ids.get(&text(v)), v) {
+                    (Some(new), _) if key == "id" || key.ends_with("_id") => new.clone().into(),
+                    (_, Value::Array(roles)) if key == "roles" => roles
+                        .iter()
+                        .map(|role| {
+                            ids.get(&text(role))
+                                .map_or(role.clone(), |n| n.clone().into())
+                        })
+                        .collect(),
+                    _ => remap(v, ids),
+                };
+                (key.clone(), v)
+            })
+            .collect::<Map<_, _>>()
+            .into(),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_parents_first_and_remaps_created_ids() {
+        let current = serde_json::json!({
+            "guild": { "id": "1", "name": "g" },
+            "roles": [{ "id": "1", "name": "@everyone", "permissions": "0" }],
+            "channels": [
+                { "id": "5", "type": 4, "name": "old" },
+                { "id": "6", "type": 0, "name": "general", "parent_id": "5" },
+            ],
+        });
+        let desired = serde_json::json!({
+            "guild": { "id": "9", "name": "new" },
+            "roles": [
+                { "id": "90", "name": "@everyone", "permissions": "0" },
+                { "id": "91", "name": "mods" },
+            ],
+            "channels": [
+                { "id": "80", "type": 0, "name": "chat", "parent_id": "81" },
+                { "id": "81", "type": 4, "name": "Text" },
+            ],
+        });
+        let steps: Vec<String> = plan(&current, &desired)
+            .iter()
+            .map(Step::to_string)
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                "+ role mods (91)",
+                "+ category Text (81)",
+                "+ channel chat (80)",
+                "- channel general (6)",
+                "- category old (5)",
+                "~ guild new (1)",
+            ]
+        );
+
+        let ids = HashMap::from([("81".to_string(), "500".to_string())]);
+        let payload = serde_json::json!({ "parent_id": "81", "name": "81" });
+        assert_eq!(
+            remap(&payload, &ids),
+            serde_json::json!({ "parent_id": "500", "name": "81" })
+        );
+    }
+
+    #[test]
+    fn plans_emojis_and_ignores_embedded_images() {
+        let current = serde_json::json!({
+            "guild": { "id": "1" },
+            "emojis": [
+                { "id": "2", "name": "wave", "url": "u2" },
+                { "id": "3", "name": "twitch", "managed": true },
+            ],
+            "stickers": [{ "id": "4", "name": "cat", "tags": "cat" }],
+        });
+        let desired = serde_json::json!({
+            "guild": { "id": "1" },
+            "emojis": [
+                { "id": "2", "name": "wave", "url": "u2", "image": "data:image/png;base64,AA==" },
+                { "id": "5", "name": "mods_only", "roles": ["91"], "image": "data:..." },
+            ],
+        });
+        let steps = plan(&current, &desired);
+        let listing: Vec<String> = steps.iter().map(Step::to_string).collect();
+        assert_eq!(listing, ["+ emoji mods_only (5)"]);
+        assert_eq!(
+            steps[0].payload,
+            serde_json::json!({ "name": "mods_only", "roles": ["91"] })
+        );
+
+        let ids = HashMap::from([("91".to_string(), "501".to_string())]);
+        assert_eq!(
+            remap(&steps[0].payload, &ids)["roles"],
+            serde_json::json!(["501"])
+        );
+    }
+
+    #[test]
+    fn plans_scheduled_events_in_new_channels_but_not_past_ones() {
+        let current = serde_json::json!({ "guild": { "id": "1" }, "scheduled_events": [] });
+        let desired = serde_json::json!({
+            "guild": { "id": "1" },
+            "channels": [{ "id": "80", "type": 2, "name": "voice" }],
+            "scheduled_events": [
+                { "id": "6", "name": "standup", "status": 1, "entity_type": 2,
+                  "channel_id": "80", "recurrence_rule": { "frequency": 3 }, "user_count": 4 },
+                { "id": "7", "name": "launch", "status": 3, "entity_type": 2, "channel_id": "80" },
+            ],
+        });
+        let steps = plan(&current, &desired);
+        let listing: Vec<String> = steps.iter().map(Step::to_string).collect();
+        assert_eq!(listing, ["+ channel voice (80)", "+ event standup (6)"]);
+
+        let ids = HashMap::from([("80".to_string(), "500".to_string())]);
+        assert_eq!(
+            remap(&steps[1].payload, &ids),
+            serde_json::json!({
+                "name": "standup",
+                "entity_type": 2,
+                "channel_id": "500",
+                "recurrence_rule": { "frequency": 3 },
+            })
+        );
+    }
+}
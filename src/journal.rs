@@ -0,0 +1,276 @@
+//! Journal of the changes a `discord import` applied, with the values they replaced, so that
+//! `discord undo` can revert them.
+//!
+//! The journal is rewritten after every applied step, so an import that fails halfway can still
+//! be undone up to the point it reached.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::atomic_file::write_atomic;
+use crate::error::CliError;
+use crate::import::{Kind, Op, Step};
+use crate::permissions;
+use crate::timestamp;
+
+/// `format` tag of a journal file.
+const JOURNAL_FORMAT: &str = "import-journal";
+
+/// Schema version of journal files.
+const JOURNAL_VERSION: u32 = 1;
+
+/// Fields of a recorded object that only serve to fetch its image again, not to recreate it.
+const IMAGE_FIELDS: &[&str] = &["url", "animated", "format_type", "image"];
+
+/// Journal of one import into `guild`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Journal {
+    pub format: String,
+    pub version: u32,
+    pub guild: u64,
+    /// The imported file.
+    pub source: String,
+    /// When the import started (RFC 3339).
+    pub started_at: String,
+    /// Names of the guild's roles by id at import time, for naming overwrite targets.
+    #[serde(default)]
+    pub roles: BTreeMap<String, String>,
+    /// When `discord undo` reverted the import, if it has.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undone_at: Option<String>,
+    /// Applied steps, in order.
+    pub entries: Vec<Entry>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// One applied step.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub op: Op,
+    pub kind: Kind,
+    /// Live id of the object (for creates, the id Discord assigned).
+    pub id: String,
+    pub name: String,
+    /// What was sent (creates and updates).
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub applied: Value,
+    /// Values the step replaced (updates) or the deleted object (deletes).
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub previous: Value,
+}
+
+/// Default journal path for an import of `source`: `<source>.journal-<UTC time>.json`.
+pub fn default_path(source: &Path) -> PathBuf {
+    let stamp: String = timestamp::now_rfc3339()
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    let mut path = source.as_os_str().to_owned();
+    path.push(format!(".journal-{stamp}.json"));
+    PathBuf::from(path)
+}
+
+impl Journal {
+    /// Start an empty journal at `path` for an import into `guild`, whose live state is
+    /// `current`. It is written right away so an unwritable path fails before the guild is
+    /// touched.
+    pub fn create(
+        path: &Path,
+        guild: u64,
+        source: &Path,
+        current: &Value,
+    ) -> Result<Self, CliError> {
+        let text = |v: &Value| v.as_str().map_or_else(|| v.to_string(), str::to_string);
+        let roles = current["roles"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|role| (text(&role["id"]), text(&role["name"])))
+            .collect();
+        let journal = Self {
+            format: JOURNAL_FORMAT.to_string(),
+            version: JOURNAL_VERSION,
+            guild,
+            source: source.display().to_string(),
+            started_at: timestamp::now_rfc3339(),
+            roles,
+            undone_at: None,
+            entries: Vec::new(),
+            path: path.to_path_buf(),
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    /// Read the journal at `path`.
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let text = std::fs::read_to_string(path)?;
+        let mut journal: Self = serde_json::from_str(&text)?;
+        if journal.format != JOURNAL_FORMAT {
+            return Err(CliError::Validation(format!(
+                "{} is not an import journal (format {:?})",
+                path.display(),
+                journal.format
+            )));
+        }
+        if journal.version > JOURNAL_VERSION {
+            return Err(CliError::Validation(format!(
+                "unsupported journal version {} (supported: {JOURNAL_VERSION})",
+                journal.version
+            )));
+        }
+        journal.path = path.to_path_buf();
+        Ok(journal)
+    }
+
+    /// Append `entry` and persist the journal.
+    pub fn record(&mut self, entry: Entry) -> Result<(), CliError> {
+        self.entries.push(entry);
+        self.save()
+    }
+
+    /// Mark the journal as reverted and persist it.
+    pub fn mark_undone(&mut self) -> Result<(), CliError> {
+        self.undone_at = Some(timestamp::now_rfc3339());
+        self.save()
+    }
+
+    /// Steps that revert the journal, last change first, and a document holding the deleted
+    /// emoji, stickers, and events (for [`crate::import::apply`] to take their images from).
+    ///
+    /// Created objects are deleted, updated ones get their previous values back, and deleted
+    /// ones are created again from their recorded fields.
+    pub fn undo_steps(&self) -> (Vec<Step>, Value) {
+        let created = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind == Kind::Role)
+            .map(|entry| (&entry.id, &entry.name));
+        let roles: Vec<Value> = self
+            .roles
+            .iter()
+            .chain(created)
+            .map(|(id, name)| serde_json::json!({ "id": id, "name": name }))
+            .collect();
+        let roles: Vec<&Value> = roles.iter().collect();
+        let mut images =
+            serde_json::json!({ "emojis": [], "stickers": [], "scheduled_events": [] });
+        let mut steps = Vec::new();
+        for entry in self.entries.iter().rev() {
+            let (op, payload, permissions) = match entry.op {
+                Op::Create => (Op::Delete, Value::Null, Vec::new()),
+                Op::Update => {
+                    let permissions = match entry.kind {
+                        Kind::Role => {
+                            permissions::role_changes(Some(&entry.applied), &entry.previous)
+                        }
+                        Kind::Category | Kind::Channel => permissions::overwrite_changes(
+                            Some(&entry.applied),
+                            &entry.previous,
+                            &roles,
+                        ),
+                        _ => Vec::new(),
+                    };
+                    (Op::Update, entry.previous.clone(), permissions)
+                }
+                Op::Delete => {
+                    let section = match entry.kind {
+                        Kind::Emoji => Some("emojis"),
+                        Kind::Sticker => Some("stickers"),
+                        Kind::Event => Some("scheduled_events"),
+                        _ => None,
+                    };
+                    if let Some(section) = section
+                        && let Some(list) = images[section].as_array_mut()
+                    {
+                        let mut item = entry.previous.clone();
+                        item["id"] = entry.id.clone().into();
+                        list.push(item);
+                    }
+                    let mut payload = entry.previous.clone();
+                    if let Value::Object(fields) = &mut payload {
+                        fields.retain(|key, _| !IMAGE_FIELDS.contains(&key.as_str()));
+                    }
+                    let permissions = match entry.kind {
+                        Kind::Role => permissions::role_changes(None, &payload),
+                        Kind::Category | Kind::Channel => {
+                            permissions::overwrite_changes(None, &payload, &roles)
+                        }
+                        _ => Vec::new(),
+                    };
+                    (Op::Create, payload, permissions)
+                }
+            };
+            steps.push(Step {
+                op,
+                kind: entry.kind,
+                id: entry.id.clone(),
+                name: entry.name.clone(),
+                payload,
+                permissions,
+                previous: Value::Null,
+            });
+        }
+        (steps, images)
+    }
+
+    fn save(&self) -> Result<(), CliError> {
+        let mut bytes = serde_json::to_vec_pretty(self)?;
+        bytes.push(b'\n');
+        write_atomic(&self.path, &bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_reverses_entries_and_inverts_them() {
+        let entry = |op, kind, id: &str, previous: Value| Entry {
+            op,
+            kind,
+            id: id.to_string(),
+            name: id.to_string(),
+            applied: Value::Null,
+            previous,
+        };
+        let journal = Journal {
+            format: JOURNAL_FORMAT.to_string(),
+            version: JOURNAL_VERSION,
+            guild: 1,
+            source: "in.json".to_string(),
+            started_at: String::new(),
+            roles: BTreeMap::new(),
+            undone_at: None,
+            entries: vec![
+                entry(Op::Create, Kind::Channel, "500", Value::Null),
+                entry(
+                    Op::Update,
+                    Kind::Guild,
+                    "1",
+                    serde_json::json!({ "name": "old" }),
+                ),
+                entry(
+                    Op::Delete,
+                    Kind::Emoji,
+                    "7",
+                    serde_json::json!({ "name": "wave", "url": "u", "image": "data:..." }),
+                ),
+            ],
+            path: PathBuf::new(),
+        };
+        let (steps, images) = journal.undo_steps();
+        let listing: Vec<String> = steps.iter().map(Step::to_string).collect();
+        assert_eq!(
+            listing,
+            ["+ emoji 7 (7)", "~ guild 1 (1)", "- channel 500 (500)"]
+        );
+        assert_eq!(steps[0].payload, serde_json::json!({ "name": "wave" }));
+        assert_eq!(images["emojis"][0]["image"], "data:...");
+    }
+}
@@ -0,0 +1,1953 @@
+//! Kubernetes helpers. Cluster access goes through `kubectl` so the user's kubeconfig,
+//! auth plugins, and contexts behave exactly as they do on the command line.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::{self, KubeProvider};
+use crate::deploy;
+use crate::error::CliError;
+use crate::retry::{self, RetryPolicy};
+
+/// Name of the local cluster created by `kube local up`.
+pub const LOCAL_CLUSTER: &str = "guildsync";
+
+/// Delay between readiness polls.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long `kube remote test` waits for its Job to finish.
+const JOB_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Log lines kept from a finished test Job.
+const LOG_TAIL_LINES: usize = 20;
+
+/// Label selector `kube logs` uses by default: the watcher of `kube remote deploy` and the pods
+/// of a chart from `kube chart generate` under its default name carry it.
+pub const WORKLOAD_SELECTOR: &str = "app.kubernetes.io/name=guildsync";
+
+/// Job run by `kube remote test` when no `--manifest` is given; `{image}` is substituted.
+const DEFAULT_TEST_JOB: &str = r#"apiVersion: batch/v1
+kind: Job
+metadata:
+  generateName: guildsync-test-
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/component: test
+spec:
+  backoffLimit: 0
+  activeDeadlineSeconds: 600
+  ttlSecondsAfterFinished: 3600
+  template:
+    metadata:
+      labels:
+        app.kubernetes.io/name: guildsync
+        app.kubernetes.io/component: test
+    spec:
+      restartPolicy: Never
+      containers:
+        - name: test
+          image: {image}
+          args: ["doctor"]
+"#;
+
+/// Job run by `kube remote test --guild`: export the guild with the token from `{secret}`, then
+/// validate the dump. `{image}`, `{guild}`, and `{secret}` are substituted.
+const GUILD_TEST_JOB: &str = r#"apiVersion: batch/v1
+kind: Job
+metadata:
+  generateName: guildsync-test-
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/component: test
+spec:
+  backoffLimit: 0
+  activeDeadlineSeconds: 600
+  ttlSecondsAfterFinished: 3600
+  template:
+    metadata:
+      labels:
+        app.kubernetes.io/name: guildsync
+        app.kubernetes.io/component: test
+    spec:
+      restartPolicy: Never
+      initContainers:
+        - name: export
+          image: {image}
+          args: ["discord", "export", "--guild", "{guild}", "--out", "/work/smoke.json"]
+          env:
+            - name: DISCORD_TOKEN
+              valueFrom:
+                secretKeyRef:
+                  name: {secret}
+                  key: token
+          volumeMounts:
+            - name: work
+              mountPath: /work
+      containers:
+        - name: validate
+          image: {image}
+          args: ["format", "validate", "--in", "/work/smoke.json", "--format", "dump"]
+          volumeMounts:
+            - name: work
+              mountPath: /work
+      volumes:
+        - name: work
+          emptyDir: {}
+"#;
+
+/// Result of a finished test Job.
+#[derive(Debug, Serialize)]
+pub struct TestReport {
+    pub job: String,
+    pub passed: bool,
+    /// Exit code of the Job's container, if the pod got far enough to report one.
+    pub exit_code: Option<i32>,
+    pub logs_tail: String,
+}
+
+/// A context from the merged kubeconfig and the file that defined it.
+#[derive(Debug, Serialize)]
+pub struct KubeContext {
+    pub name: String,
+    pub file: PathBuf,
+    pub cluster: Option<String>,
+    /// Namespace kubectl uses when none is given (`default` if unset).
+    pub namespace: Option<String>,
+    /// Whether it is the merged `current-context`.
+    pub current: bool,
+}
+
+/// Contexts defined across `kubeconfigs`, merged in order with later files winning.
+///
+/// With no files given, reads what kubectl would: the `$KUBECONFIG` files that exist, the first
+/// winning, or else `~/.kube/config` (a missing default file means no contexts).
+pub fn contexts(kubeconfigs: &[PathBuf]) -> Result<Vec<KubeContext>, CliError> {
+    let default_files: Vec<PathBuf>;
+    let files = if kubeconfigs.is_empty() {
+        default_files = default_kubeconfigs(std::env::var_os("KUBECONFIG"))
+            .into_iter()
+            .filter(|file| file.exists())
+            .collect();
+        &default_files[..]
+    } else {
+        kubeconfigs
+    };
+
+    let mut merged = BTreeMap::new();
+    let mut current = None;
+    for file in files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| CliError::Kube(format!("kubeconfig {}: {e}", file.display())))?;
+        let doc: serde_yaml::Value = serde_yaml::from_str(&text)
+            .map_err(|e| CliError::Kube(format!("kubeconfig {}: {e}", file.display())))?;
+        for context in doc["contexts"].as_sequence().into_iter().flatten() {
+            let Some(name) = context["name"].as_str() else {
+                continue;
+            };
+            let field = |key: &str| context["context"][key].as_str().map(str::to_string);
+            merged.insert(
+                name.to_string(),
+                KubeContext {
+                    name: name.to_string(),
+                    file: file.clone(),
+                    cluster: field("cluster"),
+                    namespace: field("namespace"),
+                    current: false,
+                },
+            );
+        }
+        if let Some(name) = doc["current-context"].as_str().filter(|n| !n.is_empty()) {
+            current = Some(name.to_string());
+        }
+    }
+    Ok(merged
+        .into_values()
+        .map(|mut context| {
+            tracing::debug!("context {} from {}", context.name, context.file.display());
+            context.current = current.as_ref() == Some(&context.name);
+            context
+        })
+        .collect())
+}
+
+/// Fail unless `context` is defined in the merged `kubeconfigs`.
+pub fn require_context(kubeconfigs: &[PathBuf], context: &str) -> Result<(), CliError> {
+    if contexts(kubeconfigs)?.iter().any(|c| c.name == context) {
+        return Ok(());
+    }
+    let files = if kubeconfigs.is_empty() {
+        match std::env::var_os("KUBECONFIG").filter(|v| !v.is_empty()) {
+            Some(value) => format!("$KUBECONFIG ({})", value.to_string_lossy()),
+            None => "~/.kube/config".to_string(),
+        }
+    } else {
+        kubeconfigs
+            .iter()
+            .map(|f| f.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Err(CliError::Kube(format!(
+        "context {context} not found in {files}"
+    )))
+}
+
+/// The files kubectl reads without `--kubeconfig`, in the later-wins order of [`contexts`]:
+/// the `$KUBECONFIG` list reversed, since kubectl lets its first file win, or `~/.kube/config`.
+fn default_kubeconfigs(env: Option<std::ffi::OsString>) -> Vec<PathBuf> {
+    match env.filter(|value| !value.is_empty()) {
+        Some(value) => {
+            let mut files: Vec<PathBuf> = std::env::split_paths(&value)
+                .filter(|file| !file.as_os_str().is_empty())
+                .collect();
+            files.reverse();
+            files
+        }
+        None => vec![config::expand_tilde(Path::new("~/.kube/config"))],
+    }
+}
+
+impl KubeProvider {
+    /// kubeconfig context the provider registers for a cluster called `name`.
+    pub fn context(self, name: &str) -> String {
+        match self {
+            KubeProvider::Kind => format!("kind-{name}"),
+            KubeProvider::K3d => format!("k3d-{name}"),
+            KubeProvider::Minikube => name.to_string(),
+        }
+    }
+
+    /// Program that drives the backend.
+    pub fn program(self) -> &'static str {
+        match self {
+            KubeProvider::Kind => "kind",
+            KubeProvider::K3d => "k3d",
+            KubeProvider::Minikube => "minikube",
+        }
+    }
+}
+
+/// Fail unless `name` is a cluster name every backend accepts (it ends up in container and
+/// DNS names): lowercase letters, digits, and inner `-`, at most 32 characters.
+pub fn validate_cluster_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "cluster name {name:?} must be 1-32 lowercase letters, digits, or inner '-'"
+        ))
+    }
+}
+
+/// Whether a backend knows the cluster, and whether it runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    Absent,
+    Stopped,
+    Running,
+}
+
+/// A local cluster as `kube local` drives it.
+#[derive(Debug)]
+pub struct LocalCluster {
+    pub backend: KubeProvider,
+    pub name: String,
+    /// Node image for creating the cluster; an existing cluster keeps its own.
+    pub image: Option<String>,
+    /// File the backend writes the cluster's context into; its default if `None`.
+    pub kubeconfig: Option<PathBuf>,
+}
+
+impl LocalCluster {
+    /// kubeconfig context of the cluster.
+    pub fn context(&self) -> String {
+        self.backend.context(&self.name)
+    }
+
+    /// kubeconfig files to reach the cluster through: the one it was written into, or
+    /// `fallback` (the `--kubeconfig` files, if any).
+    pub fn kubeconfigs(&self, fallback: &[PathBuf]) -> Vec<PathBuf> {
+        match &self.kubeconfig {
+            Some(file) => vec![file.clone()],
+            None => fallback.to_vec(),
+        }
+    }
+
+    /// Whether the backend knows the cluster and whether it runs.
+    pub async fn presence(&self) -> Result<Presence, CliError> {
+        let args: &[&str] = match self.backend {
+            KubeProvider::Kind => &["get", "clusters"],
+            KubeProvider::K3d => &["cluster", "list", "-o", "json"],
+            KubeProvider::Minikube => &["profile", "list", "-o", "json"],
+        };
+        // minikube exits non-zero when it has no profiles at all, still printing JSON.
+        let lenient = self.backend == KubeProvider::Minikube;
+        let listing = self.run(args, lenient).await?;
+        Ok(presence(self.backend, &self.name, &listing))
+    }
+
+    /// Create the cluster, or start it if it exists but is stopped. Returns what it found.
+    pub async fn up(&self) -> Result<Presence, CliError> {
+        let found = self.presence().await?;
+        let mut args: Vec<&str> = match (self.backend, found) {
+            (_, Presence::Running) => return Ok(found),
+            (KubeProvider::Kind, _) => vec!["create", "cluster", "--name", &self.name],
+            (KubeProvider::K3d, Presence::Absent) => vec!["cluster", "create", &self.name],
+            (KubeProvider::K3d, Presence::Stopped) => vec!["cluster", "start", &self.name],
+            (KubeProvider::Minikube, _) => vec!["start", "--profile", &self.name],
+        };
+        if let (Some(image), Presence::Absent) = (&self.image, found) {
+            args.push(match self.backend {
+                KubeProvider::Kind | KubeProvider::K3d => "--image",
+                KubeProvider::Minikube => "--base-image",
+            });
+            args.push(image);
+        }
+        tracing::info!(
+            "{} {} cluster {} (this can take a few minutes)",
+            if found == Presence::Absent {
+                "creating"
+            } else {
+                "starting"
+            },
+            self.backend.program(),
+            self.name
+        );
+        self.run(&args, false).await?;
+        Ok(found)
+    }
+
+    /// Delete the cluster and its kubeconfig context. Returns what it found.
+    pub async fn down(&self) -> Result<Presence, CliError> {
+        let found = self.presence().await?;
+        if found == Presence::Absent {
+            return Ok(found);
+        }
+        let args: &[&str] = match self.backend {
+            KubeProvider::Kind => &["delete", "cluster", "--name", &self.name],
+            KubeProvider::K3d => &["cluster", "delete", &self.name],
+            KubeProvider::Minikube => &["delete", "--profile", &self.name],
+        };
+        self.run(args, false).await?;
+        Ok(found)
+    }
+
+    /// Run the backend's program with `args`, returning stdout; with `lenient`, a failing exit
+    /// status is not an error.
+    async fn run(&self, args: &[&str], lenient: bool) -> Result<String, CliError> {
+        let program = self.backend.program();
+        let mut command = tokio::process::Command::new(program);
+        if let Some(file) = &self.kubeconfig {
+            // All three backends write (and clean up) contexts in the first $KUBECONFIG file.
+            command.env("KUBECONFIG", file);
+        }
+        tracing::debug!("{program} {}", args.join(" "));
+        let output = command
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => CliError::Kube(format!(
+                    "{program} not found on PATH; install it or choose another --backend"
+                )),
+                _ => CliError::Kube(format!("{program}: {e}")),
+            })?;
+        if !output.status.success() && !lenient {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(CliError::Kube(format!(
+                "{program} {}: {}",
+                args.join(" "),
+                stderr.trim().lines().last().unwrap_or_default()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// What a backend's cluster listing says about the cluster called `name`.
+fn presence(backend: KubeProvider, name: &str, listing: &str) -> Presence {
+    let running = |yes: bool| {
+        if yes {
+            Presence::Running
+        } else {
+            Presence::Stopped
+        }
+    };
+    match backend {
+        KubeProvider::Kind => {
+            if listing.lines().any(|line| line.trim() == name) {
+                Presence::Running
+            } else {
+                Presence::Absent
+            }
+        }
+        KubeProvider::K3d => {
+            let clusters: Value = serde_json::from_str(listing).unwrap_or_default();
+            clusters
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|c| c["name"] == name)
+                .map_or(Presence::Absent, |c| {
+                    running(c["serversRunning"].as_u64().unwrap_or_default() > 0)
+                })
+        }
+        KubeProvider::Minikube => {
+            let profiles: Value = serde_json::from_str(listing).unwrap_or_default();
+            ["valid", "invalid"]
+                .iter()
+                .filter_map(|key| profiles[key].as_array())
+                .flatten()
+                .find(|p| p["Name"] == name)
+                .map_or(Presence::Absent, |p| running(p["Status"] == "Running"))
+        }
+    }
+}
+
+/// A node as `kube local status` reports it.
+#[derive(Debug, Serialize)]
+pub struct NodeStatus {
+    pub name: String,
+    pub ready: bool,
+    pub version: String,
+}
+
+/// Pods across all namespaces by phase, with the ones not (yet) serving.
+#[derive(Debug, Default, Serialize)]
+pub struct PodSummary {
+    pub total: usize,
+    pub running: usize,
+    pub pending: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// `namespace/name` of pods that are neither succeeded nor running with every container
+    /// ready.
+    pub not_ready: Vec<String>,
+}
+
+/// Nodes and pods of the cluster in `context`.
+pub async fn cluster_status(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    policy: &RetryPolicy,
+) -> Result<(Vec<NodeStatus>, PodSummary), CliError> {
+    let nodes: Value = serde_json::from_str(
+        &kubectl_read(
+            policy,
+            kubeconfigs,
+            Some(context),
+            &["get", "nodes", "-o", "json"],
+        )
+        .await?,
+    )?;
+    let pods: Value = serde_json::from_str(
+        &kubectl_read(
+            policy,
+            kubeconfigs,
+            Some(context),
+            &["get", "pods", "--all-namespaces", "-o", "json"],
+        )
+        .await?,
+    )?;
+    Ok((node_statuses(&nodes), summarize_pods(&pods)))
+}
+
+fn node_statuses(nodes: &Value) -> Vec<NodeStatus> {
+    nodes["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|node| NodeStatus {
+            name: node["metadata"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            ready: is_ready(node),
+            version: node["status"]["nodeInfo"]["kubeletVersion"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .collect()
+}
+
+fn summarize_pods(pods: &Value) -> PodSummary {
+    let mut summary = PodSummary::default();
+    for pod in pods["items"].as_array().into_iter().flatten() {
+        summary.total += 1;
+        let phase = pod["status"]["phase"].as_str().unwrap_or_default();
+        let containers_ready = pod["status"]["containerStatuses"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .all(|c| c["ready"] == true);
+        let serving = match phase {
+            "Running" => {
+                summary.running += 1;
+                containers_ready
+            }
+            "Succeeded" => {
+                summary.succeeded += 1;
+                true
+            }
+            "Pending" => {
+                summary.pending += 1;
+                false
+            }
+            "Failed" => {
+                summary.failed += 1;
+                false
+            }
+            _ => false,
+        };
+        if !serving {
+            summary.not_ready.push(format!(
+                "{}/{}",
+                pod["metadata"]["namespace"].as_str().unwrap_or_default(),
+                pod["metadata"]["name"].as_str().unwrap_or_default()
+            ));
+        }
+    }
+    summary
+}
+
+/// Run `kubectl` against the merged `kubeconfigs` (if any) and `context`, returning stdout.
+pub async fn kubectl(
+    kubeconfigs: &[PathBuf],
+    context: Option<&str>,
+    args: &[&str],
+) -> Result<String, CliError> {
+    kubectl_with_input(kubeconfigs, context, args, None).await
+}
+
+/// [`kubectl`] for a read such as `get`, safe to repeat: retried per `policy` while the API
+/// server is unreachable or answers with a server error.
+pub async fn kubectl_read(
+    policy: &RetryPolicy,
+    kubeconfigs: &[PathBuf],
+    context: Option<&str>,
+    args: &[&str],
+) -> Result<String, CliError> {
+    retry::with_backoff_if(policy, "kubectl", is_transient, || {
+        kubectl(kubeconfigs, context, args)
+    })
+    .await
+}
+
+/// kubectl's messages for an unreachable API server or a 5xx answer, lowercased.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "unable to connect to the server",
+    "the connection to the server",
+    "connection refused",
+    "connection reset",
+    "i/o timeout",
+    "tls handshake timeout",
+    "(internalerror)",
+    "(serviceunavailable)",
+    "(servertimeout)",
+    "(timeout)",
+    "the server is currently unable to handle the request",
+    "bad gateway",
+    "gateway timeout",
+];
+
+/// Whether a kubectl failure is worth retrying: the cluster was out of reach or failed, as
+/// opposed to a missing object, a denied request, or no kubectl at all.
+fn is_transient(err: &CliError) -> bool {
+    let CliError::Kube(message) = err else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    TRANSIENT_ERRORS
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+/// Like [`kubectl`], feeding `input` to its stdin (e.g. for `-f -`).
+pub async fn kubectl_with_input(
+    kubeconfigs: &[PathBuf],
+    context: Option<&str>,
+    args: &[&str],
+    input: Option<&[u8]>,
+) -> Result<String, CliError> {
+    let output = kubectl_output(kubeconfigs, context, args, input).await?;
+    if !output.status.success() {
+        return Err(kubectl_failed(args, &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Server-side dry run of applying `manifests` in `context`: `None` if it would change
+/// nothing, else a unified diff (`kubectl diff` masks Secret values).
+pub async fn diff(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    manifests: &str,
+) -> Result<Option<String>, CliError> {
+    let manager = format!("--field-manager={}", deploy::FIELD_MANAGER);
+    let args = ["diff", "--server-side", &manager, "-f", "-"];
+    let output = kubectl_output(
+        kubeconfigs,
+        Some(context),
+        &args,
+        Some(manifests.as_bytes()),
+    )
+    .await?;
+    // 0: no differences, 1: differences, anything else: kubectl or the diff program failed.
+    match output.status.code() {
+        Some(0) => Ok(None),
+        Some(1) => Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned())),
+        _ => Err(kubectl_failed(&args, &output)),
+    }
+}
+
+/// Server-side apply `manifests` in `context`, returning the `kind/name` of each object.
+///
+/// With `force`, fields another manager owns are taken over instead of failing the apply.
+pub async fn apply(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    manifests: &str,
+    force: bool,
+) -> Result<Vec<String>, CliError> {
+    let manager = format!("--field-manager={}", deploy::FIELD_MANAGER);
+    let mut args = vec!["apply", "--server-side", &manager, "-f", "-", "-o", "name"];
+    if force {
+        args.push("--force-conflicts");
+    }
+    let applied = kubectl_with_input(
+        kubeconfigs,
+        Some(context),
+        &args,
+        Some(manifests.as_bytes()),
+    )
+    .await?;
+    Ok(applied.lines().map(str::to_string).collect())
+}
+
+/// Run `kubectl` like [`kubectl`], returning its output whether or not it succeeded.
+pub(crate) async fn kubectl_output(
+    kubeconfigs: &[PathBuf],
+    context: Option<&str>,
+    args: &[&str],
+    input: Option<&[u8]>,
+) -> Result<std::process::Output, CliError> {
+    let spawn_err = |e: std::io::Error| CliError::Kube(format!("kubectl: {e}"));
+    let mut child = kubectl_command(kubeconfigs, context)?
+        .args(args)
+        .stdin(if input.is_some() {
+            std::process::Stdio::piped()
+        } else {
+            std::process::Stdio::null()
+        })
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_err)?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input).await.map_err(spawn_err)?;
+    }
+    child.wait_with_output().await.map_err(spawn_err)
+}
+
+/// What guildsync does in a cluster, each needing its own RBAC permissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    /// `kube remote test`.
+    Test,
+    /// `kube remote deploy`.
+    Deploy,
+    /// `kube logs`.
+    Logs,
+    /// `kube secrets sync`.
+    Secrets,
+    /// `kube schedule export --apply`.
+    Schedule,
+    /// `kube port-forward`.
+    PortForward,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Capability::Test,
+        Capability::Deploy,
+        Capability::Logs,
+        Capability::Secrets,
+        Capability::Schedule,
+        Capability::PortForward,
+    ];
+
+    /// `(verb, resource)` pairs it needs in the namespace. Server-side apply is a `patch` that
+    /// also needs `create` for objects that do not exist yet.
+    fn permissions(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Capability::Test => &[
+                ("create", "jobs.batch"),
+                ("get", "jobs.batch"),
+                ("delete", "jobs.batch"),
+                ("create", "secrets"),
+                ("patch", "secrets"),
+                ("delete", "secrets"),
+                ("list", "pods"),
+                ("get", "pods/log"),
+            ],
+            Capability::Deploy => &[
+                ("create", "deployments.apps"),
+                ("patch", "deployments.apps"),
+                ("create", "configmaps"),
+                ("patch", "configmaps"),
+                ("create", "secrets"),
+                ("patch", "secrets"),
+            ],
+            Capability::Logs => &[("list", "pods"), ("get", "pods/log")],
+            Capability::Secrets => &[("create", "secrets"), ("patch", "secrets")],
+            Capability::Schedule => &[
+                ("create", "cronjobs.batch"),
+                ("patch", "cronjobs.batch"),
+                ("create", "persistentvolumeclaims"),
+                ("patch", "persistentvolumeclaims"),
+            ],
+            Capability::PortForward => &[("get", "services"), ("create", "pods/portforward")],
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Capability::Test => "test",
+            Capability::Deploy => "deploy",
+            Capability::Logs => "logs",
+            Capability::Secrets => "secrets",
+            Capability::Schedule => "schedule",
+            Capability::PortForward => "port-forward",
+        })
+    }
+}
+
+/// Whether a context is reachable and which capabilities it grants.
+#[derive(Debug, Serialize)]
+pub struct ContextCheck {
+    pub context: String,
+    /// Namespace checked; `None` for the context's own.
+    pub namespace: Option<String>,
+    /// Kubernetes version of the API server, if it answered.
+    pub server_version: Option<String>,
+    /// Why the API server could not be reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub capabilities: Vec<CapabilityCheck>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapabilityCheck {
+    pub capability: Capability,
+    pub ready: bool,
+    /// Permissions denied, as `verb resource`.
+    pub missing: Vec<String>,
+}
+
+impl ContextCheck {
+    /// Whether the API server answered and every checked capability is granted.
+    pub fn ready(&self) -> bool {
+        self.error.is_none() && self.capabilities.iter().all(|c| c.ready)
+    }
+}
+
+/// Check that `context` answers and grants `capabilities` in `namespace` (the context's own if
+/// `None`), asking `kubectl auth can-i` about each permission they need.
+pub async fn check_context(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: Option<&str>,
+    capabilities: &[Capability],
+) -> Result<ContextCheck, CliError> {
+    let mut check = ContextCheck {
+        context: context.to_string(),
+        namespace: namespace.map(str::to_string),
+        server_version: None,
+        error: None,
+        capabilities: Vec::new(),
+    };
+    let args = ["--request-timeout=10s", "get", "--raw", "/version"];
+    let output = kubectl_output(kubeconfigs, Some(context), &args, None).await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        check.error = Some(
+            stderr
+                .trim()
+                .lines()
+                .last()
+                .unwrap_or("unreachable")
+                .to_string(),
+        );
+        check.capabilities = capabilities
+            .iter()
+            .map(|&capability| CapabilityCheck {
+                capability,
+                ready: false,
+                missing: Vec::new(),
+            })
+            .collect();
+        return Ok(check);
+    }
+    let version: Value = serde_json::from_slice(&output.stdout).unwrap_or_default();
+    check.server_version = version["gitVersion"].as_str().map(str::to_string);
+
+    let mut permissions: Vec<(&str, &str)> = capabilities
+        .iter()
+        .flat_map(|c| c.permissions().iter().copied())
+        .collect();
+    permissions.sort();
+    permissions.dedup();
+    let answers = futures_util::future::join_all(permissions.iter().map(|(verb, resource)| {
+        let mut args = vec!["auth", "can-i", verb, resource];
+        if let Some(namespace) = namespace {
+            args.extend(["-n", namespace]);
+        }
+        async move {
+            // Exit 0 is "yes" and 1 is "no"; anything else (an error) counts as denied too.
+            let allowed = kubectl_output(kubeconfigs, Some(context), &args, None)
+                .await
+                .is_ok_and(|output| output.status.success());
+            ((*verb, *resource), allowed)
+        }
+    }))
+    .await;
+    let denied: Vec<(&str, &str)> = answers
+        .into_iter()
+        .filter(|(_, allowed)| !allowed)
+        .map(|(permission, _)| permission)
+        .collect();
+    check.capabilities = capabilities
+        .iter()
+        .map(|&capability| {
+            let missing: Vec<String> = capability
+                .permissions()
+                .iter()
+                .filter(|permission| denied.contains(permission))
+                .map(|(verb, resource)| format!("{verb} {resource}"))
+                .collect();
+            CapabilityCheck {
+                capability,
+                ready: missing.is_empty(),
+                missing,
+            }
+        })
+        .collect();
+    Ok(check)
+}
+
+/// `checks` as a table of contexts by capability (`ok`, `no`, or `-` when unreachable),
+/// followed by what each failing context is missing.
+pub fn readiness_matrix(checks: &[ContextCheck]) -> String {
+    let mut header = vec!["CONTEXT".to_string(), "SERVER".to_string()];
+    if let Some(first) = checks.first() {
+        header.extend(first.capabilities.iter().map(|c| c.capability.to_string()));
+    }
+    let mut rows = vec![header];
+    for check in checks {
+        let mut row = vec![
+            check.context.clone(),
+            check
+                .server_version
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        ];
+        row.extend(check.capabilities.iter().map(|c| {
+            match (&check.error, c.ready) {
+                (Some(_), _) => "-",
+                (None, true) => "ok",
+                (None, false) => "no",
+            }
+            .to_string()
+        }));
+        rows.push(row);
+    }
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    let mut table = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    for check in checks {
+        if let Some(error) = &check.error {
+            table.push_str(&format!("{}: unreachable: {error}\n", check.context));
+        }
+        for capability in check.capabilities.iter().filter(|c| !c.missing.is_empty()) {
+            table.push_str(&format!(
+                "{}: {} needs {}\n",
+                check.context,
+                capability.capability,
+                capability.missing.join(", ")
+            ));
+        }
+    }
+    table
+}
+
+/// Refuse to go on unless `context` is reachable and grants `capability` in `namespace`.
+pub async fn require_capability(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: Option<&str>,
+    capability: Capability,
+) -> Result<(), CliError> {
+    let check = check_context(kubeconfigs, context, namespace, &[capability]).await?;
+    if let Some(error) = check.error {
+        return Err(CliError::Kube(format!("context {context}: {error}")));
+    }
+    let missing = &check.capabilities[0].missing;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let scope = namespace.map_or(String::new(), |ns| format!(" in namespace {ns}"));
+    Err(CliError::Auth(format!(
+        "context {context} cannot {capability}{scope}: not allowed to {}; see `guildsync kube \
+         context check --context {context}`",
+        missing.join(", ")
+    )))
+}
+
+/// `kubectl` set up for the merged `kubeconfigs` (if any) and `context`.
+fn kubectl_command(
+    kubeconfigs: &[PathBuf],
+    context: Option<&str>,
+) -> Result<tokio::process::Command, CliError> {
+    let mut command = tokio::process::Command::new("kubectl");
+    // Without `--kubeconfig`, kubectl reads the inherited `$KUBECONFIG` itself, first file
+    // winning as usual.
+    if !kubeconfigs.is_empty() {
+        // kubectl merges KUBECONFIG first-wins; reverse so later `--kubeconfig` files win, like
+        // `contexts`.
+        let merged = std::env::join_paths(kubeconfigs.iter().rev())
+            .map_err(|e| CliError::Kube(format!("--kubeconfig: {e}")))?;
+        command.env("KUBECONFIG", merged);
+    }
+    if let Some(context) = context {
+        command.args(["--context", context]);
+    }
+    Ok(command)
+}
+
+fn kubectl_failed(args: &[&str], output: &std::process::Output) -> CliError {
+    CliError::Kube(format!(
+        "kubectl {}: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+/// Poll until every node reports `Ready`, returning the node count and time waited.
+pub async fn wait_for_nodes_ready(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    timeout: Duration,
+) -> Result<(usize, Duration), CliError> {
+    let start = Instant::now();
+    loop {
+        // The API server may not be reachable yet right after creation; keep polling.
+        match kubectl(kubeconfigs, Some(context), &["get", "nodes", "-o", "json"]).await {
+            Ok(json) => {
+                let (ready, total) = count_ready(&serde_json::from_str(&json)?);
+                tracing::debug!("{ready}/{total} nodes Ready");
+                if total > 0 && ready == total {
+                    return Ok((total, start.elapsed()));
+                }
+            }
+            Err(err) => tracing::debug!("waiting for API server: {err}"),
+        }
+        if start.elapsed() + POLL_INTERVAL > timeout {
+            return Err(CliError::Timeout(format!(
+                "nodes in context {context} not Ready after {}s",
+                timeout.as_secs()
+            )));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// `(ready, total)` nodes in a `kubectl get nodes -o json` document.
+fn count_ready(nodes: &Value) -> (usize, usize) {
+    let items = nodes["items"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let ready = items.iter().filter(|node| is_ready(node)).count();
+    (ready, items.len())
+}
+
+/// Whether `node` has a true `Ready` condition.
+fn is_ready(node: &Value) -> bool {
+    node["status"]["conditions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|c| c["type"] == "Ready" && c["status"] == "True")
+}
+
+/// What `kube remote test` runs.
+#[derive(Debug)]
+pub enum TestJob<'a> {
+    /// `guildsync doctor` in the image.
+    Doctor,
+    /// Export `guild` with `token`, then validate the dump.
+    Guild { guild: u64, token: &'a str },
+    /// A Job manifest of the user's (YAML or JSON).
+    Manifest(&'a str),
+}
+
+/// Create a test Job in `context`, stream its containers' logs to stderr as they run, wait for
+/// it to finish, and collect its exit code and last log lines. Unless `keep`, the Job, its pods,
+/// and the token Secret are deleted afterwards, also on failure, timeout, or Ctrl-C.
+pub async fn run_test_job(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    job: TestJob<'_>,
+    image: &str,
+    keep: bool,
+    policy: &RetryPolicy,
+) -> Result<TestReport, CliError> {
+    let secret = match job {
+        TestJob::Guild { token, .. } => {
+            Some(create_token_secret(kubeconfigs, context, token).await?)
+        }
+        _ => None,
+    };
+    let manifest = match job {
+        TestJob::Doctor => DEFAULT_TEST_JOB.replace("{image}", image),
+        TestJob::Guild { guild, .. } => GUILD_TEST_JOB
+            .replace("{image}", image)
+            .replace("{guild}", &guild.to_string())
+            .replace("{secret}", secret.as_ref().map_or("", |s| &s.1)),
+        TestJob::Manifest(manifest) => manifest.to_string(),
+    };
+
+    let created = create_job(kubeconfigs, context, &manifest).await;
+    let (namespace, name) = match created {
+        Ok(created) => created,
+        Err(err) => {
+            if let Some((namespace, secret)) = &secret {
+                delete(kubeconfigs, context, namespace, "secret", secret).await;
+            }
+            return Err(err);
+        }
+    };
+    tracing::info!("created job {namespace}/{name} in context {context}");
+    if let Some((_, secret)) = &secret {
+        own_secret(kubeconfigs, context, &namespace, &name, secret, policy).await;
+    }
+
+    let followed = tokio::select! {
+        followed = tokio::time::timeout(JOB_TIMEOUT, follow_job(kubeconfigs, context, &namespace, &name, policy)) => {
+            followed.unwrap_or_else(|_| Err(CliError::Timeout(format!(
+                "job {namespace}/{name} did not finish within {}s",
+                JOB_TIMEOUT.as_secs()
+            ))))
+        }
+        _ = tokio::signal::ctrl_c() => Err(CliError::Cancelled),
+    };
+
+    if keep {
+        tracing::info!("keeping job {namespace}/{name}");
+    } else {
+        delete(kubeconfigs, context, &namespace, "job", &name).await;
+        if let Some((namespace, secret)) = &secret {
+            delete(kubeconfigs, context, namespace, "secret", secret).await;
+        }
+    }
+    let (passed, exit_code, logs_tail) = followed?;
+    Ok(TestReport {
+        job: format!("{namespace}/{name}"),
+        passed,
+        exit_code,
+        logs_tail,
+    })
+}
+
+/// Create a Secret holding `token` for the guild test Job, returning its namespace and name.
+async fn create_token_secret(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    token: &str,
+) -> Result<(String, String), CliError> {
+    let secret = serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": {
+            "generateName": "guildsync-test-token-",
+            "labels": {
+                "app.kubernetes.io/name": "guildsync",
+                "app.kubernetes.io/component": "test",
+            },
+        },
+        "type": "Opaque",
+        "stringData": { "token": token },
+    });
+    let created: Value = serde_json::from_str(
+        &kubectl_with_input(
+            kubeconfigs,
+            Some(context),
+            &["create", "-f", "-", "-o", "json"],
+            Some(secret.to_string().as_bytes()),
+        )
+        .await?,
+    )?;
+    Ok(object_ref(&created))
+}
+
+/// Create the Job of `manifest`, returning its namespace and name.
+async fn create_job(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    manifest: &str,
+) -> Result<(String, String), CliError> {
+    let created: Value = serde_json::from_str(
+        &kubectl_with_input(
+            kubeconfigs,
+            Some(context),
+            &["create", "-f", "-", "-o", "json"],
+            Some(manifest.as_bytes()),
+        )
+        .await?,
+    )?;
+    if created["kind"] != "Job" {
+        // Whatever was created is not ours to run; remove it again.
+        let (namespace, name) = object_ref(&created);
+        if let Some(kind) = created["kind"].as_str() {
+            delete(kubeconfigs, context, &namespace, kind, &name).await;
+        }
+        return Err(CliError::Kube(format!(
+            "--manifest must describe a single Job, got {}",
+            created["kind"]
+        )));
+    }
+    Ok(object_ref(&created))
+}
+
+/// `(namespace, name)` of a created object.
+fn object_ref(object: &Value) -> (String, String) {
+    let field = |key: &str| object["metadata"][key].as_str().map(str::to_string);
+    (
+        field("namespace").unwrap_or_else(|| "default".to_string()),
+        field("name").unwrap_or_default(),
+    )
+}
+
+/// Make the Job own the token Secret, so Kubernetes removes it with the Job even if cleanup
+/// never runs (the Job itself expires through `ttlSecondsAfterFinished`).
+async fn own_secret(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: &str,
+    job: &str,
+    secret: &str,
+    policy: &RetryPolicy,
+) {
+    let owned = async {
+        let job: Value = serde_json::from_str(
+            &kubectl_read(
+                policy,
+                kubeconfigs,
+                Some(context),
+                &["-n", namespace, "get", "job", job, "-o", "json"],
+            )
+            .await?,
+        )?;
+        let patch = serde_json::json!({ "metadata": { "ownerReferences": [{
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "name": job["metadata"]["name"],
+            "uid": job["metadata"]["uid"],
+        }]}});
+        kubectl(
+            kubeconfigs,
+            Some(context),
+            &[
+                "-n",
+                namespace,
+                "patch",
+                "secret",
+                secret,
+                "--type",
+                "merge",
+                "-p",
+                &patch.to_string(),
+            ],
+        )
+        .await
+    };
+    if let Err(err) = owned.await {
+        tracing::warn!("secret {namespace}/{secret} is not owned by its job: {err}");
+    }
+}
+
+/// Delete `kind/name`, logging instead of failing: cleanup must not hide the test result.
+async fn delete(kubeconfigs: &[PathBuf], context: &str, namespace: &str, kind: &str, name: &str) {
+    let deleted = kubectl(
+        kubeconfigs,
+        Some(context),
+        &[
+            "-n",
+            namespace,
+            "delete",
+            kind,
+            name,
+            "--ignore-not-found",
+            "--wait=false",
+            "--cascade=background",
+        ],
+    )
+    .await;
+    match deleted {
+        Ok(_) => tracing::debug!("deleted {kind} {namespace}/{name}"),
+        Err(err) => tracing::warn!("could not delete {kind} {namespace}/{name}: {err}"),
+    }
+}
+
+/// Stream the logs of each container of the Job's pod as it starts, in order (init containers
+/// first), until the Job finishes. Returns whether it passed, the exit code, and the last log
+/// lines.
+async fn follow_job(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: &str,
+    job: &str,
+    policy: &RetryPolicy,
+) -> Result<(bool, Option<i32>, String), CliError> {
+    let ns = ["-n", namespace];
+    let selector = format!("job-name={job}");
+    let mut tail = std::collections::VecDeque::new();
+    let mut streamed = std::collections::HashSet::new();
+    loop {
+        let status: Value = serde_json::from_str(
+            &kubectl_read(
+                policy,
+                kubeconfigs,
+                Some(context),
+                &[&ns[..], &["get", "job", job, "-o", "json"]].concat(),
+            )
+            .await?,
+        )?;
+        let finished = job_finished(&status);
+        let pods: Value = serde_json::from_str(
+            &kubectl_read(
+                policy,
+                kubeconfigs,
+                Some(context),
+                &[&ns[..], &["get", "pods", "-l", &selector, "-o", "json"]].concat(),
+            )
+            .await?,
+        )?;
+        if let Some(pod) = pods["items"].as_array().and_then(|pods| pods.last()) {
+            let pod_name = pod["metadata"]["name"].as_str().unwrap_or_default();
+            for (container, started) in containers(pod) {
+                if !started {
+                    break;
+                }
+                if streamed.insert(format!("{pod_name}/{container}")) {
+                    stream_logs(
+                        kubeconfigs,
+                        context,
+                        namespace,
+                        pod_name,
+                        &container,
+                        &mut tail,
+                    )
+                    .await;
+                }
+            }
+        }
+        if let Some(passed) = finished {
+            let tail: Vec<String> = tail.into();
+            return Ok((passed, container_exit_code(&pods), tail.join("\n")));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// `(name, started)` of the pod's init containers, then its containers, in spec order.
+fn containers(pod: &Value) -> Vec<(String, bool)> {
+    let mut containers = Vec::new();
+    for (specs, statuses) in [
+        ("initContainers", "initContainerStatuses"),
+        ("containers", "containerStatuses"),
+    ] {
+        for spec in pod["spec"][specs].as_array().into_iter().flatten() {
+            let name = spec["name"].as_str().unwrap_or_default();
+            let started = pod["status"][statuses]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|s| s["name"] == name)
+                .is_some_and(|s| {
+                    s["state"]["running"].is_object() || s["state"]["terminated"].is_object()
+                });
+            containers.push((name.to_string(), started));
+        }
+    }
+    containers
+}
+
+/// Follow one container's logs to stderr until it exits, keeping the last lines in `tail`.
+async fn stream_logs(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: &str,
+    pod: &str,
+    container: &str,
+    tail: &mut std::collections::VecDeque<String>,
+) {
+    use tokio::io::AsyncBufReadExt;
+
+    let streamed = async {
+        let mut child = kubectl_command(kubeconfigs, Some(context))?
+            .args(["-n", namespace, "logs", "-f", pod, "-c", container])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        if let Some(stdout) = child.stdout.take() {
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            while let Some(line) = lines.next_line().await? {
+                eprintln!("[{container}] {line}");
+                if tail.len() == LOG_TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(format!("[{container}] {line}"));
+            }
+        }
+        Ok::<_, CliError>(child.wait().await?)
+    };
+    match streamed.await {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!("logs of {pod}/{container}: kubectl {status}"),
+        Err(err) => tracing::warn!("logs of {pod}/{container}: {err}"),
+    }
+}
+
+/// `Some(passed)` once the Job has a true `Complete` or `Failed` condition.
+fn job_finished(job: &Value) -> Option<bool> {
+    job["status"]["conditions"]
+        .as_array()?
+        .iter()
+        .filter(|c| c["status"] == "True")
+        .find_map(|c| match c["type"].as_str() {
+            Some("Complete") => Some(true),
+            Some("Failed") => Some(false),
+            _ => None,
+        })
+}
+
+/// Exit code of the most recent pod: that of the first container (init containers first) that
+/// failed, else of the first that terminated.
+fn container_exit_code(pods: &Value) -> Option<i32> {
+    let status = &pods["items"].as_array()?.last()?["status"];
+    let codes: Vec<i64> = ["initContainerStatuses", "containerStatuses"]
+        .iter()
+        .filter_map(|key| status[key].as_array())
+        .flatten()
+        .filter_map(|c| c["state"]["terminated"]["exitCode"].as_i64())
+        .collect();
+    let code = codes.iter().find(|&&code| code != 0).or(codes.first())?;
+    Some(*code as i32)
+}
+
+/// One container whose logs `kube logs` shows.
+#[derive(Debug, Serialize)]
+pub struct LogSource {
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+    pub lines: u64,
+    /// Why no (or not all) logs could be read, e.g. the container has not started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Which log lines `kube logs` asks for.
+#[derive(Debug, Default)]
+pub struct LogOptions<'a> {
+    pub follow: bool,
+    /// Most recent lines per container (all if `None`).
+    pub tail: Option<u64>,
+    /// Only lines newer than this duration (`30s`, `5m`, `1h`).
+    pub since: Option<&'a str>,
+    pub timestamps: bool,
+}
+
+/// Containers of the pods matching `selector` in `namespace` (every namespace if `None`), by
+/// pod name, limited to `container` if given.
+pub async fn log_sources(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    namespace: Option<&str>,
+    selector: &str,
+    container: Option<&str>,
+    policy: &RetryPolicy,
+) -> Result<Vec<LogSource>, CliError> {
+    let scope = match namespace {
+        Some(namespace) => vec!["-n", namespace],
+        None => vec!["--all-namespaces"],
+    };
+    let pods: Value = serde_json::from_str(
+        &kubectl_read(
+            policy,
+            kubeconfigs,
+            Some(context),
+            &[&scope[..], &["get", "pods", "-l", selector, "-o", "json"]].concat(),
+        )
+        .await?,
+    )?;
+    Ok(sources(&pods, container))
+}
+
+/// Write the logs of `sources` to stdout. With more than one source each line is prefixed with
+/// its pod, plus the container when a pod has several and the namespace when they differ.
+///
+/// Without `follow` the sources are shown one after another; with it they are followed
+/// together until every stream ends or `stop` resolves. Containers that have not started are
+/// skipped, and a stream kubectl fails to read is recorded in its `error`.
+pub async fn show_logs(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    sources: &mut [LogSource],
+    options: &LogOptions<'_>,
+    stop: impl std::future::Future<Output = ()>,
+) -> Result<(), CliError> {
+    let prefixes = log_prefixes(sources);
+    let streams = sources
+        .iter_mut()
+        .zip(prefixes)
+        .filter(|(source, _)| source.error.is_none())
+        .map(|(source, prefix)| container_logs(kubeconfigs, context, source, prefix, options));
+    if options.follow {
+        tokio::select! {
+            streamed = futures_util::future::try_join_all(streams) => {
+                streamed?;
+            }
+            () = stop => {}
+        }
+    } else {
+        for stream in streams {
+            stream.await?;
+        }
+    }
+    Ok(())
+}
+
+async fn container_logs(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    source: &mut LogSource,
+    prefix: String,
+    options: &LogOptions<'_>,
+) -> Result<(), CliError> {
+    use std::io::Write;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let mut args = vec![
+        "-n".to_string(),
+        source.namespace.clone(),
+        "logs".to_string(),
+        source.pod.clone(),
+        "-c".to_string(),
+        source.container.clone(),
+    ];
+    if options.follow {
+        args.push("-f".to_string());
+    }
+    if let Some(tail) = options.tail {
+        args.push(format!("--tail={tail}"));
+    }
+    if let Some(since) = options.since {
+        args.push(format!("--since={since}"));
+    }
+    if options.timestamps {
+        args.push("--timestamps".to_string());
+    }
+    let spawn_err = |e: std::io::Error| CliError::Kube(format!("kubectl: {e}"));
+    let mut child = kubectl_command(kubeconfigs, Some(context))?
+        .args(&args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_err)?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let shown = &mut source.lines;
+    let lines = async {
+        if let Some(stdout) = stdout {
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            while let Some(line) = lines.next_line().await? {
+                writeln!(std::io::stdout().lock(), "{prefix}{line}")?;
+                *shown += 1;
+            }
+        }
+        Ok::<_, CliError>(())
+    };
+    let errors = async {
+        let mut text = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut text).await;
+        }
+        text
+    };
+    let (lines, errors) = tokio::join!(lines, errors);
+    lines?;
+    let status = child.wait().await.map_err(spawn_err)?;
+    if !status.success() {
+        source.error = Some(match errors.trim() {
+            "" => format!("kubectl {status}"),
+            errors => errors.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// A `kube port-forward` target: a Service port forwarded to a local address.
+#[derive(Debug, Serialize)]
+pub struct Forward<'a> {
+    pub namespace: &'a str,
+    pub service: &'a str,
+    pub port: u16,
+    pub local_port: u16,
+    pub address: &'a str,
+}
+
+impl Forward<'_> {
+    /// `address:local_port -> service/name:port`, for messages.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}:{} -> service/{}:{}",
+            self.address, self.local_port, self.service, self.port
+        )
+    }
+}
+
+/// Counts reported when [`port_forward`] stops.
+#[derive(Debug, Default, Serialize)]
+pub struct ForwardStats {
+    /// Connections forwarded (`Handling connection` lines of kubectl).
+    pub connections: u64,
+    /// Times kubectl was restarted after the forward dropped.
+    pub reconnects: u64,
+}
+
+/// Keep `kubectl port-forward` running for `forward` until `stop` completes, restarting it
+/// whenever it exits (the pod behind the Service went away, the connection to the API server
+/// dropped). The Service must exist and expose `port`.
+///
+/// Restarts back off per `policy`; once that many attempts in a row fail before kubectl starts
+/// forwarding, the last failure is returned.
+pub async fn port_forward(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    forward: &Forward<'_>,
+    policy: &RetryPolicy,
+    stop: impl std::future::Future<Output = ()>,
+) -> Result<ForwardStats, CliError> {
+    let args = [
+        "-n",
+        forward.namespace,
+        "get",
+        "service",
+        forward.service,
+        "-o",
+        "jsonpath={.spec.ports[*].port}",
+    ];
+    let output = kubectl_output(kubeconfigs, Some(context), &args, None).await?;
+    if !output.status.success() {
+        if String::from_utf8_lossy(&output.stderr).contains("NotFound") {
+            return Err(CliError::NotFound(format!(
+                "no service {} in namespace {} (context {context})",
+                forward.service, forward.namespace
+            )));
+        }
+        return Err(kubectl_failed(&args, &output));
+    }
+    let ports = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !ports
+        .split_whitespace()
+        .any(|p| p == forward.port.to_string())
+    {
+        return Err(CliError::Usage(format!(
+            "service {} has no port {} (ports: {})",
+            forward.service,
+            forward.port,
+            ports.split_whitespace().collect::<Vec<_>>().join(", ")
+        )));
+    }
+    tracing::info!("forwarding {}; press Ctrl-C to stop", forward.describe());
+
+    let mut stats = ForwardStats::default();
+    let mut failures = 0;
+    tokio::pin!(stop);
+    loop {
+        let ended = tokio::select! {
+            ended = forward_once(kubeconfigs, context, forward, &mut stats, &mut failures) => ended?,
+            () = &mut stop => break,
+        };
+        if failures >= policy.max_retries.max(1) {
+            return Err(CliError::Kube(format!(
+                "port-forward {}: {ended}",
+                forward.describe()
+            )));
+        }
+        let delay = policy.backoff(failures);
+        failures += 1;
+        tracing::warn!(
+            "port-forward: {ended}; reconnecting in {}ms",
+            delay.as_millis()
+        );
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = &mut stop => break,
+        }
+        stats.reconnects += 1;
+    }
+    Ok(stats)
+}
+
+/// Run kubectl once, returning why it exited. `failures` is reset once it is forwarding.
+async fn forward_once(
+    kubeconfigs: &[PathBuf],
+    context: &str,
+    forward: &Forward<'_>,
+    stats: &mut ForwardStats,
+    failures: &mut u32,
+) -> Result<String, CliError> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let service = format!("service/{}", forward.service);
+    let ports = format!("{}:{}", forward.local_port, forward.port);
+    let spawn_err = |e: std::io::Error| CliError::Kube(format!("kubectl: {e}"));
+    let mut child = kubectl_command(kubeconfigs, Some(context))?
+        .args(["-n", forward.namespace, "port-forward", &service, &ports])
+        .args(["--address", forward.address])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_err)?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let lines = async {
+        if let Some(stdout) = stdout {
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line.starts_with("Forwarding from") {
+                    tracing::debug!("{line}");
+                    *failures = 0;
+                } else if line.starts_with("Handling connection") {
+                    stats.connections += 1;
+                    tracing::debug!("{line}");
+                }
+            }
+        }
+    };
+    let errors = async {
+        let mut text = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut text).await;
+        }
+        text
+    };
+    let ((), errors) = tokio::join!(lines, errors);
+    let status = child.wait().await.map_err(spawn_err)?;
+    // kubectl keeps running while it forwards, so an exit is always a drop or a failure.
+    Ok(match errors.trim().lines().last() {
+        Some(reason) => reason.to_string(),
+        None => format!("kubectl {status}"),
+    })
+}
+
+/// Log sources of the pods in a `kubectl get pods -o json` listing, by namespace and pod name,
+/// each pod's containers in spec order (init containers first).
+fn sources(pods: &Value, only: Option<&str>) -> Vec<LogSource> {
+    let mut items: Vec<&Value> = pods["items"].as_array().into_iter().flatten().collect();
+    let key = |pod: &Value| {
+        let field = |name: &str| {
+            pod["metadata"][name]
+                .as_str()
+                .unwrap_or_default()
+                .to_string()
+        };
+        (field("namespace"), field("name"))
+    };
+    items.sort_by_key(|pod| key(pod));
+    let mut sources = Vec::new();
+    for pod in items {
+        let (namespace, name) = key(pod);
+        for (container, started) in containers(pod) {
+            if only.is_some_and(|only| only != container) {
+                continue;
+            }
+            sources.push(LogSource {
+                namespace: namespace.clone(),
+                pod: name.clone(),
+                error: (!started).then(|| "container has not started".to_string()),
+                container,
+                lines: 0,
+            });
+        }
+    }
+    sources
+}
+
+/// Line prefixes telling `sources` apart: none for a single source.
+fn log_prefixes(sources: &[LogSource]) -> Vec<String> {
+    if sources.len() < 2 {
+        return vec![String::new(); sources.len()];
+    }
+    let namespaces = sources.iter().any(|s| s.namespace != sources[0].namespace);
+    let containers = sources
+        .windows(2)
+        .any(|pair| pair[0].namespace == pair[1].namespace && pair[0].pod == pair[1].pod);
+    sources
+        .iter()
+        .map(|s| {
+            let mut prefix = s.pod.clone();
+            if namespaces {
+                prefix = format!("{}/{prefix}", s.namespace);
+            }
+            if containers {
+                prefix = format!("{prefix}/{}", s.container);
+            }
+            format!("[{prefix}] ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cluster_listings_and_pod_phases() {
+        assert_eq!(
+            presence(KubeProvider::Kind, "guildsync", "other\nguildsync\n"),
+            Presence::Running
+        );
+        let k3d = r#"[{"name": "guildsync", "serversRunning": 0}]"#;
+        assert_eq!(
+            presence(KubeProvider::K3d, "guildsync", k3d),
+            Presence::Stopped
+        );
+        assert_eq!(presence(KubeProvider::K3d, "dev", k3d), Presence::Absent);
+        let minikube = r#"{"invalid": [], "valid": [{"Name": "guildsync", "Status": "Running"}]}"#;
+        assert_eq!(
+            presence(KubeProvider::Minikube, "guildsync", minikube),
+            Presence::Running
+        );
+        assert_eq!(
+            presence(KubeProvider::Minikube, "guildsync", ""),
+            Presence::Absent
+        );
+
+        let pods = serde_json::json!({ "items": [
+            { "metadata": { "namespace": "kube-system", "name": "dns" },
+              "status": { "phase": "Running", "containerStatuses": [{ "ready": true }] } },
+            { "metadata": { "namespace": "default", "name": "web" },
+              "status": { "phase": "Running", "containerStatuses": [{ "ready": false }] } },
+            { "metadata": { "namespace": "default", "name": "job" },
+              "status": { "phase": "Succeeded" } },
+            { "metadata": { "namespace": "default", "name": "new" },
+              "status": { "phase": "Pending" } },
+        ]});
+        let summary = summarize_pods(&pods);
+        assert_eq!((summary.total, summary.running, summary.pending), (4, 2, 1));
+        assert_eq!(summary.not_ready, ["default/web", "default/new"]);
+
+        assert!(validate_cluster_name("guildsync-2").is_ok());
+        assert!(validate_cluster_name("Guild").is_err());
+        assert!(validate_cluster_name("-x").is_err());
+    }
+
+    #[test]
+    fn orders_containers_and_prefers_failing_exit_codes() {
+        let pod = serde_json::json!({
+            "spec": {
+                "initContainers": [{ "name": "export" }],
+                "containers": [{ "name": "validate" }],
+            },
+            "status": {
+                "initContainerStatuses": [
+                    { "name": "export", "state": { "terminated": { "exitCode": 0 } } },
+                ],
+                "containerStatuses": [
+                    { "name": "validate", "state": { "waiting": {} } },
+                ],
+            },
+        });
+        assert_eq!(
+            containers(&pod),
+            [
+                ("export".to_string(), true),
+                ("validate".to_string(), false)
+            ]
+        );
+        let mut pods = serde_json::json!({ "items": [pod] });
+        assert_eq!(container_exit_code(&pods), Some(0));
+        pods["items"][0]["status"]["containerStatuses"][0]["state"] =
+            serde_json::json!({ "terminated": { "exitCode": 65 } });
+        assert_eq!(container_exit_code(&pods), Some(65));
+    }
+
+    #[test]
+    fn lists_log_sources_with_distinguishing_prefixes() {
+        let pod = |name: &str, containers: &[&str]| {
+            serde_json::json!({
+                "metadata": { "namespace": "bots", "name": name },
+                "spec": { "containers": containers.iter()
+                    .map(|c| serde_json::json!({ "name": c })).collect::<Vec<_>>() },
+                "status": { "containerStatuses": containers.iter()
+                    .map(|c| serde_json::json!({ "name": c, "state": { "running": {} } }))
+                    .collect::<Vec<_>>() },
+            })
+        };
+        let pods = serde_json::json!({ "items": [
+            pod("guildsync-b", &["watch-2"]),
+            pod("guildsync-a", &["watch-1", "watch-2"]),
+        ]});
+        let all = sources(&pods, None);
+        let names: Vec<_> = all
+            .iter()
+            .map(|s| format!("{}/{}", s.pod, s.container))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "guildsync-a/watch-1",
+                "guildsync-a/watch-2",
+                "guildsync-b/watch-2"
+            ]
+        );
+        assert_eq!(log_prefixes(&all)[0], "[guildsync-a/watch-1] ");
+
+        let one = sources(&pods, Some("watch-2"));
+        assert_eq!(log_prefixes(&one), ["[guildsync-a] ", "[guildsync-b] "]);
+        assert_eq!(log_prefixes(&one[..1]), [""]);
+    }
+
+    #[test]
+    fn tabulates_context_readiness() {
+        let capability = |capability, missing: &[&str]| CapabilityCheck {
+            capability,
+            ready: missing.is_empty(),
+            missing: missing.iter().map(|m| m.to_string()).collect(),
+        };
+        let checks = [
+            ContextCheck {
+                context: "dev".to_string(),
+                namespace: None,
+                server_version: Some("v1.30.0".to_string()),
+                error: None,
+                capabilities: vec![
+                    capability(Capability::Test, &[]),
+                    capability(Capability::Deploy, &["patch deployments.apps"]),
+                ],
+            },
+            ContextCheck {
+                context: "prod".to_string(),
+                namespace: None,
+                server_version: None,
+                error: Some("connection refused".to_string()),
+                capabilities: vec![
+                    capability(Capability::Test, &[]),
+                    capability(Capability::Deploy, &[]),
+                ],
+            },
+        ];
+        assert!(!checks[0].ready() && !checks[1].ready());
+        assert_eq!(
+            readiness_matrix(&checks),
+            "CONTEXT  SERVER   test  deploy\n\
+             dev      v1.30.0  ok    no\n\
+             prod     -        -     -\n\
+             dev: deploy needs patch deployments.apps\n\
+             prod: unreachable: connection refused\n"
+        );
+    }
+
+    #[test]
+    fn flags_merge_later_wins_and_kubeconfig_env_first_wins() {
+        let dir =
+            std::env::temp_dir().join(format!("guildsync-kubeconfigs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.yaml"), dir.join("b.yaml"));
+        for (file, cluster) in [(&a, "from-a"), (&b, "from-b")] {
+            std::fs::write(
+                file,
+                format!(
+                    "current-context: {cluster}\ncontexts:\n- name: dev\n  context: {{ cluster: {cluster} }}\n- name: {cluster}\n  context: {{ cluster: {cluster} }}\n"
+                ),
+            )
+            .unwrap();
+        }
+        let dev = |files: &[PathBuf]| {
+            let contexts = contexts(files).unwrap();
+            let current = contexts.iter().find(|c| c.current).unwrap().name.clone();
+            let dev = contexts.into_iter().find(|c| c.name == "dev").unwrap();
+            (dev.cluster.unwrap(), current)
+        };
+
+        assert_eq!(
+            dev(&[a.clone(), b.clone()]),
+            ("from-b".to_string(), "from-b".to_string())
+        );
+        let env = std::env::join_paths([&a, &b]).unwrap();
+        assert_eq!(default_kubeconfigs(Some(env)), [b.clone(), a.clone()]);
+        assert_eq!(dev(&[b, a]), ("from-a".to_string(), "from-a".to_string()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_unreachable_or_failing_servers_are_retried() {
+        let kube = |message: &str| CliError::Kube(format!("kubectl get pods: {message}"));
+        for transient in [
+            "Unable to connect to the server: dial tcp 10.0.0.1:6443: connect: connection refused",
+            "The connection to the server localhost:8080 was refused",
+            "Error from server (InternalError): an error on the server has prevented the request",
+            "Error from server (ServiceUnavailable): the server is currently unable to handle the request",
+        ] {
+            assert!(is_transient(&kube(transient)), "{transient}");
+        }
+        for permanent in [
+            "Error from server (NotFound): pods \"x\" not found",
+            "Error from server (Forbidden): pods is forbidden",
+            "error: context \"dev\" does not exist",
+        ] {
+            assert!(!is_transient(&kube(permanent)), "{permanent}");
+        }
+        assert!(!is_transient(&CliError::Kube(
+            "kubectl: No such file or directory (os error 2)".to_string()
+        )));
+    }
+}
@@ -0,0 +1,288 @@
+//! Named tmux layouts from `[terminal.layouts]`: windows of panes with their startup commands,
+//! directories, and environment.
+//!
+//! `terminal layout apply` creates a session from any of them; `terminal opencode attach` and
+//! `start` use the `opencode` layout (built in, unless the config defines its own). Pane
+//! commands may name `{agent}` and `{interpreter}`, filled in from the flags or `[terminal]`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::expand_tilde;
+use crate::tmux;
+
+/// Name of the layout the `terminal opencode` commands use.
+pub const OPENCODE: &str = "opencode";
+
+/// A session's windows, in order.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Layout {
+    /// Directory of every pane, unless a window or pane sets its own; `--dir` overrides it
+    /// (default: the current directory).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Environment of every pane.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    pub windows: Vec<Window>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Window {
+    pub name: String,
+    /// Relative to the layout's directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// tmux layout the panes are arranged in once created (`tiled`, `main-vertical`, ...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrange: Option<String>,
+    pub panes: Vec<Pane>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Pane {
+    /// Startup command; the pane drops to the user's shell when it exits. Just the shell if
+    /// unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Where the pane goes relative to the previous one; ignored for a window's first pane.
+    pub split: Split,
+    /// Width (or height) of the pane, in cells or as a percentage (`35%`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// Relative to the window's directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Select this pane when the session is created (default: the first window's first pane).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub focus: bool,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Beside the previous pane.
+    #[default]
+    Right,
+    /// Under the previous pane.
+    Below,
+}
+
+/// The layout `terminal opencode` used before layouts were configurable: one `agent` window
+/// with the agent on the left and the interpreter (or a shell) on the right.
+pub fn opencode() -> Layout {
+    Layout {
+        windows: vec![Window {
+            name: tmux::WINDOW.to_string(),
+            panes: vec![
+                Pane {
+                    command: Some("{agent}".to_string()),
+                    focus: true,
+                    ..Pane::default()
+                },
+                Pane {
+                    command: Some("{interpreter}".to_string()),
+                    size: Some("35%".to_string()),
+                    ..Pane::default()
+                },
+            ],
+            ..Window::default()
+        }],
+        ..Layout::default()
+    }
+}
+
+/// Programs the `{agent}` and `{interpreter}` placeholders stand for.
+#[derive(Copy, Clone, Debug)]
+pub struct Programs<'a> {
+    pub agent: &'a str,
+    pub interpreter: Option<&'a str>,
+}
+
+/// A window as it is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowPlan {
+    pub name: String,
+    pub arrange: Option<String>,
+    pub panes: Vec<PanePlan>,
+}
+
+/// A pane as it is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PanePlan {
+    /// Shell command the pane runs (see [`tmux::pane_command`]).
+    pub command: String,
+    /// The program of `command`, placeholders filled in; `None` for a plain shell.
+    pub program: Option<String>,
+    pub dir: PathBuf,
+    pub env: BTreeMap<String, String>,
+    pub split: Split,
+    pub size: Option<String>,
+    pub focus: bool,
+    /// Whether the command names `{agent}`.
+    pub agent: bool,
+}
+
+impl Layout {
+    /// Problems with the layout called `name`, for `Config::validate`.
+    pub fn problems(&self, name: &str) -> Vec<String> {
+        let at = format!("terminal.layouts.{name}");
+        let mut problems = Vec::new();
+        if self.windows.is_empty() {
+            problems.push(format!("{at}: needs at least one window"));
+        }
+        let mut names = std::collections::HashSet::new();
+        for (w, window) in self.windows.iter().enumerate() {
+            let at = format!("{at}.windows[{w}]");
+            if window.name.is_empty() || window.name.contains(['.', ':']) {
+                problems.push(format!(
+                    "{at}: name {:?} must be non-empty without '.' or ':'",
+                    window.name
+                ));
+            } else if !names.insert(&window.name) {
+                problems.push(format!("{at}: window name {:?} is used twice", window.name));
+            }
+            if window.panes.is_empty() {
+                problems.push(format!("{at}: needs at least one pane"));
+            }
+            for (p, pane) in window.panes.iter().enumerate() {
+                if let Some(size) = &pane.size
+                    && !valid_size(size)
+                {
+                    problems.push(format!(
+                        "{at}.panes[{p}]: size {size:?} must be a number of cells or a percentage"
+                    ));
+                }
+            }
+            let envs = std::iter::once(&window.env).chain(window.panes.iter().map(|p| &p.env));
+            problems.extend(env_problems(&at, envs));
+        }
+        problems.extend(env_problems(&at, [&self.env]));
+        problems
+    }
+
+    /// The windows to create, with directories resolved against the session's `dir` and
+    /// placeholders filled in from `programs`.
+    pub fn plan(&self, dir: &Path, programs: Programs<'_>) -> Vec<WindowPlan> {
+        let base = dir.to_path_buf();
+        let focused = self.windows.iter().flat_map(|w| &w.panes).any(|p| p.focus);
+        let mut windows = Vec::new();
+        for (w, window) in self.windows.iter().enumerate() {
+            let window_dir = window
+                .dir
+                .as_ref()
+                .map_or(base.clone(), |d| resolve(&base, d));
+            let panes = window
+                .panes
+                .iter()
+                .enumerate()
+                .map(|(p, pane)| {
+                    let template = pane.command.as_deref().unwrap_or_default();
+                    let program = template
+                        .replace("{agent}", programs.agent)
+                        .replace("{interpreter}", programs.interpreter.unwrap_or_default());
+                    let program = Some(program.trim().to_string()).filter(|p| !p.is_empty());
+                    let mut env = self.env.clone();
+                    env.extend(window.env.clone());
+                    env.extend(pane.env.clone());
+                    PanePlan {
+                        command: tmux::pane_command(program.as_deref()),
+                        program,
+                        dir: pane
+                            .dir
+                            .as_ref()
+                            .map_or(window_dir.clone(), |d| resolve(&window_dir, d)),
+                        env,
+                        split: pane.split,
+                        size: pane.size.clone(),
+                        focus: pane.focus || (!focused && w == 0 && p == 0),
+                        agent: template.contains("{agent}"),
+                    }
+                })
+                .collect();
+            windows.push(WindowPlan {
+                name: window.name.clone(),
+                arrange: window.arrange.clone(),
+                panes,
+            });
+        }
+        windows
+    }
+}
+
+/// Variable names of `envs` tmux cannot set.
+fn env_problems<'a>(
+    at: &str,
+    envs: impl IntoIterator<Item = &'a BTreeMap<String, String>>,
+) -> Vec<String> {
+    envs.into_iter()
+        .flat_map(BTreeMap::keys)
+        .filter(|key| key.is_empty() || key.contains('='))
+        .map(|key| format!("{at}: environment variable {key:?} is not valid"))
+        .collect()
+}
+
+/// `path` (tilde expanded) relative to `base`.
+fn resolve(base: &Path, path: &Path) -> PathBuf {
+    base.join(expand_tilde(path))
+}
+
+fn valid_size(size: &str) -> bool {
+    let digits = size.strip_suffix('%').unwrap_or(size);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) && digits != "0"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_fill_placeholders_and_inherit_dirs_and_env() {
+        let programs = Programs {
+            agent: "codex",
+            interpreter: None,
+        };
+        let plan = opencode().plan(Path::new("/work"), programs);
+        assert_eq!(plan.len(), 1);
+        let [agent, interpreter] = &plan[0].panes[..] else {
+            panic!("two panes expected");
+        };
+        assert_eq!(agent.program.as_deref(), Some("codex"));
+        assert!(agent.agent && agent.focus);
+        assert_eq!(interpreter.program, None);
+        assert_eq!(interpreter.command, tmux::pane_command(None));
+        assert_eq!(interpreter.dir, Path::new("/work"));
+
+        let layout: Layout = toml::from_str(
+            r#"
+            env = { A = "1" }
+            [[windows]]
+            name = "dev"
+            dir = "app"
+            env = { A = "2" }
+            panes = [{ command = "make watch", env = { B = "3" } }, { split = "below", size = "0" }]
+            [[windows]]
+            name = "dev"
+            panes = []
+            "#,
+        )
+        .unwrap();
+        let plan = layout.plan(Path::new("/work"), programs);
+        let pane = &plan[0].panes[0];
+        assert_eq!(pane.dir, Path::new("/work/app"));
+        assert_eq!(pane.env["A"], "2");
+        assert_eq!(pane.env["B"], "3");
+        assert!(!pane.agent && pane.focus);
+        assert_eq!(layout.problems("dev").len(), 3);
+        assert!(opencode().problems(OPENCODE).is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+//! Library side of `guildsync`: configuration, errors, and shared helpers used by the CLI.
+
+pub mod anonymize;
+pub mod assets;
+pub mod atomic_file;
+pub mod attachments;
+pub mod auth;
+pub mod bridge;
+pub mod build_info;
+pub mod capture;
+pub mod chart;
+pub mod checkpoint;
+pub mod chunks;
+pub mod completions;
+pub mod compression;
+pub mod config;
+pub mod csv_export;
+pub mod deploy;
+pub mod diff;
+pub mod dirwatch;
+pub mod discord;
+pub mod doctor;
+pub mod encryption;
+pub mod error;
+pub mod format;
+pub mod gateway;
+pub mod health;
+pub mod hooks;
+pub mod import;
+pub mod journal;
+pub mod kube;
+pub mod layout;
+pub mod lint;
+pub mod manifest;
+pub mod mcp;
+pub mod migrate;
+pub mod ndjson;
+pub mod notify;
+pub mod permissions;
+pub mod progress;
+pub mod prompt;
+pub mod prune;
+pub mod query;
+pub mod ratelimit;
+pub mod redact;
+pub mod relay;
+pub mod render;
+pub mod replay;
+pub mod retry;
+pub mod runner;
+pub mod schedule;
+pub mod schema;
+pub mod sessions;
+pub mod shell;
+pub mod signing;
+pub mod sqlite;
+pub mod ssh;
+pub mod ssh_config;
+pub mod stats;
+pub mod timestamp;
+pub mod tmux;
+pub mod tui;
+pub mod warnings;
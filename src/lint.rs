@@ -0,0 +1,239 @@
+//! Lint rules for `format lint`: problems the schema allows but a guild admin likely does not
+//! want, such as roles that can ping everyone or overwrites that grant administrator.
+//!
+//! Every rule has a default [`Level`]; `[formats.lint]` overrides it per rule name.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::discord::text;
+use crate::permissions;
+
+/// How seriously a rule's findings are taken.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// The rule is not checked.
+    Off,
+    Warning,
+    /// Findings fail `format lint`.
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Off => "off",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        })
+    }
+}
+
+/// Levels `--deny` turns into errors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Deny {
+    Warnings,
+}
+
+/// One lint rule.
+pub struct Rule {
+    /// Name used in output and in `[formats.lint]`.
+    pub name: &'static str,
+    pub level: Level,
+    /// What the rule checks, for `format lint --list`.
+    pub summary: &'static str,
+    /// Pointer and message of every offending item.
+    check: fn(&Value) -> Vec<(String, String)>,
+}
+
+/// Every rule, by name.
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "channel-missing-topic",
+        level: Level::Warning,
+        summary: "text, announcement, forum, and media channels without a topic",
+        check: channel_missing_topic,
+    },
+    Rule {
+        name: "overwrite-grants-administrator",
+        level: Level::Error,
+        summary: "channel permission overwrites that allow ADMINISTRATOR",
+        check: overwrite_grants_administrator,
+    },
+    Rule {
+        name: "role-mentions-everyone",
+        level: Level::Warning,
+        summary: "roles that can mention @everyone and @here",
+        check: role_mentions_everyone,
+    },
+];
+
+/// Channel types that have a topic.
+const TOPIC_CHANNEL_TYPES: &[u64] = &[0, 5, 15, 16];
+
+/// One problem found by a rule.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub level: Level,
+    /// JSON pointer of the offending item.
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            self.level, self.rule, self.pointer, self.message
+        )
+    }
+}
+
+/// Run every rule not turned off in `levels` against `document`, in rule order.
+pub fn lint(document: &Value, levels: &BTreeMap<String, Level>) -> Vec<Finding> {
+    RULES
+        .iter()
+        .flat_map(|rule| {
+            let level = levels.get(rule.name).copied().unwrap_or(rule.level);
+            let findings = match level {
+                Level::Off => Vec::new(),
+                _ => (rule.check)(document),
+            };
+            findings.into_iter().map(move |(pointer, message)| Finding {
+                rule: rule.name,
+                level,
+                pointer,
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Names in `levels` that are not rules.
+pub fn unknown_rules(levels: &BTreeMap<String, Level>) -> Vec<&str> {
+    levels
+        .keys()
+        .map(String::as_str)
+        .filter(|name| RULES.iter().all(|rule| rule.name != *name))
+        .collect()
+}
+
+fn channel_missing_topic(document: &Value) -> Vec<(String, String)> {
+    items(document, "channels")
+        .filter(|(_, channel)| {
+            channel["type"]
+                .as_u64()
+                .is_some_and(|t| TOPIC_CHANNEL_TYPES.contains(&t))
+                && channel["topic"]
+                    .as_str()
+                    .is_none_or(|t| t.trim().is_empty())
+        })
+        .map(|(pointer, channel)| {
+            (
+                pointer,
+                format!("{} has no topic", describe("channel", channel)),
+            )
+        })
+        .collect()
+}
+
+fn overwrite_grants_administrator(document: &Value) -> Vec<(String, String)> {
+    let roles: Vec<&Value> = items(document, "roles").map(|(_, role)| role).collect();
+    let mut findings = Vec::new();
+    for (pointer, channel) in items(document, "channels") {
+        let overwrites = channel["permission_overwrites"]
+            .as_array()
+            .into_iter()
+            .flatten();
+        for (i, overwrite) in overwrites.enumerate() {
+            if permissions::has(&overwrite["allow"], "ADMINISTRATOR") {
+                let target = permissions::target(&text(&overwrite["id"]), overwrite, &roles);
+                findings.push((
+                    format!("{pointer}/permission_overwrites/{i}"),
+                    format!(
+                        "{}: overwrite for {target} allows ADMINISTRATOR",
+                        describe("channel", channel)
+                    ),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+fn role_mentions_everyone(document: &Value) -> Vec<(String, String)> {
+    items(document, "roles")
+        .filter(|(_, role)| permissions::has(&role["permissions"], "MENTION_EVERYONE"))
+        .map(|(pointer, role)| {
+            (
+                pointer,
+                format!("{} can mention @everyone and @here", describe("role", role)),
+            )
+        })
+        .collect()
+}
+
+/// The items of a top-level list with their pointers.
+fn items<'a>(document: &'a Value, section: &'a str) -> impl Iterator<Item = (String, &'a Value)> {
+    document[section]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(move |(i, item)| (format!("/{section}/{i}"), item))
+}
+
+/// `<kind> <name> (<id>)`.
+fn describe(kind: &str, item: &Value) -> String {
+    format!("{kind} {} ({})", text(&item["name"]), text(&item["id"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_report_at_their_configured_level() {
+        let document = serde_json::json!({
+            "roles": [
+                { "id": "1", "name": "@everyone", "permissions": "131072" },
+                { "id": "2", "name": "mod", "permissions": "8" },
+            ],
+            "channels": [
+                { "id": "20", "name": "general", "type": 0, "topic": "hi" },
+                { "id": "21", "name": "dev", "type": 0, "topic": " ",
+                  "permission_overwrites": [{ "id": "2", "type": 0, "allow": "8", "deny": "0" }] },
+                { "id": "22", "name": "Voice", "type": 2 },
+            ],
+        });
+
+        let findings = lint(&document, &BTreeMap::new());
+        let lines: Vec<String> = findings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "warning[channel-missing-topic] /channels/1: channel dev (21) has no topic",
+                "error[overwrite-grants-administrator] /channels/1/permission_overwrites/0: \
+                 channel dev (21): overwrite for role mod (2) allows ADMINISTRATOR",
+                "warning[role-mentions-everyone] /roles/0: role @everyone (1) can mention \
+                 @everyone and @here",
+            ]
+        );
+
+        let levels = BTreeMap::from([
+            ("channel-missing-topic".to_string(), Level::Off),
+            ("role-mentions-everyone".to_string(), Level::Error),
+            ("no-such-rule".to_string(), Level::Warning),
+        ]);
+        let levels_found: Vec<Level> = lint(&document, &levels).iter().map(|f| f.level).collect();
+        assert_eq!(levels_found, [Level::Error, Level::Error]);
+        assert_eq!(unknown_rules(&levels), ["no-such-rule"]);
+    }
+}
@@ -1,31 +1,151 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::builder::BoolishValueParser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use futures_util::StreamExt;
+use guildsync::anonymize;
+use guildsync::assets;
+use guildsync::atomic_file::write_atomic;
+use guildsync::attachments;
+use guildsync::auth;
+use guildsync::bridge;
+use guildsync::build_info::{BUILD_INFO, LONG_VERSION};
+use guildsync::capture;
+use guildsync::chart;
+use guildsync::checkpoint::{self, Checkpoint};
+use guildsync::chunks::{self, SplitBy};
+use guildsync::completions;
+use guildsync::compression::Codec;
+use guildsync::config::{
+    Config, FormatsConfig, GuildExport, KubeLocalConfig, KubeProvider, expand_tilde,
+};
+use guildsync::csv_export::{self, Entity, Quote};
+use guildsync::deploy;
+use guildsync::diff::{self, DiffArgs};
+use guildsync::dirwatch::DirWatch;
+use guildsync::discord::{self, AuditLogFilters, ExportFilters};
+use guildsync::doctor;
+use guildsync::encryption;
+use guildsync::error::{CliError, ExitCode};
+use guildsync::format::{self, ConvertArgs, GuildFormat, ImportSection, Prefer, ValidateArgs};
+use guildsync::gateway;
+use guildsync::health;
+use guildsync::hooks;
+use guildsync::import;
+use guildsync::journal::{self, Journal};
+use guildsync::kube;
+use guildsync::layout::{self, Programs};
+use guildsync::lint::{self, Deny};
+use guildsync::manifest;
+use guildsync::mcp;
+use guildsync::migrate;
+use guildsync::notify;
+use guildsync::progress;
+use guildsync::prompt;
+use guildsync::prune;
+use guildsync::query::{self, QueryArgs};
+use guildsync::redact::{self, NameRedaction};
+use guildsync::relay;
+use guildsync::render::{self, Style};
+use guildsync::replay;
+use guildsync::retry::RetryPolicy;
+use guildsync::runner;
+use guildsync::schedule;
+use guildsync::schema;
+use guildsync::sessions::{self, State};
+use guildsync::shell::{self, Builtin};
+use guildsync::signing;
+use guildsync::sqlite;
+use guildsync::ssh;
+use guildsync::stats::{self, StatsArgs};
+use guildsync::timestamp;
+use guildsync::tmux;
+use guildsync::tui;
+use guildsync::warnings::Warnings;
+use rustyline::error::ReadlineError;
 use serde::Serialize;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "guildsync",
-    about = "Sync Discord guild dumps with terminal workflows; scaffold + spec",
-    long_about = "A Rust CLI scaffold for synchronizing Discord guild dumps/upload formats with terminal workflows (OpenCode/Codex/tmux/interpreters/MCP).\n\nThis repository intentionally provides a coherent CLI surface + README specification, but does not implement real Discord/Kubernetes/SSH operations yet."
+    version,
+    long_version = LONG_VERSION,
+    about = "Sync Discord guild dumps with terminal workflows",
+    long_about = "A Rust CLI for synchronizing Discord guild dumps/upload formats with terminal workflows (OpenCode/Codex/tmux/interpreters/MCP), on-demand Kubernetes clusters, and remote SSH hosts.\n\nExit codes are listed in the README."
 )]
 struct Cli {
     /// Path to a config file (defaults to platform config location).
-    #[arg(long)]
+    #[arg(long, env = "GUILDSYNC_CONFIG")]
     config: Option<PathBuf>,
 
     /// Emit machine-readable JSON output.
-    #[arg(long)]
+    #[arg(long, env = "GUILDSYNC_JSON", value_parser = BoolishValueParser::new())]
     json: bool,
 
+    /// Layout of `--json` output: indented, or one object per line (ndjson).
+    #[arg(long, value_enum, env = "GUILDSYNC_JSON_STYLE", default_value_t = JsonStyle::Pretty)]
+    json_style: JsonStyle,
+
     /// Logging verbosity.
-    #[arg(long, value_enum, default_value_t = LogLevel::Info)]
+    #[arg(long, value_enum, env = "GUILDSYNC_LOG", default_value_t = LogLevel::Info)]
     log: LogLevel,
 
+    /// Also append timestamped logs to this file (at the `--log` level).
+    #[arg(long, value_name = "PATH", env = "GUILDSYNC_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Retries for transient network failures (overrides `[retry] max_retries`).
+    #[arg(long, value_name = "N", env = "GUILDSYNC_MAX_RETRIES")]
+    max_retries: Option<u32>,
+
+    /// Initial retry backoff in milliseconds, doubled per attempt (overrides `[retry] base_ms`).
+    #[arg(long, value_name = "MS", env = "GUILDSYNC_RETRY_BASE_MS")]
+    retry_base_ms: Option<u64>,
+
+    /// Abort the whole command after this many seconds (subcommand timeouts take precedence).
+    #[arg(long, value_name = "SECS", env = "GUILDSYNC_TIMEOUT")]
+    timeout: Option<u64>,
+
+    /// Show planned changes for destructive actions without performing them.
+    #[arg(long, env = "GUILDSYNC_DRY_RUN", value_parser = BoolishValueParser::new())]
+    dry_run: bool,
+
+    /// Never draw progress bars (they are also off without a TTY or with `--json`).
+    #[arg(long, env = "GUILDSYNC_NO_PROGRESS", value_parser = BoolishValueParser::new())]
+    no_progress: bool,
+
+    /// age identity file for reading encrypted dumps (overrides `[formats] identity_file`).
+    #[arg(long, value_name = "PATH", env = "GUILDSYNC_IDENTITY")]
+    identity: Option<PathBuf>,
+
+    /// Skip confirmation prompts for destructive actions.
+    #[arg(short = 'y', long, env = "GUILDSYNC_YES", value_parser = BoolishValueParser::new())]
+    yes: bool,
+
     #[command(subcommand)]
     command: Command,
 }
 
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum JsonStyle {
+    Pretty,
+    Compact,
+}
+
+impl JsonStyle {
+    fn render<T: Serialize>(self, value: &T) -> serde_json::Result<String> {
+        match self {
+            JsonStyle::Pretty => serde_json::to_string_pretty(value),
+            JsonStyle::Compact => serde_json::to_string(value),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, ValueEnum)]
 enum LogLevel {
     Error,
@@ -35,52 +155,383 @@ enum LogLevel {
     Trace,
 }
 
+impl From<LogLevel> for tracing::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
-    /// Discord guild dump/export/import operations (stub).
+    /// Discord guild dump/export/import operations.
     Discord {
+        /// Bot token (overrides the config's `token_env`, the keyring, and the config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
         #[command(subcommand)]
         command: DiscordCommand,
     },
 
-    /// Validate and convert between dump/upload formats (stub).
+    /// Validate and convert between dump/upload formats.
     Format {
         #[command(subcommand)]
         command: FormatCommand,
     },
 
-    /// Integrations for terminal workflows (tmux, OpenCode/Codex) (stub).
+    /// Browse a dump interactively: channel tree, messages, search, and jump-to-date.
+    Tui {
+        /// Dump file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Channel or thread to open first.
+        #[arg(long, value_name = "ID")]
+        channel: Option<u64>,
+    },
+
+    /// Integrations for terminal workflows (tmux, OpenCode/Codex).
     Terminal {
         #[command(subcommand)]
         command: TerminalCommand,
     },
 
-    /// Kubernetes orchestration (local on-demand + remote test/deploy) (stub).
+    /// Post a message or embeds to a Discord channel or webhook, e.g. from CI or a script.
+    Notify {
+        /// Bot token (overrides the config's `token_env`, the keyring, and the config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Discord channel or thread ID to post to with the bot token (takes precedence over
+        /// `--webhook`).
+        #[arg(long, value_name = "ID", required_unless_present = "webhook")]
+        channel: Option<u64>,
+
+        /// Webhook URL to post to instead; needs no bot token.
+        #[arg(
+            long,
+            value_name = "URL",
+            env = "DISCORD_WEBHOOK_URL",
+            hide_env_values = true
+        )]
+        webhook: Option<String>,
+
+        /// Message text; `-` reads it from stdin.
+        #[arg(long, value_name = "TEXT", required_unless_present = "embed_json")]
+        message: Option<String>,
+
+        /// JSON file with an embed object or an array of up to 10; `-` reads it from stdin.
+        #[arg(long, value_name = "PATH")]
+        embed_json: Option<PathBuf>,
+
+        /// Name to post under (webhooks only).
+        #[arg(long, requires = "webhook")]
+        username: Option<String>,
+
+        /// Let `@everyone`, role, and user mentions in the message ping (off by default).
+        #[arg(long)]
+        allow_mentions: bool,
+    },
+
+    /// Import dump/upload files as they appear or change in a directory, until Ctrl-C.
+    WatchDir {
+        /// Directory to watch.
+        #[arg(value_name = "DIR")]
+        path: PathBuf,
+
+        /// Bot token (overrides the config's `token_env`, the keyring, and the config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Discord guild ID to import into.
+        #[arg(long)]
+        guild: u64,
+
+        /// Only validate files and show what importing them would change (same as the global
+        /// `--dry-run`).
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Import a file once it has not changed for this long.
+        #[arg(long, value_name = "MS", default_value_t = 2000)]
+        debounce: u64,
+
+        /// Pause between checks of the directory.
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        interval: u64,
+
+        /// Refuse to import a file that differs from the live guild in more than N settings and
+        /// items; `--yes` overrides.
+        #[arg(long, value_name = "N", default_value_t = 50)]
+        max_changes: usize,
+
+        /// Only import these sections of each file (repeatable).
+        #[arg(long, value_enum, value_name = "SECTION")]
+        only: Vec<ImportSection>,
+    },
+
+    /// Kubernetes orchestration (local on-demand + remote test/deploy).
     Kube {
+        /// kubeconfig file to use instead of `$KUBECONFIG` or `~/.kube/config` (repeatable;
+        /// merged in order, later files win).
+        #[arg(
+            long,
+            value_name = "PATH",
+            env = "GUILDSYNC_KUBECONFIG",
+            value_delimiter = ':'
+        )]
+        kubeconfig: Vec<PathBuf>,
+
         #[command(subcommand)]
         command: KubeCommand,
     },
 
-    /// SSH operations against remote computers (including over VPN) (stub).
+    /// SSH operations against remote computers (including over VPN).
     Ssh {
         #[command(subcommand)]
         command: SshCommand,
     },
+
+    /// Interactive shell running guildsync commands with a shared dump, guild, and token.
+    Shell,
+
+    /// Model Context Protocol server exposing guildsync actions as tools.
+    Mcp {
+        #[command(subcommand)]
+        command: McpCommand,
+    },
+
+    /// Inspect and lint the effective configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Print version and build metadata (structured with `--json`).
+    Version,
+
+    /// Check that external tools, credentials, and config are ready.
+    Doctor,
+
+    /// Shell completion scripts.
+    Completions {
+        #[command(subcommand)]
+        command: CompletionsCommand,
+    },
+
+    /// Manage the Discord bot token stored in the system keyring.
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommand {
+    /// Check a bot token against Discord and store it in the system keyring.
+    Login {
+        /// Read the token from stdin instead of prompting for it.
+        #[arg(long)]
+        token_stdin: bool,
+
+        /// Store the token without checking it against the Discord API.
+        #[arg(long)]
+        no_verify: bool,
+    },
+
+    /// Remove the stored bot token from the system keyring.
+    Logout,
+
+    /// Report which source the bot token would be taken from.
+    Status,
+
+    /// Generate an ed25519 signing key for `format sign`, store it in the system keyring, and
+    /// print its public key.
+    Keygen {
+        /// Replace a key already stored (signatures it made stay valid for its public key).
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Print the public key of the signing key, for verifiers' `trusted_keys`.
+    PublicKey,
+}
+
+#[derive(Subcommand, Debug)]
+enum CompletionsCommand {
+    /// Print the completion script for a shell to stdout.
+    Print {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Write the completion script to the shell's conventional location.
+    Install {
+        /// Shell to install for [default: detected from $SHELL].
+        #[arg(value_enum)]
+        shell: Option<Shell>,
+
+        /// Overwrite an existing completion file.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 enum DiscordCommand {
-    /// Export a guild to the guild dump format.
+    /// Export a guild to the guild dump (or upload) format.
     Export {
+        /// Discord guild ID.
+        #[arg(long, required_unless_present = "all")]
+        guild: Option<u64>,
+
+        /// Output path for the dump JSON.
+        #[arg(long, required_unless_present = "all")]
+        out: Option<PathBuf>,
+
+        /// Export every guild listed as `[[discord.guilds]]` in the config, each with its own
+        /// filters and output path.
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "guild", "out", "format", "incremental", "since", "with_attachments",
+                "with_assets", "manifest", "encrypt_to", "channels", "categories", "users",
+                "after", "before", "compress",
+            ]
+        )]
+        all: bool,
+
+        /// Guilds exported at once with `--all` (default from `[discord]` config, 2).
+        #[arg(long, value_name = "N", requires = "all")]
+        concurrency: Option<usize>,
+
+        /// Write a re-importable upload file instead of a dump.
+        #[arg(long, value_enum, default_value_t = GuildFormat::Dump)]
+        format: GuildFormat,
+
+        /// Also export messages, fetching only those newer than the checkpoint and merging
+        /// them into the existing dump at `--out`.
+        #[arg(long)]
+        incremental: bool,
+
+        /// Export messages newer than this message ID (snowflake), for channels without a
+        /// checkpoint.
+        #[arg(long, value_name = "SNOWFLAKE")]
+        since: Option<u64>,
+
+        /// Checkpoint file for `--incremental` (default `<out>.state.json`).
+        #[arg(long, value_name = "PATH", requires = "incremental")]
+        state: Option<PathBuf>,
+
+        /// Also export messages and download their attachments into `<out>.attachments/`.
+        #[arg(long)]
+        with_attachments: bool,
+
+        /// Attachment downloads in flight at once (default from `[discord]` config, 4).
+        #[arg(long, value_name = "N", requires = "with_attachments")]
+        attachment_concurrency: Option<usize>,
+
+        /// Embed emoji, sticker, and event cover images in the dump (otherwise only their CDN URLs).
+        #[arg(long)]
+        with_assets: bool,
+
+        /// Also write `<out>.manifest.json` with SHA-256 checksums of the dump and its
+        /// attachments, for `format verify`.
+        #[arg(long)]
+        manifest: bool,
+
+        /// Encrypt the dump to this age recipient, `age1…` (repeatable).
+        #[arg(long = "encrypt-to", value_name = "RECIPIENT")]
+        encrypt_to: Vec<String>,
+
+        /// Only export this channel (repeatable).
+        #[arg(long = "channel", value_name = "ID")]
+        channels: Vec<u64>,
+
+        /// Only export the channels in this category (repeatable).
+        #[arg(long = "category", value_name = "ID")]
+        categories: Vec<u64>,
+
+        /// Only export messages by this user (repeatable; implies exporting messages).
+        #[arg(long = "user", value_name = "ID")]
+        users: Vec<u64>,
+
+        /// Only export messages after this message ID, date (`YYYY-MM-DD`), or RFC 3339 time.
+        #[arg(long, value_name = "WHEN", value_parser = discord::parse_bound)]
+        after: Option<u64>,
+
+        /// Only export messages before this message ID, date, or RFC 3339 time.
+        #[arg(long, value_name = "WHEN", value_parser = discord::parse_bound)]
+        before: Option<u64>,
+
+        /// Compress the output [default: as the extension of `--out` implies, `.gz` or `.zst`].
+        #[arg(long, value_enum)]
+        compress: Option<Codec>,
+    },
+
+    /// Export the guild audit log to a versioned JSON file.
+    AuditLog {
         /// Discord guild ID.
         #[arg(long)]
         guild: u64,
 
-        /// Output path for the dump JSON.
+        /// Output path for the audit log JSON.
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Only entries of this audit log event type (e.g. 20 for member kicks).
+        #[arg(long, value_name = "N")]
+        action_type: Option<u16>,
+
+        /// Only entries made by this user ID.
+        #[arg(long, value_name = "ID")]
+        actor: Option<u64>,
+    },
+
+    /// Export the guild member list to a versioned JSON file.
+    Members {
+        /// Discord guild ID.
+        #[arg(long)]
+        guild: u64,
+
+        /// Output path for the member list JSON.
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Hash usernames and nicknames into stable pseudonyms, or drop them.
+        #[arg(long, value_enum, value_name = "MODE")]
+        redact: Option<NameRedaction>,
+    },
+
+    /// Append a guild's message and member events from the gateway to an NDJSON file until
+    /// Ctrl-C.
+    Watch {
+        /// Discord guild ID.
+        #[arg(long)]
+        guild: u64,
+
+        /// NDJSON file to append events to (created if missing).
         #[arg(long)]
         out: PathBuf,
     },
 
+    /// Compare two dump files, or a live guild against a dump.
+    Diff {
+        /// Old and new dump files; with `--guild`, only the new one.
+        #[arg(value_name = "DUMP", num_args = 1..=2, required = true)]
+        files: Vec<PathBuf>,
+
+        /// Compare this live guild against the dump (what importing it would change).
+        #[arg(long)]
+        guild: Option<u64>,
+    },
+
     /// Import a dump/upload file into a guild.
     Import {
         /// Input file path.
@@ -91,159 +542,5994 @@ enum DiscordCommand {
         #[arg(long)]
         guild: u64,
 
-        /// Only validate inputs and show planned actions.
+        /// Only validate inputs and show planned actions (same as the global `--dry-run`).
         #[arg(long)]
         dry_run: bool,
-    },
-}
 
-#[derive(Subcommand, Debug)]
-enum FormatCommand {
-    /// Validate a dump or upload-format file.
-    Validate {
-        /// Input file path.
+        /// Refuse to import if the file differs from the live guild in more than N settings
+        /// and items; `--yes` overrides.
+        #[arg(long, value_name = "N", default_value_t = 50)]
+        max_changes: usize,
+
+        /// Only import these sections, ignoring the rest of the file (repeatable).
+        #[arg(long, value_enum, value_name = "SECTION")]
+        only: Vec<ImportSection>,
+
+        /// Where to journal the applied changes for `discord undo` (default
+        /// `<in>.journal-<UTC time>.json`).
         #[arg(long, value_name = "PATH")]
-        r#in: PathBuf,
+        journal: Option<PathBuf>,
 
-        /// Expected format.
-        #[arg(long, value_enum)]
-        format: Option<GuildFormat>,
-    },
-}
+        /// Re-post the file's messages in the channels the import creates, through webhooks
+        /// named after the original authors.
+        #[arg(long)]
+        replay_messages: bool,
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
-enum GuildFormat {
-    Dump,
-    Upload,
-}
+        /// Refuse to replay more than N messages.
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1000,
+            requires = "replay_messages"
+        )]
+        max_messages: usize,
 
-#[derive(Subcommand, Debug)]
-enum TerminalCommand {
-    /// Attach the current workflow to an OpenCode/Codex session (documentation only).
-    Opencode {
-        #[command(subcommand)]
-        command: TerminalOpenCodeCommand,
+        /// Pause between replayed messages (default from `[discord]` config, 2000).
+        #[arg(long, value_name = "MS", requires = "replay_messages")]
+        replay_interval: Option<u64>,
     },
-}
 
-#[derive(Subcommand, Debug)]
-enum TerminalOpenCodeCommand {
-    /// Attach to a tmux session intended to host OpenCode/Codex and interpreters.
-    Attach {
-        /// tmux session name.
-        #[arg(long)]
-        tmux: Option<String>,
+    /// Revert an import using the journal it wrote.
+    Undo {
+        /// Journal written by `discord import`.
+        #[arg(long, value_name = "PATH")]
+        journal: PathBuf,
     },
-}
 
-#[derive(Subcommand, Debug)]
-enum KubeCommand {
-    /// Local on-demand cluster workflows (kind/k3d/minikube) (stub).
-    Local {
+    /// Discord guild templates (server templates).
+    Template {
         #[command(subcommand)]
-        command: KubeLocalCommand,
+        command: TemplateCommand,
     },
 
-    /// Remote cluster workflows (test/deploy) by kube context (stub).
-    Remote {
+    /// Tidy a guild by archiving what it no longer uses.
+    Prune {
         #[command(subcommand)]
-        command: KubeRemoteCommand,
+        command: PruneCommand,
     },
 }
 
 #[derive(Subcommand, Debug)]
-enum KubeLocalCommand {
-    Up,
-    Down,
-    Status,
-}
+enum TemplateCommand {
+    /// Stage a dump's roles, categories, and channels in a guild and save them as its template.
+    Create {
+        /// Dump or upload file whose structure the template takes.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
 
-#[derive(Subcommand, Debug)]
-enum KubeRemoteCommand {
-    /// Run on-demand tests against a remote cluster.
-    Test {
-        /// kubeconfig context name.
+        /// Staging guild the structure is applied to; its template is created or replaced.
         #[arg(long)]
-        context: String,
-    },
+        guild: u64,
 
-    /// Deploy to a remote cluster.
-    Deploy {
-        /// kubeconfig context name.
+        /// Template name (default: the dump's guild name).
         #[arg(long)]
-        context: String,
+        name: Option<String>,
+
+        /// Template description.
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Where to journal the changes to the staging guild (default
+        /// `<in>.journal-<UTC time>.json`).
+        #[arg(long, value_name = "PATH")]
+        journal: Option<PathBuf>,
     },
 }
 
 #[derive(Subcommand, Debug)]
-enum SshCommand {
-    /// Execute a command on a remote host.
-    Exec {
-        /// Host (SSH config host alias or hostname).
+enum PruneCommand {
+    /// Write a plan archiving inactive channels and removing empty categories and unused roles,
+    /// for `discord import` to apply.
+    Plan {
+        /// Dump to analyse (from `discord export`).
+        #[arg(long, value_name = "PATH", required_unless_present = "guild")]
+        r#in: Option<PathBuf>,
+
+        /// Analyse this live guild instead of a dump.
+        #[arg(long, conflicts_with = "in")]
+        guild: Option<u64>,
+
+        /// Output path for the plan (a dump with the proposed changes made).
         #[arg(long)]
-        host: String,
+        out: PathBuf,
 
-        /// Command to execute remotely.
-        #[arg(last = true, required = true)]
-        cmd: Vec<String>,
+        /// Days without messages after which a channel is inactive.
+        #[arg(long, value_name = "N", default_value_t = 90)]
+        days: u64,
+
+        /// Move inactive channels into this category instead of deleting them.
+        #[arg(long, value_name = "ID")]
+        archive_category: Option<u64>,
+
+        /// Member list (from `discord members`) for finding roles nobody holds; with `--guild`
+        /// it is fetched instead.
+        #[arg(long, value_name = "PATH")]
+        members: Option<PathBuf>,
     },
 }
 
-#[derive(Serialize)]
-struct JsonOut<'a> {
-    ok: bool,
-    action: &'a str,
-    message: &'a str,
-}
+#[derive(Subcommand, Debug)]
+enum FormatCommand {
+    /// Validate a dump or upload-format file.
+    Validate(ValidateArgs),
 
-fn main() {
-    let cli = Cli::parse();
+    /// Convert a dump to an upload file, or an upload file back to a dump.
+    Convert(ConvertArgs),
 
-    // Note: this is a scaffold. All commands intentionally return "not implemented".
-    let action = match &cli.command {
-        Command::Discord { command } => match command {
-            DiscordCommand::Export { .. } => "discord.export",
-            DiscordCommand::Import { .. } => "discord.import",
-        },
-        Command::Format { command } => match command {
-            FormatCommand::Validate { .. } => "format.validate",
-        },
-        Command::Terminal { command } => match command {
-            TerminalCommand::Opencode { command } => match command {
-                TerminalOpenCodeCommand::Attach { .. } => "terminal.opencode.attach",
-            },
-        },
-        Command::Kube { command } => match command {
-            KubeCommand::Local { command } => match command {
-                KubeLocalCommand::Up => "kube.local.up",
-                KubeLocalCommand::Down => "kube.local.down",
-                KubeLocalCommand::Status => "kube.local.status",
-            },
-            KubeCommand::Remote { command } => match command {
-                KubeRemoteCommand::Test { .. } => "kube.remote.test",
-                KubeRemoteCommand::Deploy { .. } => "kube.remote.deploy",
-            },
-        },
-        Command::Ssh { command } => match command {
-            SshCommand::Exec { .. } => "ssh.exec",
-        },
-    };
+    /// Summarize a dump's messages: counts per channel and author, attachments, emoji, and
+    /// growth over time.
+    Stats {
+        #[command(flatten)]
+        args: StatsArgs,
 
-    if cli.json {
-        let out = JsonOut {
-            ok: false,
-            action,
-            message: "scaffold only; not implemented",
-        };
-        println!(
-            "{}",
-            serde_json::to_string_pretty(&out).unwrap_or_else(|_| "{\"ok\":false}".to_string())
-        );
-        return;
-    }
+        /// Rows shown per table (`--json` lists everything).
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        top: usize,
+    },
+
+    /// Extract data from a dump or upload file with a JMESPath expression.
+    Query {
+        #[command(flatten)]
+        args: QueryArgs,
+
+        /// Print string results without JSON quotes.
+        #[arg(long, short = 'r')]
+        raw: bool,
+    },
+
+    /// Render a channel's messages as a readable HTML or Markdown transcript.
+    Render {
+        /// Dump file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Channel or thread whose messages to render.
+        #[arg(long, value_name = "ID")]
+        channel: u64,
+
+        /// Output path for the transcript.
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Transcript format [default: from the `--out` extension, `.html` or `.md`].
+        #[arg(long = "as", value_enum, value_name = "FORMAT")]
+        style: Option<Style>,
+    },
+
+    /// Re-hash a dump and its attachments against their manifest; exit 1 on any mismatch.
+    Verify {
+        /// Dump file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Manifest path [default: `<in>.manifest.json`].
+        #[arg(long, value_name = "PATH")]
+        manifest: Option<PathBuf>,
+    },
+
+    /// Sign a file with the signing key, writing a detached signature.
+    Sign {
+        /// File to sign, as it will be shipped (compressed, if it is).
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the signature [default: `<in>.sig`].
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+
+    /// Check a detached signature against trusted public keys; exit 1 unless it is valid.
+    VerifySignature {
+        /// Signed file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Signature path [default: `<in>.sig`].
+        #[arg(long, value_name = "PATH")]
+        signature: Option<PathBuf>,
 
-    eprintln!("{action}: scaffold only; not implemented");
-    eprintln!("config: {:?}", cli.config);
-    eprintln!("log: {:?}", cli.log);
-    std::process::exit(2);
+        /// Trusted base64 public key (repeatable; added to `[formats] trusted_keys`).
+        #[arg(long = "public-key", value_name = "KEY")]
+        public_keys: Vec<String>,
+    },
+
+    /// Load a dump into a SQLite database for SQL queries.
+    ExportSqlite {
+        /// Dump file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the database (replaced if it exists).
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Member list (from `discord members`) to load into the `members` table.
+        #[arg(long, value_name = "PATH")]
+        members: Option<PathBuf>,
+    },
+
+    /// Export a dump's members, messages, or roles as CSV for spreadsheets.
+    ExportCsv {
+        /// Dump file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the CSV file.
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// What each row describes.
+        #[arg(long, value_enum)]
+        entity: Entity,
+
+        /// Columns to write, in order (default: all of the entity's).
+        #[arg(long, value_name = "COLUMN", value_delimiter = ',')]
+        columns: Vec<String>,
+
+        /// Member list (from `discord members`); required for `--entity members`.
+        #[arg(long, value_name = "PATH")]
+        members: Option<PathBuf>,
+
+        /// Cell separator, such as `;` for spreadsheets in locales with decimal commas.
+        #[arg(long, value_name = "CHAR", default_value_t = ',')]
+        delimiter: char,
+
+        /// When to quote cells.
+        #[arg(long, value_enum, default_value_t = Quote::Necessary)]
+        quote: Quote,
+
+        /// Start the file with a UTF-8 byte order mark, so Excel reads non-ASCII text correctly.
+        #[arg(long)]
+        bom: bool,
+    },
+
+    /// Check a dump or upload file against lint rules; exit 1 on errors.
+    Lint {
+        /// Input file path.
+        #[arg(long, value_name = "PATH", required_unless_present = "list")]
+        r#in: Option<PathBuf>,
+
+        /// Treat findings of this level as errors (`--deny warnings` for CI gates).
+        #[arg(long, value_enum, value_name = "LEVEL")]
+        deny: Vec<Deny>,
+
+        /// List the rules with their configured levels instead.
+        #[arg(long, conflicts_with_all = ["in", "deny"])]
+        list: bool,
+    },
+
+    /// Compare two dump files: settings, items, and messages; exit 1 if they differ.
+    Diff(DiffArgs),
+
+    /// Upgrade a dump or upload file to a newer format version.
+    Migrate {
+        /// Input file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Version to upgrade to [default: the configured version of the file's format].
+        #[arg(long, value_name = "N")]
+        to_version: Option<u64>,
+
+        /// Output path for the upgraded file (may equal `--in`).
+        #[arg(long, value_name = "PATH", required_unless_present = "check")]
+        out: Option<PathBuf>,
+
+        /// Only report what would change; exit 1 if the file needs upgrading.
+        #[arg(long, conflicts_with = "out")]
+        check: bool,
+    },
+
+    /// Split a dump into a base file and chunks of its messages, listed in a manifest.
+    Split {
+        /// Dump to split.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// New directory for the manifest, base, and chunk files.
+        #[arg(long, value_name = "DIR")]
+        out_dir: PathBuf,
+
+        /// How messages are divided into chunks.
+        #[arg(long, value_enum, default_value_t = SplitBy::Channel)]
+        by: SplitBy,
+
+        /// Largest chunk file, e.g. `50M` (units K, M, G); required with `--by size`.
+        #[arg(long, value_name = "SIZE", value_parser = chunks::parse_size,
+              required_if_eq("by", "size"))]
+        max_size: Option<u64>,
+    },
+
+    /// Reassemble a dump split by `format split`.
+    Join {
+        /// Directory written by `format split`.
+        #[arg(long, value_name = "DIR")]
+        r#in: PathBuf,
+
+        /// Output path for the reassembled dump.
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+    },
+
+    /// Hash user identities and strip message content/invite codes for sharing.
+    Redact {
+        /// Input file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the redacted file.
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Field name to leave untouched (repeatable).
+        #[arg(long, value_name = "FIELD")]
+        keep: Vec<String>,
+    },
+
+    /// Replace users with stable pseudonyms, scrub message content, and drop attachments.
+    Anonymize {
+        /// Input file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the anonymized file.
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Mapping file of originals to pseudonyms, reused if it exists and updated (keep it
+        /// private: it re-identifies every user).
+        #[arg(long, value_name = "PATH")]
+        mapping: Option<PathBuf>,
+    },
+
+    /// Sort keys and id-bearing arrays so equal guilds serialize identically.
+    Canonicalize {
+        /// Input file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the canonical file (may equal `--in`).
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+    },
+
+    /// Canonicalize a dump or upload file and normalize its timestamps, for committing to git.
+    Normalize {
+        /// Input file path.
+        #[arg(long, value_name = "PATH")]
+        r#in: PathBuf,
+
+        /// Output path for the normalized file (may equal `--in`).
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+    },
+
+    /// Merge a `"partial": true` delta dump into a full base dump, or combine overlapping dumps.
+    Merge {
+        /// Full dump to merge into.
+        #[arg(
+            long,
+            value_name = "PATH",
+            required_unless_present = "in",
+            requires = "delta"
+        )]
+        base: Option<PathBuf>,
+
+        /// Partial dump with new and changed items.
+        #[arg(long, value_name = "PATH", requires = "base")]
+        delta: Option<PathBuf>,
+
+        /// Overlapping dumps to combine, oldest first (repeatable, at least two).
+        #[arg(long = "in", value_name = "PATH", conflicts_with_all = ["base", "delta"])]
+        r#in: Vec<PathBuf>,
+
+        /// Output path for the merged, full dump.
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+
+        /// Side that wins when the same id has different content [default: delta, or newer
+        /// with `--in`].
+        #[arg(long, value_enum)]
+        prefer: Option<Prefer>,
+    },
+
+    /// Print the JSON Schema of a format version, for external tooling.
+    Schema {
+        /// Format the schema describes.
+        #[arg(long, value_enum)]
+        format: GuildFormat,
+
+        /// Format version (default: the configured `[formats]` version).
+        #[arg(long, value_name = "N")]
+        version: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TerminalCommand {
+    /// Work with OpenCode/Codex sessions.
+    Opencode {
+        #[command(subcommand)]
+        command: TerminalOpenCodeCommand,
+    },
+
+    /// Create tmux sessions from the layouts of `[terminal.layouts]`.
+    Layout {
+        #[command(subcommand)]
+        command: TerminalLayoutCommand,
+    },
+
+    /// Relay new messages of a Discord channel into a tmux pane or a FIFO until Ctrl-C.
+    Send {
+        /// Bot token (overrides the config's `token_env`, the keyring, and the config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Discord channel ID to relay.
+        #[arg(long)]
+        channel: u64,
+
+        /// tmux pane to type messages into (`session:window.pane`, `%3`, ...).
+        #[arg(long, value_name = "TARGET", required_unless_present = "fifo")]
+        pane: Option<String>,
+
+        /// Named pipe (from `mkfifo`) to write messages to instead, one line each.
+        #[arg(long, value_name = "PATH", conflicts_with = "pane")]
+        fifo: Option<PathBuf>,
+
+        /// Line format (default: `[terminal] send_template`), with placeholders such as
+        /// `{author}` and `{content}`.
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Only relay messages of this user ID (repeatable; adds to
+        /// `[terminal] bridge_allowed_users`).
+        #[arg(long = "allow-user", value_name = "ID")]
+        allow_users: Vec<u64>,
+
+        /// Press Enter after each message typed into the pane; needs an allowlist.
+        #[arg(long, conflicts_with = "fifo")]
+        enter: bool,
+
+        /// Relay messages from bots too (skipped by default, so relays cannot loop).
+        #[arg(long)]
+        include_bots: bool,
+
+        /// Poll the channel's messages instead of listening on the gateway.
+        #[arg(long)]
+        poll: bool,
+
+        /// Seconds between polls.
+        #[arg(long, value_name = "SECS", default_value_t = 5, requires = "poll")]
+        interval: u64,
+    },
+
+    /// Post a tmux pane's output to a Discord channel or thread as code blocks.
+    Capture {
+        /// Bot token (overrides the config's `token_env`, the keyring, and the config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// tmux pane to capture (`session:window.pane`, `%3`, ...).
+        #[arg(long, value_name = "TARGET")]
+        pane: String,
+
+        /// Discord channel or thread ID to post to.
+        #[arg(long)]
+        channel: u64,
+
+        /// Keep capturing and post new output until Ctrl-C.
+        #[arg(long)]
+        follow: bool,
+
+        /// Seconds between captures.
+        #[arg(long, value_name = "SECS", default_value_t = 2, requires = "follow")]
+        interval: u64,
+
+        /// Post the last N lines, scrollback included, instead of the visible pane.
+        #[arg(long, value_name = "N", conflicts_with = "follow")]
+        lines: Option<usize>,
+
+        /// Convert colored output to plain text instead of an `ansi` code block.
+        #[arg(long)]
+        plain: bool,
+
+        /// Messages posted per capture at most; earlier lines beyond them are left out.
+        #[arg(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        max_messages: u64,
+
+        /// Pause between messages (default from `[terminal] capture_interval_ms`, 1000).
+        #[arg(long, value_name = "MS")]
+        post_interval: Option<u64>,
+    },
+
+    /// Relay a channel into a tmux session and post the session's output back, until Ctrl-C.
+    Bridge {
+        /// Bot token (overrides the config's `token_env`, the keyring, and the config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Discord channel or thread ID to bridge.
+        #[arg(long)]
+        channel: u64,
+
+        /// tmux session to bridge: its agent pane if started by `terminal opencode start`,
+        /// otherwise its active pane.
+        #[arg(long, value_name = "SESSION")]
+        tmux: String,
+
+        /// Only relay messages of this user ID (repeatable; adds to
+        /// `[terminal] bridge_allowed_users`).
+        #[arg(long = "allow-user", value_name = "ID")]
+        allow_users: Vec<u64>,
+
+        /// Line format (default: `[terminal] send_template`).
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Press Enter after each relayed message; needs an allowlist.
+        #[arg(long)]
+        enter: bool,
+
+        /// Relay messages from other bots too.
+        #[arg(long)]
+        include_bots: bool,
+
+        /// Poll the channel every N seconds instead of listening on the gateway.
+        #[arg(long, value_name = "SECS")]
+        poll: Option<u64>,
+
+        /// Seconds between captures of the pane.
+        #[arg(long, value_name = "SECS", default_value_t = 2)]
+        interval: u64,
+
+        /// Post output as plain text instead of an `ansi` code block.
+        #[arg(long)]
+        plain: bool,
+
+        /// Messages posted per capture at most; earlier lines beyond them are left out.
+        #[arg(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        max_messages: u64,
+
+        /// Pause between posted messages (default from `[terminal] capture_interval_ms`, 1000).
+        #[arg(long, value_name = "MS")]
+        post_interval: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TerminalOpenCodeCommand {
+    /// Attach to a tmux session hosting OpenCode/Codex and an interpreter, creating it if needed.
+    Attach {
+        /// tmux session name (default: `[terminal] tmux_default_session`).
+        #[arg(long, value_name = "SESSION")]
+        tmux: Option<String>,
+
+        /// Program for the agent pane (default: `[terminal] agent_command`).
+        #[arg(long, value_name = "COMMAND")]
+        agent: Option<String>,
+
+        /// Program for the interpreter pane (default: `[terminal] interpreter_command`, else
+        /// your shell).
+        #[arg(long, value_name = "COMMAND")]
+        interpreter: Option<String>,
+
+        /// Working directory of a new session's panes (default: the layout's `dir`, else the
+        /// current directory).
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Layout of a new session, from `[terminal.layouts]`.
+        #[arg(long, value_name = "NAME", default_value = layout::OPENCODE)]
+        layout: String,
+
+        /// Only create the session; do not attach to it.
+        #[arg(long)]
+        detach: bool,
+    },
+
+    /// Start OpenCode/Codex in a new tmux session that guildsync tracks.
+    Start {
+        /// tmux session name (default: `[terminal] tmux_default_session`).
+        #[arg(long, value_name = "SESSION")]
+        tmux: Option<String>,
+
+        /// Agent to run (default: `[terminal] agent_command`).
+        #[arg(long, value_name = "COMMAND")]
+        agent: Option<String>,
+
+        /// Program for the interpreter pane (default: `[terminal] interpreter_command`, else
+        /// your shell).
+        #[arg(long, value_name = "COMMAND")]
+        interpreter: Option<String>,
+
+        /// Working directory of the session's panes (default: the layout's `dir`, else the
+        /// current directory).
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Layout of the session, from `[terminal.layouts]`; the agent runs in its first
+        /// `{agent}` pane.
+        #[arg(long, value_name = "NAME", default_value = layout::OPENCODE)]
+        layout: String,
+    },
+
+    /// Stop a tracked session, killing its agent and interpreter.
+    Stop {
+        /// tmux session name (default: `[terminal] tmux_default_session`).
+        #[arg(long, value_name = "SESSION", conflicts_with = "all")]
+        tmux: Option<String>,
+
+        /// Stop every tracked session.
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// List tracked sessions and their health.
+    List,
+
+    /// Report a tracked session's health; exits 1 unless its agent is running.
+    Status {
+        /// tmux session name (default: `[terminal] tmux_default_session`).
+        #[arg(long, value_name = "SESSION")]
+        tmux: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TerminalLayoutCommand {
+    /// Create a tmux session with a layout's windows, panes, commands, and environment.
+    Apply {
+        /// Layout name (a key of `[terminal.layouts]`, or the built-in `opencode`).
+        name: String,
+
+        /// tmux session name (default: the layout name).
+        #[arg(long, value_name = "SESSION")]
+        tmux: Option<String>,
+
+        /// Working directory of the panes (default: the layout's `dir`, else the current
+        /// directory).
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Attach to the session once it is created.
+        #[arg(long)]
+        attach: bool,
+    },
+
+    /// List the configured layouts.
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeCommand {
+    /// Local on-demand cluster lifecycle (kind/k3d/minikube).
+    Local {
+        #[command(subcommand)]
+        command: KubeLocalCommand,
+    },
+
+    /// Remote cluster workflows (test/deploy) by kube context.
+    Remote {
+        #[command(subcommand)]
+        command: KubeRemoteCommand,
+    },
+
+    /// kubeconfig contexts and whether guildsync can work in them.
+    Context {
+        #[command(subcommand)]
+        command: KubeContextCommand,
+    },
+
+    /// Helm packaging.
+    Chart {
+        #[command(subcommand)]
+        command: KubeChartCommand,
+    },
+
+    /// Kubernetes Secrets kept in sync with local credentials.
+    Secrets {
+        #[command(subcommand)]
+        command: KubeSecretsCommand,
+    },
+
+    /// CronJobs running guildsync on a schedule.
+    Schedule {
+        #[command(subcommand)]
+        command: KubeScheduleCommand,
+    },
+
+    /// Show the logs of guildsync pods, found by label, prefixing each line with its pod.
+    Logs {
+        /// kubeconfig context name.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: String,
+
+        /// Namespace of the pods [default: `kube.deploy.namespace`, else default].
+        #[arg(long, short = 'n', conflicts_with = "all_namespaces")]
+        namespace: Option<String>,
+
+        /// Look for pods in every namespace.
+        #[arg(long, short = 'A')]
+        all_namespaces: bool,
+
+        /// Only pods of this release (`app.kubernetes.io/instance`).
+        #[arg(long, conflicts_with = "selector")]
+        instance: Option<String>,
+
+        /// Label selector instead of `app.kubernetes.io/name=guildsync`.
+        #[arg(long, short = 'l')]
+        selector: Option<String>,
+
+        /// Only this container of each pod.
+        #[arg(long, short = 'c')]
+        container: Option<String>,
+
+        /// Keep streaming new lines until the pods stop or Ctrl-C.
+        #[arg(long, short = 'f')]
+        follow: bool,
+
+        /// Most recent lines per container [default: all].
+        #[arg(long, value_name = "LINES")]
+        tail: Option<u64>,
+
+        /// Only lines newer than this (`30s`, `5m`, `1h`).
+        #[arg(long, value_name = "DURATION")]
+        since: Option<String>,
+
+        /// Prefix each line with its timestamp.
+        #[arg(long)]
+        timestamps: bool,
+    },
+
+    /// Forward a local port to a port of the deployed Service, reconnecting when it drops,
+    /// until Ctrl-C.
+    PortForward {
+        /// kubeconfig context name.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: String,
+
+        /// Namespace of the Service [default: `kube.deploy.namespace`, else default].
+        #[arg(long, short = 'n')]
+        namespace: Option<String>,
+
+        /// Service to forward to [default: `kube.deploy.name`, else guildsync].
+        #[arg(long)]
+        service: Option<String>,
+
+        /// Service port.
+        #[arg(long)]
+        port: u16,
+
+        /// Local port [default: the Service port].
+        #[arg(long)]
+        local_port: Option<u16>,
+
+        /// Local address to listen on.
+        #[arg(long, default_value = "127.0.0.1")]
+        address: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeSecretsCommand {
+    /// Create or update the bot token Secret from the local token (keyring, environment, or
+    /// config), after showing what would change.
+    Sync {
+        /// kubeconfig context name.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: String,
+
+        /// Bot token to push (overrides the config's `token_env`, the keyring, and the
+        /// config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Namespace of the Secret [default: `kube.deploy.namespace`, else default].
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Secret name [default: `<kube.deploy.name>-token`, as `kube remote deploy` names it].
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// Key holding the token [default: `discord.token_env`].
+        #[arg(long)]
+        key: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeChartCommand {
+    /// Write a Helm chart running guildsync in watch, bridge, or daemon mode, with values
+    /// starting from the current config.
+    Generate {
+        /// Chart directory (created if missing).
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+
+        /// Chart name.
+        #[arg(long, default_value = "guildsync", value_parser = parse_chart_name)]
+        name: String,
+
+        /// Mode the chart's `values.yaml` selects.
+        #[arg(long, value_enum, default_value_t = chart::Mode::Watch)]
+        mode: chart::Mode,
+
+        /// Guild ID for the values (repeatable) [default: the `[[discord.guilds]]` entries].
+        #[arg(long = "guild", value_name = "ID")]
+        guilds: Vec<u64>,
+
+        /// Overwrite a chart already in `--out`.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+fn parse_chart_name(name: &str) -> Result<String, String> {
+    chart::validate_name(name).map(|()| name.to_string())
+}
+
+fn parse_cron(expr: &str) -> Result<String, String> {
+    schedule::validate_cron(expr).map(|()| expr.to_string())
+}
+
+fn parse_s3_url(url: &str) -> Result<String, String> {
+    match url.strip_prefix("s3://") {
+        Some(rest) if !rest.is_empty() => Ok(url.to_string()),
+        _ => Err(format!("expected s3://BUCKET[/PREFIX], got `{url}`")),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeScheduleCommand {
+    /// Render (or apply) a CronJob exporting a guild on a cron schedule, keeping the dump on a
+    /// PersistentVolumeClaim or uploading dated copies to S3.
+    Export {
+        /// Guild to export.
+        #[arg(long)]
+        guild: u64,
+
+        /// Cron schedule, e.g. "0 3 * * *" or @daily.
+        #[arg(long, value_parser = parse_cron)]
+        cron: String,
+
+        /// Time zone the schedule is in.
+        #[arg(long, default_value = "Etc/UTC")]
+        time_zone: String,
+
+        /// CronJob name [default: `<kube.deploy.name>-export-<guild>`].
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Namespace [default: `kube.deploy.namespace`, else default].
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Exporter image [default: `kube.deploy.image`, else `kube.remote.image`].
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Secret holding the bot token [default: `<kube.deploy.name>-token`, as
+        /// `kube secrets sync` and `kube remote deploy` write it].
+        #[arg(long)]
+        secret: Option<String>,
+
+        /// Key of the token in the Secret [default: `discord.token_env`].
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Existing PersistentVolumeClaim for the dumps [default: `<name>-dumps`, created].
+        #[arg(long, conflicts_with = "s3")]
+        claim: Option<String>,
+
+        /// Size to create the claim with [default: 1Gi when the claim is created].
+        #[arg(long, conflicts_with = "s3")]
+        size: Option<String>,
+
+        /// Upload each dump to `<URL>/<guild>/<time>.json` instead of keeping it on a claim.
+        #[arg(long, value_name = "s3://BUCKET[/PREFIX]", value_parser = parse_s3_url)]
+        s3: Option<String>,
+
+        /// Secret with the uploader's `AWS_*` variables [default: `<name>-s3`].
+        #[arg(long, requires = "s3")]
+        s3_secret: Option<String>,
+
+        /// Image running `aws s3 cp`.
+        #[arg(long, requires = "s3", default_value = "amazon/aws-cli:latest")]
+        uploader_image: String,
+
+        /// Write the manifests to this file instead of printing them.
+        #[arg(long, value_name = "PATH", conflicts_with = "apply")]
+        out: Option<PathBuf>,
+
+        /// Apply the manifests with server-side apply, after showing what would change.
+        #[arg(long, requires = "context")]
+        apply: bool,
+
+        /// kubeconfig context name, for `--apply`.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeLocalCommand {
+    /// Create (or start) the local cluster and wait until its nodes are Ready.
+    Up {
+        #[command(flatten)]
+        cluster: LocalClusterArgs,
+
+        /// Node image to create the cluster with [default: `kube.local.node_image`, else the
+        /// backend's].
+        #[arg(long, value_name = "IMAGE")]
+        image: Option<String>,
+
+        /// Return once the backend has created the cluster, without waiting for Ready nodes.
+        #[arg(long, conflicts_with = "timeout")]
+        no_wait: bool,
+
+        /// Deprecated: waiting is now the default. Still accepted, with a warning.
+        #[arg(long, conflicts_with = "no_wait")]
+        wait: bool,
+
+        /// Seconds to wait for readiness before failing [default: 300].
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+    },
+    /// Delete the local cluster and its kubeconfig context.
+    Down {
+        #[command(flatten)]
+        cluster: LocalClusterArgs,
+    },
+    /// Report the local cluster's nodes and pods.
+    Status {
+        #[command(flatten)]
+        cluster: LocalClusterArgs,
+    },
+}
+
+/// Which local cluster a `kube local` command acts on.
+#[derive(clap::Args, Debug)]
+struct LocalClusterArgs {
+    /// Backend running the cluster [default: `kube.local.provider`, else kind].
+    #[arg(long, value_enum)]
+    backend: Option<KubeProvider>,
+
+    /// Cluster name [default: `kube.local.name`, else guildsync].
+    #[arg(long)]
+    name: Option<String>,
+}
+
+impl LocalClusterArgs {
+    /// The cluster these flags and `[kube.local]` select; `--kubeconfig` files are where
+    /// its context goes unless the config names one.
+    fn cluster(
+        &self,
+        config: &KubeLocalConfig,
+        image: Option<&str>,
+        kubeconfigs: &[PathBuf],
+    ) -> Result<kube::LocalCluster, CliError> {
+        let name = self.name.clone().unwrap_or_else(|| config.name.clone());
+        kube::validate_cluster_name(&name).map_err(CliError::Usage)?;
+        Ok(kube::LocalCluster {
+            backend: self.backend.unwrap_or(config.provider),
+            name,
+            image: image
+                .map(str::to_string)
+                .or_else(|| config.node_image.clone()),
+            kubeconfig: config
+                .kubeconfig
+                .as_deref()
+                .map(expand_tilde)
+                .or_else(|| kubeconfigs.last().cloned()),
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeRemoteCommand {
+    /// Run a test Job against a remote cluster, streaming its logs, and report its result.
+    Test {
+        /// kubeconfig context name.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: String,
+
+        /// Export this guild and validate the dump in the Job, instead of running `doctor`.
+        #[arg(long, conflicts_with = "manifest")]
+        guild: Option<u64>,
+
+        /// Bot token for `--guild` (overrides the config's `token_env`, the keyring, and the
+        /// config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Job manifest (YAML or JSON) to run instead of the built-in smoke test.
+        #[arg(long, value_name = "PATH")]
+        manifest: Option<PathBuf>,
+
+        /// Leave the Job, its pod, and the token Secret in the cluster afterwards.
+        #[arg(long)]
+        keep: bool,
+    },
+
+    /// Deploy the gateway watcher (Deployment, token Secret, config ConfigMap) with
+    /// server-side apply, after showing what would change.
+    Deploy {
+        /// kubeconfig context name.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: String,
+
+        /// Bot token for the Secret (overrides the config's `token_env`, the keyring, and the
+        /// config's `token`).
+        #[arg(long, env = "DISCORD_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        /// Guild the watcher follows [default: `kube.deploy.guild`, else the only
+        /// `[[discord.guilds]]` entry].
+        #[arg(long)]
+        guild: Option<u64>,
+
+        /// Namespace to deploy into [default: `kube.deploy.namespace`, else default].
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Watcher image [default: `kube.deploy.image`, else `kube.remote.image`].
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Directory of templates replacing the built-in `configmap.yaml`, `secret.yaml`, and
+        /// `deployment.yaml`; its other `*.yaml` files are applied too.
+        #[arg(long, value_name = "DIR")]
+        templates: Option<PathBuf>,
+
+        /// Template variable, overriding `[kube.deploy.vars]` and the built-in ones
+        /// (repeatable).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = deploy::parse_var)]
+        set: Vec<(String, String)>,
+
+        /// Print the rendered manifests (token masked) without contacting the cluster.
+        #[arg(long)]
+        render: bool,
+
+        /// Take over fields another field manager owns instead of failing.
+        #[arg(long)]
+        force_conflicts: bool,
+    },
+
+    /// Summarize whether the deployed workloads are healthy, degraded, or down, from their
+    /// Deployments, CronJobs, and Jobs, pod restarts, recent warning events, and optionally a
+    /// health endpoint.
+    Status {
+        /// kubeconfig context name.
+        #[arg(long, env = "GUILDSYNC_KUBE_CONTEXT")]
+        context: String,
+
+        /// Namespace of the workloads [default: `kube.deploy.namespace`, else default].
+        #[arg(long, short = 'n')]
+        namespace: Option<String>,
+
+        /// Only workloads of this release (`app.kubernetes.io/instance`).
+        #[arg(long, conflicts_with = "selector")]
+        instance: Option<String>,
+
+        /// Label selector instead of `app.kubernetes.io/name=guildsync`.
+        #[arg(long, short = 'l')]
+        selector: Option<String>,
+
+        /// Also ask an HTTP health endpoint on this port of the Service, through the API
+        /// server's proxy.
+        #[arg(long, value_name = "PORT")]
+        health_port: Option<u16>,
+
+        /// Path of the health endpoint.
+        #[arg(
+            long,
+            value_name = "PATH",
+            default_value = "/healthz",
+            requires = "health_port"
+        )]
+        health_path: String,
+
+        /// Service serving the health endpoint [default: `kube.deploy.name`, else guildsync].
+        #[arg(long, requires = "health_port")]
+        service: Option<String>,
+    },
+
+    /// Same as `kube context list`.
+    #[command(hide = true)]
+    Contexts,
+}
+
+#[derive(Subcommand, Debug)]
+enum KubeContextCommand {
+    /// List contexts from the merged kubeconfig files and where each is defined.
+    List,
+
+    /// Check that contexts answer and grant the permissions guildsync needs, as a matrix of
+    /// contexts by command.
+    Check {
+        /// Context to check (repeatable) [default: every context].
+        #[arg(
+            long = "context",
+            value_name = "CONTEXT",
+            env = "GUILDSYNC_KUBE_CONTEXT"
+        )]
+        contexts: Vec<String>,
+
+        /// Namespace to check permissions in [default: `kube.deploy.namespace`, else default].
+        #[arg(long)]
+        namespace: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SshCommand {
+    /// Execute a command on a remote host.
+    Exec {
+        /// Host (SSH config host alias or hostname); repeat to run on several at once.
+        #[arg(long = "host", value_name = "HOST", required_unless_present = "groups")]
+        hosts: Vec<String>,
+
+        /// Also run on the hosts of this `[ssh.groups]` entry (repeatable).
+        #[arg(long = "group", value_name = "NAME")]
+        groups: Vec<String>,
+
+        /// Hosts the command runs on at once [default: `ssh.concurrency`, 8].
+        #[arg(long, value_name = "N")]
+        concurrency: Option<usize>,
+
+        /// Environment variable to export remotely before the command (repeatable).
+        #[arg(long, value_name = "KEY=VALUE", value_parser = ssh::parse_env)]
+        env: Vec<(String, String)>,
+
+        /// Local shell script to upload and run instead of a command.
+        #[arg(long, value_name = "PATH", conflicts_with = "cmd")]
+        script: Option<PathBuf>,
+
+        /// Forward this process's stdin to the command.
+        #[arg(long, conflicts_with = "script")]
+        stdin: bool,
+
+        /// Prefix each line of output with `[<host>] `.
+        #[arg(long)]
+        prefix: bool,
+
+        /// Seconds the command may run before the connection is closed and it fails with exit
+        /// code 124.
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+
+        /// Command to execute remotely.
+        #[arg(last = true, required_unless_present = "script")]
+        cmd: Vec<String>,
+    },
+
+    /// Show what hosts resolve to through the OpenSSH config: address, port, user, identity
+    /// files, and jump host.
+    Resolve {
+        /// Host or alias (repeatable).
+        #[arg(long = "host", value_name = "HOST", required_unless_present = "groups")]
+        hosts: Vec<String>,
+
+        /// Also resolve the hosts of this `[ssh.groups]` entry (repeatable).
+        #[arg(long = "group", value_name = "NAME")]
+        groups: Vec<String>,
+    },
+
+    /// Copy a local file or directory (a dump, attachments) to a remote host over SFTP.
+    Push {
+        /// Host (SSH config host alias or hostname).
+        #[arg(long)]
+        host: String,
+
+        /// Local file, or directory with `--recursive`.
+        local: PathBuf,
+
+        /// Destination on the host; relative paths start in the login directory.
+        remote: String,
+
+        /// Copy directories and their contents.
+        #[arg(long, short = 'r')]
+        recursive: bool,
+
+        /// Continue files a previous copy left partial instead of copying them again.
+        #[arg(long)]
+        resume: bool,
+    },
+
+    /// Copy a file or directory (a dump, attachments) from a remote host over SFTP.
+    Pull {
+        /// Host (SSH config host alias or hostname).
+        #[arg(long)]
+        host: String,
+
+        /// File on the host, or directory with `--recursive`.
+        remote: String,
+
+        /// Local destination; an existing directory receives it under its own name.
+        local: PathBuf,
+
+        /// Copy directories and their contents.
+        #[arg(long, short = 'r')]
+        recursive: bool,
+
+        /// Continue files a previous copy left partial instead of copying them again.
+        #[arg(long)]
+        resume: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum McpCommand {
+    /// Serve MCP over stdio (JSON-RPC, one message per line) until stdin closes.
+    Serve {
+        /// Also expose tools that modify files or remote state.
+        #[arg(long)]
+        allow_write: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the effective config (file merged with flags), with secrets redacted.
+    Show,
+
+    /// Check that the config file parses and that referenced paths exist.
+    Validate,
+}
+
+#[derive(Serialize)]
+struct JsonOut<'a> {
+    ok: bool,
+    action: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+    /// Non-fatal caveats; always present (possibly empty) for stable parsing.
+    warnings: &'a [String],
+    #[serde(flatten)]
+    data: Option<&'a serde_json::Value>,
+}
+
+/// Result of a successful action.
+#[derive(Default)]
+struct Outcome {
+    /// One-line summary, shown in both text and JSON output. Empty for actions that
+    /// own stdout (e.g. `mcp serve`), in which case nothing is printed.
+    message: String,
+    /// Extra human-readable output printed after the message in text mode.
+    body: Option<String>,
+    /// Structured fields merged into the JSON envelope.
+    data: Option<serde_json::Value>,
+    /// Key of a per-item array in `data` (hosts, guilds) that `--json-style compact`
+    /// writes one line per element of, ahead of the summary line.
+    items: Option<&'static str>,
+    /// Non-zero when the action ran to completion but its result is a failure
+    /// (e.g. a failing `doctor` check); the output is still printed in full.
+    exit: ExitCode,
+}
+
+impl Outcome {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Command {
+    /// Dotted action name used in logs and JSON output.
+    fn action(&self) -> &'static str {
+        match self {
+            Command::Discord { command, .. } => match command {
+                DiscordCommand::Export { .. } => "discord.export",
+                DiscordCommand::AuditLog { .. } => "discord.audit-log",
+                DiscordCommand::Members { .. } => "discord.members",
+                DiscordCommand::Watch { .. } => "discord.watch",
+                DiscordCommand::Diff { .. } => "discord.diff",
+                DiscordCommand::Import { .. } => "discord.import",
+                DiscordCommand::Undo { .. } => "discord.undo",
+                DiscordCommand::Template { command } => match command {
+                    TemplateCommand::Create { .. } => "discord.template.create",
+                },
+                DiscordCommand::Prune { command } => match command {
+                    PruneCommand::Plan { .. } => "discord.prune.plan",
+                },
+            },
+            Command::Format { command } => match command {
+                FormatCommand::Validate(_) => "format.validate",
+                FormatCommand::Convert(_) => "format.convert",
+                FormatCommand::Stats { .. } => "format.stats",
+                FormatCommand::Query { .. } => "format.query",
+                FormatCommand::Render { .. } => "format.render",
+                FormatCommand::ExportSqlite { .. } => "format.export-sqlite",
+                FormatCommand::ExportCsv { .. } => "format.export-csv",
+                FormatCommand::Verify { .. } => "format.verify",
+                FormatCommand::Sign { .. } => "format.sign",
+                FormatCommand::VerifySignature { .. } => "format.verify-signature",
+                FormatCommand::Lint { .. } => "format.lint",
+                FormatCommand::Diff(_) => "format.diff",
+                FormatCommand::Migrate { .. } => "format.migrate",
+                FormatCommand::Split { .. } => "format.split",
+                FormatCommand::Join { .. } => "format.join",
+                FormatCommand::Redact { .. } => "format.redact",
+                FormatCommand::Anonymize { .. } => "format.anonymize",
+                FormatCommand::Canonicalize { .. } => "format.canonicalize",
+                FormatCommand::Normalize { .. } => "format.normalize",
+                FormatCommand::Schema { .. } => "format.schema",
+                FormatCommand::Merge { .. } => "format.merge",
+            },
+            Command::Tui { .. } => "tui",
+            Command::Terminal { command } => match command {
+                TerminalCommand::Opencode { command } => match command {
+                    TerminalOpenCodeCommand::Attach { .. } => "terminal.opencode.attach",
+                    TerminalOpenCodeCommand::Start { .. } => "terminal.opencode.start",
+                    TerminalOpenCodeCommand::Stop { .. } => "terminal.opencode.stop",
+                    TerminalOpenCodeCommand::List => "terminal.opencode.list",
+                    TerminalOpenCodeCommand::Status { .. } => "terminal.opencode.status",
+                },
+                TerminalCommand::Layout { command } => match command {
+                    TerminalLayoutCommand::Apply { .. } => "terminal.layout.apply",
+                    TerminalLayoutCommand::List => "terminal.layout.list",
+                },
+                TerminalCommand::Send { .. } => "terminal.send",
+                TerminalCommand::Capture { .. } => "terminal.capture",
+                TerminalCommand::Bridge { .. } => "terminal.bridge",
+            },
+            Command::Notify { .. } => "notify",
+            Command::WatchDir { .. } => "watch-dir",
+            Command::Kube { command, .. } => match command {
+                KubeCommand::Local { command } => match command {
+                    KubeLocalCommand::Up { .. } => "kube.local.up",
+                    KubeLocalCommand::Down { .. } => "kube.local.down",
+                    KubeLocalCommand::Status { .. } => "kube.local.status",
+                },
+                KubeCommand::Chart { command } => match command {
+                    KubeChartCommand::Generate { .. } => "kube.chart.generate",
+                },
+                KubeCommand::Secrets { command } => match command {
+                    KubeSecretsCommand::Sync { .. } => "kube.secrets.sync",
+                },
+                KubeCommand::Schedule { command } => match command {
+                    KubeScheduleCommand::Export { .. } => "kube.schedule.export",
+                },
+                KubeCommand::Context { command } => match command {
+                    KubeContextCommand::List => "kube.context.list",
+                    KubeContextCommand::Check { .. } => "kube.context.check",
+                },
+                KubeCommand::Logs { .. } => "kube.logs",
+                KubeCommand::PortForward { .. } => "kube.port-forward",
+                KubeCommand::Remote { command } => match command {
+                    KubeRemoteCommand::Test { .. } => "kube.remote.test",
+                    KubeRemoteCommand::Deploy { .. } => "kube.remote.deploy",
+                    KubeRemoteCommand::Status { .. } => "kube.remote.status",
+                    KubeRemoteCommand::Contexts => "kube.remote.contexts",
+                },
+            },
+            Command::Ssh { command } => match command {
+                SshCommand::Exec { .. } => "ssh.exec",
+                SshCommand::Resolve { .. } => "ssh.resolve",
+                SshCommand::Push { .. } => "ssh.push",
+                SshCommand::Pull { .. } => "ssh.pull",
+            },
+            Command::Shell => "shell",
+            Command::Mcp { command } => match command {
+                McpCommand::Serve { .. } => "mcp.serve",
+            },
+            Command::Config { command } => match command {
+                ConfigCommand::Show => "config.show",
+                ConfigCommand::Validate => "config.validate",
+            },
+            Command::Version => "version",
+            Command::Doctor => "doctor",
+            Command::Completions { command } => match command {
+                CompletionsCommand::Print { .. } => "completions.print",
+                CompletionsCommand::Install { .. } => "completions.install",
+            },
+            Command::Auth { command } => match command {
+                AuthCommand::Login { .. } => "auth.login",
+                AuthCommand::Logout => "auth.logout",
+                AuthCommand::Status => "auth.status",
+                AuthCommand::Keygen { .. } => "auth.keygen",
+                AuthCommand::PublicKey => "auth.public-key",
+            },
+        }
+    }
+
+    /// A deadline set by the subcommand's own flags, which overrides the global `--timeout`.
+    fn local_timeout(&self) -> Option<u64> {
+        match self {
+            Command::Kube {
+                command:
+                    KubeCommand::Local {
+                        command: KubeLocalCommand::Up { timeout, .. },
+                    },
+                ..
+            }
+            | Command::Ssh {
+                command: SshCommand::Exec { timeout, .. },
+            } => *timeout,
+            _ => None,
+        }
+    }
+
+    /// Whether the command handles Ctrl-C itself, as a normal way to stop, instead of being
+    /// cancelled by it.
+    fn stops_on_ctrl_c(&self) -> bool {
+        matches!(
+            self,
+            Command::Discord {
+                command: DiscordCommand::Watch { .. },
+                ..
+            } | Command::Terminal {
+                command: TerminalCommand::Send { .. }
+                    | TerminalCommand::Capture { follow: true, .. }
+                    | TerminalCommand::Bridge { .. },
+            } | Command::Kube {
+                command: KubeCommand::Remote {
+                    command: KubeRemoteCommand::Test { .. }
+                },
+                ..
+            } | Command::Kube {
+                command: KubeCommand::Logs { follow: true, .. },
+                ..
+            } | Command::Kube {
+                command: KubeCommand::PortForward { .. },
+                ..
+            } | Command::Shell
+                | Command::WatchDir { .. }
+        )
+    }
+
+    /// Whether the action is destructive enough to ask for confirmation first.
+    fn needs_confirmation(&self) -> bool {
+        matches!(
+            self,
+            Command::Kube {
+                command: KubeCommand::Local {
+                    command: KubeLocalCommand::Down { .. }
+                },
+                ..
+            }
+        )
+    }
+
+    /// Description of what a destructive action would change; `None` for read-only actions
+    /// and for `discord import`, which computes, shows, and confirms its own step-by-step plan.
+    fn plan(&self) -> Option<String> {
+        match self {
+            Command::Kube {
+                command:
+                    KubeCommand::Local {
+                        command: KubeLocalCommand::Down { .. },
+                    },
+                ..
+            } => Some("tear down the local cluster".to_string()),
+            Command::Ssh {
+                command:
+                    SshCommand::Exec {
+                        hosts,
+                        groups,
+                        script,
+                        cmd,
+                        ..
+                    },
+            } => {
+                let on: Vec<String> = hosts
+                    .iter()
+                    .cloned()
+                    .chain(groups.iter().map(|group| format!("group {group}")))
+                    .collect();
+                let on = on.join(", ");
+                Some(match script {
+                    Some(script) => format!("run script {} on {on}", script.display()),
+                    None => format!("run `{}` on {on}", cmd.join(" ")),
+                })
+            }
+            Command::Ssh {
+                command:
+                    SshCommand::Push {
+                        host,
+                        local,
+                        remote,
+                        ..
+                    },
+            } => Some(format!("copy {} to {host}:{remote}", local.display())),
+            Command::Ssh {
+                command:
+                    SshCommand::Pull {
+                        host,
+                        remote,
+                        local,
+                        ..
+                    },
+            } => Some(format!("copy {host}:{remote} to {}", local.display())),
+            _ => None,
+        }
+    }
+
+    /// The `--token` of commands that talk to Discord, for the shell to fill in.
+    fn token_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            Command::Discord { token, .. }
+            | Command::Notify { token, .. }
+            | Command::WatchDir { token, .. }
+            | Command::Kube {
+                command:
+                    KubeCommand::Remote {
+                        command:
+                            KubeRemoteCommand::Deploy { token, .. }
+                            | KubeRemoteCommand::Test { token, .. },
+                    },
+                ..
+            }
+            | Command::Kube {
+                command:
+                    KubeCommand::Secrets {
+                        command: KubeSecretsCommand::Sync { token, .. },
+                    },
+                ..
+            }
+            | Command::Terminal {
+                command:
+                    TerminalCommand::Send { token, .. }
+                    | TerminalCommand::Capture { token, .. }
+                    | TerminalCommand::Bridge { token, .. },
+            } => Some(token),
+            _ => None,
+        }
+    }
+}
+
+impl Cli {
+    /// Whether long-running actions should draw a progress bar on stderr.
+    fn progress(&self) -> bool {
+        self.progress_on(std::io::stdout().is_terminal())
+    }
+
+    /// [`Cli::progress`], given whether stdout is a terminal.
+    fn progress_on(&self, tty: bool) -> bool {
+        !self.no_progress
+            && !self.json
+            && tty
+            && !matches!(
+                self.command,
+                Command::Discord {
+                    command: DiscordCommand::Export { all: true, .. },
+                    ..
+                }
+            )
+    }
+
+    /// Whether to draw the one bar of a batch (`discord export --all`), whose guilds run
+    /// concurrently and so do not draw their own.
+    fn progress_for_batch(&self) -> bool {
+        !self.no_progress && !self.json && std::io::stdout().is_terminal()
+    }
+
+    /// Take the global flags of `shell` (the `guildsync shell` invocation) this command line
+    /// leaves unset.
+    fn inherit(&mut self, shell: &Cli) {
+        self.json |= shell.json;
+        self.dry_run |= shell.dry_run;
+        self.no_progress |= shell.no_progress;
+        self.yes |= shell.yes;
+        self.timeout = self.timeout.or(shell.timeout);
+        self.max_retries = self.max_retries.or(shell.max_retries);
+        self.retry_base_ms = self.retry_base_ms.or(shell.retry_base_ms);
+        self.identity = self.identity.clone().or_else(|| shell.identity.clone());
+    }
+
+    /// Whether destructive actions should only report their plan.
+    fn dry_run(&self) -> bool {
+        self.dry_run
+            || matches!(
+                self.command,
+                Command::Discord {
+                    command: DiscordCommand::Import { dry_run: true, .. },
+                    ..
+                } | Command::WatchDir { dry_run: true, .. }
+            )
+    }
+
+    /// `command` with this invocation's global flags, for commands that run others
+    /// (`watch-dir`).
+    fn with_command(&self, command: Command) -> Cli {
+        Cli {
+            config: self.config.clone(),
+            json: self.json,
+            json_style: self.json_style,
+            log: self.log,
+            log_file: self.log_file.clone(),
+            max_retries: self.max_retries,
+            retry_base_ms: self.retry_base_ms,
+            timeout: self.timeout,
+            dry_run: self.dry_run(),
+            no_progress: self.no_progress,
+            identity: self.identity.clone(),
+            yes: self.yes,
+            command,
+        }
+    }
+}
+
+/// Apply global flags on top of the loaded config so handlers see a single merged view.
+fn merge_flags(cli: &Cli, mut config: Config) -> Config {
+    if let Some(max_retries) = cli.max_retries {
+        config.retry.max_retries = max_retries;
+    }
+    if let Some(base_ms) = cli.retry_base_ms {
+        config.retry.base_ms = base_ms;
+    }
+    if let Some(identity) = &cli.identity {
+        config.formats.identity_file = Some(identity.clone());
+    }
+    config
+}
+
+/// Binary name used in generated completion scripts.
+const BIN_NAME: &str = "guildsync";
+
+/// Completion script for `shell`.
+fn completion_script(shell: Shell) -> String {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), BIN_NAME, &mut script);
+    String::from_utf8_lossy(&script).into_owned()
+}
+
+/// Export `job.id` as `discord export` does for one guild.
+async fn export_guild(
+    cli: &Cli,
+    config: &Config,
+    client: &discord::Client,
+    job: &GuildExport,
+    attachment_concurrency: usize,
+    warnings: &Warnings,
+) -> Result<Outcome, CliError> {
+    let (guild, action) = (job.id, cli.command.action());
+    let (after, before) = job.bounds().map_err(CliError::Config)?;
+    let ids = |ids: &[u64]| ids.iter().map(u64::to_string).collect();
+    let filters = ExportFilters {
+        channels: ids(&job.channels),
+        categories: ids(&job.categories),
+        users: ids(&job.users),
+        after: after.map(|id| id.to_string()),
+        before: before.map(|id| id.to_string()),
+    };
+    let recipients =
+        encryption::recipients(&job.encrypt_to, "--encrypt-to").map_err(CliError::Usage)?;
+    let messages =
+        job.incremental || job.since.is_some() || job.with_attachments || filters.scopes_messages();
+    if messages && job.format == GuildFormat::Upload {
+        return Err(CliError::Usage(
+            "--incremental, --since, --with-attachments, --user, --after, and \
+             --before export messages, which upload files do not carry; use \
+             --format dump"
+                .to_string(),
+        ));
+    }
+    let mut dump = fetch_dump(cli, config, client, guild).await?;
+    filters.retain_channels(&mut dump);
+    fetch_threads(cli, client, guild, &mut dump, warnings).await?;
+    // Active threads are listed guild-wide; drop those of filtered-out channels.
+    filters.retain_channels(&mut dump);
+    if !filters.is_empty() {
+        dump["filters"] = serde_json::to_value(&filters)?;
+    }
+    if job.with_assets {
+        let images = assets::SECTIONS
+            .iter()
+            .filter_map(|section| dump[*section].as_array())
+            .map(Vec::len)
+            .sum::<usize>();
+        let bar = progress::bar(cli.progress(), images as u64, action);
+        let missing = assets::embed_all(client, &mut dump, &bar).await?;
+        bar.finish_and_clear();
+        for what in missing {
+            warnings.push(format!("{what}: image is gone; not embedded"));
+        }
+    }
+    if !messages {
+        let document = match job.format {
+            GuildFormat::Dump => dump,
+            GuildFormat::Upload => format::to_upload(dump, config.formats.upload_version),
+        };
+        format::validate_value(&document, Some(job.format), &config.formats)?;
+        format::write_sealed(&job.out, &document, job.codec(), &recipients)?;
+        let manifest = job
+            .manifest
+            .then(|| manifest::write(&job.out, &document))
+            .transpose()?;
+        return Ok(Outcome {
+            data: Some(serde_json::json!({ "manifest": manifest })),
+            ..Outcome::new(format!(
+                "exported guild {guild} to {} ({})",
+                job.out.display(),
+                job.format
+            ))
+        });
+    }
+
+    let state_path = job
+        .state
+        .clone()
+        .unwrap_or_else(|| checkpoint::default_path(&job.out));
+    let mut checkpoint = if job.incremental {
+        Checkpoint::load(&state_path, guild)?
+    } else {
+        Checkpoint {
+            guild,
+            ..Checkpoint::default()
+        }
+    };
+    let new_messages = fetch_new_messages(
+        cli,
+        client,
+        &mut dump,
+        &mut checkpoint,
+        job.since,
+        &filters,
+        warnings,
+    )
+    .await?;
+    let attachments = if job.with_attachments {
+        let dir = attachments::default_dir(&job.out);
+        let bar = progress::bar(cli.progress(), 0, action);
+        let stats = attachments::download_all(
+            &mut dump,
+            &dir,
+            attachment_concurrency,
+            &client.policy(),
+            action,
+            &bar,
+        )
+        .await?;
+        bar.finish_and_clear();
+        for what in &stats.missing {
+            warnings.push(format!("{what} is gone; not downloaded"));
+        }
+        if !recipients.is_empty() {
+            warnings.push(format!(
+                "attachments in {} are not encrypted",
+                dir.display()
+            ));
+        }
+        Some(stats)
+    } else {
+        None
+    };
+
+    let document = if job.incremental && job.out.exists() {
+        let base = format::read_document(&job.out)?;
+        format::merge(&base, &dump, Prefer::Delta)?.0
+    } else {
+        dump
+    };
+    format::validate_value(&document, Some(job.format), &config.formats)?;
+    format::write_sealed(&job.out, &document, job.codec(), &recipients)?;
+    // Only after the dump is in place: a crash in between re-fetches messages,
+    // which merge by id, rather than skipping them.
+    if job.incremental {
+        checkpoint.updated_at = timestamp::now_rfc3339();
+        checkpoint.save(&state_path)?;
+    }
+    let manifest = job
+        .manifest
+        .then(|| manifest::write(&job.out, &document))
+        .transpose()?;
+    Ok(Outcome {
+        data: Some(serde_json::json!({
+            "new_messages": new_messages,
+            "checkpoint": job.incremental.then(|| state_path.display().to_string()),
+            "attachments": attachments,
+            "manifest": manifest,
+        })),
+        ..Outcome::new(format!(
+            "exported guild {guild} to {} ({}, {new_messages} new message(s){})",
+            job.out.display(),
+            job.format,
+            attachments.as_ref().map_or_else(String::new, |a| format!(
+                ", {} attachment(s) downloaded, {} already present",
+                a.downloaded, a.cached
+            ))
+        ))
+    })
+}
+
+/// `discord export --all`: export every configured guild, `concurrency` at a time, and report
+/// on each. One guild failing does not stop the others; the exit status is then 1.
+async fn export_all(
+    cli: &Cli,
+    config: &Config,
+    client: &discord::Client,
+    concurrency: usize,
+    warnings: &Warnings,
+) -> Result<Outcome, CliError> {
+    let jobs = &config.discord.guilds;
+    if jobs.is_empty() {
+        return Err(CliError::Config(
+            "--all: no guilds configured; add [[discord.guilds]] tables with `id` and `out`"
+                .to_string(),
+        ));
+    }
+    let bar = progress::bar(
+        cli.progress_for_batch(),
+        jobs.len() as u64,
+        cli.command.action(),
+    );
+    let (bar, attachment_concurrency) = (&bar, config.discord.attachment_concurrency);
+    let results: Vec<(&GuildExport, Result<Outcome, CliError>, Warnings)> =
+        futures_util::stream::iter(jobs)
+            .map(|job| async move {
+                let own = Warnings::default();
+                let result =
+                    export_guild(cli, config, client, job, attachment_concurrency, &own).await;
+                bar.inc(1);
+                (job, result, own)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+    bar.finish_and_clear();
+
+    let mut lines = Vec::new();
+    let mut report = Vec::new();
+    let mut failed = 0;
+    for (job, result, own) in results {
+        for warning in own.into_vec() {
+            warnings.push(format!("guild {}: {warning}", job.id));
+        }
+        let mut entry = serde_json::json!({ "guild": job.id, "out": job.out });
+        match result {
+            Ok(outcome) => {
+                lines.push(format!("ok      {}\n", outcome.message));
+                entry["ok"] = true.into();
+                entry["message"] = outcome.message.into();
+                if let Some(serde_json::Value::Object(data)) = outcome.data {
+                    entry.as_object_mut().expect("object").extend(data);
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                lines.push(format!("failed  guild {}: {err}\n", job.id));
+                entry["ok"] = false.into();
+                entry["error"] = err.to_string().into();
+            }
+        }
+        report.push(entry);
+    }
+    Ok(Outcome {
+        message: format!(
+            "exported {} of {} guild(s){}",
+            jobs.len() - failed,
+            jobs.len(),
+            if failed > 0 {
+                format!(", {failed} failed")
+            } else {
+                String::new()
+            }
+        ),
+        body: Some(lines.concat()),
+        data: Some(serde_json::json!({ "guilds": report, "failed": failed })),
+        items: Some("guilds"),
+        exit: if failed > 0 {
+            ExitCode::Failure
+        } else {
+            ExitCode::Ok
+        },
+    })
+}
+
+/// `discord prune plan`: write the plan for `dump` to `out`.
+fn prune_plan(
+    dump: &serde_json::Value,
+    out: &Path,
+    days: u64,
+    archive_category: Option<u64>,
+    members: Option<&[serde_json::Value]>,
+) -> Result<Outcome, CliError> {
+    let archive_category = archive_category.map(|id| id.to_string());
+    if let Some(id) = &archive_category {
+        let is_category = discord::list(&dump["channels"])
+            .any(|c| c["id"] == id.as_str() && c["type"] == discord::CATEGORY_TYPE);
+        if !is_category {
+            return Err(CliError::Validation(format!(
+                "--archive-category {id} is not a category of the guild"
+            )));
+        }
+    }
+    let criteria = prune::Criteria {
+        inactive_days: days,
+        now: timestamp::now(),
+        archive_category: archive_category.as_deref(),
+        members,
+    };
+    let (plan, actions) = prune::plan(dump, &criteria);
+    format::write_document(out, &plan)?;
+    let listing: String = actions.iter().map(|a| format!("{a}\n")).collect();
+    Ok(Outcome {
+        body: (!actions.is_empty()).then_some(listing),
+        data: Some(serde_json::json!({ "actions": actions })),
+        ..Outcome::new(format!(
+            "proposed {} change(s) in {}; review it, then apply it with `discord import --in {}`",
+            actions.len(),
+            out.display(),
+            out.display()
+        ))
+    })
+}
+
+/// The members of a `discord members` file.
+fn read_members(path: &Path) -> Result<Vec<serde_json::Value>, CliError> {
+    let document = format::read_document(path)?;
+    if document["format"] != discord::MEMBERS_FORMAT {
+        return Err(CliError::Validation(format!(
+            "{} is not a member list (from `discord members`)",
+            path.display()
+        )));
+    }
+    Ok(document["members"].as_array().cloned().unwrap_or_default())
+}
+
+/// Fetch the live guild as a dump document, one section at a time.
+async fn fetch_dump(
+    cli: &Cli,
+    config: &Config,
+    client: &discord::Client,
+    guild: u64,
+) -> Result<serde_json::Value, CliError> {
+    let bar = progress::bar(
+        cli.progress(),
+        discord::DUMP_SECTIONS.len() as u64,
+        cli.command.action(),
+    );
+    let mut dump = discord::fetch_dump(client, guild, config.formats.dump_version, &bar).await?;
+    bar.finish_and_clear();
+    assets::add_urls(&mut dump, &config.discord.cdn_base);
+    Ok(dump)
+}
+
+/// Apply `steps` (planned from `document`) to `guild`, recording them in `journal` (written to
+/// `journal_path`), and return the ids of created objects by their id in `document`.
+async fn apply_journaled(
+    cli: &Cli,
+    client: &discord::Client,
+    guild: u64,
+    journal: &mut Journal,
+    journal_path: &Path,
+    document: &serde_json::Value,
+    steps: &[import::Step],
+) -> Result<HashMap<String, String>, CliError> {
+    let bar = progress::bar(cli.progress(), steps.len() as u64, cli.command.action());
+    let ids = import::apply(client, guild, document, steps, Some(journal), &bar)
+        .await
+        .inspect_err(|_| {
+            tracing::error!(
+                "changes applied so far are journaled in {}",
+                journal_path.display()
+            );
+        })?;
+    bar.finish_and_clear();
+    Ok(ids)
+}
+
+/// Outcome of `discord diff` and `format diff`: the report as text and as `--json` data.
+fn diff_outcome(report: &diff::Report, old: &str, new: &str) -> Outcome {
+    let count = report.change_count();
+    let message = if count == 0 {
+        format!("no differences between {old} and {new}")
+    } else {
+        format!("{count} difference(s) from {old} to {new}")
+    };
+    Outcome {
+        body: (count > 0).then(|| report.to_string()),
+        data: Some(serde_json::json!({ "changes": count, "diff": report })),
+        ..Outcome::new(message)
+    }
+}
+
+/// Add the threads of `dump`'s channels as its `threads` section, warning about channels whose
+/// archived threads the bot may not list.
+async fn fetch_threads(
+    cli: &Cli,
+    client: &discord::Client,
+    guild: u64,
+    dump: &mut serde_json::Value,
+    warnings: &Warnings,
+) -> Result<(), CliError> {
+    let channels = dump["channels"].as_array().cloned().unwrap_or_default();
+    let bar = progress::bar(cli.progress(), channels.len() as u64, cli.command.action());
+    let (threads, denied) = discord::fetch_threads(client, guild, &channels, &bar).await?;
+    bar.finish_and_clear();
+    if !denied.is_empty() {
+        warnings.push(format!(
+            "some archived threads not exported for {} channel(s) the bot lacks permission in: {}",
+            denied.len(),
+            denied.join(", ")
+        ));
+    }
+    dump["threads"] = threads.into();
+    Ok(())
+}
+
+/// Fetch messages newer than each channel's watermark (or `since`) and within `filters` into
+/// `dump`'s channels and threads, advancing `checkpoint`. Channels the bot cannot read are skipped with a
+/// warning.
+async fn fetch_new_messages(
+    cli: &Cli,
+    client: &discord::Client,
+    dump: &mut serde_json::Value,
+    checkpoint: &mut Checkpoint,
+    since: Option<u64>,
+    filters: &ExportFilters,
+    warnings: &Warnings,
+) -> Result<usize, CliError> {
+    let action = cli.command.action();
+    let channels: Vec<&mut serde_json::Value> = dump
+        .as_object_mut()
+        .into_iter()
+        .flat_map(|obj| obj.iter_mut())
+        .filter(|(key, _)| *key == "channels" || *key == "threads")
+        .flat_map(|(_, section)| section.as_array_mut().into_iter().flatten())
+        .collect();
+    let bar = progress::bar(cli.progress(), channels.len() as u64, action);
+    let mut total = 0;
+    for channel in channels {
+        bar.inc(1);
+        let readable = channel["type"]
+            .as_u64()
+            .is_some_and(|t| discord::MESSAGE_CHANNEL_TYPES.contains(&t));
+        if !readable {
+            continue;
+        }
+        let id = channel["id"].as_str().unwrap_or_default().to_string();
+        bar.set_message(channel["name"].as_str().unwrap_or_default().to_string());
+        let after = checkpoint
+            .channels
+            .get(&id)
+            .copied()
+            .or(since)
+            .unwrap_or(0)
+            .max(filters.after_id().unwrap_or(0));
+        let fetched = discord::fetch_messages(client, &id, after, filters.before_id()).await;
+        let mut messages = match fetched {
+            Ok(messages) => messages,
+            Err(err @ CliError::Auth(_)) => {
+                warnings.push(format!("skipping messages in channel {id}: {err}"));
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(newest) = messages.last() {
+            checkpoint.channels.insert(id, discord::snowflake(newest));
+        }
+        messages.retain(|message| filters.keeps_message(message));
+        total += messages.len();
+        channel["messages"] = messages.into();
+    }
+    bar.finish_and_clear();
+    Ok(total)
+}
+
+/// A bot token from stdin (`--token-stdin`) or typed at a hidden prompt.
+async fn read_token(from_stdin: bool) -> Result<String, CliError> {
+    if !from_stdin && !std::io::stdin().is_terminal() {
+        return Err(CliError::Usage(
+            "stdin is not a terminal; pipe the token with --token-stdin".to_string(),
+        ));
+    }
+    let token = tokio::task::spawn_blocking(move || {
+        if from_stdin {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).map(|_| line)
+        } else {
+            rpassword::prompt_password("Discord bot token: ")
+        }
+    })
+    .await
+    .map_err(|e| CliError::Io(std::io::Error::other(e)))??;
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        return Err(CliError::Usage("empty bot token".to_string()));
+    }
+    Ok(token)
+}
+
+/// Run the configured pre-hook; a failure aborts the action only if it is required.
+async fn pre_hook(config: &Config, action: &str, warnings: &Warnings) -> Result<(), CliError> {
+    if let Some(pre) = &config.hooks.pre_hook
+        && let Err(err) = hooks::run(pre, action, "started").await
+    {
+        if config.hooks.pre_hook_required {
+            return Err(err);
+        }
+        warnings.push(format!("pre-hook failed: {err}"));
+    }
+    Ok(())
+}
+
+/// Run the configured post-hook with the status of `result`; a failure is only a warning.
+async fn post_hook(
+    config: &Config,
+    action: &str,
+    result: &Result<Outcome, CliError>,
+    warnings: &Warnings,
+) {
+    if let Some(post) = &config.hooks.post_hook {
+        let status = match result {
+            Ok(outcome) if outcome.exit == ExitCode::Ok => "ok",
+            _ => "error",
+        };
+        if let Err(err) = hooks::run(post, action, status).await {
+            warnings.push(format!("post-hook failed: {err}"));
+        }
+    }
+}
+
+/// Execute the selected command.
+async fn run(cli: &Cli, config: &Config, warnings: &Warnings) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    let policy = RetryPolicy {
+        max_retries: config.retry.max_retries,
+        base: Duration::from_millis(config.retry.base_ms),
+    };
+
+    if let Some(plan) = cli.command.plan() {
+        if cli.dry_run() {
+            return Ok(Outcome::new(format!("{action}: dry run; would {plan}")));
+        }
+        if cli.command.needs_confirmation()
+            && !cli.yes
+            && !prompt::confirm(&format!("About to {plan}. Are you sure?")).await?
+        {
+            return Ok(Outcome {
+                data: Some(serde_json::json!({ "cancelled": true })),
+                ..Outcome::new(format!("{action}: cancelled"))
+            });
+        }
+    }
+
+    // Network-backed actions go through the retry helper (Discord calls via `discord::Client`,
+    // which applies it per request) so transient failures are handled uniformly.
+    match &cli.command {
+        Command::Discord { token, command } => {
+            Box::pin(run_discord(cli, config, warnings, policy, token, command)).await
+        }
+        Command::Kube {
+            kubeconfig,
+            command,
+        } => Box::pin(run_kube(cli, config, warnings, policy, kubeconfig, command)).await,
+        Command::Format { command } => Box::pin(run_format(cli, config, warnings, command)).await,
+        Command::Shell => {
+            shell(cli, config).await?;
+            Ok(Outcome::default())
+        }
+        Command::Tui { r#in, channel } => {
+            if !std::io::stdout().is_terminal() || !std::io::stdin().is_terminal() {
+                return Err(CliError::Usage(
+                    "tui needs an interactive terminal; use `format query` or `format render` \
+                     in scripts"
+                        .to_string(),
+                ));
+            }
+            let document = format::read_document(r#in)?;
+            format::validate_value(&document, Some(GuildFormat::Dump), &config.formats)?;
+            let mut browser = tui::Browser::new(&document);
+            if browser.nodes.is_empty() {
+                return Err(CliError::NotFound(format!(
+                    "{} has no channels to browse",
+                    r#in.display()
+                )));
+            }
+            if let Some(channel) = channel
+                && !browser.select(&channel.to_string())
+            {
+                return Err(CliError::NotFound(format!(
+                    "channel {channel} is not in {}",
+                    r#in.display()
+                )));
+            }
+            tokio::task::spawn_blocking(move || tui::run(browser))
+                .await
+                .map_err(|e| CliError::Io(std::io::Error::other(e)))??;
+            Ok(Outcome::default())
+        }
+        Command::Notify {
+            token,
+            channel,
+            webhook,
+            message,
+            embed_json,
+            username,
+            allow_mentions,
+        } => {
+            let stdin = |what: &str| -> Result<String, CliError> {
+                if std::io::stdin().is_terminal() {
+                    return Err(CliError::Usage(format!(
+                        "{what} -: pipe the input in, or pass it directly"
+                    )));
+                }
+                std::io::read_to_string(std::io::stdin()).map_err(CliError::from)
+            };
+            let from_stdin = |path: &Option<PathBuf>| path.as_deref() == Some(Path::new("-"));
+            if message.as_deref() == Some("-") && from_stdin(embed_json) {
+                return Err(CliError::Usage(
+                    "--message and --embed-json cannot both read stdin".to_string(),
+                ));
+            }
+            let content = match message.as_deref() {
+                Some("-") => Some(stdin("--message")?),
+                other => other.map(str::to_string),
+            };
+            let embeds = match embed_json {
+                Some(path) if path == Path::new("-") => {
+                    Some(format::parse_json(&stdin("--embed-json")?)?)
+                }
+                Some(path) => Some(format::read_document(path)?),
+                None => None,
+            };
+            let hook = match channel {
+                Some(_) => None,
+                None => webhook.as_deref().map(notify::Webhook::parse).transpose()?,
+            };
+            let body = notify::Notice {
+                content: content.as_deref(),
+                embeds,
+                username: username.as_deref().filter(|_| hook.is_some()),
+                mentions: *allow_mentions,
+            }
+            .body()?;
+            let target = match (&hook, channel) {
+                (Some(hook), _) => format!("webhook {}", hook.id),
+                (None, Some(channel)) => format!("channel {channel}"),
+                (None, None) => unreachable!("clap requires --channel or --webhook"),
+            };
+            if cli.dry_run() {
+                return Ok(Outcome {
+                    body: Some(serde_json::to_string_pretty(&body)? + "\n"),
+                    data: Some(serde_json::json!({ "target": target, "body": body })),
+                    ..Outcome::new(format!("{action}: dry run; would post to {target}"))
+                });
+            }
+            let posted = match (&hook, channel) {
+                (Some(hook), _) => {
+                    let client = discord::Client::new(&hook.base, String::new(), policy, action)?;
+                    client
+                        .post(&hook.path(), &body)
+                        .await
+                        .map_err(|e| hook.redact(e))?
+                }
+                (None, Some(channel)) => {
+                    let token = config.discord.resolve_token(token.as_deref())?;
+                    let client =
+                        discord::Client::new(&config.discord.api_base, token, policy, action)?;
+                    client
+                        .post(&format!("/channels/{channel}/messages"), &body)
+                        .await?
+                }
+                (None, None) => unreachable!("clap requires --channel or --webhook"),
+            };
+            let id = posted["id"].as_str().unwrap_or_default();
+            Ok(Outcome {
+                data: Some(serde_json::json!({
+                    "id": id,
+                    "channel_id": posted["channel_id"],
+                })),
+                ..Outcome::new(format!("{action}: posted message {id} to {target}"))
+            })
+        }
+        Command::WatchDir {
+            path,
+            token,
+            guild,
+            debounce,
+            interval,
+            max_changes,
+            only,
+            ..
+        } => {
+            if !path.is_dir() {
+                return Err(CliError::NotFound(format!(
+                    "{} is not a directory",
+                    path.display()
+                )));
+            }
+            let token = config.discord.resolve_token(token.as_deref())?;
+            let mut watch = DirWatch::new(path, Duration::from_millis(*debounce))?;
+            let mut ticker = tokio::time::interval(Duration::from_millis((*interval).max(50)));
+            let stop = tokio::signal::ctrl_c();
+            tokio::pin!(stop);
+            tracing::info!(
+                "watching {} for files to import into guild {guild}; press Ctrl-C to stop",
+                path.display()
+            );
+            let (mut imported, mut failed) = (0, 0);
+            let mut files = Vec::new();
+            'watch: loop {
+                tokio::select! {
+                    _ = &mut stop => break,
+                    _ = ticker.tick() => {}
+                }
+                for file in watch.poll(std::time::Instant::now())? {
+                    tracing::info!("{} changed", file.display());
+                    let import = cli.with_command(Command::Discord {
+                        token: Some(token.clone()),
+                        command: DiscordCommand::Import {
+                            r#in: file.clone(),
+                            guild: *guild,
+                            dry_run: false,
+                            max_changes: *max_changes,
+                            only: only.clone(),
+                            journal: None,
+                            replay_messages: false,
+                            max_messages: 1000,
+                            replay_interval: None,
+                        },
+                    });
+                    let warnings = Warnings::default();
+                    let result = Box::pin(execute(&import, config, &warnings)).await;
+                    let code = report(&import, &result, warnings);
+                    if matches!(result, Err(CliError::Cancelled)) {
+                        break 'watch;
+                    }
+                    if code == ExitCode::Ok {
+                        imported += 1;
+                    } else {
+                        failed += 1;
+                    }
+                    files.push(serde_json::json!({
+                        "path": file,
+                        "ok": code == ExitCode::Ok,
+                        "exit_code": code.code(),
+                    }));
+                }
+            }
+            let (verb, to) = if cli.dry_run() {
+                ("previewed", "for")
+            } else {
+                ("imported", "into")
+            };
+            Ok(Outcome {
+                data: Some(serde_json::json!({
+                    "guild": guild.to_string(),
+                    verb: imported,
+                    "failed": failed,
+                    "files": files,
+                })),
+                exit: if failed > 0 {
+                    ExitCode::Failure
+                } else {
+                    ExitCode::Ok
+                },
+                ..Outcome::new(format!(
+                    "{action}: {verb} {imported} file(s) {to} guild {guild}, {failed} failed"
+                ))
+            })
+        }
+        Command::Ssh { command } => Box::pin(run_ssh(cli, config, command)).await,
+        Command::Mcp { command } => match command {
+            McpCommand::Serve { allow_write } => {
+                mcp::serve(config, *allow_write).await?;
+                // stdout belongs to the protocol; nothing else is printed.
+                Ok(Outcome::default())
+            }
+        },
+        Command::Config { command } => match command {
+            ConfigCommand::Show => {
+                let redacted = config.redacted();
+                let source = match &config.source {
+                    Some(path) => path.display().to_string(),
+                    None => "built-in defaults".to_string(),
+                };
+                Ok(Outcome {
+                    message: format!("effective config from {source}"),
+                    body: Some(
+                        toml::to_string_pretty(&redacted)
+                            .map_err(|e| CliError::Config(e.to_string()))?,
+                    ),
+                    data: Some(serde_json::json!({
+                        "source": config.source,
+                        "config": redacted,
+                    })),
+                    ..Outcome::default()
+                })
+            }
+            ConfigCommand::Validate => {
+                config.validate()?;
+                Ok(Outcome::new("config is valid"))
+            }
+        },
+        Command::Version => Ok(Outcome {
+            message: format!(
+                "guildsync {} (commit {}, built {}, {})",
+                BUILD_INFO.version, BUILD_INFO.commit, BUILD_INFO.build_date, BUILD_INFO.rustc
+            ),
+            data: Some(serde_json::to_value(&BUILD_INFO)?),
+            ..Outcome::default()
+        }),
+        Command::Completions { command } => match command {
+            CompletionsCommand::Print { shell } => {
+                print!("{}", completion_script(*shell));
+                Ok(Outcome::default())
+            }
+            CompletionsCommand::Install { shell, force } => {
+                let Some(shell) = shell.or_else(Shell::from_env) else {
+                    // Print the most common script with where each shell loads its own from.
+                    let script = completion_script(Shell::Bash);
+                    let places: Vec<String> = [Shell::Bash, Shell::Zsh, Shell::Fish]
+                        .into_iter()
+                        .filter_map(|shell| {
+                            let path = completions::install_path(shell, BIN_NAME)?;
+                            Some(format!("{shell}: {}", path.display()))
+                        })
+                        .collect();
+                    return Ok(Outcome {
+                        message: format!(
+                            "{action}: cannot detect the shell from $SHELL; the bash script \
+                             follows. Save it (or `{BIN_NAME} completions print <SHELL>` for \
+                             another shell) where the shell loads completions from ({}), or \
+                             name the shell to install it: `{BIN_NAME} completions install zsh`",
+                            places.join("; ")
+                        ),
+                        body: Some(script.clone()),
+                        data: Some(serde_json::json!({
+                            "shell": Shell::Bash.to_string(),
+                            "detected": false,
+                            "installed": false,
+                            "script": script,
+                        })),
+                        ..Outcome::default()
+                    });
+                };
+                let script = completion_script(shell);
+                let Some(path) = completions::install_path(shell, BIN_NAME) else {
+                    return Ok(Outcome {
+                        message: format!(
+                            "{action}: no standard completion directory for {shell}; save the \
+                             script below where {shell} loads completions from"
+                        ),
+                        body: Some(script.clone()),
+                        data: Some(serde_json::json!({
+                            "shell": shell.to_string(),
+                            "installed": false,
+                            "script": script,
+                        })),
+                        ..Outcome::default()
+                    });
+                };
+                completions::install(&path, script.as_bytes(), *force)?;
+                let hint = match shell {
+                    Shell::Zsh => " (add ~/.zsh/completions to fpath before compinit)",
+                    _ => "",
+                };
+                Ok(Outcome {
+                    message: format!("{action}: wrote {}{hint}", path.display()),
+                    data: Some(serde_json::json!({
+                        "shell": shell.to_string(),
+                        "installed": true,
+                        "path": path,
+                    })),
+                    ..Outcome::default()
+                })
+            }
+        },
+        Command::Auth { command } => match command {
+            AuthCommand::Login {
+                token_stdin,
+                no_verify,
+            } => {
+                let token = read_token(*token_stdin).await?;
+                let mut data = serde_json::json!({ "verified": !*no_verify });
+                let mut user = String::new();
+                if !*no_verify {
+                    let client = discord::Client::new(
+                        &config.discord.api_base,
+                        token.clone(),
+                        policy,
+                        action,
+                    )?;
+                    let me = client.get("/users/@me").await?;
+                    user = format!(" for {}", me["username"].as_str().unwrap_or("unknown bot"));
+                    data["user"] = me["username"].clone();
+                }
+                auth::store_token(&token)?;
+                Ok(Outcome {
+                    data: Some(data),
+                    ..Outcome::new(format!(
+                        "{action}: stored bot token{user} in the system keyring"
+                    ))
+                })
+            }
+            AuthCommand::Logout => {
+                let removed = auth::delete_token()?;
+                Ok(Outcome {
+                    data: Some(serde_json::json!({ "removed": removed })),
+                    ..Outcome::new(if removed {
+                        format!("{action}: removed bot token from the system keyring")
+                    } else {
+                        format!("{action}: no bot token in the system keyring")
+                    })
+                })
+            }
+            AuthCommand::Status => {
+                let (_, source) = config.discord.resolve_token_with_source(None)?;
+                Ok(Outcome {
+                    data: Some(serde_json::json!({ "source": source })),
+                    ..Outcome::new(format!("{action}: using bot token from {source}"))
+                })
+            }
+            AuthCommand::Keygen { force } => {
+                if !*force && auth::stored_signing_key()?.is_some() {
+                    return Err(CliError::Usage(
+                        "a signing key is already stored; pass --force to replace it".to_string(),
+                    ));
+                }
+                let encoded = signing::generate()?;
+                let key = signing::signing_key(&encoded).map_err(CliError::Validation)?;
+                auth::store_signing_key(&encoded)?;
+                let public_key = signing::public_key_of(&key);
+                Ok(Outcome {
+                    data: Some(serde_json::json!({ "public_key": public_key })),
+                    ..Outcome::new(format!(
+                        "{action}: stored a new signing key in the system keyring; public key {public_key}"
+                    ))
+                })
+            }
+            AuthCommand::PublicKey => {
+                let key = signing::resolve_key(&config.formats.signing_key_env)?;
+                let public_key = signing::public_key_of(&key);
+                Ok(Outcome {
+                    data: Some(serde_json::json!({ "public_key": public_key })),
+                    ..Outcome::new(format!("{action}: {public_key}"))
+                })
+            }
+        },
+        Command::Doctor => {
+            let checks = doctor::run_checks(cli.config.as_deref()).await;
+            let passed = checks.iter().filter(|c| c.ok).count();
+            let critical_failed = checks.iter().any(|c| c.critical && !c.ok);
+            let body: String = checks
+                .iter()
+                .map(|c| {
+                    let mark = if c.ok { "✓" } else { "✗" };
+                    let critical = if c.critical && !c.ok {
+                        " (critical)"
+                    } else {
+                        ""
+                    };
+                    format!("{mark} {:<14} {}{critical}\n", c.name, c.detail)
+                })
+                .collect();
+            Ok(Outcome {
+                message: format!("{action}: {passed}/{} checks passed", checks.len()),
+                body: Some(body),
+                data: Some(serde_json::json!({ "checks": checks })),
+                exit: if critical_failed {
+                    ExitCode::Failure
+                } else {
+                    ExitCode::Ok
+                },
+                items: None,
+            })
+        }
+        Command::Terminal { command } => {
+            Box::pin(run_terminal(cli, config, warnings, policy, command)).await
+        }
+    }
+}
+
+/// [`run`] for `discord` subcommands.
+async fn run_discord(
+    cli: &Cli,
+    config: &Config,
+    warnings: &Warnings,
+    policy: RetryPolicy,
+    token: &Option<String>,
+    command: &DiscordCommand,
+) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    match command {
+        // Comparing two files needs no token.
+        DiscordCommand::Diff { files, guild: None } => {
+            let [old, new] = files.as_slice() else {
+                return Err(CliError::Usage(
+                    "discord diff needs two dump files, or --guild and one".to_string(),
+                ));
+            };
+            let report = diff::report(&format::read_document(old)?, &format::read_document(new)?);
+            Ok(diff_outcome(
+                &report,
+                &old.display().to_string(),
+                &new.display().to_string(),
+            ))
+        }
+        // So does planning from a dump.
+        DiscordCommand::Prune {
+            command:
+                PruneCommand::Plan {
+                    r#in: Some(r#in),
+                    out,
+                    days,
+                    archive_category,
+                    members,
+                    ..
+                },
+        } => {
+            let dump = format::read_document(r#in)?;
+            format::validate_value(&dump, Some(GuildFormat::Dump), &config.formats).map_err(
+                |err| match err {
+                    CliError::Violations(mut violations) => {
+                        for v in &mut violations {
+                            if v.code == "format-mismatch" {
+                                v.message
+                                    .push_str("; upload files do not record channel activity");
+                            }
+                        }
+                        CliError::Violations(violations)
+                    }
+                    err => err,
+                },
+            )?;
+            let members = match members {
+                Some(path) => Some(read_members(path)?),
+                None => {
+                    warnings.push("roles not analysed; pass --members to find unused ones");
+                    None
+                }
+            };
+            prune_plan(&dump, out, *days, *archive_category, members.as_deref())
+        }
+        _ => {
+            let token = config.discord.resolve_token(token.as_deref())?;
+            let client = discord::Client::new(&config.discord.api_base, token, policy, action)?;
+            match command {
+                DiscordCommand::Export {
+                    all: true,
+                    concurrency,
+                    ..
+                } => {
+                    let concurrency = concurrency.unwrap_or(config.discord.export_concurrency);
+                    export_all(cli, config, &client, concurrency, warnings).await
+                }
+                DiscordCommand::Export {
+                    guild,
+                    out,
+                    format,
+                    incremental,
+                    since,
+                    state,
+                    with_attachments,
+                    attachment_concurrency,
+                    with_assets,
+                    manifest,
+                    encrypt_to,
+                    channels,
+                    categories,
+                    users,
+                    after,
+                    before,
+                    compress,
+                    ..
+                } => {
+                    let (Some(guild), Some(out)) = (guild, out) else {
+                        return Err(CliError::Usage(
+                            "--guild and --out are required without --all".to_string(),
+                        ));
+                    };
+                    let job = GuildExport {
+                        id: *guild,
+                        out: out.clone(),
+                        format: *format,
+                        incremental: *incremental,
+                        since: *since,
+                        state: state.clone(),
+                        with_attachments: *with_attachments,
+                        with_assets: *with_assets,
+                        manifest: *manifest,
+                        encrypt_to: encrypt_to.clone(),
+                        channels: channels.clone(),
+                        categories: categories.clone(),
+                        users: users.clone(),
+                        after: after.map(|id| id.to_string()),
+                        before: before.map(|id| id.to_string()),
+                        compress: *compress,
+                    };
+                    let attachment_concurrency =
+                        attachment_concurrency.unwrap_or(config.discord.attachment_concurrency);
+                    export_guild(cli, config, &client, &job, attachment_concurrency, warnings).await
+                }
+                DiscordCommand::AuditLog {
+                    guild,
+                    out,
+                    action_type,
+                    actor,
+                } => {
+                    let filters = AuditLogFilters {
+                        action_type: *action_type,
+                        actor: actor.map(|id| id.to_string()),
+                    };
+                    let bar = progress::bar(cli.progress(), 0, action);
+                    let document =
+                        discord::fetch_audit_log(&client, *guild, &filters, &bar).await?;
+                    bar.finish_and_clear();
+                    format::write_document(out, &document)?;
+                    let count = document["entries"].as_array().map_or(0, Vec::len);
+                    Ok(Outcome {
+                        data: Some(serde_json::json!({ "entries": count })),
+                        ..Outcome::new(format!(
+                            "exported {count} audit log entr{} of guild {guild} to {}",
+                            if count == 1 { "y" } else { "ies" },
+                            out.display()
+                        ))
+                    })
+                }
+                DiscordCommand::Watch { guild, out } => {
+                    tracing::info!("watching guild {guild}; press Ctrl-C to stop");
+                    let stop = async {
+                        let _ = tokio::signal::ctrl_c().await;
+                    };
+                    let stats = gateway::watch(&client, *guild, out, stop).await?;
+                    Ok(Outcome {
+                        data: Some(serde_json::to_value(&stats)?),
+                        ..Outcome::new(format!(
+                            "appended {} event(s) of guild {guild} to {}",
+                            stats.events,
+                            out.display()
+                        ))
+                    })
+                }
+                DiscordCommand::Prune {
+                    command:
+                        PruneCommand::Plan {
+                            guild,
+                            out,
+                            days,
+                            archive_category,
+                            members,
+                            ..
+                        },
+                } => {
+                    let Some(guild) = guild else {
+                        unreachable!("plans from a dump are handled without a token");
+                    };
+                    let dump = fetch_dump(cli, config, &client, *guild).await?;
+                    let members = match members {
+                        Some(path) => Some(read_members(path)?),
+                        None => {
+                            let bar = progress::bar(cli.progress(), 0, action);
+                            match discord::fetch_members(&client, *guild, None, &bar).await {
+                                Ok(document) => Some(
+                                    document["members"].as_array().cloned().unwrap_or_default(),
+                                ),
+                                Err(err @ CliError::Auth(_)) => {
+                                    warnings.push(format!("roles not analysed: {err}"));
+                                    None
+                                }
+                                Err(err) => return Err(err),
+                            }
+                        }
+                    };
+                    prune_plan(&dump, out, *days, *archive_category, members.as_deref())
+                }
+                DiscordCommand::Members { guild, out, redact } => {
+                    let bar = progress::bar(cli.progress(), 0, action);
+                    let document = discord::fetch_members(&client, *guild, *redact, &bar).await?;
+                    bar.finish_and_clear();
+                    format::write_document(out, &document)?;
+                    let count = document["members"].as_array().map_or(0, Vec::len);
+                    Ok(Outcome {
+                        data: Some(serde_json::json!({ "members": count })),
+                        ..Outcome::new(format!(
+                            "exported {count} member(s) of guild {guild} to {}",
+                            out.display()
+                        ))
+                    })
+                }
+                DiscordCommand::Diff { files, guild } => {
+                    let ([file], Some(guild)) = (files.as_slice(), guild) else {
+                        return Err(CliError::Usage(
+                            "discord diff --guild takes one dump file".to_string(),
+                        ));
+                    };
+                    let document = format::read_document(file)?;
+                    let filters: ExportFilters = match document.get("filters") {
+                        Some(filters) => serde_json::from_value(filters.clone())?,
+                        None => ExportFilters::default(),
+                    };
+                    let mut live = fetch_dump(cli, config, &client, *guild).await?;
+                    filters.retain_channels(&mut live);
+                    if document.get("threads").is_some() {
+                        fetch_threads(cli, &client, *guild, &mut live, warnings).await?;
+                        filters.retain_channels(&mut live);
+                    }
+                    let report = diff::report(&live, &document);
+                    Ok(diff_outcome(
+                        &report,
+                        &format!("guild {guild}"),
+                        &file.display().to_string(),
+                    ))
+                }
+                DiscordCommand::Import {
+                    r#in,
+                    guild,
+                    max_changes,
+                    only,
+                    journal,
+                    replay_messages,
+                    max_messages,
+                    replay_interval,
+                    ..
+                } => {
+                    let mut document = format::read_document(r#in)?;
+                    format::validate_value(&document, None, &config.formats)?;
+                    if let Some(serde_json::Value::Array(threads)) = document
+                        .as_object_mut()
+                        .and_then(|obj| obj.shift_remove("threads"))
+                        && !threads.is_empty()
+                    {
+                        warnings.push(format!(
+                            "{} thread(s) in {} are not imported; threads are export-only",
+                            threads.len(),
+                            r#in.display()
+                        ));
+                    }
+                    // A channel-filtered export only speaks for its channels: import just those,
+                    // leaving roles, settings, and every other channel alone.
+                    let filters: ExportFilters = match document.get("filters") {
+                        Some(filters) => serde_json::from_value(filters.clone())?,
+                        None => ExportFilters::default(),
+                    };
+                    let implied = only.is_empty() && filters.scopes_channels();
+                    let only = if implied {
+                        &vec![ImportSection::Categories, ImportSection::Channels]
+                    } else {
+                        only
+                    };
+                    if !only.is_empty() {
+                        let missing;
+                        (document, missing) = format::select_sections(&document, only);
+                        for section in missing.into_iter().filter(|_| !implied) {
+                            warnings.push(format!(
+                                "--only {section}: nothing to import in {}",
+                                r#in.display()
+                            ));
+                        }
+                    }
+                    let mut current = fetch_dump(cli, config, &client, *guild).await?;
+                    if !only.is_empty() {
+                        (current, _) = format::select_sections(&current, only);
+                    }
+                    filters.retain_channels(&mut current);
+                    let preflight = diff::diff(&current, &document);
+                    let count = preflight.change_count();
+                    tracing::info!("preflight: {}", preflight.summary());
+                    let steps = import::plan(&current, &document);
+                    let listing = import::listing(&steps);
+                    let pending = if *replay_messages {
+                        replay::pending(&document, &steps)
+                    } else {
+                        Vec::new()
+                    };
+                    let messages: usize = pending.iter().map(|p| p.messages.len()).sum();
+                    if messages > *max_messages {
+                        return Err(CliError::Validation(format!(
+                            "refusing to replay {messages} messages: more than --max-messages {max_messages}"
+                        )));
+                    }
+                    let replay_note = if messages > 0 {
+                        format!(
+                            " and replay {messages} message(s) in {} channel(s)",
+                            pending.len()
+                        )
+                    } else {
+                        String::new()
+                    };
+                    let data = |applied: usize| {
+                        serde_json::json!({
+                            "applied": applied,
+                            "preflight": preflight,
+                            "plan": steps,
+                        })
+                    };
+                    if cli.dry_run() {
+                        let mut data = data(0);
+                        if *replay_messages {
+                            data["replay"] = serde_json::json!({
+                                "channels": pending.len(),
+                                "messages": messages,
+                            });
+                        }
+                        return Ok(Outcome {
+                            message: format!(
+                                "{action}: dry run; would apply {} step(s) to guild {guild}{replay_note}",
+                                steps.len()
+                            ),
+                            body: Some(listing),
+                            data: Some(data),
+                            ..Outcome::default()
+                        });
+                    }
+                    if steps.is_empty() {
+                        return Ok(Outcome {
+                            data: Some(data(0)),
+                            ..Outcome::new(format!("{action}: guild {guild} already matches"))
+                        });
+                    }
+                    if count > *max_changes {
+                        if !cli.yes {
+                            return Err(CliError::TooManyChanges {
+                                count,
+                                limit: *max_changes,
+                            });
+                        }
+                        warnings.push(format!(
+                            "{count} changes exceed --max-changes {max_changes}; continuing because of --yes"
+                        ));
+                    }
+                    if !cli.yes {
+                        eprint!("{listing}");
+                        let question = format!(
+                            "About to apply {} step(s) from {} to guild {guild}{replay_note}. Are you sure?",
+                            steps.len(),
+                            r#in.display()
+                        );
+                        if !prompt::confirm(&question).await? {
+                            return Ok(Outcome {
+                                data: Some(serde_json::json!({ "cancelled": true })),
+                                ..Outcome::new(format!("{action}: cancelled"))
+                            });
+                        }
+                    }
+                    let journal_path = journal
+                        .clone()
+                        .unwrap_or_else(|| journal::default_path(r#in));
+                    let mut journal = Journal::create(&journal_path, *guild, r#in, &current)?;
+                    let ids = apply_journaled(
+                        cli,
+                        &client,
+                        *guild,
+                        &mut journal,
+                        &journal_path,
+                        &document,
+                        &steps,
+                    )
+                    .await?;
+                    let applied = steps.len();
+                    let mut data = data(applied);
+                    data["journal"] = journal_path.display().to_string().into();
+                    let mut replayed = String::new();
+                    if messages > 0 {
+                        let bar = progress::bar(cli.progress(), messages as u64, action);
+                        let interval = Duration::from_millis(
+                            replay_interval.unwrap_or(config.discord.replay_interval_ms),
+                        );
+                        let stats = replay::replay(
+                            &client,
+                            &pending,
+                            &ids,
+                            &config.discord.cdn_base,
+                            interval,
+                            &bar,
+                        )
+                        .await
+                        .inspect_err(|_| {
+                            tracing::error!(
+                                "the import itself was applied and is journaled in {}",
+                                journal_path.display()
+                            );
+                        })?;
+                        bar.finish_and_clear();
+                        replayed = format!(
+                            "; replayed {} message(s) in {} channel(s)",
+                            stats.messages, stats.channels
+                        );
+                        data["replay"] = serde_json::to_value(&stats)?;
+                    }
+                    Ok(Outcome {
+                        message: format!(
+                            "{action}: applied {applied} step(s) to guild {guild}{replayed}; revert \
+                             with `guildsync discord undo --journal {}`",
+                            journal_path.display()
+                        ),
+                        data: Some(data),
+                        ..Outcome::default()
+                    })
+                }
+                DiscordCommand::Template {
+                    command:
+                        TemplateCommand::Create {
+                            r#in,
+                            guild,
+                            name,
+                            description,
+                            journal,
+                        },
+                } => {
+                    let document = format::read_document(r#in)?;
+                    format::validate_value(&document, None, &config.formats)?;
+                    let name = name
+                        .clone()
+                        .or_else(|| document["guild"]["name"].as_str().map(str::to_string))
+                        .unwrap_or_else(|| "guildsync".to_string());
+                    let sections = [
+                        ImportSection::Roles,
+                        ImportSection::Categories,
+                        ImportSection::Channels,
+                    ];
+                    let (document, _) = format::select_sections(&document, &sections);
+                    let current = fetch_dump(cli, config, &client, *guild).await?;
+                    let (current, _) = format::select_sections(&current, &sections);
+                    let steps = import::plan(&current, &document);
+                    let listing = import::listing(&steps);
+                    if cli.dry_run() {
+                        return Ok(Outcome {
+                            message: format!(
+                                "{action}: dry run; would apply {} step(s) to guild {guild} and \
+                                 save it as template {name:?}",
+                                steps.len()
+                            ),
+                            body: Some(listing),
+                            data: Some(serde_json::json!({ "applied": 0, "plan": steps })),
+                            ..Outcome::default()
+                        });
+                    }
+                    if !steps.is_empty() && !cli.yes {
+                        eprint!("{listing}");
+                        let question = format!(
+                            "About to apply {} step(s) from {} to guild {guild} to stage template \
+                             {name:?}. Are you sure?",
+                            steps.len(),
+                            r#in.display()
+                        );
+                        if !prompt::confirm(&question).await? {
+                            return Ok(Outcome {
+                                data: Some(serde_json::json!({ "cancelled": true })),
+                                ..Outcome::new(format!("{action}: cancelled"))
+                            });
+                        }
+                    }
+                    let mut data = serde_json::json!({ "applied": steps.len(), "plan": steps });
+                    if !steps.is_empty() {
+                        let journal_path = journal
+                            .clone()
+                            .unwrap_or_else(|| journal::default_path(r#in));
+                        let mut journal = Journal::create(&journal_path, *guild, r#in, &current)?;
+                        apply_journaled(
+                            cli,
+                            &client,
+                            *guild,
+                            &mut journal,
+                            &journal_path,
+                            &document,
+                            &steps,
+                        )
+                        .await?;
+                        data["journal"] = journal_path.display().to_string().into();
+                    }
+                    let template =
+                        discord::save_template(&client, *guild, &name, description.as_deref())
+                            .await?;
+                    let code = template["code"].as_str().unwrap_or_default();
+                    let url = format!("https://discord.new/{code}");
+                    data["template"] = code.into();
+                    data["url"] = url.clone().into();
+                    Ok(Outcome {
+                        data: Some(data),
+                        ..Outcome::new(format!(
+                            "{action}: applied {} step(s) to guild {guild} and saved template \
+                             {name:?}: {url}",
+                            steps.len()
+                        ))
+                    })
+                }
+                DiscordCommand::Undo { journal: path } => {
+                    let mut journal = Journal::load(path)?;
+                    if let Some(undone_at) = &journal.undone_at {
+                        return Err(CliError::Usage(format!(
+                            "{} was already undone at {undone_at}",
+                            path.display()
+                        )));
+                    }
+                    let guild = journal.guild;
+                    let (steps, images) = journal.undo_steps();
+                    let listing = import::listing(&steps);
+                    let data = |reverted: usize| serde_json::json!({ "reverted": reverted, "guild": guild, "plan": steps });
+                    if cli.dry_run() {
+                        return Ok(Outcome {
+                            message: format!(
+                                "{action}: dry run; would revert {} step(s) in guild {guild}",
+                                steps.len()
+                            ),
+                            body: Some(listing),
+                            data: Some(data(0)),
+                            ..Outcome::default()
+                        });
+                    }
+                    if !cli.yes {
+                        eprint!("{listing}");
+                        let question = format!(
+                            "About to revert {} step(s) from {} in guild {guild}. Are you sure?",
+                            steps.len(),
+                            journal.source
+                        );
+                        if !prompt::confirm(&question).await? {
+                            return Ok(Outcome {
+                                data: Some(serde_json::json!({ "cancelled": true })),
+                                ..Outcome::new(format!("{action}: cancelled"))
+                            });
+                        }
+                    }
+                    let bar = progress::bar(cli.progress(), steps.len() as u64, action);
+                    import::apply(&client, guild, &images, &steps, None, &bar).await?;
+                    let reverted = steps.len();
+                    bar.finish_and_clear();
+                    journal.mark_undone()?;
+                    Ok(Outcome {
+                        message: format!("{action}: reverted {reverted} step(s) in guild {guild}"),
+                        data: Some(data(reverted)),
+                        ..Outcome::default()
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// [`run`] for `kube` subcommands.
+async fn run_kube(
+    cli: &Cli,
+    config: &Config,
+    warnings: &Warnings,
+    policy: RetryPolicy,
+    kubeconfig: &[PathBuf],
+    command: &KubeCommand,
+) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    match command {
+        KubeCommand::Local {
+            command:
+                KubeLocalCommand::Up {
+                    cluster,
+                    image,
+                    no_wait,
+                    wait,
+                    timeout,
+                },
+        } => {
+            if *wait {
+                warnings.push("--wait is deprecated: kube local up now waits by default");
+            }
+            let start = std::time::Instant::now();
+            let cluster = cluster.cluster(&config.kube.local, image.as_deref(), kubeconfig)?;
+            let found = cluster.up().await?;
+            let context = cluster.context();
+            let kubeconfigs = cluster.kubeconfigs(kubeconfig);
+            kube::require_context(&kubeconfigs, &context)?;
+            let how = match found {
+                kube::Presence::Absent => "created",
+                kube::Presence::Stopped => "started",
+                kube::Presence::Running => "already running",
+            };
+            let mut data = serde_json::json!({
+                "backend": cluster.backend,
+                "name": cluster.name,
+                "context": context,
+                "found": found,
+            });
+            if *no_wait {
+                return Ok(Outcome {
+                    data: Some(data),
+                    ..Outcome::new(format!(
+                        "{action}: {} cluster {} {how} (context {context})",
+                        cluster.backend.program(),
+                        cluster.name
+                    ))
+                });
+            }
+            let timeout = Duration::from_secs(timeout.unwrap_or(300));
+            let (nodes, _) = kube::wait_for_nodes_ready(&kubeconfigs, &context, timeout).await?;
+            let elapsed = start.elapsed();
+            data["nodes"] = nodes.into();
+            data["elapsed_secs"] = elapsed.as_secs_f64().into();
+            Ok(Outcome {
+                data: Some(data),
+                ..Outcome::new(format!(
+                    "{action}: {} cluster {} {how}; {nodes} node(s) Ready after {:.1}s \
+                     (context {context})",
+                    cluster.backend.program(),
+                    cluster.name,
+                    elapsed.as_secs_f64()
+                ))
+            })
+        }
+        KubeCommand::Local {
+            command: KubeLocalCommand::Down { cluster },
+        } => {
+            let cluster = cluster.cluster(&config.kube.local, None, kubeconfig)?;
+            let found = cluster.down().await?;
+            let what = format!("{} cluster {}", cluster.backend.program(), cluster.name);
+            Ok(Outcome {
+                data: Some(serde_json::json!({
+                    "backend": cluster.backend,
+                    "name": cluster.name,
+                    "deleted": found != kube::Presence::Absent,
+                })),
+                ..Outcome::new(if found == kube::Presence::Absent {
+                    format!("{action}: no {what}; nothing to delete")
+                } else {
+                    format!("{action}: deleted {what}")
+                })
+            })
+        }
+        KubeCommand::Local {
+            command: KubeLocalCommand::Status { cluster },
+        } => {
+            let cluster = cluster.cluster(&config.kube.local, None, kubeconfig)?;
+            let what = format!("{} cluster {}", cluster.backend.program(), cluster.name);
+            let found = cluster.presence().await?;
+            match found {
+                kube::Presence::Absent => {
+                    return Err(CliError::NotFound(format!(
+                        "no {what}; create it with `kube local up`"
+                    )));
+                }
+                kube::Presence::Stopped => {
+                    return Ok(Outcome {
+                        data: Some(serde_json::json!({
+                            "backend": cluster.backend,
+                            "name": cluster.name,
+                            "found": found,
+                        })),
+                        exit: ExitCode::Failure,
+                        ..Outcome::new(format!(
+                            "{action}: {what} is stopped; start it with `kube local up`"
+                        ))
+                    });
+                }
+                kube::Presence::Running => {}
+            }
+            let context = cluster.context();
+            let (nodes, pods) =
+                kube::cluster_status(&cluster.kubeconfigs(kubeconfig), &context, &policy).await?;
+            let ready = nodes.iter().filter(|n| n.ready).count();
+            let mut body = String::new();
+            for node in &nodes {
+                let state = if node.ready { "Ready" } else { "NotReady" };
+                body.push_str(&format!("{}\t{state}\t{}\n", node.name, node.version));
+            }
+            body.push_str(&format!(
+                "pods: {} running, {} pending, {} succeeded, {} failed\n",
+                pods.running, pods.pending, pods.succeeded, pods.failed
+            ));
+            for pod in &pods.not_ready {
+                body.push_str(&format!("not ready: {pod}\n"));
+            }
+            let healthy = ready == nodes.len() && !nodes.is_empty();
+            Ok(Outcome {
+                message: format!(
+                    "{action}: {what} running; {ready}/{} node(s) Ready, {}/{} pod(s) ready \
+                     (context {context})",
+                    nodes.len(),
+                    pods.total - pods.not_ready.len(),
+                    pods.total
+                ),
+                body: Some(body),
+                data: Some(serde_json::json!({
+                    "backend": cluster.backend,
+                    "name": cluster.name,
+                    "context": context,
+                    "found": found,
+                    "nodes": nodes,
+                    "pods": pods,
+                })),
+                exit: if healthy {
+                    ExitCode::Ok
+                } else {
+                    ExitCode::Failure
+                },
+                items: None,
+            })
+        }
+        KubeCommand::Remote {
+            command:
+                KubeRemoteCommand::Test {
+                    context,
+                    guild,
+                    token,
+                    manifest,
+                    keep,
+                },
+        } => {
+            let manifest = manifest
+                .as_deref()
+                .map(std::fs::read_to_string)
+                .transpose()?;
+            let token = guild
+                .map(|_| config.discord.resolve_token(token.as_deref()))
+                .transpose()?;
+            let job = match (&manifest, guild, &token) {
+                (Some(manifest), ..) => kube::TestJob::Manifest(manifest),
+                (None, Some(guild), Some(token)) => kube::TestJob::Guild {
+                    guild: *guild,
+                    token,
+                },
+                _ => kube::TestJob::Doctor,
+            };
+            kube::require_context(kubeconfig, context)?;
+            kube::require_capability(kubeconfig, context, None, kube::Capability::Test).await?;
+            let report = kube::run_test_job(
+                kubeconfig,
+                context,
+                job,
+                &config.kube.remote.image,
+                *keep,
+                &policy,
+            )
+            .await?;
+            // A test that ran and failed exits 1; a Job that never got to run its test is a
+            // cluster problem.
+            let (message, exit) = match report.exit_code {
+                _ if report.passed => {
+                    (format!("{action}: job {} passed", report.job), ExitCode::Ok)
+                }
+                Some(code) => (
+                    format!("{action}: job {} failed (exit code {code})", report.job),
+                    ExitCode::Failure,
+                ),
+                None => {
+                    let err =
+                        CliError::Kube(format!("job {} failed before its test exited", report.job));
+                    (err.to_string(), err.exit_code())
+                }
+            };
+            // The logs were streamed to stderr while the Job ran.
+            Ok(Outcome {
+                message,
+                data: Some(serde_json::to_value(&report)?),
+                exit,
+                ..Outcome::default()
+            })
+        }
+        KubeCommand::Logs {
+            context,
+            namespace,
+            all_namespaces,
+            instance,
+            selector,
+            container,
+            follow,
+            tail,
+            since,
+            timestamps,
+        } => {
+            let namespace = (!*all_namespaces).then(|| {
+                namespace
+                    .as_deref()
+                    .unwrap_or(&config.kube.deploy.namespace)
+            });
+            let selector = match (selector, instance) {
+                (Some(selector), _) => selector.clone(),
+                (None, Some(instance)) => {
+                    format!(
+                        "{},app.kubernetes.io/instance={instance}",
+                        kube::WORKLOAD_SELECTOR
+                    )
+                }
+                (None, None) => kube::WORKLOAD_SELECTOR.to_string(),
+            };
+            kube::require_context(kubeconfig, context)?;
+            let mut sources = kube::log_sources(
+                kubeconfig,
+                context,
+                namespace,
+                &selector,
+                container.as_deref(),
+                &policy,
+            )
+            .await?;
+            if sources.is_empty() {
+                let scope = match namespace {
+                    Some(namespace) => format!("namespace {namespace}"),
+                    None => "any namespace".to_string(),
+                };
+                let what = match container {
+                    Some(container) => format!("pods with a container {container}"),
+                    None => "pods".to_string(),
+                };
+                return Err(CliError::NotFound(format!(
+                    "no {what} match {selector} in {scope} (context {context})"
+                )));
+            }
+            let options = kube::LogOptions {
+                follow: *follow,
+                tail: *tail,
+                since: since.as_deref(),
+                timestamps: *timestamps,
+            };
+            if *follow {
+                tracing::info!(
+                    "following {} container(s); press Ctrl-C to stop",
+                    sources.iter().filter(|s| s.error.is_none()).count()
+                );
+            }
+            let stop = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+            kube::show_logs(kubeconfig, context, &mut sources, &options, stop).await?;
+            if let [first, ..] = sources.as_slice()
+                && sources.iter().all(|s| s.error.is_some())
+            {
+                return Err(CliError::Kube(format!(
+                    "no logs could be read; {}/{}: {}",
+                    first.pod,
+                    first.container,
+                    first.error.as_deref().unwrap_or_default()
+                )));
+            }
+            for source in &sources {
+                if let Some(error) = &source.error {
+                    warnings.push(format!(
+                        "{}/{} ({}): {error}",
+                        source.namespace, source.pod, source.container
+                    ));
+                }
+            }
+            // The logs themselves are the output.
+            Ok(Outcome::default())
+        }
+        KubeCommand::Secrets {
+            command:
+                KubeSecretsCommand::Sync {
+                    context,
+                    token,
+                    namespace,
+                    secret,
+                    key,
+                },
+        } => {
+            let settings = &config.kube.deploy;
+            let (token, source) = config.discord.resolve_token_with_source(token.as_deref())?;
+            let namespace = namespace.as_deref().unwrap_or(&settings.namespace);
+            let secret = secret
+                .clone()
+                .unwrap_or_else(|| format!("{}-token", settings.name));
+            let key = key.as_deref().unwrap_or(&config.discord.token_env);
+            let manifest = deploy::token_secret(&settings.name, namespace, &secret, key, &token)?;
+            let data = serde_json::json!({
+                "context": context,
+                "namespace": namespace,
+                "secret": secret,
+                "key": key,
+                "source": source,
+            });
+
+            kube::require_context(kubeconfig, context)?;
+            let Some(diff) = kube::diff(kubeconfig, context, &manifest.yaml).await? else {
+                return Ok(Outcome {
+                    data: Some(serde_json::json!({ "secret": data, "changed": false })),
+                    ..Outcome::new(format!(
+                        "{action}: secret {namespace}/{secret} is up to date (context {context})"
+                    ))
+                });
+            };
+            // kubectl diff masks Secret values already; this guards against one that does not.
+            let diff = deploy::mask(&diff, &token);
+            if cli.dry_run() {
+                return Ok(Outcome {
+                    body: Some(diff),
+                    data: Some(serde_json::json!({ "secret": data, "changed": true })),
+                    ..Outcome::new(format!(
+                        "{action}: dry run; would update secret {namespace}/{secret} with the bot \
+                         token from {source} (context {context})"
+                    ))
+                });
+            }
+            if !cli.yes {
+                eprint!("{diff}");
+                let question = format!(
+                    "About to update secret {namespace}/{secret} in kube context {context} with \
+                     the bot token from {source}. Are you sure?"
+                );
+                if !prompt::confirm(&question).await? {
+                    return Ok(Outcome {
+                        data: Some(serde_json::json!({ "cancelled": true })),
+                        ..Outcome::new(format!("{action}: cancelled"))
+                    });
+                }
+            }
+            kube::apply(kubeconfig, context, &manifest.yaml, false).await?;
+            Ok(Outcome {
+                data: Some(serde_json::json!({ "secret": data, "changed": true })),
+                ..Outcome::new(format!(
+                    "{action}: updated secret {namespace}/{secret} with the bot token from \
+                     {source} (context {context})"
+                ))
+            })
+        }
+        KubeCommand::Schedule {
+            command:
+                KubeScheduleCommand::Export {
+                    guild,
+                    cron,
+                    time_zone,
+                    name,
+                    namespace,
+                    image,
+                    secret,
+                    key,
+                    claim,
+                    size,
+                    s3,
+                    s3_secret,
+                    uploader_image,
+                    out,
+                    apply,
+                    context,
+                },
+        } => {
+            let settings = &config.kube.deploy;
+            let name = name
+                .clone()
+                .unwrap_or_else(|| format!("{}-export-{guild}", settings.name));
+            schedule::validate_name(&name).map_err(CliError::Usage)?;
+            let default_claim = format!("{name}-dumps");
+            let default_s3_secret = format!("{name}-s3");
+            let destination = match (s3, claim) {
+                (Some(url), _) => schedule::Destination::S3 {
+                    url,
+                    secret: s3_secret.as_deref().unwrap_or(&default_s3_secret),
+                    image: uploader_image,
+                },
+                (None, Some(claim)) => schedule::Destination::Claim {
+                    name: claim,
+                    size: size.as_deref(),
+                },
+                (None, None) => schedule::Destination::Claim {
+                    name: &default_claim,
+                    size: Some(size.as_deref().unwrap_or("1Gi")),
+                },
+            };
+            let default_secret = format!("{}-token", settings.name);
+            let export = schedule::ScheduledExport {
+                name: &name,
+                namespace: namespace.as_deref().unwrap_or(&settings.namespace),
+                image: image
+                    .as_deref()
+                    .or(settings.image.as_deref())
+                    .unwrap_or(&config.kube.remote.image),
+                guild: *guild,
+                cron,
+                time_zone,
+                secret: secret.as_deref().unwrap_or(&default_secret),
+                key: key.as_deref().unwrap_or(&config.discord.token_env),
+                destination,
+            };
+            let manifests = export.manifests()?;
+            let stream = deploy::stream(&manifests);
+            let objects = serde_json::to_value(&manifests)?;
+            let context = match (apply, context) {
+                (true, Some(context)) => context,
+                _ => {
+                    if let Some(out) = out {
+                        write_atomic(out, stream.as_bytes())?;
+                        return Ok(Outcome {
+                            data: Some(serde_json::json!({ "manifests": objects, "out": out })),
+                            ..Outcome::new(format!(
+                                "{action}: wrote {} manifest(s) to {}",
+                                manifests.len(),
+                                out.display()
+                            ))
+                        });
+                    }
+                    return Ok(Outcome {
+                        body: Some(stream),
+                        data: Some(serde_json::json!({ "manifests": objects })),
+                        ..Outcome::new(format!(
+                            "{action}: rendered {} manifest(s)",
+                            manifests.len()
+                        ))
+                    });
+                }
+            };
+
+            kube::require_context(kubeconfig, context)?;
+            let Some(diff) = kube::diff(kubeconfig, context, &stream).await? else {
+                return Ok(Outcome {
+                    data: Some(serde_json::json!({ "manifests": objects, "changed": false })),
+                    ..Outcome::new(format!("{action}: context {context} is up to date"))
+                });
+            };
+            if cli.dry_run() {
+                return Ok(Outcome {
+                    body: Some(diff),
+                    data: Some(serde_json::json!({ "manifests": objects, "changed": true })),
+                    ..Outcome::new(format!(
+                        "{action}: dry run; would apply {} manifest(s) to context {context}",
+                        manifests.len()
+                    ))
+                });
+            }
+            if !cli.yes {
+                eprint!("{diff}");
+                let question = format!(
+                    "About to schedule the export of guild {guild} ({cron}) in kube context \
+                     {context}. Are you sure?"
+                );
+                if !prompt::confirm(&question).await? {
+                    return Ok(Outcome {
+                        data: Some(serde_json::json!({ "cancelled": true })),
+                        ..Outcome::new(format!("{action}: cancelled"))
+                    });
+                }
+            }
+            let applied = kube::apply(kubeconfig, context, &stream, false).await?;
+            Ok(Outcome {
+                body: Some(applied.iter().map(|object| format!("{object}\n")).collect()),
+                data: Some(serde_json::json!({
+                    "manifests": objects,
+                    "changed": true,
+                    "applied": applied,
+                })),
+                ..Outcome::new(format!(
+                    "{action}: applied {} manifest(s) to context {context}",
+                    applied.len()
+                ))
+            })
+        }
+        KubeCommand::Context {
+            command:
+                KubeContextCommand::Check {
+                    contexts,
+                    namespace,
+                },
+        } => {
+            let names = if contexts.is_empty() {
+                kube::contexts(kubeconfig)?
+                    .into_iter()
+                    .map(|c| c.name)
+                    .collect()
+            } else {
+                for context in contexts {
+                    kube::require_context(kubeconfig, context)?;
+                }
+                contexts.clone()
+            };
+            if names.is_empty() {
+                return Err(CliError::NotFound(
+                    "no kubeconfig contexts; pass --kubeconfig or create ~/.kube/config"
+                        .to_string(),
+                ));
+            }
+            let namespace = namespace
+                .as_deref()
+                .unwrap_or(&config.kube.deploy.namespace);
+            let checks = futures_util::future::try_join_all(names.iter().map(|context| {
+                kube::check_context(kubeconfig, context, Some(namespace), &kube::Capability::ALL)
+            }))
+            .await?;
+            let ready = checks.iter().filter(|c| c.ready()).count();
+            Ok(Outcome {
+                message: format!(
+                    "{action}: {ready}/{} context(s) ready in namespace {namespace}",
+                    checks.len()
+                ),
+                body: Some(kube::readiness_matrix(&checks)),
+                data: Some(serde_json::json!({ "namespace": namespace, "contexts": checks })),
+                exit: if ready == checks.len() {
+                    ExitCode::Ok
+                } else {
+                    ExitCode::Failure
+                },
+                items: None,
+            })
+        }
+        KubeCommand::PortForward {
+            context,
+            namespace,
+            service,
+            port,
+            local_port,
+            address,
+        } => {
+            let forward = kube::Forward {
+                namespace: namespace
+                    .as_deref()
+                    .unwrap_or(&config.kube.deploy.namespace),
+                service: service.as_deref().unwrap_or(&config.kube.deploy.name),
+                port: *port,
+                local_port: local_port.unwrap_or(*port),
+                address,
+            };
+            kube::require_context(kubeconfig, context)?;
+            let stop = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+            let stats = kube::port_forward(kubeconfig, context, &forward, &policy, stop).await?;
+            Ok(Outcome {
+                data: Some(serde_json::json!({ "forward": forward, "stats": stats })),
+                ..Outcome::new(format!(
+                    "{action}: stopped forwarding {} ({} connection(s), {} reconnect(s))",
+                    forward.describe(),
+                    stats.connections,
+                    stats.reconnects
+                ))
+            })
+        }
+        KubeCommand::Remote {
+            command:
+                KubeRemoteCommand::Status {
+                    context,
+                    namespace,
+                    instance,
+                    selector,
+                    health_port,
+                    health_path,
+                    service,
+                },
+        } => {
+            let namespace = namespace
+                .as_deref()
+                .unwrap_or(&config.kube.deploy.namespace);
+            let selector = match (selector, instance) {
+                (Some(selector), _) => selector.clone(),
+                (None, Some(instance)) => {
+                    format!(
+                        "{},app.kubernetes.io/instance={instance}",
+                        kube::WORKLOAD_SELECTOR
+                    )
+                }
+                (None, None) => kube::WORKLOAD_SELECTOR.to_string(),
+            };
+            if !health_path.starts_with('/') {
+                return Err(CliError::Usage(format!(
+                    "--health-path must start with '/', got `{health_path}`"
+                )));
+            }
+            kube::require_context(kubeconfig, context)?;
+            let probe = health_port.map(|port| health::ProbeTarget {
+                service: service.as_deref().unwrap_or(&config.kube.deploy.name),
+                port,
+                path: health_path,
+            });
+            let Some(status) = health::remote_status(
+                kubeconfig,
+                context,
+                namespace,
+                &selector,
+                probe.as_ref(),
+                &policy,
+            )
+            .await?
+            else {
+                return Err(CliError::NotFound(format!(
+                    "no workloads match {selector} in namespace {namespace} (context {context}); \
+                     deploy with `kube remote deploy`"
+                )));
+            };
+            let mut message = format!(
+                "{action}: {} in namespace {namespace} (context {context})",
+                status.health
+            );
+            if let [first, rest @ ..] = status.reasons.as_slice() {
+                message.push_str(&format!("; {first}"));
+                if !rest.is_empty() {
+                    message.push_str(&format!(" (+{} more)", rest.len()));
+                }
+            }
+            Ok(Outcome {
+                message,
+                body: Some(status.report()),
+                exit: if status.health == health::Health::Healthy {
+                    ExitCode::Ok
+                } else {
+                    ExitCode::Failure
+                },
+                data: Some(serde_json::json!({
+                    "context": context,
+                    "namespace": namespace,
+                    "selector": selector,
+                    "status": status,
+                })),
+                items: None,
+            })
+        }
+        KubeCommand::Remote {
+            command: KubeRemoteCommand::Contexts,
+        }
+        | KubeCommand::Context {
+            command: KubeContextCommand::List,
+        } => {
+            let contexts = kube::contexts(kubeconfig)?;
+            let body = contexts
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{} {}\t{}\t{}\t{}\n",
+                        if c.current { "*" } else { " " },
+                        c.name,
+                        c.cluster.as_deref().unwrap_or("-"),
+                        c.namespace.as_deref().unwrap_or("default"),
+                        c.file.display()
+                    )
+                })
+                .collect();
+            Ok(Outcome {
+                message: format!("{action}: {} context(s)", contexts.len()),
+                body: Some(body),
+                data: Some(serde_json::json!({ "contexts": contexts })),
+                ..Outcome::default()
+            })
+        }
+        KubeCommand::Remote {
+            command:
+                KubeRemoteCommand::Deploy {
+                    context,
+                    token,
+                    guild,
+                    namespace,
+                    image,
+                    templates,
+                    set,
+                    render,
+                    force_conflicts,
+                },
+        } => {
+            let settings = &config.kube.deploy;
+            let guild = match guild.or(settings.guild) {
+                Some(guild) => guild,
+                None => match config.discord.guilds.as_slice() {
+                    [only] => only.id,
+                    _ => {
+                        return Err(CliError::Usage(
+                            "--guild is required unless kube.deploy.guild is set or the config \
+                             lists exactly one guild"
+                                .to_string(),
+                        ));
+                    }
+                },
+            };
+            let token = config.discord.resolve_token(token.as_deref())?;
+            let pod_config = pod_config(config)?;
+            let release = deploy::Release {
+                name: &settings.name,
+                namespace: namespace.as_deref().unwrap_or(&settings.namespace),
+                image: image
+                    .as_deref()
+                    .or(settings.image.as_deref())
+                    .unwrap_or(&config.kube.remote.image),
+                guild,
+                config: &pod_config,
+                token_env: &config.discord.token_env,
+                token: &token,
+            };
+            let mut overrides = settings.vars.clone();
+            overrides.extend(set.iter().cloned());
+            let vars = release.vars(&overrides);
+            let templates = templates
+                .clone()
+                .or_else(|| settings.templates.as_deref().map(expand_tilde));
+            let manifests = deploy::render(templates.as_deref(), &vars)?;
+            let stream = deploy::stream(&manifests);
+            let listing: String = manifests
+                .iter()
+                .map(|m| format!("{}/{} ({})\n", m.kind, m.name, m.file))
+                .collect();
+            let objects = serde_json::to_value(&manifests)?;
+            if *render {
+                return Ok(Outcome {
+                    body: Some(deploy::mask(&stream, &token)),
+                    data: Some(serde_json::json!({ "manifests": objects })),
+                    ..Outcome::new(format!(
+                        "{action}: rendered {} manifest(s)",
+                        manifests.len()
+                    ))
+                });
+            }
+
+            kube::require_context(kubeconfig, context)?;
+            kube::require_capability(
+                kubeconfig,
+                context,
+                Some(release.namespace),
+                kube::Capability::Deploy,
+            )
+            .await?;
+            let Some(diff) = kube::diff(kubeconfig, context, &stream).await? else {
+                return Ok(Outcome {
+                    data: Some(serde_json::json!({ "manifests": objects, "changed": false })),
+                    ..Outcome::new(format!("{action}: context {context} is up to date"))
+                });
+            };
+            if cli.dry_run() {
+                return Ok(Outcome {
+                    body: Some(diff),
+                    data: Some(serde_json::json!({ "manifests": objects, "changed": true })),
+                    ..Outcome::new(format!(
+                        "{action}: dry run; would apply {} manifest(s) to context {context}",
+                        manifests.len()
+                    ))
+                });
+            }
+            if !cli.yes {
+                eprint!("{diff}");
+                let question = format!(
+                    "About to apply {} manifest(s) to kube context {context}. Are you sure?",
+                    manifests.len()
+                );
+                if !prompt::confirm(&question).await? {
+                    return Ok(Outcome {
+                        data: Some(serde_json::json!({ "cancelled": true })),
+                        ..Outcome::new(format!("{action}: cancelled"))
+                    });
+                }
+            }
+            let applied = kube::apply(kubeconfig, context, &stream, *force_conflicts).await?;
+            Ok(Outcome {
+                body: Some(listing),
+                data: Some(serde_json::json!({
+                    "manifests": objects,
+                    "changed": true,
+                    "applied": applied,
+                })),
+                ..Outcome::new(format!(
+                    "{action}: applied {} manifest(s) to context {context}",
+                    applied.len()
+                ))
+            })
+        }
+        KubeCommand::Chart {
+            command:
+                KubeChartCommand::Generate {
+                    out,
+                    name,
+                    mode,
+                    guilds,
+                    force,
+                },
+        } => {
+            let guilds = if guilds.is_empty() {
+                config.discord.guilds.iter().map(|g| g.id).collect()
+            } else {
+                guilds.clone()
+            };
+            let chart = chart::Chart {
+                name,
+                mode: *mode,
+                image: config
+                    .kube
+                    .deploy
+                    .image
+                    .as_deref()
+                    .unwrap_or(&config.kube.remote.image),
+                guilds: &guilds,
+                config: &pod_config(config)?,
+            };
+            let files = chart.files();
+            chart::write(out, &files, *force)?;
+            if guilds.is_empty() && *mode != chart::Mode::Bridge {
+                warnings.push(
+                    "no guild IDs in the config or --guild; set `guilds` in values.yaml"
+                        .to_string(),
+                );
+            }
+            Ok(Outcome {
+                body: Some(
+                    files
+                        .iter()
+                        .map(|(path, _)| format!("{}\n", out.join(path).display()))
+                        .collect(),
+                ),
+                data: Some(serde_json::json!({
+                    "out": out,
+                    "mode": mode,
+                    "guilds": guilds,
+                    "files": files.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+                })),
+                ..Outcome::new(format!(
+                    "{action}: wrote chart {name} to {} (install with `helm install {name} {}`)",
+                    out.display(),
+                    out.display()
+                ))
+            })
+        }
+    }
+}
+
+/// [`run`] for `ssh` subcommands.
+async fn run_ssh(cli: &Cli, config: &Config, command: &SshCommand) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    match command {
+        SshCommand::Exec {
+            hosts,
+            groups,
+            concurrency,
+            env,
+            script,
+            stdin,
+            prefix,
+            timeout,
+            cmd,
+        } => {
+            let hosts = ssh::resolve_hosts(&config.ssh, hosts, groups)?;
+            if hosts.is_empty() {
+                return Err(CliError::Usage(format!(
+                    "no hosts in group(s) {}",
+                    groups.join(", ")
+                )));
+            }
+            let [host] = hosts.as_slice() else {
+                if *stdin {
+                    return Err(CliError::Usage(
+                        "--stdin needs a single host: our stdin can only be read once".to_string(),
+                    ));
+                }
+                // Streamed lines are written with the bar hidden, so they do not garble it.
+                let bar = progress::bar(!cli.json && cli.progress(), hosts.len() as u64, action);
+                let bar = &bar;
+                // Lines of different hosts interleave, so they are always prefixed.
+                let outcomes: Vec<ssh::HostOutcome> = futures_util::stream::iter(&hosts)
+                    .map(|host| async move {
+                        let options = ssh::ExecOptions {
+                            output: if cli.json {
+                                ssh::Output::Capture
+                            } else {
+                                ssh::Output::Stream {
+                                    prefix: Some(format!("[{host}] ")),
+                                    bar: Some(bar.clone()),
+                                }
+                            },
+                            stdin: false,
+                            timeout: timeout.map(Duration::from_secs),
+                        };
+                        let result = match script {
+                            Some(script) => {
+                                ssh::exec_script(&config.ssh, host, env, script, &options).await
+                            }
+                            None => ssh::exec(&config.ssh, host, env, cmd, &options).await,
+                        };
+                        bar.inc(1);
+                        ssh::HostOutcome::new(host, result)
+                    })
+                    .buffered(concurrency.unwrap_or(config.ssh.concurrency).max(1))
+                    .collect()
+                    .await;
+                bar.finish_and_clear();
+                let failed = outcomes.iter().filter(|o| !o.ok).count();
+                return Ok(Outcome {
+                    body: Some(ssh::outcome_table(&outcomes)),
+                    data: Some(serde_json::json!({ "hosts": outcomes, "failed": failed })),
+                    items: Some("hosts"),
+                    exit: if failed > 0 {
+                        ExitCode::Failure
+                    } else {
+                        ExitCode::Ok
+                    },
+                    ..Outcome::new(format!(
+                        "{action}: {} of {} host(s) succeeded",
+                        hosts.len() - failed,
+                        hosts.len()
+                    ))
+                });
+            };
+            // `--json` reports the output; otherwise it is passed through as it arrives.
+            let output = if cli.json {
+                ssh::Output::Capture
+            } else {
+                ssh::Output::Stream {
+                    prefix: prefix.then(|| format!("[{host}] ")),
+                    bar: None,
+                }
+            };
+            let options = ssh::ExecOptions {
+                output,
+                stdin: *stdin,
+                timeout: timeout.map(Duration::from_secs),
+            };
+            let result = match script {
+                Some(script) => ssh::exec_script(&config.ssh, host, env, script, &options).await?,
+                None => ssh::exec(&config.ssh, host, env, cmd, &options).await?,
+            };
+            // The remote exit code becomes ours; codes above 255 cannot come back over ssh.
+            let exit = match u8::try_from(result.exit_code) {
+                Ok(0) => ExitCode::Ok,
+                Ok(code) => ExitCode::Remote(code),
+                Err(_) => ExitCode::Failure,
+            };
+            if !cli.json {
+                return Ok(Outcome {
+                    exit,
+                    ..Outcome::default()
+                });
+            }
+            Ok(Outcome {
+                exit,
+                data: Some(serde_json::to_value(&result)?),
+                ..Outcome::new(format!("{action}: {host} exited {}", result.exit_code))
+            })
+        }
+        SshCommand::Resolve { hosts, groups } => {
+            let resolved = ssh::resolve_hosts(&config.ssh, hosts, groups)?
+                .iter()
+                .map(|host| {
+                    // `[ssh]` fills in what the OpenSSH config leaves unset, as when connecting.
+                    let mut resolved = ssh::resolve_host(&config.ssh, host)?;
+                    resolved.user = resolved.user.or_else(|| config.ssh.user.clone());
+                    if resolved.identity_files.is_empty() {
+                        resolved
+                            .identity_files
+                            .extend(config.ssh.identity_file.as_deref().map(expand_tilde));
+                    }
+                    Ok::<_, CliError>(resolved)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let body = resolved
+                .iter()
+                .map(|host| format!("{}\n", host.describe()))
+                .collect();
+            Ok(Outcome {
+                body: Some(body),
+                data: Some(serde_json::json!({ "hosts": resolved })),
+                ..Outcome::new(format!("{action}: {} host(s)", resolved.len()))
+            })
+        }
+        command @ (SshCommand::Push {
+            host,
+            local,
+            remote,
+            recursive,
+            resume,
+        }
+        | SshCommand::Pull {
+            host,
+            local,
+            remote,
+            recursive,
+            resume,
+        }) => {
+            let direction = match command {
+                SshCommand::Push { .. } => ssh::Direction::Push,
+                _ => ssh::Direction::Pull,
+            };
+            if direction == ssh::Direction::Push {
+                let meta = std::fs::metadata(local)
+                    .map_err(|e| CliError::NotFound(format!("{}: {e}", local.display())))?;
+                if meta.is_dir() && !*recursive {
+                    return Err(CliError::Usage(format!(
+                        "{} is a directory; pass --recursive to copy it",
+                        local.display()
+                    )));
+                }
+            }
+            let transfer = ssh::Transfer {
+                host,
+                direction,
+                local,
+                remote,
+                recursive: *recursive,
+                resume: *resume,
+                progress: cli.progress(),
+            };
+            ssh::transfer(&config.ssh, &transfer).await?;
+            // What was copied, as it now stands on this side.
+            let copied = match direction {
+                ssh::Direction::Push => local.clone(),
+                ssh::Direction::Pull if local.is_dir() => {
+                    let name = remote.trim_end_matches('/').rsplit('/').next();
+                    local.join(name.unwrap_or_default())
+                }
+                ssh::Direction::Pull => local.clone(),
+            };
+            let size = ssh::local_size(&copied).ok();
+            let (from, to) = match direction {
+                ssh::Direction::Push => (local.display().to_string(), format!("{host}:{remote}")),
+                ssh::Direction::Pull => (format!("{host}:{remote}"), local.display().to_string()),
+            };
+            let mut message = format!("{action}: copied {from} to {to}");
+            if let Some((files, bytes)) = size {
+                message.push_str(&format!(" ({files} file(s), {})", stats::human_size(bytes)));
+            }
+            Ok(Outcome {
+                data: Some(serde_json::json!({
+                    "host": host,
+                    "direction": direction,
+                    "local": local,
+                    "remote": remote,
+                    "files": size.map(|(files, _)| files),
+                    "bytes": size.map(|(_, bytes)| bytes),
+                })),
+                ..Outcome::new(message)
+            })
+        }
+    }
+}
+
+/// [`run`] for `terminal` subcommands.
+async fn run_terminal(
+    cli: &Cli,
+    config: &Config,
+    warnings: &Warnings,
+    policy: RetryPolicy,
+    command: &TerminalCommand,
+) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    match command {
+        TerminalCommand::Opencode {
+            command:
+                TerminalOpenCodeCommand::Attach {
+                    tmux: session,
+                    agent,
+                    interpreter,
+                    dir,
+                    layout,
+                    detach,
+                },
+        } => {
+            let terminal = &config.terminal;
+            let name = session.as_deref().unwrap_or(&terminal.tmux_default_session);
+            tmux::validate_session_name(name).map_err(CliError::Usage)?;
+            let layout = terminal.layout(layout)?;
+            let agent = agent.as_deref().unwrap_or(&terminal.agent_command);
+            let interpreter = interpreter
+                .as_deref()
+                .or(terminal.interpreter_command.as_deref());
+            if !*detach && !tmux::inside_tmux() && !std::io::stdin().is_terminal() {
+                return Err(CliError::Usage(
+                    "stdin is not a terminal; pass --detach to only create the session".to_string(),
+                ));
+            }
+            let dir = session_dir(dir.as_deref(), &layout)?;
+            let created = !tmux::has_session(name).await?;
+            if created {
+                let plan = layout.plan(&dir, Programs { agent, interpreter });
+                warn_missing_programs(&plan, warnings);
+                tmux::create_session(name, &plan).await?;
+            }
+            if !*detach {
+                tmux::attach(name).await?;
+            }
+            let verb = if created { "created" } else { "reusing" };
+            Ok(Outcome {
+                message: format!("{action}: {verb} tmux session {name}"),
+                data: Some(serde_json::json!({ "session": name, "created": created })),
+                ..Outcome::default()
+            })
+        }
+        TerminalCommand::Opencode {
+            command:
+                TerminalOpenCodeCommand::Start {
+                    tmux: session,
+                    agent,
+                    interpreter,
+                    dir,
+                    layout,
+                },
+        } => {
+            let terminal = &config.terminal;
+            let name = session.as_deref().unwrap_or(&terminal.tmux_default_session);
+            tmux::validate_session_name(name).map_err(CliError::Usage)?;
+            let layout = terminal.layout(layout)?;
+            let agent = agent.as_deref().unwrap_or(&terminal.agent_command);
+            let interpreter = interpreter
+                .as_deref()
+                .or(terminal.interpreter_command.as_deref());
+            let dir = session_dir(dir.as_deref(), &layout)?;
+            if !tmux::program_exists(agent) {
+                return Err(CliError::NotFound(format!(
+                    "{agent}: not found on PATH; install it or pass --agent"
+                )));
+            }
+            if let Some(interpreter) = interpreter.filter(|i| !tmux::program_exists(i)) {
+                warnings.push(format!(
+                    "{interpreter}: not found on PATH; its pane will fall back to your shell"
+                ));
+            }
+            let path = sessions::default_path();
+            let mut state = State::load(&path)?;
+            if tmux::has_session(name).await? {
+                let hint = if state.get(name).is_some() {
+                    "stop it first with `terminal opencode stop`"
+                } else {
+                    "it was not started by guildsync; pick another name with --tmux"
+                };
+                return Err(CliError::Usage(format!(
+                    "tmux session {name} already exists; {hint}"
+                )));
+            }
+            let session = sessions::start(name, &dir, agent, interpreter, &layout).await?;
+            let pid = session.pid;
+            state.insert(session.clone());
+            state.save(&path)?;
+            Ok(Outcome {
+                data: Some(serde_json::to_value(&session)?),
+                ..Outcome::new(format!(
+                    "{action}: started {agent} in tmux session {name} (pid {pid}); attach with \
+                     `guildsync terminal opencode attach --tmux {name}`"
+                ))
+            })
+        }
+        TerminalCommand::Layout {
+            command:
+                TerminalLayoutCommand::Apply {
+                    name,
+                    tmux: session,
+                    dir,
+                    attach,
+                },
+        } => {
+            let layout = config.terminal.layout(name)?;
+            let session = session.as_deref().unwrap_or(name);
+            tmux::validate_session_name(session).map_err(CliError::Usage)?;
+            if *attach && !tmux::inside_tmux() && !std::io::stdin().is_terminal() {
+                return Err(CliError::Usage(
+                    "stdin is not a terminal; leave out --attach".to_string(),
+                ));
+            }
+            let dir = session_dir(dir.as_deref(), &layout)?;
+            if tmux::has_session(session).await? {
+                return Err(CliError::Usage(format!(
+                    "tmux session {session} already exists; pick another name with --tmux"
+                )));
+            }
+            let programs = Programs {
+                agent: &config.terminal.agent_command,
+                interpreter: config.terminal.interpreter_command.as_deref(),
+            };
+            let plan = layout.plan(&dir, programs);
+            warn_missing_programs(&plan, warnings);
+            let ids = tmux::create_session(session, &plan).await?;
+            if *attach {
+                tmux::attach(session).await?;
+            }
+            let windows: Vec<_> = plan
+                .iter()
+                .zip(&ids)
+                .map(|(window, panes)| serde_json::json!({ "name": window.name, "panes": panes }))
+                .collect();
+            let panes: usize = ids.iter().map(Vec::len).sum();
+            Ok(Outcome {
+                data: Some(serde_json::json!({
+                    "session": session,
+                    "layout": name,
+                    "dir": dir,
+                    "windows": windows,
+                })),
+                ..Outcome::new(format!(
+                    "{action}: created tmux session {session} from layout {name} ({} window(s), \
+                     {panes} pane(s))",
+                    windows.len()
+                ))
+            })
+        }
+        TerminalCommand::Layout {
+            command: TerminalLayoutCommand::List,
+        } => {
+            let mut layouts = config.terminal.layouts.clone();
+            let builtin = !layouts.contains_key(layout::OPENCODE);
+            if builtin {
+                layouts.insert(layout::OPENCODE.to_string(), layout::opencode());
+            }
+            let mut lines = Vec::new();
+            let mut listed = Vec::new();
+            for (name, layout) in &layouts {
+                let builtin = builtin && name == layout::OPENCODE;
+                let windows: Vec<&str> = layout.windows.iter().map(|w| w.name.as_str()).collect();
+                let panes: usize = layout.windows.iter().map(|w| w.panes.len()).sum();
+                lines.push(format!(
+                    "{name:<16} {panes} pane(s) in {}{}\n",
+                    windows.join(", "),
+                    if builtin { " (built in)" } else { "" }
+                ));
+                listed.push(serde_json::json!({
+                    "name": name,
+                    "builtin": builtin,
+                    "windows": windows,
+                    "panes": panes,
+                }));
+            }
+            Ok(Outcome {
+                body: Some(lines.concat()),
+                data: Some(serde_json::json!({ "layouts": listed })),
+                ..Outcome::new(format!("{action}: {} layout(s)", listed.len()))
+            })
+        }
+        TerminalCommand::Opencode {
+            command: TerminalOpenCodeCommand::Stop { tmux: session, all },
+        } => {
+            let path = sessions::default_path();
+            let mut state = State::load(&path)?;
+            let names: Vec<String> = if *all {
+                state.sessions.iter().map(|s| s.name.clone()).collect()
+            } else {
+                let name = session
+                    .clone()
+                    .unwrap_or_else(|| config.terminal.tmux_default_session.clone());
+                if state.get(&name).is_none() {
+                    return Err(CliError::NotFound(format!(
+                        "no session {name} started by guildsync; see `terminal opencode list`"
+                    )));
+                }
+                vec![name]
+            };
+            for name in &names {
+                if tmux::has_session(name).await? {
+                    tmux::kill_session(name).await?;
+                } else {
+                    warnings.push(format!("tmux session {name} was already gone"));
+                }
+                state.remove(name);
+            }
+            state.save(&path)?;
+            Ok(Outcome {
+                data: Some(serde_json::json!({ "stopped": names })),
+                ..Outcome::new(format!("{action}: stopped {} session(s)", names.len()))
+            })
+        }
+        TerminalCommand::Opencode {
+            command: TerminalOpenCodeCommand::List,
+        } => {
+            let state = State::load(&sessions::default_path())?;
+            let mut statuses = Vec::new();
+            for session in &state.sessions {
+                statuses.push(sessions::status(session).await?);
+            }
+            let running = statuses
+                .iter()
+                .filter(|s| s.health == sessions::Health::Running)
+                .count();
+            let body: String = statuses.iter().map(|s| s.line() + "\n").collect();
+            Ok(Outcome {
+                message: format!("{action}: {} session(s), {running} running", statuses.len()),
+                body: (!body.is_empty()).then_some(body),
+                data: Some(serde_json::json!({ "sessions": statuses })),
+                ..Outcome::default()
+            })
+        }
+        TerminalCommand::Opencode {
+            command: TerminalOpenCodeCommand::Status { tmux: session },
+        } => {
+            let name = session
+                .as_deref()
+                .unwrap_or(&config.terminal.tmux_default_session);
+            let state = State::load(&sessions::default_path())?;
+            let Some(session) = state.get(name) else {
+                return Err(CliError::NotFound(format!(
+                    "no session {name} started by guildsync; see `terminal opencode list`"
+                )));
+            };
+            let status = sessions::status(session).await?;
+            Ok(Outcome {
+                message: format!("{action}: {name} is {}", status.health),
+                body: Some(status.line() + "\n"),
+                exit: if status.health == sessions::Health::Running {
+                    ExitCode::Ok
+                } else {
+                    ExitCode::Failure
+                },
+                data: Some(serde_json::to_value(&status)?),
+                items: None,
+            })
+        }
+        TerminalCommand::Send {
+            token,
+            channel,
+            pane,
+            fifo,
+            template,
+            allow_users,
+            enter,
+            include_bots,
+            poll,
+            interval,
+        } => {
+            let allowed_users: Vec<String> = allow_users
+                .iter()
+                .chain(&config.terminal.bridge_allowed_users)
+                .map(u64::to_string)
+                .collect();
+            if *enter && allowed_users.is_empty() {
+                return Err(CliError::Usage(
+                    "--enter runs relayed messages as commands; limit who can send them with \
+                     --allow-user or [terminal] bridge_allowed_users"
+                        .to_string(),
+                ));
+            }
+            let template = match template {
+                Some(text) => relay::Template::parse(text)
+                    .map_err(|e| CliError::Usage(format!("--template: {e}")))?,
+                None => relay::Template::parse(&config.terminal.send_template)
+                    .map_err(|e| CliError::Config(format!("terminal.send_template: {e}")))?,
+            };
+            let (target, to) = match (pane, fifo) {
+                (Some(pane), _) => {
+                    if !tmux::pane_exists(pane).await? {
+                        return Err(CliError::NotFound(format!(
+                            "no tmux pane {pane}; list them with `tmux list-panes -a`"
+                        )));
+                    }
+                    let target = relay::Target::Pane {
+                        target: pane.clone(),
+                        enter: *enter,
+                    };
+                    (target, format!("pane {pane}"))
+                }
+                (None, Some(fifo)) => {
+                    if !fifo.exists() {
+                        return Err(CliError::NotFound(format!(
+                            "{} does not exist; create it with `mkfifo {}`",
+                            fifo.display(),
+                            fifo.display()
+                        )));
+                    }
+                    (
+                        relay::Target::fifo(fifo.clone()),
+                        fifo.display().to_string(),
+                    )
+                }
+                (None, None) => unreachable!("clap requires --pane or --fifo"),
+            };
+            let token = config.discord.resolve_token(token.as_deref())?;
+            let client = discord::Client::new(&config.discord.api_base, token, policy, action)?;
+            let channel_info = client.get(&format!("/channels/{channel}")).await?;
+            let mut relay = relay::Relay {
+                channel: channel.to_string(),
+                template,
+                target,
+                include_bots: *include_bots,
+                allowed_users,
+                self_id: None,
+                echoes: None,
+                runner: None,
+                stats: relay::Stats::default(),
+            };
+            tracing::info!("relaying channel {channel}; press Ctrl-C to stop");
+            let stop = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+            if *poll {
+                relay
+                    .poll(&client, Duration::from_secs((*interval).max(1)), stop)
+                    .await?;
+            } else {
+                let Some(guild) = channel_info["guild_id"]
+                    .as_str()
+                    .and_then(|id| id.parse().ok())
+                else {
+                    return Err(CliError::Usage(format!(
+                        "channel {channel} is not in a guild; relay it with --poll"
+                    )));
+                };
+                gateway::listen(&client, guild, &mut relay, stop).await?;
+            }
+            Ok(Outcome {
+                data: Some(serde_json::to_value(&relay.stats)?),
+                ..Outcome::new(format!(
+                    "{action}: relayed {} message(s) from channel {channel} to {to}",
+                    relay.stats.relayed
+                ))
+            })
+        }
+        TerminalCommand::Capture {
+            token,
+            pane,
+            channel,
+            follow,
+            interval,
+            lines,
+            plain,
+            max_messages,
+            post_interval,
+        } => {
+            if !tmux::pane_exists(pane).await? {
+                return Err(CliError::NotFound(format!(
+                    "no tmux pane {pane}; list them with `tmux list-panes -a`"
+                )));
+            }
+            let client = if cli.dry_run() {
+                None
+            } else {
+                let token = config.discord.resolve_token(token.as_deref())?;
+                let client = discord::Client::new(&config.discord.api_base, token, policy, action)?;
+                client.get(&format!("/channels/{channel}")).await?;
+                Some(client)
+            };
+            let mut capture = capture::Capture {
+                pane: pane.clone(),
+                channel: channel.to_string(),
+                plain: *plain,
+                max_messages: *max_messages as usize,
+                pause: Duration::from_millis(
+                    post_interval.unwrap_or(config.terminal.capture_interval_ms),
+                ),
+                echoes: None,
+                stats: capture::Stats::default(),
+            };
+            if *follow {
+                tracing::info!("following pane {pane}; press Ctrl-C to stop");
+                let stop = async {
+                    let _ = tokio::signal::ctrl_c().await;
+                };
+                capture
+                    .follow(
+                        client.as_ref(),
+                        Duration::from_secs((*interval).max(1)),
+                        stop,
+                    )
+                    .await?;
+            } else {
+                capture.once(client.as_ref(), *lines).await?;
+            }
+            let verb = if client.is_some() {
+                "posted"
+            } else {
+                "dry run; would post"
+            };
+            Ok(Outcome {
+                data: Some(serde_json::to_value(&capture.stats)?),
+                ..Outcome::new(format!(
+                    "{action}: {verb} {} message(s) ({} line(s)) from pane {pane} to channel {channel}",
+                    capture.stats.messages, capture.stats.lines
+                ))
+            })
+        }
+        TerminalCommand::Bridge {
+            token,
+            channel,
+            tmux: name,
+            allow_users,
+            template,
+            enter,
+            include_bots,
+            poll,
+            interval,
+            plain,
+            max_messages,
+            post_interval,
+        } => {
+            let allowed_users: Vec<String> = allow_users
+                .iter()
+                .chain(&config.terminal.bridge_allowed_users)
+                .map(u64::to_string)
+                .collect();
+            if *enter && allowed_users.is_empty() {
+                return Err(CliError::Usage(
+                    "--enter runs relayed messages as commands; limit who can send them with \
+                     --allow-user or [terminal] bridge_allowed_users"
+                        .to_string(),
+                ));
+            }
+            let template = match template {
+                Some(text) => relay::Template::parse(text)
+                    .map_err(|e| CliError::Usage(format!("--template: {e}")))?,
+                None => relay::Template::parse(&config.terminal.send_template)
+                    .map_err(|e| CliError::Config(format!("terminal.send_template: {e}")))?,
+            };
+            tmux::validate_session_name(name).map_err(CliError::Usage)?;
+            if !tmux::has_session(name).await? {
+                return Err(CliError::NotFound(format!(
+                    "no tmux session {name}; start one with `guildsync terminal opencode start`"
+                )));
+            }
+            let state = State::load(&sessions::default_path())?;
+            let pane = match state.get(name) {
+                Some(session) if tmux::pane_exists(&session.pane).await? => session.pane.clone(),
+                _ => format!("={name}:"),
+            };
+
+            config
+                .terminal
+                .runner
+                .validate()
+                .map_err(CliError::Config)?;
+            if config.terminal.runner.channels.contains(channel) && allowed_users.is_empty() {
+                return Err(CliError::Usage(format!(
+                    "[terminal.runner] runs the code blocks of channel {channel}'s messages; \
+                     limit who can send them with --allow-user or [terminal] bridge_allowed_users"
+                )));
+            }
+
+            let token = config.discord.resolve_token(token.as_deref())?;
+            let client = Arc::new(discord::Client::new(
+                &config.discord.api_base,
+                token,
+                policy,
+                action,
+            )?);
+            let channel_info = client.get(&format!("/channels/{channel}")).await?;
+            let me = client.get("/users/@me").await?;
+            let runner =
+                config
+                    .terminal
+                    .runner
+                    .channels
+                    .contains(channel)
+                    .then(|| runner::Runner {
+                        config: config.terminal.runner.clone(),
+                        client: client.clone(),
+                        allowed_users: allowed_users.clone(),
+                        max_messages: *max_messages as usize,
+                    });
+            let listen = match poll {
+                Some(secs) => bridge::Listen::Poll(Duration::from_secs((*secs).max(1))),
+                None => match channel_info["guild_id"]
+                    .as_str()
+                    .and_then(|id| id.parse().ok())
+                {
+                    Some(guild) => bridge::Listen::Gateway(guild),
+                    None => {
+                        return Err(CliError::Usage(format!(
+                            "channel {channel} is not in a guild; bridge it with --poll"
+                        )));
+                    }
+                },
+            };
+            let echoes = bridge::Echoes::default();
+            let mut relay = relay::Relay {
+                channel: channel.to_string(),
+                template,
+                target: relay::Target::Pane {
+                    target: pane.clone(),
+                    enter: *enter,
+                },
+                include_bots: *include_bots,
+                allowed_users,
+                self_id: me["id"].as_str().map(str::to_string),
+                echoes: Some(echoes.clone()),
+                runner,
+                stats: relay::Stats::default(),
+            };
+            let mut capture = capture::Capture {
+                pane,
+                channel: channel.to_string(),
+                plain: *plain,
+                max_messages: *max_messages as usize,
+                pause: Duration::from_millis(
+                    post_interval.unwrap_or(config.terminal.capture_interval_ms),
+                ),
+                echoes: Some(echoes),
+                stats: capture::Stats::default(),
+            };
+            tracing::info!(
+                "bridging channel {channel} and tmux session {name}; press Ctrl-C to stop"
+            );
+            let stop = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+            bridge::run(
+                &client,
+                &mut relay,
+                &mut capture,
+                listen,
+                Duration::from_secs((*interval).max(1)),
+                stop,
+            )
+            .await?;
+            Ok(Outcome {
+                data: Some(serde_json::json!({
+                    "relay": relay.stats,
+                    "capture": capture.stats,
+                })),
+                ..Outcome::new(format!(
+                    "{action}: relayed {} message(s) into {name}, posted {} message(s) ({} line(s)) to channel {channel}",
+                    relay.stats.relayed, capture.stats.messages, capture.stats.lines
+                ))
+            })
+        }
+    }
+}
+
+/// [`run`] for `format` subcommands.
+async fn run_format(
+    cli: &Cli,
+    config: &Config,
+    warnings: &Warnings,
+    command: &FormatCommand,
+) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    match command {
+        FormatCommand::Validate(args) => {
+            let validated = format::validate_format(args, &config.formats)?;
+            let pinned = if validated.expected_version.is_some() {
+                " (matches --expect-version)"
+            } else {
+                ""
+            };
+            Ok(Outcome {
+                message: format!(
+                    "{}: valid {} v{}{pinned}",
+                    args.r#in.display(),
+                    validated.format,
+                    validated.version
+                ),
+                data: Some(serde_json::to_value(&validated)?),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Convert(args) => {
+            let converted = format::convert(args, &config.formats)?;
+            let dropped = match (converted.dropped_messages, converted.dropped_fields) {
+                (0, 0) => String::new(),
+                (messages, fields) => {
+                    format!("; dropped {messages} message(s) and {fields} export-only field(s)")
+                }
+            };
+            Ok(Outcome {
+                data: Some(serde_json::to_value(&converted)?),
+                ..Outcome::new(format!(
+                    "converted {} ({}) to {} ({} v{}){dropped}",
+                    args.r#in.display(),
+                    converted.from,
+                    args.out.display(),
+                    converted.to,
+                    converted.version
+                ))
+            })
+        }
+        FormatCommand::Migrate {
+            r#in,
+            to_version,
+            out,
+            check,
+        } => {
+            let mut document = format::read_document(r#in)?;
+            // The file is checked against its own version, however old.
+            let lenient = FormatsConfig {
+                strict: false,
+                ..config.formats.clone()
+            };
+            let validated = format::validate_value(&document, None, &lenient)?;
+            let to = to_version.unwrap_or(u64::from(match validated.format {
+                GuildFormat::Dump => config.formats.dump_version,
+                GuildFormat::Upload => config.formats.upload_version,
+            }));
+            let migrated = migrate::migrate(&mut document, to)?;
+            format::validate_value(&document, Some(validated.format), &lenient)?;
+            let body = migrated.changes.iter().map(|c| format!("{c}\n")).collect();
+            let data = Some(serde_json::to_value(&migrated)?);
+            let (from, changes) = (migrated.from, migrated.changes.len());
+            if *check {
+                let needed = from != to;
+                return Ok(Outcome {
+                    message: if needed {
+                        format!(
+                            "{}: {} v{from} needs upgrading to v{to} ({changes} change(s))",
+                            r#in.display(),
+                            validated.format
+                        )
+                    } else {
+                        format!("{}: already at v{to}", r#in.display())
+                    },
+                    body: Some(body),
+                    data,
+                    exit: if needed {
+                        ExitCode::Failure
+                    } else {
+                        ExitCode::Ok
+                    },
+                    items: None,
+                });
+            }
+            let out = out.as_ref().expect("clap requires --out without --check");
+            format::write_document(out, &document)?;
+            Ok(Outcome {
+                message: format!(
+                    "migrated {} ({} v{from}) to {} (v{to}, {changes} change(s))",
+                    r#in.display(),
+                    validated.format,
+                    out.display()
+                ),
+                body: Some(body),
+                data,
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Stats { args, top } => {
+            let stats = stats::stats_file(args, &config.formats)?;
+            if stats.messages == 0 {
+                warnings.push(format!(
+                    "{} has no messages; export them with --since",
+                    args.r#in.display()
+                ));
+            }
+            Ok(Outcome {
+                message: format!("{action}: {}", args.r#in.display()),
+                body: Some(stats.table(*top)),
+                data: Some(serde_json::to_value(&stats)?),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Query { args, raw } => {
+            let mut stdout = std::io::stdout().lock();
+            if args.records {
+                let expression = query::compile(&args.expression)?;
+                let matched = query::search_records(&expression, &args.r#in, |result| {
+                    query::write_result(&mut stdout, &result, *raw, false)
+                })?;
+                if matched == 0 {
+                    warnings.push(format!("no record of {} matched", args.r#in.display()));
+                }
+            } else {
+                query::write_result(&mut stdout, &query::query(args)?, *raw, true)?;
+            }
+            Ok(Outcome::default())
+        }
+        FormatCommand::Render {
+            r#in,
+            channel,
+            out,
+            style,
+        } => {
+            let style = style.or_else(|| Style::for_path(out)).ok_or_else(|| {
+                CliError::Usage(format!(
+                    "cannot tell the transcript format from {}; pass --as html or --as markdown",
+                    out.display()
+                ))
+            })?;
+            let document = format::read_document(r#in)?;
+            format::validate_value(&document, Some(GuildFormat::Dump), &config.formats)?;
+            let (transcript, messages) = render::render(&document, &channel.to_string(), style)?;
+            if messages == 0 {
+                warnings.push(format!(
+                    "channel {channel} has no messages in {}; export them with --since",
+                    r#in.display()
+                ));
+            }
+            write_atomic(out, transcript.as_bytes())?;
+            Ok(Outcome {
+                message: format!(
+                    "{action}: wrote {messages} message(s) of channel {channel} to {}",
+                    out.display()
+                ),
+                data: Some(serde_json::json!({ "out": out, "messages": messages })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Verify { r#in, manifest } => {
+            let path = manifest
+                .clone()
+                .unwrap_or_else(|| manifest::default_path(r#in));
+            let manifest = manifest::read(&path)?;
+            let mismatches = manifest::verify(&path, &manifest)?;
+            let verified = manifest.files.len() - mismatches.len();
+            let body: String = mismatches.iter().map(|m| format!("{m}\n")).collect();
+            Ok(Outcome {
+                message: format!(
+                    "{action}: {verified} of {} file(s) match {}",
+                    manifest.files.len(),
+                    path.display()
+                ),
+                body: (!body.is_empty()).then_some(body),
+                data: Some(serde_json::json!({
+                    "manifest": path,
+                    "files": manifest.files.len(),
+                    "mismatches": mismatches,
+                })),
+                exit: if mismatches.is_empty() {
+                    ExitCode::Ok
+                } else {
+                    ExitCode::Failure
+                },
+                items: None,
+            })
+        }
+        FormatCommand::Sign { r#in, out } => {
+            let key = signing::resolve_key(&config.formats.signing_key_env)?;
+            let out = out.clone().unwrap_or_else(|| signing::default_path(r#in));
+            let signature = signing::sign(&std::fs::read(r#in)?, &key, &out)?;
+            Ok(Outcome {
+                message: format!(
+                    "{action}: signed {} with key {} to {}",
+                    r#in.display(),
+                    signature.public_key,
+                    out.display()
+                ),
+                data: Some(serde_json::json!({
+                    "signature": out,
+                    "public_key": signature.public_key,
+                })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::VerifySignature {
+            r#in,
+            signature,
+            public_keys,
+        } => {
+            let trusted = public_keys
+                .iter()
+                .map(|key| {
+                    signing::public_key(key)
+                        .map_err(|e| CliError::Usage(format!("--public-key {key}: {e}")))
+                })
+                .chain(config.formats.trusted_keys.iter().map(|key| {
+                    signing::public_key(key)
+                        .map_err(|e| CliError::Config(format!("formats.trusted_keys {key}: {e}")))
+                }))
+                .collect::<Result<Vec<_>, _>>()?;
+            if trusted.is_empty() {
+                return Err(CliError::Usage(
+                    "no trusted keys; pass --public-key or set [formats] trusted_keys".to_string(),
+                ));
+            }
+            let path = signature
+                .clone()
+                .unwrap_or_else(|| signing::default_path(r#in));
+            let file = signing::read(&path)?;
+            let verdict = signing::verify(&std::fs::read(r#in)?, &file, &trusted)?;
+            Ok(Outcome {
+                message: format!("{action}: {}: {verdict}", r#in.display()),
+                data: Some(serde_json::json!({
+                    "verdict": verdict,
+                    "public_key": file.public_key,
+                    "signed_at": file.signed_at,
+                })),
+                exit: if verdict == signing::Verdict::Valid {
+                    ExitCode::Ok
+                } else {
+                    ExitCode::Failure
+                },
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::ExportSqlite { r#in, out, members } => {
+            let document = format::read_document(r#in)?;
+            format::validate_value(&document, Some(GuildFormat::Dump), &config.formats)?;
+            let members = match members {
+                Some(path) => read_members(path)?,
+                None => Vec::new(),
+            };
+            let loaded = sqlite::export(&document, &members, out)?;
+            Ok(Outcome {
+                message: format!(
+                    "{action}: loaded {} message(s) in {} channel(s) and thread(s) into {}",
+                    loaded.messages,
+                    loaded.channels,
+                    out.display()
+                ),
+                data: Some(serde_json::to_value(&loaded)?),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::ExportCsv {
+            r#in,
+            out,
+            entity,
+            columns,
+            members,
+            delimiter,
+            quote,
+            bom,
+        } => {
+            let delimiter = u8::try_from(*delimiter)
+                    .ok()
+                    .filter(|d| d.is_ascii() && !matches!(d, b'"' | b'\n' | b'\r'))
+                    .ok_or_else(|| {
+                        CliError::Usage(format!(
+                            "--delimiter {delimiter:?}: expected one ASCII character other than a quote or line break"
+                        ))
+                    })?;
+            let options = csv_export::Options {
+                columns: entity
+                    .select(columns)
+                    .map_err(|e| CliError::Usage(format!("--columns: {e}")))?,
+                delimiter,
+                quote: *quote,
+                bom: *bom,
+            };
+            let members = match members {
+                Some(path) => read_members(path)?,
+                None if *entity == Entity::Members => {
+                    return Err(CliError::Usage(
+                        "--entity members needs --members <PATH> (from `discord members`)"
+                            .to_string(),
+                    ));
+                }
+                None => Vec::new(),
+            };
+            let document = format::read_document(r#in)?;
+            format::validate_value(&document, Some(GuildFormat::Dump), &config.formats)?;
+            let rows = csv_export::export(&document, &members, *entity, &options, out)?;
+            Ok(Outcome {
+                message: format!("{action}: wrote {rows} row(s) to {}", out.display()),
+                data: Some(serde_json::json!({
+                    "out": out,
+                    "rows": rows,
+                    "columns": options.columns,
+                })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Lint { r#in, deny, list } => {
+            for name in lint::unknown_rules(&config.formats.lint) {
+                warnings.push(format!("formats.lint: unknown rule `{name}`"));
+            }
+            if *list {
+                let rules: Vec<serde_json::Value> = lint::RULES
+                    .iter()
+                    .map(|rule| {
+                        let level = config.formats.lint.get(rule.name).unwrap_or(&rule.level);
+                        serde_json::json!({
+                            "rule": rule.name,
+                            "level": level,
+                            "summary": rule.summary,
+                        })
+                    })
+                    .collect();
+                let body = rules
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "{:<32} {:<8} {}\n",
+                            r["rule"].as_str().unwrap_or_default(),
+                            r["level"].as_str().unwrap_or_default(),
+                            r["summary"].as_str().unwrap_or_default()
+                        )
+                    })
+                    .collect();
+                return Ok(Outcome {
+                    body: Some(body),
+                    data: Some(serde_json::json!({ "rules": rules })),
+                    ..Outcome::new(format!("{} lint rule(s)", lint::RULES.len()))
+                });
+            }
+            let path = r#in.as_ref().expect("clap requires --in without --list");
+            let document = format::read_document(path)?;
+            format::validate_value(&document, None, &config.formats)?;
+            let findings = lint::lint(&document, &config.formats.lint);
+            let count = |level| findings.iter().filter(|f| f.level == level).count();
+            let (errors, warned) = (count(lint::Level::Error), count(lint::Level::Warning));
+            let failed = errors > 0 || (deny.contains(&Deny::Warnings) && warned > 0);
+            Ok(Outcome {
+                message: format!("{}: {errors} error(s), {warned} warning(s)", path.display()),
+                body: Some(findings.iter().map(|f| format!("{f}\n")).collect()),
+                data: Some(serde_json::json!({
+                    "errors": errors,
+                    "warnings": warned,
+                    "findings": findings,
+                })),
+                exit: if failed {
+                    ExitCode::Failure
+                } else {
+                    ExitCode::Ok
+                },
+                items: None,
+            })
+        }
+        FormatCommand::Diff(args) => {
+            let report = diff::compare_files(args, &config.formats)?;
+            let outcome = diff_outcome(
+                &report,
+                &args.old.display().to_string(),
+                &args.new.display().to_string(),
+            );
+            Ok(Outcome {
+                exit: if report.change_count() > 0 {
+                    ExitCode::Failure
+                } else {
+                    ExitCode::Ok
+                },
+                ..outcome
+            })
+        }
+        FormatCommand::Split {
+            r#in,
+            out_dir,
+            by,
+            max_size,
+        } => {
+            let manifest = chunks::write_split(r#in, out_dir, *by, *max_size, &config.formats)?;
+            let messages: usize = manifest.chunks.iter().map(|c| c.messages).sum();
+            Ok(Outcome {
+                message: format!(
+                    "split {} into {} chunk(s) of {messages} message(s) in {}",
+                    r#in.display(),
+                    manifest.chunks.len(),
+                    out_dir.display()
+                ),
+                data: Some(serde_json::to_value(&manifest)?),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Join { r#in, out } => {
+            let (manifest, joined) = chunks::read_split(r#in)?;
+            format::validate_value(&joined, Some(GuildFormat::Dump), &config.formats)?;
+            format::write_document(out, &joined)?;
+            Ok(Outcome::new(format!(
+                "joined {} chunk(s) from {} into {}",
+                manifest.chunks.len(),
+                r#in.display(),
+                out.display()
+            )))
+        }
+        FormatCommand::Redact { r#in, out, keep } => {
+            let mut value = format::read_document(r#in)?;
+            format::validate_value(&value, None, &config.formats)?;
+            let count = redact::redact(&mut value, keep);
+            format::validate_value(&value, None, &config.formats)?;
+            format::write_document(out, &value)?;
+            Ok(Outcome {
+                message: format!("{action}: redacted {count} fields"),
+                data: Some(serde_json::json!({ "redacted": count, "out": out })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Anonymize {
+            r#in,
+            out,
+            mapping: mapping_path,
+        } => {
+            let rules = anonymize::Rules::compile(&config.formats.anonymize)?;
+            let mut mapping = match mapping_path {
+                Some(path) if path.exists() => anonymize::read_mapping(path)?,
+                _ => anonymize::Mapping::default(),
+            };
+            let mut value = format::read_document(r#in)?;
+            format::validate_value(&value, None, &config.formats)?;
+            let stats = anonymize::anonymize(&mut value, &rules, &mut mapping);
+            format::validate_value(&value, None, &config.formats)?;
+            format::write_document(out, &value)?;
+            if let Some(path) = mapping_path {
+                anonymize::write_mapping(path, &mapping)?;
+            }
+            let matches: usize = stats.matches.values().sum();
+            Ok(Outcome {
+                message: format!(
+                    "{action}: wrote {} ({} user(s), {matches} content match(es), {} attachment(s) removed)",
+                    out.display(),
+                    stats.users,
+                    stats.attachments
+                ),
+                data: Some(serde_json::json!({
+                    "out": out,
+                    "mapping": mapping_path,
+                    "stats": stats,
+                })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Merge {
+            base,
+            delta,
+            r#in,
+            out,
+            prefer,
+        } => {
+            let (inputs, prefer) = match (base, delta) {
+                (Some(base), Some(delta)) => (
+                    vec![base.clone(), delta.clone()],
+                    prefer.unwrap_or(Prefer::Delta),
+                ),
+                _ if r#in.len() >= 2 => (r#in.clone(), prefer.unwrap_or(Prefer::Newer)),
+                _ => {
+                    return Err(CliError::Usage(
+                        "format merge needs --base and --delta, or --in at least twice".to_string(),
+                    ));
+                }
+            };
+            let mut documents = Vec::new();
+            for path in &inputs {
+                let document = format::read_document(path)?;
+                format::validate_value(&document, None, &config.formats)?;
+                documents.push(document);
+            }
+            if base.is_some() {
+                if documents[0]["partial"] == true {
+                    warnings.push(format!("{} is itself partial", inputs[0].display()));
+                }
+                if documents[1]["partial"] != true {
+                    warnings.push(format!(
+                        "{} is not marked \"partial\": true",
+                        inputs[1].display()
+                    ));
+                }
+            }
+            let mut documents = documents.into_iter();
+            let mut merged = documents.next().expect("at least two inputs");
+            let (mut added, mut conflicts) = (0, 0);
+            let (mut body, mut steps) = (String::new(), Vec::new());
+            for (path, document) in inputs[1..].iter().zip(documents) {
+                let stats;
+                (merged, stats) = format::merge(&merged, &document, prefer)?;
+                added += stats.added;
+                conflicts += stats.conflicts;
+                for conflict in &stats.resolved {
+                    let kept = match conflict.kept {
+                        Prefer::Base if base.is_some() => inputs[0].display().to_string(),
+                        Prefer::Base => "earlier value".to_string(),
+                        _ => path.display().to_string(),
+                    };
+                    body.push_str(&format!("~ {} (kept {kept})\n", conflict.pointer));
+                }
+                steps.push(serde_json::json!({ "in": path, "stats": stats }));
+            }
+            format::validate_value(&merged, None, &config.formats)?;
+            format::write_document(out, &merged)?;
+            Ok(Outcome {
+                message: format!(
+                    "{action}: wrote {} ({added} added, {conflicts} conflicts)",
+                    out.display(),
+                ),
+                body: Some(body),
+                data: Some(serde_json::json!({
+                    "out": out,
+                    "stats": { "added": added, "conflicts": conflicts },
+                    "merged": steps,
+                })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Schema { format, version } => {
+            let version = version.unwrap_or(u64::from(match format {
+                GuildFormat::Dump => config.formats.dump_version,
+                GuildFormat::Upload => config.formats.upload_version,
+            }));
+            let schema = schema::schema(*format, version).ok_or_else(|| {
+                CliError::NotFound(format!(
+                    "no schema for {format} version {version} (known versions: {})",
+                    schema::versions()
+                ))
+            })?;
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            Ok(Outcome::default())
+        }
+        FormatCommand::Canonicalize { r#in, out } => {
+            let before = std::fs::metadata(r#in)?.len();
+            let mut value = format::read_document(r#in)?;
+            format::validate_value(&value, None, &config.formats)?;
+            format::canonicalize(&mut value);
+            format::write_document(out, &value)?;
+            let after = std::fs::metadata(out)?.len();
+            Ok(Outcome {
+                message: format!(
+                    "{action}: wrote {} ({before} -> {after} bytes)",
+                    out.display()
+                ),
+                data: Some(serde_json::json!({
+                    "out": out,
+                    "bytes_before": before,
+                    "bytes_after": after,
+                })),
+                ..Outcome::default()
+            })
+        }
+        FormatCommand::Normalize { r#in, out } => {
+            let mut value = format::read_document(r#in)?;
+            format::validate_value(&value, None, &config.formats)?;
+            let timestamps = format::normalize(&mut value);
+            format::validate_value(&value, None, &config.formats)?;
+            format::write_document(out, &value)?;
+            Ok(Outcome {
+                message: format!(
+                    "{action}: wrote {} ({timestamps} timestamp(s) normalized)",
+                    out.display()
+                ),
+                data: Some(serde_json::json!({ "out": out, "timestamps": timestamps })),
+                ..Outcome::default()
+            })
+        }
+    }
+}
+
+/// The config file a pod runs with: the effective config without an inline token, which
+/// reaches the pod through a Secret instead.
+fn pod_config(config: &Config) -> Result<String, CliError> {
+    let mut config = config.clone();
+    config.discord.token = None;
+    toml::to_string_pretty(&config).map_err(|e| CliError::Config(e.to_string()))
+}
+
+/// Warn about panes of `plan` whose program is not on `PATH`.
+fn warn_missing_programs(plan: &[layout::WindowPlan], warnings: &Warnings) {
+    let programs = plan
+        .iter()
+        .flat_map(|w| &w.panes)
+        .filter_map(|p| p.program.as_deref());
+    for program in programs {
+        if !tmux::program_exists(program) {
+            warnings.push(format!(
+                "{program}: not found on PATH; its pane will fall back to your shell"
+            ));
+        }
+    }
+}
+
+/// `--dir` of a new tmux session, else the layout's `dir`, else the current directory.
+fn session_dir(dir: Option<&Path>, layout: &layout::Layout) -> Result<PathBuf, CliError> {
+    let (dir, what) = match (dir, &layout.dir) {
+        (Some(dir), _) => (std::env::current_dir()?.join(expand_tilde(dir)), "--dir"),
+        (None, Some(dir)) => (
+            std::env::current_dir()?.join(expand_tilde(dir)),
+            "layout dir",
+        ),
+        (None, None) => (std::env::current_dir()?, "current directory"),
+    };
+    if !dir.is_dir() {
+        return Err(CliError::Usage(format!(
+            "{what} {}: not a directory",
+            dir.display()
+        )));
+    }
+    Ok(dir)
+}
+
+/// Read command lines until `exit` or the end of input, running each like a separate
+/// invocation with the global flags and config of `cli` and the shell's context.
+async fn shell(cli: &Cli, config: &Config) -> Result<(), CliError> {
+    let root = Cli::command();
+    // Listing candidates shows the names of completed IDs.
+    let settings = rustyline::Config::builder()
+        .completion_type(rustyline::CompletionType::List)
+        .build();
+    let mut editor =
+        rustyline::Editor::<shell::Completion, rustyline::history::FileHistory>::with_config(
+            settings,
+        )
+        .map_err(readline_error)?;
+    editor.set_helper(Some(shell::Completion::new(root.clone())));
+    let history = shell::history_path();
+    // A first run has no history yet.
+    let _ = editor.load_history(&history);
+    let mut context = shell::Context::default();
+    // Resolved on the first command that needs it, and again after `auth`.
+    let mut token: Option<String> = None;
+    loop {
+        let prompt = context.prompt();
+        let line = match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(readline_error(err)),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+        let words = match shell::split(&line) {
+            Ok(words) => words,
+            Err(err) => {
+                eprintln!("{err}");
+                continue;
+            }
+        };
+        if let Some(builtin) = shell::Builtin::parse(&words) {
+            match builtin {
+                Err(err) => eprintln!("{err}"),
+                Ok(Builtin::Exit) => break,
+                Ok(Builtin::Help) => {
+                    for (usage, about) in shell::BUILTINS {
+                        println!("{usage:<13} {about}");
+                    }
+                    println!();
+                    for command in root.get_subcommands().filter(|c| !c.is_hide_set()) {
+                        let about = command.get_about().map(|a| a.to_string());
+                        println!("{:<13} {}", command.get_name(), about.unwrap_or_default());
+                    }
+                }
+                Ok(Builtin::Context) => {
+                    let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+                    println!(
+                        "dump:  {}",
+                        show(context.dump.as_ref().map(|p| p.display().to_string()))
+                    );
+                    println!("guild: {}", show(context.guild.clone()));
+                    println!(
+                        "token: {}",
+                        if token.is_some() {
+                            "resolved"
+                        } else {
+                            "not resolved yet"
+                        }
+                    );
+                }
+                Ok(Builtin::Open(path)) => {
+                    let document = format::read_document(&path).and_then(|document| {
+                        format::validate_value(
+                            &document,
+                            Some(GuildFormat::Dump),
+                            &config.formats,
+                        )?;
+                        Ok(document)
+                    });
+                    match document {
+                        Ok(document) => {
+                            let ids = shell::ids(&document);
+                            println!("opened {} ({} IDs to complete)", path.display(), ids.len());
+                            if let Some(helper) = editor.helper_mut() {
+                                helper.ids = ids;
+                            }
+                            context.dump = Some(path);
+                        }
+                        Err(err) => eprintln!("{err}"),
+                    }
+                }
+                Ok(Builtin::Close) => {
+                    context.dump = None;
+                    if let Some(helper) = editor.helper_mut() {
+                        helper.ids.clear();
+                    }
+                }
+                Ok(Builtin::Use(guild)) => context.guild = guild,
+            }
+            continue;
+        }
+
+        let words = context.apply(&root, words);
+        let mut line_cli =
+            match Cli::try_parse_from(std::iter::once("guildsync".to_string()).chain(words)) {
+                Ok(line_cli) => line_cli,
+                Err(err) => {
+                    let _ = err.print();
+                    continue;
+                }
+            };
+        if matches!(line_cli.command, Command::Shell) {
+            eprintln!("already in the shell");
+            continue;
+        }
+        line_cli.inherit(cli);
+        if let Some(slot) = line_cli.command.token_mut()
+            && slot.is_none()
+        {
+            if token.is_none() {
+                token = config.discord.resolve_token(None).ok();
+            }
+            slot.clone_from(&token);
+        }
+        if matches!(line_cli.command, Command::Auth { .. }) {
+            token = None;
+        }
+        let config = merge_flags(&line_cli, config.clone());
+        let warnings = Warnings::default();
+        let result = Box::pin(execute(&line_cli, &config, &warnings)).await;
+        report(&line_cli, &result, warnings);
+    }
+    if let Some(dir) = history.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(err) = editor.save_history(&history) {
+        tracing::debug!("shell history {}: {err}", history.display());
+    }
+    Ok(())
+}
+
+fn readline_error(err: ReadlineError) -> CliError {
+    match err {
+        ReadlineError::Io(err) => CliError::Io(err),
+        err => CliError::Io(std::io::Error::other(format!("line editor: {err}"))),
+    }
+}
+
+/// Log to stderr and, with `--log-file`, append timestamped lines to that file as well.
+fn init_logging(cli: &Cli) -> Result<(), CliError> {
+    use tracing_subscriber::prelude::*;
+
+    let file = cli
+        .log_file
+        .as_deref()
+        .map(|path| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| {
+                    std::io::Error::new(e.kind(), format!("--log-file {}: {e}", path.display()))
+                })
+        })
+        .transpose()?;
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::filter::LevelFilter::from_level(
+            tracing::Level::from(cli.log),
+        ))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal())
+                .with_target(false)
+                .without_time(),
+        )
+        .with(file.map(|file| {
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::sync::Mutex::new(file))
+                .with_ansi(false)
+                .with_target(false)
+        }))
+        .init();
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        // `--help` / `--version` also arrive here, on stdout, and are not failures.
+        let code = if err.use_stderr() {
+            ExitCode::Usage
+        } else {
+            ExitCode::Ok
+        };
+        let _ = err.print();
+        std::process::exit(code.code());
+    });
+
+    let config = init_logging(&cli).and_then(|()| match Config::load(cli.config.as_deref()) {
+        Ok(config) => Ok(config),
+        // `doctor` reports config problems as a check instead of failing up front.
+        Err(_) if matches!(cli.command, Command::Doctor) => Ok(Config::default()),
+        Err(err) => Err(err),
+    });
+    let warnings = Warnings::default();
+    let result = match config {
+        Ok(config) => {
+            let config = merge_flags(&cli, config);
+            if let Some(identity) = &config.formats.identity_file {
+                encryption::use_identity_file(expand_tilde(identity));
+            }
+            execute(&cli, &config, &warnings).await
+        }
+        Err(err) => Err(err),
+    };
+    let exit = report(&cli, &result, warnings);
+    if exit != ExitCode::Ok {
+        std::process::exit(exit.code());
+    }
+}
+
+/// Run the command of `cli` under its deadline, cancelled by Ctrl-C unless it stops on Ctrl-C
+/// itself, between the hooks. The post-hook runs outside the deadline and Ctrl-C, so it also
+/// reports a timed-out or cancelled command.
+async fn execute(cli: &Cli, config: &Config, warnings: &Warnings) -> Result<Outcome, CliError> {
+    let action = cli.command.action();
+    pre_hook(config, action, warnings).await?;
+    // Dropping the in-flight future on Ctrl-C runs its destructors, which
+    // discard any uncommitted temp files.
+    let deadline = match cli.command.local_timeout() {
+        Some(_) => None,
+        None => cli.timeout,
+    };
+    let bounded = async {
+        match deadline {
+            Some(secs) => {
+                tokio::time::timeout(Duration::from_secs(secs), run(cli, config, warnings))
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(CliError::Timeout(format!(
+                            "{action} exceeded --timeout {secs}s"
+                        )))
+                    })
+            }
+            None => run(cli, config, warnings).await,
+        }
+    };
+    let interrupted = async {
+        if cli.command.stops_on_ctrl_c() {
+            std::future::pending().await
+        } else {
+            tokio::signal::ctrl_c().await
+        }
+    };
+    let result = tokio::select! {
+        result = bounded => result,
+        _ = interrupted => Err(CliError::Cancelled),
+    };
+    post_hook(config, action, &result, warnings).await;
+    result
+}
+
+/// Print `result` and `warnings` as text or `--json`, and return the exit code.
+fn report(cli: &Cli, result: &Result<Outcome, CliError>, warnings: Warnings) -> ExitCode {
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(err) => &Outcome {
+            data: err.data(),
+            ..Outcome::new(err.to_string())
+        },
+    };
+
+    let warnings = warnings.into_vec();
+    if !cli.json {
+        for warning in &warnings {
+            eprintln!("warning: {warning}");
+        }
+    }
+
+    if result.is_ok() && outcome.message.is_empty() {
+        // The action already wrote its own output.
+    } else if cli.json {
+        for line in json_lines(cli, result.is_ok(), outcome, &warnings) {
+            println!("{line}");
+        }
+    } else if result.is_ok() {
+        println!("{}", outcome.message);
+        if let Some(body) = &outcome.body {
+            print!("{body}");
+        }
+    } else {
+        eprintln!("{}", outcome.message);
+    }
+
+    match result {
+        Ok(outcome) => outcome.exit,
+        Err(err) => err.exit_code(),
+    }
+}
+
+/// The `--json` output of `outcome`: the envelope, preceded in compact style by one line
+/// per element of its `items` array, which the envelope then leaves out.
+fn json_lines(cli: &Cli, ok: bool, outcome: &Outcome, warnings: &[String]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut data = outcome.data.clone();
+    if let (JsonStyle::Compact, Some(key), Some(serde_json::Value::Object(fields))) =
+        (cli.json_style, outcome.items, data.as_mut())
+        && let Some(serde_json::Value::Array(items)) = fields.remove(key)
+    {
+        lines.extend(items.iter().map(|item| item.to_string()));
+    }
+    let out = JsonOut {
+        ok: ok && outcome.exit == ExitCode::Ok,
+        action: cli.command.action(),
+        message: &outcome.message,
+        dry_run: cli.dry_run(),
+        warnings,
+        data: data.as_ref(),
+    };
+    lines.push(
+        cli.json_style
+            .render(&out)
+            .unwrap_or_else(|_| "{\"ok\":false}".to_string()),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Held by tests that set or depend on process environment variables.
+    static ENV: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Environment variables set by a test, restored when dropped.
+    #[derive(Default)]
+    struct EnvVars(Vec<(&'static str, Option<std::ffi::OsString>)>);
+
+    impl EnvVars {
+        fn set(&mut self, key: &'static str, value: &str) {
+            if !self.0.iter().any(|(k, _)| *k == key) {
+                self.0.push((key, std::env::var_os(key)));
+            }
+            // SAFETY: tests touching the environment hold `ENV`.
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+
+    impl EnvVars {
+        fn remove(&mut self, key: &'static str) {
+            if !self.0.iter().any(|(k, _)| *k == key) {
+                self.0.push((key, std::env::var_os(key)));
+            }
+            // SAFETY: as in `set`.
+            unsafe { std::env::remove_var(key) };
+        }
+    }
+
+    impl Drop for EnvVars {
+        fn drop(&mut self) {
+            for (key, value) in self.0.drain(..) {
+                // SAFETY: as in `set`.
+                unsafe {
+                    match value {
+                        Some(value) => std::env::set_var(key, value),
+                        None => std::env::remove_var(key),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parse `args` with no other test changing the environment meanwhile.
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let _env = ENV
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        Cli::try_parse_from(std::iter::once("guildsync").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn env_vars_apply_when_flags_are_omitted() {
+        let _env = ENV
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut vars = EnvVars::default();
+        vars.set("GUILDSYNC_CONFIG", "/etc/guildsync.yaml");
+        vars.set("GUILDSYNC_JSON", "1");
+        vars.set("GUILDSYNC_LOG", "debug");
+        vars.set("GUILDSYNC_DRY_RUN", "true");
+        vars.set("GUILDSYNC_MAX_RETRIES", "9");
+        vars.set("DISCORD_TOKEN", "env-token");
+        vars.set("GUILDSYNC_KUBE_CONTEXT", "staging");
+        vars.set("GUILDSYNC_KUBECONFIG", "/tmp/a.yaml:/tmp/b.yaml");
+        let cli = Cli::try_parse_from([
+            "guildsync",
+            "discord",
+            "export",
+            "--guild",
+            "1",
+            "--out",
+            "x",
+        ])
+        .unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("/etc/guildsync.yaml")));
+        assert!(cli.json);
+        assert!(matches!(cli.log, LogLevel::Debug));
+        assert!(cli.dry_run);
+        assert_eq!(cli.max_retries, Some(9));
+        assert!(matches!(
+            cli.command,
+            Command::Discord { token: Some(ref t), .. } if t == "env-token"
+        ));
+
+        let cli = Cli::try_parse_from(["guildsync", "kube", "remote", "deploy"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Kube {
+                ref kubeconfig,
+                command: KubeCommand::Remote {
+                    command: KubeRemoteCommand::Deploy { ref context, .. },
+                },
+            } if kubeconfig == &[PathBuf::from("/tmp/a.yaml"), PathBuf::from("/tmp/b.yaml")]
+                && context == "staging"
+        ));
+
+        // Explicit flags beat the environment.
+        let cli = Cli::try_parse_from([
+            "guildsync",
+            "--log",
+            "warn",
+            "--config",
+            "cli.toml",
+            "config",
+            "show",
+        ])
+        .unwrap();
+        assert!(matches!(cli.log, LogLevel::Warn));
+        assert_eq!(cli.config, Some(PathBuf::from("cli.toml")));
+
+        // Falsey literals disable boolean flags; garbage is rejected.
+        vars.set("GUILDSYNC_JSON", "off");
+        assert!(
+            !Cli::try_parse_from(["guildsync", "config", "show"])
+                .unwrap()
+                .json
+        );
+        vars.set("GUILDSYNC_JSON", "maybe");
+        assert!(Cli::try_parse_from(["guildsync", "config", "show"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn hooks_run_under_timeout() {
+        let cli = parse(&["--timeout", "30", "config", "show"]).unwrap();
+        let mut config = Config::default();
+        config.hooks.pre_hook = Some("exit 3".to_string());
+        let result = execute(&cli, &config, &Warnings::default()).await;
+        assert!(matches!(result, Err(CliError::Hook(_))));
+    }
+
+    #[tokio::test]
+    async fn post_hook_reports_a_timed_out_command() {
+        // A webhook that accepts the request and never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let webhook = format!("http://{}/api/webhooks/1/t", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let cli = parse(&[
+            "--timeout",
+            "1",
+            "notify",
+            "--webhook",
+            &webhook,
+            "--message",
+            "hi",
+        ])
+        .unwrap();
+        let status =
+            std::env::temp_dir().join(format!("guildsync-post-hook-{}", std::process::id()));
+        let mut config = Config::default();
+        config.hooks.post_hook = Some(format!("echo {{status}} > '{}'", status.display()));
+        let result = execute(&cli, &config, &Warnings::default()).await;
+        assert!(matches!(result, Err(CliError::Timeout(_))));
+        assert_eq!(std::fs::read_to_string(&status).unwrap(), "error\n");
+        let _ = std::fs::remove_file(&status);
+    }
+
+    #[test]
+    fn multi_host_exec_draws_a_bar_on_a_tty_without_json() {
+        let args = ["ssh", "exec", "--host", "a", "--host", "b", "--", "true"];
+        let cli = parse(&args).unwrap();
+        assert!(cli.progress_on(true));
+        assert!(!cli.progress_on(false));
+        let cli = parse(&[&["--json"][..], &args].concat()).unwrap();
+        assert!(!cli.progress_on(true));
+        let cli = parse(&[&["--no-progress"][..], &args].concat()).unwrap();
+        assert!(!cli.progress_on(true));
+    }
+
+    #[test]
+    fn kube_local_up_still_accepts_the_deprecated_wait_flag() {
+        let cli = parse(&["kube", "local", "up", "--wait"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Kube {
+                command: KubeCommand::Local {
+                    command: KubeLocalCommand::Up { wait: true, .. },
+                },
+                ..
+            }
+        ));
+        assert!(parse(&["kube", "local", "up", "--wait", "--no-wait"]).is_err());
+    }
+
+    #[test]
+    fn compact_json_writes_one_line_per_host_then_the_summary() {
+        let outcome = Outcome {
+            data: Some(serde_json::json!({
+                "hosts": [{ "host": "a", "ok": true }, { "host": "b", "ok": false }],
+                "failed": 1,
+            })),
+            items: Some("hosts"),
+            exit: ExitCode::Failure,
+            ..Outcome::new("ssh.exec: 1 of 2 host(s) succeeded")
+        };
+        let args = ["ssh", "exec", "--host", "a", "--host", "b", "--", "true"];
+        let cli = parse(&[&["--json", "--json-style", "compact"][..], &args].concat()).unwrap();
+        let lines = json_lines(&cli, true, &outcome, &[]);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| !line.contains('\n')));
+        let summary: serde_json::Value = serde_json::from_str(&lines[2]).unwrap();
+        assert_eq!(summary["failed"], 1);
+        assert!(summary.get("hosts").is_none());
+
+        let cli = parse(&[&["--json"][..], &args].concat()).unwrap();
+        let lines = json_lines(&cli, true, &outcome, &[]);
+        assert_eq!(lines.len(), 1);
+        let envelope: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(envelope["hosts"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn completions_install_prints_the_script_without_a_detectable_shell() {
+        let _env = ENV
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut vars = EnvVars::default();
+        vars.remove("SHELL");
+        let cli = Cli::try_parse_from(["guildsync", "completions", "install"]).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let outcome = runtime
+            .block_on(execute(&cli, &Config::default(), &Warnings::default()))
+            .unwrap();
+        assert_eq!(outcome.exit, ExitCode::Ok);
+        assert!(outcome.message.contains("cannot detect the shell"));
+        assert!(outcome.body.unwrap().contains("complete"));
+        let data = outcome.data.unwrap();
+        assert_eq!(
+            (&data["installed"], &data["detected"]),
+            (&false.into(), &false.into())
+        );
+    }
 }
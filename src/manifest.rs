@@ -0,0 +1,188 @@
+//! Integrity manifests for `discord export --manifest` and `format verify`.
+//!
+//! A manifest lists the SHA-256 hash and size of a dump file as written (compressed, if it is)
+//! and of every attachment file it references, with paths relative to the manifest's directory.
+//! Verifying re-hashes each file, so corruption or tampering in a long-term archive is found
+//! before the archive is needed.
+
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::atomic_file::write_atomic;
+use crate::attachments;
+use crate::error::CliError;
+use crate::timestamp;
+
+/// `format` of a manifest file.
+pub const MANIFEST_FORMAT: &str = "manifest";
+pub const MANIFEST_VERSION: u64 = 1;
+
+/// Default manifest path for a dump written to `out`: `<out>.manifest.json`.
+pub fn default_path(out: &Path) -> PathBuf {
+    let mut path = out.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u64,
+    pub created_at: String,
+    pub files: Vec<Entry>,
+}
+
+/// One file and what it hashed to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Relative to the manifest's directory.
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// Hash the dump at `out`, whose parsed contents are `dump`, and its attachment files, and
+/// write the manifest next to it. Returns the manifest's path.
+pub fn write(out: &Path, dump: &Value) -> Result<PathBuf, CliError> {
+    let dir = out.parent().unwrap_or(Path::new(""));
+    let name = out
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let files = std::iter::once(name)
+        .chain(attachments::stored_files(dump))
+        .map(|path| {
+            let (sha256, size) = hash(&dir.join(&path))?;
+            Ok(Entry { path, sha256, size })
+        })
+        .collect::<Result<_, CliError>>()?;
+    let manifest = Manifest {
+        format: MANIFEST_FORMAT.to_string(),
+        version: MANIFEST_VERSION,
+        created_at: timestamp::now_rfc3339(),
+        files,
+    };
+    let path = default_path(out);
+    let mut bytes = serde_json::to_vec_pretty(&manifest)?;
+    bytes.push(b'\n');
+    write_atomic(&path, &bytes)?;
+    Ok(path)
+}
+
+pub fn read(path: &Path) -> Result<Manifest, CliError> {
+    let text = match std::fs::read_to_string(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(CliError::NotFound(format!(
+                "no manifest at {}; export with --manifest to write one",
+                path.display()
+            )));
+        }
+        text => text?,
+    };
+    let manifest: Manifest = serde_json::from_str(&text)
+        .map_err(|e| CliError::Validation(format!("{}: {e}", path.display())))?;
+    if manifest.format != MANIFEST_FORMAT || manifest.version != MANIFEST_VERSION {
+        return Err(CliError::Validation(format!(
+            "{}: expected a {MANIFEST_FORMAT} v{MANIFEST_VERSION} file, found {} v{}",
+            path.display(),
+            manifest.format,
+            manifest.version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// A file that no longer matches its manifest entry.
+#[derive(Debug, Serialize)]
+pub struct Mismatch {
+    pub path: String,
+    /// `None` if the file is gone.
+    pub found: Option<Entry>,
+    pub expected: Entry,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            None => write!(f, "- {}: missing", self.path),
+            Some(found) => write!(
+                f,
+                "~ {}: sha256 {} ({} bytes), expected {} ({} bytes)",
+                self.path, found.sha256, found.size, self.expected.sha256, self.expected.size
+            ),
+        }
+    }
+}
+
+/// Re-hash every file of `manifest`, which was read from `manifest_path`, and return those
+/// that are missing or changed.
+pub fn verify(manifest_path: &Path, manifest: &Manifest) -> Result<Vec<Mismatch>, CliError> {
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let mut mismatches = Vec::new();
+    for expected in &manifest.files {
+        let found = match hash(&dir.join(&expected.path)) {
+            Ok((sha256, size)) => Some(Entry {
+                path: expected.path.clone(),
+                sha256,
+                size,
+            }),
+            Err(CliError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        if found.as_ref() != Some(expected) {
+            mismatches.push(Mismatch {
+                path: expected.path.clone(),
+                found,
+                expected: expected.clone(),
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Hex SHA-256 and size of the file at `path`.
+fn hash(path: &Path) -> Result<(String, u64), CliError> {
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_finds_changed_and_missing_files() {
+        let dir = std::env::temp_dir().join(format!("guildsync-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("guild.json.attachments")).unwrap();
+        let out = dir.join("guild.json");
+        let dump = serde_json::json!({ "channels": [{ "id": "1", "messages": [{ "id": "2",
+            "attachments": [{ "id": "3", "file": "guild.json.attachments/a.png" }] }] }] });
+        std::fs::write(&out, dump.to_string()).unwrap();
+        std::fs::write(dir.join("guild.json.attachments/a.png"), b"png").unwrap();
+
+        let path = write(&out, &dump).unwrap();
+        let manifest = read(&path).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert!(verify(&path, &manifest).unwrap().is_empty());
+
+        std::fs::write(&out, "{}").unwrap();
+        std::fs::remove_file(dir.join("guild.json.attachments/a.png")).unwrap();
+        let lines: Vec<String> = verify(&path, &manifest)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert!(
+            lines[0].starts_with("~ guild.json: sha256 44136fa3"),
+            "{lines:?}"
+        );
+        assert_eq!(lines[1], "- guild.json.attachments/a.png: missing");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
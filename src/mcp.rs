@@ -0,0 +1,462 @@
+//! Minimal Model Context Protocol server: JSON-RPC 2.0 over stdio, one message per line.
+//!
+//! Tools call the same library functions as the CLI. Tools that write files or touch
+//! remote state are only listed and callable with `--allow-write`.
+
+use std::any::TypeId;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::ArgAction;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::assets;
+use crate::compression::Codec;
+use crate::config::Config;
+use crate::diff::{self, DiffArgs};
+use crate::discord::{self, Client, ExportFilters};
+use crate::error::CliError;
+use crate::format::{self, ConvertArgs, GuildFormat, ValidateArgs};
+use crate::query::{self, QueryArgs};
+use crate::retry::RetryPolicy;
+use crate::stats::{self, StatsArgs};
+
+/// Protocol revisions this server understands, newest last.
+const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+struct Tool {
+    name: &'static str,
+    /// Earlier names, still callable but not listed.
+    aliases: &'static [&'static str],
+    description: &'static str,
+    /// Whether the tool has side effects and needs `--allow-write`.
+    writes: bool,
+    schema: fn() -> Value,
+}
+
+const TOOLS: &[Tool] = &[
+    Tool {
+        name: "validate_dump",
+        aliases: &["format_validate"],
+        description: "Validate a guild dump or upload-format file (same as `guildsync format validate`).",
+        writes: false,
+        schema: input_schema::<ValidateArgs>,
+    },
+    Tool {
+        name: "convert_dump",
+        aliases: &["format_convert"],
+        description: "Convert a guild dump to an upload file or back (same as `guildsync format convert`).",
+        writes: true,
+        schema: input_schema::<ConvertArgs>,
+    },
+    Tool {
+        name: "diff_dumps",
+        aliases: &["format_diff"],
+        description: "Compare two guild dumps: settings, items, permissions, and messages (same as `guildsync format diff`).",
+        writes: false,
+        schema: input_schema::<DiffArgs>,
+    },
+    Tool {
+        name: "query_dump",
+        aliases: &["format_query"],
+        description: "Run a JMESPath expression over a guild dump or an NDJSON archive's records (same as `guildsync format query`).",
+        writes: false,
+        schema: input_schema::<QueryArgs>,
+    },
+    Tool {
+        name: "dump_stats",
+        aliases: &["format_stats"],
+        description: "Summarize a guild dump's messages, authors, attachments, emoji, and growth (same as `guildsync format stats`).",
+        writes: false,
+        schema: input_schema::<StatsArgs>,
+    },
+    Tool {
+        name: "export_guild",
+        aliases: &["discord_export"],
+        description: "Export a guild from Discord to a dump or upload file, optionally with messages (a subset of `guildsync discord export`).",
+        writes: true,
+        schema: input_schema::<ExportArgs>,
+    },
+];
+
+/// Arguments of the `export_guild` tool: the core flags of `discord export`.
+#[derive(clap::Args, Debug, Deserialize)]
+struct ExportArgs {
+    /// Discord guild ID.
+    #[arg(long)]
+    guild: u64,
+
+    /// Output path for the dump JSON.
+    #[arg(long, value_name = "PATH")]
+    out: PathBuf,
+
+    /// Write a re-importable upload file instead of a dump.
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    format: Option<GuildFormat>,
+
+    /// Also export messages newer than this message ID (snowflake); 0 exports all of them.
+    #[arg(long, value_name = "SNOWFLAKE")]
+    #[serde(default)]
+    since: Option<u64>,
+
+    /// Only export these channels.
+    #[arg(long = "channel", value_name = "ID")]
+    #[serde(default)]
+    channels: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Serve requests from stdin until it closes.
+pub async fn serve(config: &Config, allow_write: bool) -> Result<(), CliError> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                tracing::debug!("mcp: {}", request.method);
+                // Notifications carry no id and get no response.
+                let Some(id) = request.id else { continue };
+                match handle(&request.method, &request.params, config, allow_write).await {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => error_response(id, code, &message),
+                }
+            }
+            Err(e) => error_response(Value::Null, PARSE_ERROR, &e.to_string()),
+        };
+        let mut bytes = serde_json::to_vec(&response)?;
+        bytes.push(b'\n');
+        stdout.write_all(&bytes).await?;
+        stdout.flush().await?;
+    }
+    Ok(())
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+async fn handle(
+    method: &str,
+    params: &Value,
+    config: &Config,
+    allow_write: bool,
+) -> Result<Value, (i64, String)> {
+    match method {
+        "initialize" => {
+            let requested = params["protocolVersion"].as_str();
+            let version = PROTOCOL_VERSIONS
+                .iter()
+                .find(|v| Some(**v) == requested)
+                .or(PROTOCOL_VERSIONS.last())
+                .copied();
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "guildsync", "version": env!("CARGO_PKG_VERSION") },
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => {
+            let tools: Vec<Value> = TOOLS
+                .iter()
+                .filter(|tool| allow_write || !tool.writes)
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "inputSchema": (tool.schema)(),
+                    })
+                })
+                .collect();
+            Ok(json!({ "tools": tools }))
+        }
+        "tools/call" => {
+            let name = params["name"].as_str().unwrap_or_default();
+            let tool = TOOLS
+                .iter()
+                .find(|tool| tool.name == name || tool.aliases.contains(&name))
+                .filter(|tool| allow_write || !tool.writes)
+                .ok_or_else(|| (INVALID_PARAMS, format!("unknown tool: {name}")))?;
+            let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+            Ok(match call(tool.name, arguments, config).await {
+                Ok(structured) => json!({
+                    "content": [{ "type": "text", "text": structured.to_string() }],
+                    "structuredContent": structured,
+                    "isError": false,
+                }),
+                Err(err) => json!({
+                    "content": [{ "type": "text", "text": err.to_string() }],
+                    "isError": true,
+                }),
+            })
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("method not found: {method}"))),
+    }
+}
+
+async fn call(tool: &str, arguments: Value, config: &Config) -> Result<Value, CliError> {
+    match tool {
+        "validate_dump" => {
+            let args: ValidateArgs = serde_json::from_value(arguments)?;
+            let validated = format::validate_format(&args, &config.formats)?;
+            Ok(serde_json::to_value(validated)?)
+        }
+        "convert_dump" => {
+            let args: ConvertArgs = serde_json::from_value(arguments)?;
+            let converted = format::convert(&args, &config.formats)?;
+            Ok(serde_json::to_value(converted)?)
+        }
+        "diff_dumps" => {
+            let args: DiffArgs = serde_json::from_value(arguments)?;
+            let report = diff::compare_files(&args, &config.formats)?;
+            Ok(json!({ "changes": report.change_count(), "diff": report }))
+        }
+        "query_dump" => {
+            let args: QueryArgs = serde_json::from_value(arguments)?;
+            Ok(json!({ "result": query::query(&args)? }))
+        }
+        "dump_stats" => {
+            let args: StatsArgs = serde_json::from_value(arguments)?;
+            Ok(serde_json::to_value(stats::stats_file(
+                &args,
+                &config.formats,
+            )?)?)
+        }
+        "export_guild" => export(serde_json::from_value(arguments)?, config).await,
+        _ => unreachable!("tool {tool} is listed in TOOLS"),
+    }
+}
+
+/// Export as `discord export` does without its progress bars, attachments, assets, and
+/// encryption; warnings are returned with the result.
+async fn export(args: ExportArgs, config: &Config) -> Result<Value, CliError> {
+    let format = args.format.unwrap_or(GuildFormat::Dump);
+    if args.since.is_some() && format == GuildFormat::Upload {
+        return Err(CliError::Usage(
+            "since exports messages, which upload files do not carry; use format dump".to_string(),
+        ));
+    }
+    let token = config.discord.resolve_token(None)?;
+    let policy = RetryPolicy {
+        max_retries: config.retry.max_retries,
+        base: Duration::from_millis(config.retry.base_ms),
+    };
+    let client = Client::new(&config.discord.api_base, token, policy, "mcp.export_guild")?;
+    let hidden = indicatif::ProgressBar::hidden();
+    let mut warnings = Vec::new();
+
+    let mut dump =
+        discord::fetch_dump(&client, args.guild, config.formats.dump_version, &hidden).await?;
+    assets::add_urls(&mut dump, &config.discord.cdn_base);
+    let filters = ExportFilters {
+        channels: args.channels.iter().map(u64::to_string).collect(),
+        ..ExportFilters::default()
+    };
+    filters.retain_channels(&mut dump);
+    let channels = dump["channels"].as_array().cloned().unwrap_or_default();
+    let (threads, denied) = discord::fetch_threads(&client, args.guild, &channels, &hidden).await?;
+    if !denied.is_empty() {
+        warnings.push(format!(
+            "archived threads not exported for channel(s) the bot lacks permission in: {}",
+            denied.join(", ")
+        ));
+    }
+    dump["threads"] = threads.into();
+    filters.retain_channels(&mut dump);
+    if !filters.is_empty() {
+        dump["filters"] = serde_json::to_value(&filters)?;
+    }
+
+    let mut messages = 0;
+    if let Some(since) = args.since {
+        for section in ["channels", "threads"] {
+            for channel in dump[section].as_array_mut().into_iter().flatten() {
+                let readable = channel["type"]
+                    .as_u64()
+                    .is_some_and(|t| discord::MESSAGE_CHANNEL_TYPES.contains(&t));
+                if !readable {
+                    continue;
+                }
+                let id = channel["id"].as_str().unwrap_or_default().to_string();
+                match discord::fetch_messages(&client, &id, since, None).await {
+                    Ok(fetched) => {
+                        messages += fetched.len();
+                        channel["messages"] = fetched.into();
+                    }
+                    Err(err @ CliError::Auth(_)) => {
+                        warnings.push(format!("skipping messages in channel {id}: {err}"));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+
+    let document = match format {
+        GuildFormat::Dump => dump,
+        GuildFormat::Upload => format::to_upload(dump, config.formats.upload_version),
+    };
+    format::validate_value(&document, Some(format), &config.formats)?;
+    format::write_sealed(&args.out, &document, Codec::for_path(&args.out), &[])?;
+    Ok(json!({
+        "guild": args.guild.to_string(),
+        "out": args.out,
+        "format": format,
+        "messages": messages,
+        "warnings": warnings,
+    }))
+}
+
+/// JSON Schema for a tool's arguments, derived from the clap definition the CLI uses.
+fn input_schema<A: clap::Args>() -> Value {
+    let command = A::augment_args(clap::Command::new("tool"));
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+
+    for arg in command.get_arguments() {
+        let name = arg.get_id().as_str();
+        if name == "help" {
+            continue;
+        }
+        let possible: Vec<String> = arg
+            .get_possible_values()
+            .iter()
+            .map(|v| v.get_name().to_string())
+            .collect();
+        let type_id = arg.get_value_parser().type_id();
+        let mut schema = if matches!(arg.get_action(), ArgAction::SetTrue) {
+            json!({ "type": "boolean" })
+        } else if !possible.is_empty() {
+            json!({ "type": "string", "enum": possible })
+        } else if type_id == TypeId::of::<u64>() || type_id == TypeId::of::<u32>() {
+            json!({ "type": "integer", "minimum": 0 })
+        } else if type_id == TypeId::of::<PathBuf>() || type_id == TypeId::of::<String>() {
+            json!({ "type": "string" })
+        } else {
+            json!({})
+        };
+        if matches!(arg.get_action(), ArgAction::Append) {
+            schema = json!({ "type": "array", "items": schema });
+        }
+        if let Some(help) = arg.get_help() {
+            schema["description"] = json!(help.to_string());
+        }
+        if arg.is_required_set() {
+            required.push(name.to_string());
+        }
+        properties.insert(name.to_string(), schema);
+    }
+
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn initialize_negotiates_a_protocol_version() {
+        let config = Config::default();
+        let result = handle(
+            "initialize",
+            &json!({ "protocolVersion": "2025-03-26" }),
+            &config,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["protocolVersion"], "2025-03-26");
+        assert_eq!(result["serverInfo"]["name"], "guildsync");
+
+        let result = handle(
+            "initialize",
+            &json!({ "protocolVersion": "1999-01-01" }),
+            &config,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            result["protocolVersion"],
+            *PROTOCOL_VERSIONS.last().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn write_tools_are_hidden_without_allow_write() {
+        let config = Config::default();
+        let names = |result: Value| -> Vec<String> {
+            result["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|tool| tool["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let read_only = names(
+            handle("tools/list", &json!({}), &config, false)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            read_only,
+            ["validate_dump", "diff_dumps", "query_dump", "dump_stats"]
+        );
+        let all = names(
+            handle("tools/list", &json!({}), &config, true)
+                .await
+                .unwrap(),
+        );
+        assert!(all.contains(&"export_guild".to_string()));
+        assert!(all.contains(&"convert_dump".to_string()));
+
+        let call = json!({ "name": "export_guild", "arguments": { "guild": 1, "out": "x.json" } });
+        let (code, _) = handle("tools/call", &call, &config, false)
+            .await
+            .unwrap_err();
+        assert_eq!(code, INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn failed_calls_are_reported_as_tool_errors() {
+        let config = Config::default();
+        let (code, message) = handle("tools/call", &json!({ "name": "nope" }), &config, false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            (code, message.as_str()),
+            (INVALID_PARAMS, "unknown tool: nope")
+        );
+
+        // A failing tool is a successful response carrying `isError`, under old names too.
+        for name in ["validate_dump", "format_validate"] {
+            let call = json!({ "name": name, "arguments": { "in": "/nonexistent/dump.json" } });
+            let result = handle("tools/call", &call, &config, false).await.unwrap();
+            assert_eq!(result["isError"], true, "{name}");
+            assert!(!result["content"][0]["text"].as_str().unwrap().is_empty());
+        }
+
+        let (code, _) = handle("resources/list", &json!({}), &config, false)
+            .await
+            .unwrap_err();
+        assert_eq!(code, METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,128 @@
+//! Upgrades of dump and upload files to newer format versions, for `format migrate`.
+//!
+//! Each [`Step`] upgrades a document by exactly one version and describes what it changed;
+//! [`migrate`] chains them from the file's version to the target. Version 1 is the only format
+//! version so far, so there are no steps yet: when the format changes, the new version gets a
+//! schema in `schemas/` and a step here from the one before.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::CliError;
+use crate::schema;
+
+/// Upgrade of a document from version `from` to `from + 1`.
+pub struct Step {
+    pub from: u64,
+    /// Rewrite the document in place, returning a line per change made.
+    pub upgrade: fn(&mut Value) -> Vec<String>,
+}
+
+/// Every upgrade step, oldest first.
+pub const STEPS: &[Step] = &[];
+
+/// What [`migrate`] did (or, with `--check`, would do).
+#[derive(Debug, Serialize)]
+pub struct Migrated {
+    pub from: u64,
+    pub to: u64,
+    /// Changes made, each prefixed with the step (`v1 -> v2: ...`).
+    pub changes: Vec<String>,
+}
+
+/// Upgrade `document` in place to version `to`, applying every step in between.
+pub fn migrate(document: &mut Value, to: u64) -> Result<Migrated, CliError> {
+    upgrade(document, to, STEPS)
+}
+
+fn upgrade(document: &mut Value, to: u64, steps: &[Step]) -> Result<Migrated, CliError> {
+    let from = document["version"]
+        .as_u64()
+        .filter(|v| *v > 0)
+        .ok_or_else(|| CliError::Validation("`version` must be a positive integer".to_string()))?;
+    if to < from {
+        return Err(CliError::Usage(format!(
+            "cannot downgrade from version {from} to {to}"
+        )));
+    }
+    let mut changes = Vec::new();
+    for version in from..to {
+        let next = version + 1;
+        let step = steps.iter().find(|s| s.from == version).ok_or_else(|| {
+            CliError::NotFound(format!(
+                "no migration from version {version} to {next} (known versions: {})",
+                schema::versions()
+            ))
+        })?;
+        changes.extend(
+            (step.upgrade)(document)
+                .into_iter()
+                .map(|change| format!("v{version} -> v{next}: {change}")),
+        );
+        document["version"] = next.into();
+    }
+    Ok(Migrated { from, to, changes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_chained_up_to_the_target_version() {
+        fn rename_topic(document: &mut Value) -> Vec<String> {
+            let mut changes = Vec::new();
+            for channel in document["channels"].as_array_mut().into_iter().flatten() {
+                if let Some(topic) = channel
+                    .as_object_mut()
+                    .and_then(|c| c.shift_remove("topic"))
+                {
+                    changes.push(format!(
+                        "channel {}: `topic` renamed",
+                        channel["id"].as_str().unwrap()
+                    ));
+                    channel["description"] = topic;
+                }
+            }
+            changes
+        }
+        fn nothing(_: &mut Value) -> Vec<String> {
+            Vec::new()
+        }
+        let steps = [
+            Step {
+                from: 1,
+                upgrade: rename_topic,
+            },
+            Step {
+                from: 2,
+                upgrade: nothing,
+            },
+        ];
+        let mut document = serde_json::json!({
+            "version": 1,
+            "channels": [{ "id": "20", "topic": "hi" }],
+        });
+
+        let migrated = upgrade(&mut document, 3, &steps).unwrap();
+        assert_eq!((migrated.from, migrated.to), (1, 3));
+        assert_eq!(migrated.changes, ["v1 -> v2: channel 20: `topic` renamed"]);
+        assert_eq!(document["version"], 3);
+        assert_eq!(document["channels"][0]["description"], "hi");
+
+        assert!(
+            upgrade(&mut document, 3, &steps)
+                .unwrap()
+                .changes
+                .is_empty()
+        );
+        assert!(matches!(
+            upgrade(&mut document, 4, &steps),
+            Err(CliError::NotFound(_))
+        ));
+        assert!(matches!(
+            upgrade(&mut document, 1, &steps),
+            Err(CliError::Usage(_))
+        ));
+    }
+}
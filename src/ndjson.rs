@@ -0,0 +1,349 @@
+//! Newline-delimited JSON representation of dump and upload files, for archives too large to
+//! handle as one JSON document.
+//!
+//! A file ending in `.ndjson` (or `.ndjson.gz`, `.ndjson.zst`) holds one record per line: first a `header` with
+//! the top-level fields (`format`, `version`, `guild`, ...), then a record per role, channel,
+//! thread, emoji, sticker, and scheduled event, with each channel's messages as `message` records
+//! after it:
+//!
+//! ```text
+//! {"record":"header","data":{"format":"dump","version":1,"guild":{"id":"1"}}}
+//! {"record":"channel","data":{"id":"20","type":0,"name":"general"}}
+//! {"record":"message","channel_id":"20","data":{"id":"98","content":"hi"}}
+//! ```
+//!
+//! [`validate`] checks such a file record by record, keeping only what cross-record checks need
+//! (channel ids and forum tags), so its memory use does not grow with the number of messages.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::atomic_file::AtomicFile;
+use crate::compression::{self, Codec};
+use crate::config::FormatsConfig;
+use crate::error::CliError;
+use crate::format::{self, GuildFormat, LIST_KEYS, Validated};
+use crate::schema::{self, Checker, Violation};
+
+/// Record name of the items of each top-level list.
+const RECORDS: &[(&str, &str)] = &[
+    ("roles", "role"),
+    ("channels", "channel"),
+    ("threads", "thread"),
+    ("emojis", "emoji"),
+    ("stickers", "sticker"),
+    ("scheduled_events", "scheduled_event"),
+];
+
+const HEADER: &str = "header";
+const MESSAGE: &str = "message";
+
+/// Whether `path` names an NDJSON file.
+pub fn is_ndjson(path: &Path) -> bool {
+    compression::is_kind(path, "ndjson")
+}
+
+/// Write `document` to `path` as NDJSON records, atomically and compressed with `codec`.
+pub fn write(path: &Path, document: &Value, codec: Codec) -> Result<(), CliError> {
+    let mut file = AtomicFile::create(path)?;
+    write_to(&mut file, document, codec)?;
+    file.commit()
+}
+
+/// Write `document` to `out` as NDJSON records, compressed with `codec`.
+pub fn write_to<W: Write>(out: W, document: &Value, codec: Codec) -> Result<(), CliError> {
+    compression::stream_to(codec, out, |out| {
+        let mut header = document.clone();
+        if let Value::Object(obj) = &mut header {
+            // Empty lists stay in the header; there are no records to bring them back.
+            obj.retain(|key, value| {
+                !LIST_KEYS.contains(&key.as_str()) || value.as_array().is_none_or(Vec::is_empty)
+            });
+        }
+        line(
+            out,
+            &serde_json::json!({ "record": HEADER, "data": header }),
+        )?;
+        for (section, record) in RECORDS {
+            for item in document[*section].as_array().into_iter().flatten() {
+                let messages = item
+                    .get("messages")
+                    .filter(|m| *section == "channels" && m.is_array());
+                let Some(messages) = messages else {
+                    line(out, &serde_json::json!({ "record": record, "data": item }))?;
+                    continue;
+                };
+                let mut item = item.clone();
+                item.as_object_mut().map(|obj| obj.shift_remove("messages"));
+                line(out, &serde_json::json!({ "record": record, "data": item }))?;
+                for message in messages.as_array().into_iter().flatten() {
+                    line(
+                        out,
+                        &serde_json::json!({
+                            "record": MESSAGE,
+                            "channel_id": item["id"],
+                            "data": message,
+                        }),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Read an NDJSON file back into one document.
+pub fn read(path: &Path) -> Result<Value, CliError> {
+    let mut records = Records::open(path)?;
+    let mut document = records.header()?;
+    // Channel ids to their index in `channels`, for attaching messages.
+    let mut channels: HashMap<String, usize> = HashMap::new();
+    while let Some((n, record, mut value)) = records.next()? {
+        let data = value["data"].take();
+        if record == MESSAGE {
+            let Some(&i) = channels.get(&id(&value["channel_id"])) else {
+                return Err(orphan(n, &value));
+            };
+            let channel = &mut document["channels"][i];
+            if !channel["messages"].is_array() {
+                channel["messages"] = Value::Array(Vec::new());
+            }
+            if let Some(messages) = channel["messages"].as_array_mut() {
+                messages.push(data);
+            }
+            continue;
+        }
+        let section = section_of(n, &record)?;
+        if !document[section].is_array() {
+            document[section] = Value::Array(Vec::new());
+        }
+        let items = document[section]
+            .as_array_mut()
+            .expect("set to an array above");
+        if section == "channels" {
+            channels.insert(id(&data["id"]), items.len());
+        }
+        items.push(data);
+    }
+    Ok(document)
+}
+
+/// The header fields of an NDJSON file, read without the rest of it.
+pub fn header(path: &Path) -> Result<Value, CliError> {
+    Records::open(path)?.header()
+}
+
+/// Call `f` with each record of an NDJSON file as written, header first, one at a time.
+pub fn for_each_record(
+    path: &Path,
+    mut f: impl FnMut(Value) -> Result<(), CliError>,
+) -> Result<(), CliError> {
+    let mut records = Records::open(path)?;
+    let header = records.header()?;
+    f(serde_json::json!({ "record": HEADER, "data": header }))?;
+    while let Some((_, _, value)) = records.next()? {
+        f(value)?;
+    }
+    Ok(())
+}
+
+/// Check an NDJSON file like [`format::validate_value`] checks a document, one record at a
+/// time. Violations carry the pointers they would have in the equivalent JSON document.
+///
+/// Returns the header with an empty list for every section present, for checking top-level
+/// keys.
+pub fn validate(
+    path: &Path,
+    expected: Option<GuildFormat>,
+    formats: &FormatsConfig,
+) -> Result<(Validated, Value), CliError> {
+    let mut records = Records::open(path)?;
+    let mut skeleton = records.header()?;
+    let (validated, schema) = format::check_header(&skeleton, expected, formats)?;
+    let mut violations = schema::check(&schema, &skeleton);
+
+    // One compiled checker per part of the schema, by its pointer.
+    let mut checkers: HashMap<String, Checker> = HashMap::new();
+    let mut check = |part: String, data: &Value, at: String| {
+        checkers
+            .entry(part)
+            .or_insert_with_key(|part| {
+                Checker::new(&schema::subschema(&schema, part).unwrap_or(Value::Bool(true)))
+            })
+            .check(data, &at)
+    };
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    // What thread references need of each channel: its index, type, and tag ids.
+    let mut channels: HashMap<String, (usize, Value)> = HashMap::new();
+    let mut message_counts: HashMap<String, usize> = HashMap::new();
+    let mut threads: Vec<Value> = Vec::new();
+
+    while let Some((n, record, value)) = records.next()? {
+        let data = &value["data"];
+        if record == MESSAGE {
+            let channel_id = id(&value["channel_id"]);
+            let Some((i, _)) = channels.get(&channel_id) else {
+                return Err(orphan(n, &value));
+            };
+            let count = message_counts.entry(channel_id).or_default();
+            let at = format!("/channels/{i}/messages/{count}");
+            *count += 1;
+            let messages = "/$defs/channel/properties/messages/items".to_string();
+            violations.extend(check(messages, data, at));
+            continue;
+        }
+        let section = section_of(n, &record)?;
+        let count = counts.entry(section).or_default();
+        let i = *count;
+        *count += 1;
+        let part = format!("/properties/{section}/items");
+        violations.extend(check(part, data, format!("/{section}/{i}")));
+        if section == "channels" {
+            let tags: Vec<Value> = data["available_tags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|tag| serde_json::json!({ "id": tag["id"] }))
+                .collect();
+            let reduced = serde_json::json!({ "type": data["type"], "available_tags": tags });
+            channels.insert(id(&data["id"]), (i, reduced));
+        } else if section == "threads" {
+            threads.push(serde_json::json!({
+                "parent_id": data["parent_id"],
+                "applied_tags": data["applied_tags"],
+            }));
+        }
+    }
+
+    if violations.is_empty() {
+        for (i, thread) in threads.iter().enumerate() {
+            let parent = channels.get(&id(&thread["parent_id"])).map(|(_, c)| c);
+            violations.extend(format::thread_violations(i, thread, parent));
+        }
+    }
+    if !violations.is_empty() {
+        violations.sort_by(|a: &Violation, b| a.pointer.cmp(&b.pointer));
+        return Err(CliError::Violations(violations));
+    }
+    for section in counts.keys() {
+        skeleton[*section] = Value::Array(Vec::new());
+    }
+    Ok((validated, skeleton))
+}
+
+/// The records of an NDJSON file, with their line numbers.
+struct Records {
+    lines: std::iter::Enumerate<std::io::Lines<Box<dyn BufRead>>>,
+}
+
+impl Records {
+    fn open(path: &Path) -> Result<Self, CliError> {
+        Ok(Self {
+            lines: compression::open(path)?.lines().enumerate(),
+        })
+    }
+
+    /// The next record as its line number, name, and value; blank lines are skipped.
+    fn next(&mut self) -> Result<Option<(usize, String, Value)>, CliError> {
+        for (i, text) in self.lines.by_ref() {
+            let text = text?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let n = i + 1;
+            let value: Value = serde_json::from_str(&text)
+                .map_err(|e| CliError::Validation(format!("line {n}: {e}")))?;
+            let Some(record) = value["record"].as_str() else {
+                return Err(CliError::Validation(format!(
+                    "line {n}: not a record (no `record` name)"
+                )));
+            };
+            return Ok(Some((n, record.to_string(), value)));
+        }
+        Ok(None)
+    }
+
+    /// The header fields, which must be the first record.
+    fn header(&mut self) -> Result<Value, CliError> {
+        match self.next()? {
+            Some((_, record, mut value)) if record == HEADER => Ok(value["data"].take()),
+            Some((n, record, _)) => Err(CliError::Validation(format!(
+                "line {n}: expected the `{HEADER}` record first, found `{record}`"
+            ))),
+            None => Err(CliError::Validation(
+                "empty file: no `header` record".to_string(),
+            )),
+        }
+    }
+}
+
+/// The top-level list that records named `record` belong to.
+fn section_of(n: usize, record: &str) -> Result<&'static str, CliError> {
+    RECORDS
+        .iter()
+        .find(|(_, r)| *r == record)
+        .map(|(section, _)| *section)
+        .ok_or_else(|| CliError::Validation(format!("line {n}: unknown record `{record}`")))
+}
+
+fn orphan(n: usize, value: &Value) -> CliError {
+    CliError::Validation(format!(
+        "line {n}: message for channel {} before (or without) that channel's record",
+        value["channel_id"]
+    ))
+}
+
+fn line(out: &mut dyn Write, record: &Value) -> Result<(), CliError> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn id(value: &Value) -> String {
+    value
+        .as_str()
+        .map_or_else(|| value.to_string(), str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_round_trip_and_validate_record_by_record() {
+        let dir = std::env::temp_dir().join(format!("guildsync-ndjson-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("guild.ndjson.gz");
+        let mut document = serde_json::json!({
+            "format": "dump",
+            "version": 1,
+            "guild": { "id": "1" },
+            "roles": [],
+            "channels": [
+                { "id": "20", "type": 15, "available_tags": [{ "id": "7", "name": "bug" }] },
+                { "id": "21", "type": 0, "messages": [{ "id": "98" }, { "id": "99" }] },
+            ],
+            "threads": [{ "id": "30", "type": 11, "parent_id": "20", "applied_tags": ["7"] }],
+        });
+        write(&path, &document, Codec::Gzip).unwrap();
+        assert_eq!(read(&path).unwrap(), document);
+        let formats = FormatsConfig::default();
+        let (validated, skeleton) = validate(&path, None, &formats).unwrap();
+        assert_eq!(validated.format, GuildFormat::Dump);
+        assert_eq!(skeleton["channels"], serde_json::json!([]));
+
+        document["channels"][1]["messages"][1] = serde_json::json!({ "content": "no id" });
+        document["threads"][0]["applied_tags"] = serde_json::json!(["8"]);
+        write(&path, &document, Codec::Gzip).unwrap();
+        let Err(CliError::Violations(violations)) = validate(&path, None, &formats) else {
+            panic!("expected violations");
+        };
+        // Thread references are only checked once every record has the right shape.
+        let pointers: Vec<&str> = violations.iter().map(|v| v.pointer.as_str()).collect();
+        assert_eq!(pointers, ["/channels/1/messages/1"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
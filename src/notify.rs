@@ -0,0 +1,191 @@
+//! Status messages for `notify`: a message body built from text and embed JSON, posted to a
+//! channel with the bot token or to a webhook URL, which needs none.
+
+use reqwest::Url;
+use serde_json::Value;
+
+use crate::error::CliError;
+
+/// Longest message content Discord accepts.
+pub const MAX_CONTENT: usize = 2000;
+
+/// Most embeds one message may carry.
+pub const MAX_EMBEDS: usize = 10;
+
+/// Longest webhook username Discord accepts.
+const MAX_USERNAME: usize = 80;
+
+/// What to post.
+#[derive(Debug, Default)]
+pub struct Notice<'a> {
+    pub content: Option<&'a str>,
+    /// One embed object or an array of them.
+    pub embeds: Option<Value>,
+    /// Name to post under; webhooks only.
+    pub username: Option<&'a str>,
+    /// Let mentions in the content ping.
+    pub mentions: bool,
+}
+
+impl Notice<'_> {
+    /// The create-message (or execute-webhook) body. Mentions do not ping unless asked to.
+    pub fn body(&self) -> Result<Value, CliError> {
+        let content = self.content.map(str::trim_end).filter(|c| !c.is_empty());
+        let embeds = match &self.embeds {
+            None => Vec::new(),
+            Some(Value::Array(embeds)) => embeds.clone(),
+            Some(embed) => vec![embed.clone()],
+        };
+        if content.is_none() && embeds.is_empty() {
+            return Err(CliError::Usage(
+                "nothing to post: the message and embeds are empty".to_string(),
+            ));
+        }
+        if let Some(content) = content {
+            let chars = content.chars().count();
+            if chars > MAX_CONTENT {
+                return Err(CliError::Validation(format!(
+                    "message is {chars} characters; Discord allows {MAX_CONTENT}"
+                )));
+            }
+        }
+        if embeds.len() > MAX_EMBEDS {
+            return Err(CliError::Validation(format!(
+                "{} embeds; Discord allows {MAX_EMBEDS} per message",
+                embeds.len()
+            )));
+        }
+        if let Some(i) = embeds.iter().position(|embed| !embed.is_object()) {
+            return Err(CliError::Validation(format!(
+                "embed {i} is not a JSON object"
+            )));
+        }
+
+        let mut body = serde_json::json!({ "embeds": embeds });
+        if let Some(content) = content {
+            body["content"] = content.into();
+        }
+        if let Some(username) = self.username {
+            body["username"] = username
+                .chars()
+                .take(MAX_USERNAME)
+                .collect::<String>()
+                .into();
+        }
+        if !self.mentions {
+            body["allowed_mentions"] = serde_json::json!({ "parse": [] });
+        }
+        Ok(body)
+    }
+}
+
+/// A webhook URL split into the API base and the execute path, so it can go through
+/// [`crate::discord::Client`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Webhook {
+    /// E.g. `https://discord.com/api`.
+    pub base: String,
+    pub id: String,
+    token: String,
+    /// Thread to post in, from the URL's `thread_id`.
+    thread: Option<String>,
+}
+
+impl Webhook {
+    /// Parse `https://discord.com/api/webhooks/{id}/{token}` (any host, API version prefix
+    /// allowed, `?thread_id=` kept).
+    pub fn parse(url: &str) -> Result<Self, CliError> {
+        let invalid =
+            || CliError::Usage("--webhook: expected .../api/webhooks/{id}/{token}".into());
+        let url = Url::parse(url.trim()).map_err(|_| invalid())?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(invalid());
+        }
+        let segments: Vec<&str> = url.path_segments().ok_or_else(invalid)?.collect();
+        let at = segments
+            .iter()
+            .rposition(|s| *s == "webhooks")
+            .ok_or_else(invalid)?;
+        let [id, token] = &segments[at + 1..] else {
+            return Err(invalid());
+        };
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) || token.is_empty() {
+            return Err(invalid());
+        }
+        let thread = url
+            .query_pairs()
+            .find(|(key, _)| key == "thread_id")
+            .map(|(_, value)| value.into_owned());
+        let mut base = url.clone();
+        base.set_query(None);
+        base.set_path(&segments[..at].join("/"));
+        Ok(Self {
+            base: base.as_str().trim_end_matches('/').to_string(),
+            id: id.to_string(),
+            token: token.to_string(),
+            thread,
+        })
+    }
+
+    /// Path executing the webhook and returning the created message.
+    pub fn path(&self) -> String {
+        let mut path = format!("/webhooks/{}/{}?wait=true", self.id, self.token);
+        if let Some(thread) = &self.thread {
+            path.push_str(&format!("&thread_id={thread}"));
+        }
+        path
+    }
+
+    /// `err` with the webhook token, which anyone could post with, masked.
+    pub fn redact(&self, err: CliError) -> CliError {
+        let mask = |text: String| text.replace(&self.token, "***");
+        match err {
+            CliError::Auth(text) => CliError::Auth(mask(text)),
+            CliError::NotFound(text) => CliError::NotFound(mask(text)),
+            CliError::Network(text) => CliError::Network(mask(text)),
+            CliError::Validation(text) => CliError::Validation(mask(text)),
+            err => err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_bodies_and_parses_webhooks() {
+        let notice = Notice {
+            content: Some("deploy ok\n"),
+            embeds: Some(serde_json::json!({ "title": "build 12" })),
+            ..Notice::default()
+        };
+        assert_eq!(
+            notice.body().unwrap(),
+            serde_json::json!({
+                "content": "deploy ok",
+                "embeds": [{ "title": "build 12" }],
+                "allowed_mentions": { "parse": [] },
+            })
+        );
+        let empty = Notice {
+            content: Some(" "),
+            ..Notice::default()
+        };
+        assert!(matches!(empty.body(), Err(CliError::Usage(_))));
+        let bad = Notice {
+            embeds: Some(serde_json::json!(["title"])),
+            ..Notice::default()
+        };
+        assert!(matches!(bad.body(), Err(CliError::Validation(_))));
+
+        let hook =
+            Webhook::parse("https://discord.com/api/v10/webhooks/123/abc-DEF?thread_id=9").unwrap();
+        assert_eq!(hook.base, "https://discord.com/api/v10");
+        assert_eq!(hook.path(), "/webhooks/123/abc-DEF?wait=true&thread_id=9");
+        let err = hook.redact(CliError::NotFound("POST /webhooks/123/abc-DEF".into()));
+        assert_eq!(err.to_string(), "not found: POST /webhooks/123/***");
+        assert!(Webhook::parse("https://discord.com/api/webhooks/123").is_err());
+        assert!(Webhook::parse("ftp://discord.com/api/webhooks/1/t").is_err());
+    }
+}
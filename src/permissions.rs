@@ -0,0 +1,232 @@
+//! Discord permission bitfields as named flags, and what an import changes about them.
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::discord::text;
+
+/// Permission flag names by bit position, per the Discord API documentation.
+const FLAGS: &[(u32, &str)] = &[
+    (0, "CREATE_INSTANT_INVITE"),
+    (1, "KICK_MEMBERS"),
+    (2, "BAN_MEMBERS"),
+    (3, "ADMINISTRATOR"),
+    (4, "MANAGE_CHANNELS"),
+    (5, "MANAGE_GUILD"),
+    (6, "ADD_REACTIONS"),
+    (7, "VIEW_AUDIT_LOG"),
+    (8, "PRIORITY_SPEAKER"),
+    (9, "STREAM"),
+    (10, "VIEW_CHANNEL"),
+    (11, "SEND_MESSAGES"),
+    (12, "SEND_TTS_MESSAGES"),
+    (13, "MANAGE_MESSAGES"),
+    (14, "EMBED_LINKS"),
+    (15, "ATTACH_FILES"),
+    (16, "READ_MESSAGE_HISTORY"),
+    (17, "MENTION_EVERYONE"),
+    (18, "USE_EXTERNAL_EMOJIS"),
+    (19, "VIEW_GUILD_INSIGHTS"),
+    (20, "CONNECT"),
+    (21, "SPEAK"),
+    (22, "MUTE_MEMBERS"),
+    (23, "DEAFEN_MEMBERS"),
+    (24, "MOVE_MEMBERS"),
+    (25, "USE_VAD"),
+    (26, "CHANGE_NICKNAME"),
+    (27, "MANAGE_NICKNAMES"),
+    (28, "MANAGE_ROLES"),
+    (29, "MANAGE_WEBHOOKS"),
+    (30, "MANAGE_GUILD_EXPRESSIONS"),
+    (31, "USE_APPLICATION_COMMANDS"),
+    (32, "REQUEST_TO_SPEAK"),
+    (33, "MANAGE_EVENTS"),
+    (34, "MANAGE_THREADS"),
+    (35, "CREATE_PUBLIC_THREADS"),
+    (36, "CREATE_PRIVATE_THREADS"),
+    (37, "USE_EXTERNAL_STICKERS"),
+    (38, "SEND_MESSAGES_IN_THREADS"),
+    (39, "USE_EMBEDDED_ACTIVITIES"),
+    (40, "MODERATE_MEMBERS"),
+    (41, "VIEW_CREATOR_MONETIZATION_ANALYTICS"),
+    (42, "USE_SOUNDBOARD"),
+    (43, "CREATE_GUILD_EXPRESSIONS"),
+    (44, "CREATE_EVENTS"),
+    (45, "USE_EXTERNAL_SOUNDS"),
+    (46, "SEND_VOICE_MESSAGES"),
+    (49, "SEND_POLLS"),
+    (50, "USE_EXTERNAL_APPS"),
+];
+
+/// Overwrite `type` of a member (as opposed to a role, `0`).
+const MEMBER_OVERWRITE: u64 = 1;
+
+/// Flags added to and removed from one permission bitfield.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct PermissionChange {
+    /// `permissions` of a role, or `allow`/`deny` of a channel's overwrite for `target`.
+    pub field: &'static str,
+    /// The overwrite's role (`role <name> (<id>)`) or member (`member <id>`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl fmt::Display for PermissionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(target) = &self.target {
+            write!(f, "overwrite {target} ")?;
+        }
+        write!(f, "{}:", self.field)?;
+        for flag in &self.added {
+            write!(f, " +{flag}")?;
+        }
+        for flag in &self.removed {
+            write!(f, " -{flag}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Change to a role's `permissions` from `current` (`None` for a new role) to `desired`.
+pub fn role_changes(current: Option<&Value>, desired: &Value) -> Vec<PermissionChange> {
+    if desired.get("permissions").is_none() {
+        return Vec::new();
+    }
+    let old = current.map_or(0, |role| bits(&role["permissions"]));
+    change("permissions", None, old, bits(&desired["permissions"]))
+        .into_iter()
+        .collect()
+}
+
+/// Changes to a channel's `permission_overwrites` from `current` (`None` for a new channel) to
+/// `desired`, per overwrite target. `roles` lists the guild's roles for naming targets.
+pub fn overwrite_changes(
+    current: Option<&Value>,
+    desired: &Value,
+    roles: &[&Value],
+) -> Vec<PermissionChange> {
+    let Some(wanted) = desired.get("permission_overwrites") else {
+        return Vec::new();
+    };
+    let old = overwrites(current.map_or(&Value::Null, |c| &c["permission_overwrites"]));
+    let new = overwrites(wanted);
+    let mut changes = Vec::new();
+    let mut ids: Vec<&String> = old.keys().chain(new.keys()).collect();
+    ids.sort_by_key(|id| (id.parse::<u64>().ok(), *id));
+    ids.dedup();
+    for id in ids {
+        let (before, after) = (old.get(id), new.get(id));
+        let overwrite = after.or(before).copied().unwrap_or(&Value::Null);
+        let target = target(id, overwrite, roles);
+        for field in ["allow", "deny"] {
+            let old = before.map_or(0, |o| bits(&o[field]));
+            let new = after.map_or(0, |o| bits(&o[field]));
+            changes.extend(change(field, Some(target.clone()), old, new));
+        }
+    }
+    changes
+}
+
+/// Who a channel permission overwrite applies to: `role <name> (<id>)` or `member <id>`.
+pub fn target(id: &str, overwrite: &Value, roles: &[&Value]) -> String {
+    if overwrite["type"].as_u64() == Some(MEMBER_OVERWRITE) {
+        format!("member {id}")
+    } else {
+        let name = roles.iter().find(|r| text(&r["id"]) == id);
+        format!(
+            "role {} ({id})",
+            name.map_or(id.to_string(), |r| text(&r["name"]))
+        )
+    }
+}
+
+/// Whether the bitfield `value` (a string or number) has the flag named `flag` set.
+pub fn has(value: &Value, flag: &str) -> bool {
+    FLAGS
+        .iter()
+        .find(|(_, name)| *name == flag)
+        .is_some_and(|(bit, _)| bits(value) & (1 << bit) != 0)
+}
+
+/// Names of the flags set in `bits`; unknown bits are named `BIT_<n>`.
+pub fn names(bits: u64) -> Vec<String> {
+    (0..64)
+        .filter(|bit| bits & (1 << bit) != 0)
+        .map(|bit| match FLAGS.iter().find(|(b, _)| *b == bit) {
+            Some((_, name)) => name.to_string(),
+            None => format!("BIT_{bit}"),
+        })
+        .collect()
+}
+
+fn change(
+    field: &'static str,
+    target: Option<String>,
+    old: u64,
+    new: u64,
+) -> Option<PermissionChange> {
+    (old != new).then(|| PermissionChange {
+        field,
+        target,
+        added: names(new & !old),
+        removed: names(old & !new),
+    })
+}
+
+fn overwrites(value: &Value) -> std::collections::HashMap<String, &Value> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|overwrite| (text(&overwrite["id"]), overwrite))
+        .collect()
+}
+
+/// A permission bitfield, sent by Discord as a decimal string.
+fn bits(value: &Value) -> u64 {
+    value
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .or_else(|| value.as_u64())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overwrite_changes_name_flags_and_targets() {
+        let mods = serde_json::json!({ "id": "9", "name": "mods" });
+        let current = serde_json::json!({ "permission_overwrites": [
+            { "id": "9", "type": 0, "allow": "2048", "deny": "0" },
+            { "id": "7", "type": 1, "allow": "1024", "deny": "0" },
+        ]});
+        let desired = serde_json::json!({ "permission_overwrites": [
+            { "id": "9", "type": 0, "allow": "1024", "deny": "2048" },
+        ]});
+        let changes: Vec<String> = overwrite_changes(Some(&current), &desired, &[&mods])
+            .iter()
+            .map(PermissionChange::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "overwrite member 7 allow: -VIEW_CHANNEL",
+                "overwrite role mods (9) allow: +VIEW_CHANNEL -SEND_MESSAGES",
+                "overwrite role mods (9) deny: +SEND_MESSAGES",
+            ]
+        );
+
+        let role = serde_json::json!({ "permissions": "8" });
+        assert_eq!(
+            role_changes(None, &role)[0].to_string(),
+            "permissions: +ADMINISTRATOR"
+        );
+        assert_eq!(names(1 << 60), ["BIT_60"]);
+    }
+}
@@ -0,0 +1,29 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use crate::error::CliError;
+
+/// Ask a yes/no question on stderr; anything but `y`/`yes` is a no.
+///
+/// When stdin is not a terminal there is nobody to answer, so the question is
+/// treated as declined.
+pub async fn confirm(question: &str) -> Result<bool, CliError> {
+    if !std::io::stdin().is_terminal() {
+        tracing::warn!("stdin is not a terminal; pass --yes to confirm: {question}");
+        return Ok(false);
+    }
+    let question = question.to_string();
+    // Read on a blocking thread so Ctrl-C can still cancel the run while we wait.
+    tokio::task::spawn_blocking(move || {
+        let mut stderr = std::io::stderr();
+        write!(stderr, "{question} [y/N] ")?;
+        stderr.flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        Ok(matches!(
+            answer.trim().to_ascii_lowercase().as_str(),
+            "y" | "yes"
+        ))
+    })
+    .await
+    .map_err(|e| CliError::Io(std::io::Error::other(e)))?
+}
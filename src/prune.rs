@@ -0,0 +1,285 @@
+//! Archival planning for `discord prune plan`.
+//!
+//! A prune plan is the analysed dump with the proposed changes already made: inactive channels
+//! moved into an archive category (or removed), empty categories and unused roles removed. Since
+//! `discord import` turns a guild into what a file describes, importing the plan carries the
+//! changes out, with the usual dry run, confirmation, and undo journal. The proposals and their
+//! reasons are recorded in the file as `prune`.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::discord::{self, CATEGORY_TYPE, list, text};
+
+/// Channel types whose activity is known from their last message: text, announcement, forum,
+/// and media channels. Voice and stage channels are never proposed.
+const MESSAGE_CHANNEL_TYPES: &[u64] = &[0, 5, 15, 16];
+
+/// Discord's epoch (2015-01-01T00:00:00Z) in Unix seconds.
+const DISCORD_EPOCH_SECS: u64 = 1_420_070_400;
+
+const DAY_SECS: u64 = 86_400;
+
+/// What a plan proposes for one object.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Proposal {
+    /// Move the channel into the archive category.
+    Archive,
+    Delete,
+}
+
+/// One proposed change and why.
+#[derive(Debug, Serialize)]
+pub struct Action {
+    pub action: Proposal,
+    /// `channel`, `category`, or `role`.
+    pub kind: &'static str,
+    pub id: String,
+    pub name: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            Proposal::Archive => "archive",
+            Proposal::Delete => "delete",
+        };
+        write!(
+            f,
+            "{action} {} {} ({}): {}",
+            self.kind, self.name, self.id, self.reason
+        )
+    }
+}
+
+/// Criteria for [`plan`].
+#[derive(Debug)]
+pub struct Criteria<'a> {
+    /// Channels without a message for this many days are inactive.
+    pub inactive_days: u64,
+    /// Now, in Unix seconds.
+    pub now: u64,
+    /// Category that inactive channels are moved into; without one they are deleted.
+    pub archive_category: Option<&'a str>,
+    /// The guild's members, for finding roles nobody holds; without them roles are kept.
+    pub members: Option<&'a [Value]>,
+}
+
+/// The plan for `dump`: a copy with the proposed changes made and recorded as `prune`, and the
+/// proposals themselves.
+pub fn plan(dump: &Value, criteria: &Criteria) -> (Value, Vec<Action>) {
+    let mut actions = Vec::new();
+    let channels: Vec<&Value> = list(&dump["channels"]).collect();
+    let cutoff = criteria
+        .now
+        .saturating_sub(criteria.inactive_days * DAY_SECS);
+
+    for channel in &channels {
+        let id = text(&channel["id"]);
+        let kind = channel["type"].as_u64();
+        if !kind.is_some_and(|t| MESSAGE_CHANNEL_TYPES.contains(&t))
+            || criteria.archive_category == Some(channel["parent_id"].as_str().unwrap_or_default())
+        {
+            continue;
+        }
+        let last = last_activity(channel);
+        if last > cutoff {
+            continue;
+        }
+        let days = criteria.now.saturating_sub(last) / DAY_SECS;
+        let reason = if last == created(channel) {
+            format!("no messages since it was created {days} days ago")
+        } else {
+            format!("no messages for {days} days")
+        };
+        actions.push(Action {
+            action: match criteria.archive_category {
+                Some(_) => Proposal::Archive,
+                None => Proposal::Delete,
+            },
+            kind: "channel",
+            id,
+            name: text(&channel["name"]),
+            reason,
+        });
+    }
+
+    // Categories left without channels, counting the deletions proposed above.
+    let deleted: HashSet<&str> = actions
+        .iter()
+        .filter(|a| a.action == Proposal::Delete)
+        .map(|a| a.id.as_str())
+        .collect();
+    let archived: HashSet<&str> = actions
+        .iter()
+        .filter(|a| a.action == Proposal::Archive)
+        .map(|a| a.id.as_str())
+        .collect();
+    let mut empty = Vec::new();
+    for category in channels
+        .iter()
+        .filter(|c| c["type"].as_u64() == Some(CATEGORY_TYPE))
+    {
+        let id = text(&category["id"]);
+        if criteria.archive_category == Some(id.as_str()) {
+            continue;
+        }
+        let children = channels.iter().filter(|c| {
+            c["parent_id"].as_str() == Some(id.as_str())
+                && !deleted.contains(text(&c["id"]).as_str())
+                && !archived.contains(text(&c["id"]).as_str())
+        });
+        if children.count() == 0 {
+            empty.push(Action {
+                action: Proposal::Delete,
+                kind: "category",
+                name: text(&category["name"]),
+                id,
+                reason: "no channels left".to_string(),
+            });
+        }
+    }
+    actions.extend(empty);
+
+    if let Some(members) = criteria.members {
+        let held: HashSet<String> = members
+            .iter()
+            .flat_map(|member| list(&member["roles"]))
+            .map(text)
+            .collect();
+        let everyone = text(&dump["guild"]["id"]);
+        for role in list(&dump["roles"]) {
+            let id = text(&role["id"]);
+            if id == everyone || role["managed"] == true || held.contains(&id) {
+                continue;
+            }
+            actions.push(Action {
+                action: Proposal::Delete,
+                kind: "role",
+                id,
+                name: text(&role["name"]),
+                reason: "no members".to_string(),
+            });
+        }
+    }
+
+    let mut planned = dump.clone();
+    let proposed = |id: &Value, action| {
+        actions
+            .iter()
+            .any(|a| a.action == action && a.id == text(id))
+    };
+    for section in ["channels", "roles"] {
+        if let Some(items) = planned[section].as_array_mut() {
+            items.retain(|item| !proposed(&item["id"], Proposal::Delete));
+        }
+    }
+    if let (Some(archive), Some(items)) = (
+        criteria.archive_category,
+        planned["channels"].as_array_mut(),
+    ) {
+        for channel in items
+            .iter_mut()
+            .filter(|c| proposed(&c["id"], Proposal::Archive))
+        {
+            channel["parent_id"] = archive.into();
+        }
+    }
+    planned["prune"] = serde_json::json!({
+        "inactive_days": criteria.inactive_days,
+        "archive_category": criteria.archive_category,
+        "actions": actions,
+    });
+    (planned, actions)
+}
+
+/// Unix seconds of a channel's newest message (per `last_message_id` and any exported
+/// messages), or of its creation if it has none.
+fn last_activity(channel: &Value) -> u64 {
+    let newest = std::iter::once(&channel["last_message_id"])
+        .chain(list(&channel["messages"]).map(|m| &m["id"]))
+        .filter_map(|id| text(id).parse::<u64>().ok())
+        .max();
+    newest.map_or_else(|| created(channel), seconds)
+}
+
+fn created(channel: &Value) -> u64 {
+    seconds(discord::snowflake(channel))
+}
+
+/// Unix seconds at which `snowflake` was generated.
+fn seconds(snowflake: u64) -> u64 {
+    (snowflake >> 22) / 1000 + DISCORD_EPOCH_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inactive_channels_empty_categories_and_unheld_roles_are_proposed() {
+        // Snowflakes generated `days` days after the Discord epoch.
+        let at = |days: u64| (days * DAY_SECS * 1000) << 22;
+        let now = DISCORD_EPOCH_SECS + 400 * DAY_SECS;
+        let dump = serde_json::json!({
+            "guild": { "id": "1" },
+            "roles": [
+                { "id": "1", "name": "@everyone" },
+                { "id": "2", "name": "mods" },
+                { "id": "3", "name": "old" },
+            ],
+            "channels": [
+                { "id": at(10).to_string(), "type": 4, "name": "lobby" },
+                { "id": at(11).to_string(), "type": 0, "name": "quiet",
+                  "parent_id": at(10).to_string(), "last_message_id": at(100).to_string() },
+                { "id": at(12).to_string(), "type": 0, "name": "busy",
+                  "last_message_id": at(390).to_string() },
+                { "id": at(13).to_string(), "type": 2, "name": "voice" },
+                { "id": at(14).to_string(), "type": 0, "name": "stale",
+                  "last_message_id": at(50).to_string() },
+            ],
+        });
+        let members = [serde_json::json!({ "id": "9", "roles": ["2"] })];
+        let criteria = Criteria {
+            inactive_days: 90,
+            now,
+            archive_category: None,
+            members: Some(&members),
+        };
+        let (planned, actions) = plan(&dump, &criteria);
+        let summary: Vec<String> = actions
+            .iter()
+            .map(|a| format!("{} {}: {}", a.kind, a.name, a.reason))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "channel quiet: no messages for 300 days",
+                "channel stale: no messages for 350 days",
+                "category lobby: no channels left",
+                "role old: no members",
+            ]
+        );
+        assert_eq!(actions[3].to_string(), "delete role old (3): no members");
+        assert_eq!(planned["channels"].as_array().unwrap().len(), 2);
+        assert_eq!(planned["roles"].as_array().unwrap().len(), 2);
+        assert_eq!(planned["prune"]["actions"].as_array().unwrap().len(), 4);
+
+        let archive = at(10).to_string();
+        let criteria = Criteria {
+            archive_category: Some(&archive),
+            members: None,
+            ..criteria
+        };
+        let (planned, actions) = plan(&dump, &criteria);
+        // `quiet` is archived already, and the archive category is never empty.
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].action, Proposal::Archive);
+        assert_eq!(planned["channels"][4]["parent_id"], archive);
+    }
+}
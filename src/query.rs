@@ -0,0 +1,125 @@
+//! [JMESPath](https://jmespath.org) queries over dumps for `format query`.
+//!
+//! A query runs against the whole document, or with `--records` against each record of an NDJSON
+//! file in turn (`{"record": "message", "channel_id": ..., "data": {...}}`), so a large archive is
+//! never held in memory at once.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use jmespath::Expression;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::CliError;
+use crate::format;
+use crate::ndjson;
+
+/// Arguments of `format query`, shared by the CLI and the MCP tool.
+#[derive(clap::Args, Debug, Deserialize)]
+pub struct QueryArgs {
+    /// Dump or upload file path.
+    #[arg(long, value_name = "PATH")]
+    pub r#in: PathBuf,
+
+    /// JMESPath expression, e.g. 'channels[?type==`0`].name'.
+    pub expression: String,
+
+    /// Run the expression on each record of an NDJSON file instead of the whole document, with
+    /// one result per record it matches.
+    #[arg(long)]
+    #[serde(default)]
+    pub records: bool,
+}
+
+/// The result of `args`: the expression's result on the document, or with `records` the
+/// non-null results of the records as an array.
+pub fn query(args: &QueryArgs) -> Result<Value, CliError> {
+    let expression = compile(&args.expression)?;
+    if args.records {
+        let mut results = Vec::new();
+        search_records(&expression, &args.r#in, |result| {
+            results.push(result);
+            Ok(())
+        })?;
+        return Ok(Value::Array(results));
+    }
+    search(&expression, &format::read_document(&args.r#in)?)
+}
+
+/// Compile `expression`, reporting syntax errors as usage errors.
+pub fn compile(expression: &str) -> Result<Expression<'static>, CliError> {
+    jmespath::compile(expression).map_err(|e| CliError::Usage(format!("query: {e}")))
+}
+
+/// The result of `expression` on `value`.
+pub fn search(expression: &Expression<'_>, value: &Value) -> Result<Value, CliError> {
+    let result = expression
+        .search(value)
+        .map_err(|e| CliError::Validation(format!("query: {e}")))?;
+    Ok(serde_json::to_value(&*result)?)
+}
+
+/// Run `expression` on each record of the NDJSON file at `path`, passing every non-null
+/// result to `matched`. Returns how many records matched.
+pub fn search_records(
+    expression: &Expression<'_>,
+    path: &Path,
+    mut matched: impl FnMut(Value) -> Result<(), CliError>,
+) -> Result<usize, CliError> {
+    if !ndjson::is_ndjson(path) {
+        return Err(CliError::Usage(format!(
+            "--records needs an NDJSON file; {} is one JSON document",
+            path.display()
+        )));
+    }
+    let mut count = 0;
+    ndjson::for_each_record(path, |value| {
+        let result = search(expression, &value)?;
+        if !result.is_null() {
+            count += 1;
+            matched(result)?;
+        }
+        Ok(())
+    })?;
+    Ok(count)
+}
+
+/// Write `result` as JSON (pretty unless one per line), or as bare text if `raw` and a string.
+pub fn write_result(
+    out: &mut dyn Write,
+    result: &Value,
+    raw: bool,
+    pretty: bool,
+) -> Result<(), CliError> {
+    match result {
+        Value::String(text) if raw => out.write_all(text.as_bytes())?,
+        _ if pretty => serde_json::to_writer_pretty(&mut *out, result)?,
+        _ => serde_json::to_writer(&mut *out, result)?,
+    }
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_and_projects_the_document() {
+        let document = serde_json::json!({ "channels": [
+            { "id": "1", "type": 0, "name": "general" },
+            { "id": "2", "type": 2, "name": "voice" },
+        ]});
+        let expression = compile("channels[?type==`0`].name").unwrap();
+        assert_eq!(
+            search(&expression, &document).unwrap(),
+            serde_json::json!(["general"])
+        );
+        assert!(matches!(compile("channels[?"), Err(CliError::Usage(_))));
+
+        let mut out = Vec::new();
+        write_result(&mut out, &serde_json::json!("general"), true, true).unwrap();
+        assert_eq!(out, b"general\n");
+    }
+}
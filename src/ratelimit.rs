@@ -0,0 +1,136 @@
+//! Client-side tracking of Discord's `X-RateLimit-*` headers.
+//!
+//! Requests are grouped into routes the way Discord buckets them (method plus path, keeping
+//! only the major `channels`/`guilds`/`webhooks` ids). Requests on one route are serialized, and
+//! one whose bucket is exhausted waits for the bucket to reset instead of provoking a 429. A
+//! global limit blocks every route until it lifts.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use tokio::sync::OwnedMutexGuard;
+use tokio::time::Instant;
+
+/// Path segments whose following id is a major parameter with its own buckets.
+const MAJOR_PARAMETERS: &[&str] = &["channels", "guilds", "webhooks"];
+
+/// What the last response on a route said about its bucket.
+#[derive(Debug, Default)]
+struct Bucket {
+    remaining: Option<u64>,
+    reset_at: Option<Instant>,
+}
+
+/// Rate-limit state shared by all requests of one client.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    routes: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Bucket>>>>,
+    global_until: Mutex<Option<Instant>>,
+}
+
+/// Exclusive use of a route for one request; report the response with [`Permit::update`].
+pub struct Permit<'a> {
+    limiter: &'a RateLimiter,
+    bucket: OwnedMutexGuard<Bucket>,
+}
+
+impl RateLimiter {
+    /// Wait until a request on `route` may be sent without exceeding a known limit.
+    pub async fn acquire(&self, route: &str) -> Permit<'_> {
+        let bucket = {
+            let mut routes = self.routes.lock().expect("rate limiter lock");
+            routes.entry(route.to_string()).or_default().clone()
+        };
+        let bucket = bucket.lock_owned().await;
+        let global = *self.global_until.lock().expect("rate limiter lock");
+        if let Some(until) = global {
+            wait_until(until, "global").await;
+        }
+        if bucket.remaining == Some(0)
+            && let Some(reset_at) = bucket.reset_at
+        {
+            wait_until(reset_at, route).await;
+        }
+        Permit {
+            limiter: self,
+            bucket,
+        }
+    }
+}
+
+impl Permit<'_> {
+    /// Record the rate-limit headers of the response (including a `429`).
+    pub fn update(mut self, headers: &HeaderMap) {
+        let reset_after = header(headers, "x-ratelimit-reset-after")
+            .and_then(|secs| secs.parse::<f64>().ok())
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+        if header(headers, "x-ratelimit-global") == Some("true") {
+            let retry_after = header(headers, "retry-after")
+                .and_then(|secs| secs.parse::<f64>().ok())
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+            if let Some(delay) = retry_after.or(reset_after) {
+                *self.limiter.global_until.lock().expect("rate limiter lock") =
+                    Some(Instant::now() + delay);
+            }
+            return;
+        }
+        if let Some(remaining) = header(headers, "x-ratelimit-remaining") {
+            self.bucket.remaining = remaining.parse().ok();
+        }
+        if let Some(delay) = reset_after {
+            self.bucket.reset_at = Some(Instant::now() + delay);
+        }
+    }
+}
+
+/// Bucket key for a request: method and path with minor ids and the query string erased,
+/// e.g. `DELETE /guilds/1/roles/{id}`.
+pub fn route(method: &str, path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    let mut previous = "";
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            let is_id = !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit());
+            let keep = !is_id || MAJOR_PARAMETERS.contains(&previous);
+            previous = segment;
+            if keep { segment } else { "{id}" }
+        })
+        .collect();
+    format!("{method} {}", segments.join("/"))
+}
+
+async fn wait_until(until: Instant, what: &str) {
+    let now = Instant::now();
+    if until > now {
+        tracing::debug!(
+            "rate limit on {what}; waiting {}ms",
+            (until - now).as_millis()
+        );
+        tokio::time::sleep_until(until).await;
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_keep_only_major_ids() {
+        assert_eq!(route("GET", "/guilds/1/roles"), "GET /guilds/1/roles");
+        assert_eq!(
+            route("PATCH", "/guilds/1/roles/22"),
+            "PATCH /guilds/1/roles/{id}"
+        );
+        assert_eq!(
+            route("GET", "/channels/6/messages?after=0&limit=100"),
+            "GET /channels/6/messages"
+        );
+    }
+}
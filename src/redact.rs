@@ -0,0 +1,133 @@
+//! PII redaction for sharing dumps: hashes identities and strips free text while
+//! keeping the structural fields `format validate` relies on.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Keys whose values identify a person; replaced with a stable pseudonym.
+const HASH_KEYS: &[&str] = &[
+    "user_id",
+    "author_id",
+    "owner_id",
+    "username",
+    "global_name",
+    "nick",
+    "display_name",
+];
+
+/// Keys whose values are free text or secrets; replaced with an empty string.
+const STRIP_KEYS: &[&str] = &["content", "email", "invite_code"];
+
+/// Objects describing a person; their `id` is hashed as well.
+const PERSON_KEYS: &[&str] = &["author", "user", "member", "owner"];
+
+/// Member name fields that `discord members --redact` hashes or drops.
+const NAME_KEYS: &[&str] = &["username", "global_name", "nick"];
+
+/// What `discord members --redact` does with member names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameRedaction {
+    /// Replace names with stable `anon-…` pseudonyms.
+    Hash,
+    /// Remove names.
+    Drop,
+}
+
+impl NameRedaction {
+    /// Hash or drop the username, display name, and nickname of `member`.
+    pub fn apply(self, member: &mut Value) {
+        let Value::Object(member) = member else {
+            return;
+        };
+        for key in NAME_KEYS {
+            match self {
+                NameRedaction::Hash => {
+                    if let Some(value) = member.get_mut(*key) {
+                        replace(value, pseudonym);
+                    }
+                }
+                NameRedaction::Drop => {
+                    member.shift_remove(*key);
+                }
+            }
+        }
+    }
+}
+
+/// Redact `value` in place, leaving keys listed in `keep` untouched.
+///
+/// Returns the number of fields changed.
+pub fn redact(value: &mut Value, keep: &[String]) -> usize {
+    walk(value, None, keep)
+}
+
+fn walk(value: &mut Value, parent: Option<&str>, keep: &[String]) -> usize {
+    match value {
+        Value::Object(map) => redact_object(map, parent, keep),
+        Value::Array(items) => items.iter_mut().map(|item| walk(item, parent, keep)).sum(),
+        _ => 0,
+    }
+}
+
+fn redact_object(map: &mut Map<String, Value>, parent: Option<&str>, keep: &[String]) -> usize {
+    let person = parent.is_some_and(|p| PERSON_KEYS.contains(&p));
+    let invite = parent == Some("invites");
+    let mut count = 0;
+
+    for (key, value) in map.iter_mut() {
+        if keep.iter().any(|k| k == key) {
+            continue;
+        }
+        let key = key.as_str();
+        if HASH_KEYS.contains(&key) || (person && key == "id") {
+            count += usize::from(replace(value, pseudonym));
+        } else if STRIP_KEYS.contains(&key) || (invite && key == "code") {
+            count += usize::from(replace(value, |_| String::new()));
+        } else {
+            count += walk(value, Some(key), keep);
+        }
+    }
+    count
+}
+
+/// Replace a scalar with `f(original)` as a string; nulls and containers are left as-is.
+fn replace(value: &mut Value, f: impl Fn(&str) -> String) -> bool {
+    let original = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return false,
+    };
+    *value = Value::String(f(&original));
+    true
+}
+
+/// Stable, non-reversible stand-in for an identifier: `anon-` + 12 hex chars of SHA-256.
+fn pseudonym(original: &str) -> String {
+    let digest = Sha256::digest(original.as_bytes());
+    let hex: String = digest[..6].iter().map(|b| format!("{b:02x}")).collect();
+    format!("anon-{hex}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn member_names_are_hashed_or_dropped() {
+        let member = serde_json::json!({
+            "id": "7", "username": "alice", "global_name": null, "nick": "Al", "roles": ["2"],
+        });
+        let mut hashed = member.clone();
+        NameRedaction::Hash.apply(&mut hashed);
+        assert_eq!(hashed["username"], pseudonym("alice"));
+        assert_eq!(hashed["global_name"], Value::Null);
+        assert_eq!(hashed["id"], "7");
+
+        let mut dropped = member;
+        NameRedaction::Drop.apply(&mut dropped);
+        assert_eq!(dropped, serde_json::json!({ "id": "7", "roles": ["2"] }));
+    }
+}
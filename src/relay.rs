@@ -0,0 +1,292 @@
+//! Relay of Discord channel messages into a terminal for `terminal send`.
+//!
+//! New messages of one channel arrive from the gateway (or by polling the channel) and are
+//! formatted with a template, then typed into a tmux pane or written to a FIFO, one line each.
+//! Message text is untrusted: line breaks become ` ↵ ` and other control characters (escape
+//! sequences included) are dropped, and Enter is only pressed with `--enter`, so a message
+//! cannot run commands in a shell pane on its own.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use crate::bridge::Echoes;
+use crate::discord::{self, Client, list, text};
+use crate::error::CliError;
+use crate::gateway::Sink;
+use crate::runner::Runner;
+use crate::timestamp;
+use crate::tmux;
+
+/// Template used without `--template` or `[terminal] send_template`.
+pub const DEFAULT_TEMPLATE: &str = "[{time}] {author}: {content}";
+
+/// Placeholders a template may use.
+const PLACEHOLDERS: &[&str] = &[
+    "author",
+    "author_id",
+    "channel_id",
+    "content",
+    "id",
+    "time",
+    "timestamp",
+    "attachments",
+];
+
+/// A message format such as `[{time}] {author}: {content}`.
+#[derive(Debug, Clone)]
+pub struct Template(String);
+
+impl Template {
+    /// Parse `text`, rejecting unknown or unclosed placeholders.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                return Err(format!("unclosed `{{` in template {text:?}"));
+            };
+            let name = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{name}}}; known: {}",
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{p}}}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(Self(text.to_string()))
+    }
+
+    /// One line for `message`, safe to type into a terminal.
+    pub fn render(&self, message: &Value) -> String {
+        let author = &message["author"];
+        let timestamp = message["timestamp"]
+            .as_str()
+            .and_then(timestamp::normalize)
+            .unwrap_or_default();
+        let attachments: Vec<&str> = list(&message["attachments"])
+            .filter_map(|a| a["filename"].as_str())
+            .collect();
+        let value = |name: &str| match name {
+            "author" => ["global_name", "username"]
+                .iter()
+                .find_map(|key| author[*key].as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            "author_id" => text(&author["id"]),
+            "channel_id" => text(&message["channel_id"]),
+            "content" => text(&message["content"]),
+            "id" => text(&message["id"]),
+            "time" => timestamp.get(11..16).unwrap_or_default().to_string(),
+            "timestamp" => timestamp.clone(),
+            "attachments" => attachments.join(", "),
+            _ => unreachable!("parse admits only known placeholders"),
+        };
+        // One pass over the template, so placeholders in message text stay as written.
+        let mut line = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = start + rest[start..].find('}').expect("parse checked braces");
+            line.push_str(&rest[..start]);
+            line.push_str(&value(&rest[start + 1..end]));
+            rest = &rest[end + 1..];
+        }
+        line.push_str(rest);
+        sanitize(&line)
+    }
+}
+
+/// `text` on one line without control characters: line breaks become ` ↵ `, tabs spaces.
+pub fn sanitize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' | '\u{2028}' | '\u{2029}' => out.push_str(" ↵ "),
+            '\t' => out.push(' '),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Where relayed lines go.
+#[derive(Debug)]
+pub enum Target {
+    /// A tmux pane (`session:window.pane`, `%3`, ...), typed into with `send-keys`.
+    Pane { target: String, enter: bool },
+    /// A named pipe (or any file), appended to one line at a time. It stays open between
+    /// lines and is reopened when its reader goes away.
+    Fifo {
+        path: PathBuf,
+        file: Option<tokio::fs::File>,
+    },
+}
+
+impl Target {
+    /// A FIFO target, opened on its first line (opening a FIFO waits for a reader).
+    pub fn fifo(path: PathBuf) -> Self {
+        Target::Fifo { path, file: None }
+    }
+
+    async fn send(&mut self, line: &str) -> Result<(), CliError> {
+        match self {
+            Target::Pane { target, enter } => tmux::send_keys(target, line, *enter).await,
+            Target::Fifo { path, file } => {
+                let line = format!("{line}\n");
+                loop {
+                    let fifo = match file {
+                        Some(fifo) => fifo,
+                        None => file.insert(
+                            tokio::fs::OpenOptions::new()
+                                .append(true)
+                                .open(&*path)
+                                .await?,
+                        ),
+                    };
+                    match fifo.write_all(line.as_bytes()).await {
+                        Ok(()) => return Ok(()),
+                        // The reader closed its end; wait for the next one.
+                        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => *file = None,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Counts reported when a relay stops.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// Messages written to the target.
+    pub relayed: u64,
+    /// Messages skipped because a bot wrote them.
+    pub skipped_bots: u64,
+    /// Messages skipped because their author is not allowed.
+    pub skipped_users: u64,
+    /// Messages whose code blocks were run instead of relayed.
+    pub ran: u64,
+}
+
+/// Relays new messages of one channel to a [`Target`].
+pub struct Relay {
+    pub channel: String,
+    pub template: Template,
+    pub target: Target,
+    pub include_bots: bool,
+    /// Authors (user IDs) whose messages are relayed; everyone's if empty.
+    pub allowed_users: Vec<String>,
+    /// The bot's own user ID, whose messages are never relayed.
+    pub self_id: Option<String>,
+    /// Where typed lines are recorded, for a [`crate::bridge`] capture to skip.
+    pub echoes: Option<Echoes>,
+    /// Runs the code blocks of messages in channels that opt in.
+    pub runner: Option<Runner>,
+    pub stats: Stats,
+}
+
+impl Relay {
+    /// Relay `message` if it belongs to the channel and is not from a skipped bot.
+    async fn message(&mut self, message: &Value) -> Result<bool, CliError> {
+        if text(&message["channel_id"]) != self.channel {
+            return Ok(false);
+        }
+        let author = text(&message["author"]["id"]);
+        let own = self.self_id.as_ref() == Some(&author);
+        if own || (message["author"]["bot"] == true && !self.include_bots) {
+            self.stats.skipped_bots += 1;
+            return Ok(false);
+        }
+        if !self.allowed_users.is_empty() && !self.allowed_users.contains(&author) {
+            self.stats.skipped_users += 1;
+            return Ok(false);
+        }
+        if let Some(runner) = &self.runner {
+            let blocks = runner.runnable(&self.channel, &author, &text(&message["content"]));
+            if !blocks.is_empty() {
+                let id = text(&message["id"]);
+                // A failed run is reported, not fatal to the relay.
+                if let Err(err) = runner.run_and_reply(&self.channel, &id, blocks).await {
+                    tracing::warn!("running the code of message {id}: {err}");
+                }
+                self.stats.ran += 1;
+                return Ok(true);
+            }
+        }
+        let line = self.template.render(message);
+        if let Some(echoes) = &self.echoes {
+            echoes.record(&line);
+        }
+        self.target.send(&line).await?;
+        self.stats.relayed += 1;
+        Ok(true)
+    }
+
+    /// Poll the channel every `interval` and relay messages newer than the latest one at the
+    /// start, until `stop` completes.
+    pub async fn poll(
+        &mut self,
+        client: &Client,
+        interval: Duration,
+        stop: impl Future<Output = ()>,
+    ) -> Result<(), CliError> {
+        let path = format!("/channels/{}/messages?limit=1", self.channel);
+        let latest = client.get(&path).await?;
+        let mut after = list(&latest).map(discord::snowflake).max().unwrap_or(0);
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep(interval) => {}
+                () = &mut stop => return Ok(()),
+            }
+            for mut message in discord::fetch_messages(client, &self.channel, after, None).await? {
+                after = after.max(discord::snowflake(&message));
+                if message["channel_id"].is_null() {
+                    message["channel_id"] = self.channel.clone().into();
+                }
+                self.message(&message).await?;
+            }
+        }
+    }
+}
+
+impl Sink for Relay {
+    async fn deliver(&mut self, record: &Value) -> Result<bool, CliError> {
+        if record["type"] != "MESSAGE_CREATE" {
+            return Ok(false);
+        }
+        self.message(&record["data"]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_render_one_safe_line() {
+        let message = serde_json::json!({
+            "id": "5", "channel_id": "20",
+            "author": { "id": "7", "username": "alice" },
+            "timestamp": "2025-01-31T23:30:00-01:00",
+            "content": "ls\r\nrm -rf / \u{1b}[31mred\u{7} {author}",
+        });
+        let template = Template::parse(DEFAULT_TEMPLATE).unwrap();
+        assert_eq!(
+            template.render(&message),
+            "[00:30] alice: ls ↵ rm -rf / [31mred {author}"
+        );
+        assert!(Template::parse("{author} {nope}").is_err());
+        assert!(Template::parse("{author").is_err());
+    }
+}
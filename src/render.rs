@@ -0,0 +1,371 @@
+//! Human-readable transcripts of a channel's messages for `format render`.
+//!
+//! A transcript lists each message with its author, time, the message it replies to, its
+//! content with user, role, and channel mentions resolved to names, its embeds, and links to its
+//! attachments. HTML output is a single self-contained page; Markdown keeps message content as
+//! written, since Discord formats it as Markdown already.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use clap::ValueEnum;
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+use crate::discord::{list, text};
+use crate::error::CliError;
+use crate::timestamp;
+
+/// Output format of a transcript.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Style {
+    Html,
+    Markdown,
+}
+
+impl Style {
+    /// The style a destination's extension implies: `.html`/`.htm` or `.md`/`.markdown`.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "html" | "htm" => Some(Style::Html),
+            "md" | "markdown" => Some(Style::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Longest excerpt of a replied-to message.
+const EXCERPT_CHARS: usize = 80;
+
+/// Render the messages of channel or thread `channel_id` in `document`, returning the
+/// transcript and the number of messages in it.
+pub fn render(
+    document: &Value,
+    channel_id: &str,
+    style: Style,
+) -> Result<(String, usize), CliError> {
+    let channel = ["channels", "threads"]
+        .iter()
+        .flat_map(|section| list(&document[*section]))
+        .find(|channel| text(&channel["id"]) == channel_id)
+        .ok_or_else(|| {
+            CliError::NotFound(format!("no channel or thread {channel_id} in the file"))
+        })?;
+    let mut messages: Vec<&Value> = list(&channel["messages"]).collect();
+    messages.sort_by_key(|message| text(&message["id"]).parse::<u64>().unwrap_or(0));
+
+    let names = Names::new(document, &messages);
+    let by_id: HashMap<String, &Value> = messages
+        .iter()
+        .map(|message| (text(&message["id"]), *message))
+        .collect();
+    let title = format!(
+        "#{} ({})",
+        text_field(channel, "name").unwrap_or_else(|| channel_id.to_string()),
+        document["guild"]["name"].as_str().unwrap_or("guild")
+    );
+    let mut out = match style {
+        Style::Html => html_header(&title),
+        Style::Markdown => format!("# {title}\n\n"),
+    };
+    for message in &messages {
+        let reply = message["message_reference"]["message_id"]
+            .as_str()
+            .map(|id| match by_id.get(id) {
+                Some(original) => format!(
+                    "replying to {}: {}",
+                    names.author(original),
+                    excerpt(&names.content(original))
+                ),
+                None => "replying to a message not in this file".to_string(),
+            });
+        match style {
+            Style::Html => html_message(&mut out, message, reply, &names),
+            Style::Markdown => markdown_message(&mut out, message, reply, &names),
+        }
+    }
+    if style == Style::Html {
+        out.push_str("</main>\n</body>\n</html>\n");
+    }
+    Ok((out, messages.len()))
+}
+
+/// Display names of the users, roles, and channels mentions can refer to.
+struct Names {
+    users: HashMap<String, String>,
+    roles: HashMap<String, String>,
+    channels: HashMap<String, String>,
+    mention: Regex,
+}
+
+impl Names {
+    fn new(document: &Value, messages: &[&Value]) -> Self {
+        let mut users = HashMap::new();
+        for message in messages {
+            let people = std::iter::once(&message["author"]).chain(list(&message["mentions"]));
+            for user in people.filter(|user| user.is_object()) {
+                users.insert(text(&user["id"]), display_name(user));
+            }
+        }
+        let names = |sections: &[&str]| {
+            sections
+                .iter()
+                .flat_map(|section| list(&document[*section]))
+                .map(|item| (text(&item["id"]), text(&item["name"])))
+                .collect()
+        };
+        Names {
+            users,
+            roles: names(&["roles"]),
+            channels: names(&["channels", "threads"]),
+            mention: Regex::new(r"<(@!?|@&|#)(\d+)>").expect("valid pattern"),
+        }
+    }
+
+    fn author(&self, message: &Value) -> String {
+        display_name(&message["author"])
+    }
+
+    /// `message`'s content with mentions replaced by `@name` and `#channel`.
+    fn content(&self, message: &Value) -> String {
+        let content = message["content"].as_str().unwrap_or_default();
+        self.mention
+            .replace_all(content, |caps: &Captures| {
+                let (kind, id) = (&caps[1], &caps[2]);
+                let (names, sigil) = match kind {
+                    "@&" => (&self.roles, "@"),
+                    "#" => (&self.channels, "#"),
+                    _ => (&self.users, "@"),
+                };
+                match names.get(id) {
+                    Some(name) => format!("{sigil}{name}"),
+                    None => format!("{sigil}{id}"),
+                }
+            })
+            .into_owned()
+    }
+}
+
+fn html_header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 50rem; margin: 2rem auto; color: #222; }}\n\
+         .message {{ margin: 0 0 1rem; }}\n\
+         .meta {{ color: #666; font-size: 0.85rem; }}\n\
+         .author {{ font-weight: bold; color: #222; }}\n\
+         .reply {{ color: #666; font-size: 0.85rem; border-left: 2px solid #ccc; padding-left: 0.5rem; }}\n\
+         .content {{ white-space: pre-wrap; }}\n\
+         .embed {{ border-left: 4px solid #5865f2; background: #f4f4f6; padding: 0.5rem; margin: 0.25rem 0; }}\n\
+         </style>\n</head>\n<body>\n<main>\n<h1>{title}</h1>\n",
+        title = escape(title)
+    )
+}
+
+fn html_message(out: &mut String, message: &Value, reply: Option<String>, names: &Names) {
+    out.push_str(&format!(
+        "<article class=\"message\" id=\"m{}\">\n",
+        escape(&text(&message["id"]))
+    ));
+    if let Some(reply) = reply {
+        out.push_str(&format!("<div class=\"reply\">{}</div>\n", escape(&reply)));
+    }
+    out.push_str(&format!(
+        "<div class=\"meta\"><span class=\"author\">{}</span> {}</div>\n",
+        escape(&names.author(message)),
+        escape(&when(message))
+    ));
+    let content = names.content(message);
+    if !content.is_empty() {
+        out.push_str(&format!(
+            "<div class=\"content\">{}</div>\n",
+            escape(&content)
+        ));
+    }
+    for embed in list(&message["embeds"]) {
+        out.push_str("<div class=\"embed\">");
+        let title = text_field(embed, "title");
+        match (title, embed["url"].as_str().filter(|url| is_web(url))) {
+            (Some(title), Some(url)) => out.push_str(&format!(
+                "<a href=\"{}\"><strong>{}</strong></a>",
+                escape(url),
+                escape(&title)
+            )),
+            (Some(title), None) => out.push_str(&format!("<strong>{}</strong>", escape(&title))),
+            (None, Some(url)) => {
+                out.push_str(&format!("<a href=\"{}\">{}</a>", escape(url), escape(url)))
+            }
+            (None, None) => {}
+        }
+        if let Some(description) = text_field(embed, "description") {
+            out.push_str(&format!(
+                "<div class=\"content\">{}</div>",
+                escape(&description)
+            ));
+        }
+        for field in list(&embed["fields"]) {
+            out.push_str(&format!(
+                "<div><strong>{}</strong>: {}</div>",
+                escape(&text(&field["name"])),
+                escape(&text(&field["value"]))
+            ));
+        }
+        out.push_str("</div>\n");
+    }
+    for attachment in list(&message["attachments"]) {
+        let name = escape(&text(&attachment["filename"]));
+        match attachment["url"].as_str().filter(|url| is_web(url)) {
+            Some(url) => out.push_str(&format!(
+                "<div class=\"attachment\">📎 <a href=\"{}\">{name}</a></div>\n",
+                escape(url)
+            )),
+            None => out.push_str(&format!("<div class=\"attachment\">📎 {name}</div>\n")),
+        }
+    }
+    out.push_str("</article>\n");
+}
+
+fn markdown_message(out: &mut String, message: &Value, reply: Option<String>, names: &Names) {
+    if let Some(reply) = reply {
+        out.push_str(&format!("> ↪ {reply}\n\n"));
+    }
+    out.push_str(&format!(
+        "**{}** · {}\n\n",
+        names.author(message),
+        when(message)
+    ));
+    let content = names.content(message);
+    if !content.is_empty() {
+        out.push_str(&content);
+        out.push_str("\n\n");
+    }
+    for embed in list(&message["embeds"]) {
+        let title = text_field(embed, "title");
+        match (title, embed["url"].as_str().filter(|url| is_web(url))) {
+            (Some(title), Some(url)) => out.push_str(&format!("> **[{title}]({url})**\n")),
+            (Some(title), None) => out.push_str(&format!("> **{title}**\n")),
+            (None, Some(url)) => out.push_str(&format!("> <{url}>\n")),
+            (None, None) => {}
+        }
+        if let Some(description) = text_field(embed, "description") {
+            for line in description.lines() {
+                out.push_str(&format!("> {line}\n"));
+            }
+        }
+        for field in list(&embed["fields"]) {
+            out.push_str(&format!(
+                "> **{}**: {}\n",
+                text(&field["name"]),
+                text(&field["value"])
+            ));
+        }
+        out.push('\n');
+    }
+    for attachment in list(&message["attachments"]) {
+        let name = text(&attachment["filename"]);
+        match attachment["url"].as_str().filter(|url| is_web(url)) {
+            Some(url) => out.push_str(&format!("📎 [{name}]({url})\n\n")),
+            None => out.push_str(&format!("📎 {name}\n\n")),
+        }
+    }
+    out.push_str("---\n\n");
+}
+
+/// When `message` was sent, in UTC to the minute, noting an edit.
+fn when(message: &Value) -> String {
+    let sent = message["timestamp"]
+        .as_str()
+        .map(readable_time)
+        .unwrap_or_default();
+    match message["edited_timestamp"].as_str() {
+        Some(edited) => format!("{sent} (edited {})", readable_time(edited)),
+        None => sent,
+    }
+}
+
+/// `2025-01-31T12:00:00.123+00:00` as `2025-01-31 12:00 UTC`; other text as it is.
+fn readable_time(text: &str) -> String {
+    match timestamp::normalize(text) {
+        Some(utc) => format!("{} {} UTC", &utc[..10], &utc[11..16]),
+        None => text.to_string(),
+    }
+}
+
+/// The name a user is shown with: their global display name, else their username.
+fn display_name(user: &Value) -> String {
+    user["global_name"]
+        .as_str()
+        .or(user["username"].as_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+fn excerpt(content: &str) -> String {
+    let line = content.lines().next().unwrap_or_default();
+    if line.chars().count() > EXCERPT_CHARS || content.lines().nth(1).is_some() {
+        format!("{}…", line.chars().take(EXCERPT_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Whether `url` is safe to link from a page: `http` or `https`, not `javascript:` and the like.
+fn is_web(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.starts_with("https://") || lower.starts_with("http://")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn text_field(value: &Value, key: &str) -> Option<String> {
+    value[key]
+        .as_str()
+        .filter(|s| !s.trim().is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcripts_resolve_mentions_and_replies() {
+        let alice = serde_json::json!({ "id": "7", "username": "alice", "global_name": "Alice" });
+        let document = serde_json::json!({
+            "guild": { "id": "1", "name": "Guild" },
+            "roles": [{ "id": "3", "name": "mods" }],
+            "channels": [{ "id": "20", "name": "general", "type": 0, "messages": [
+                { "id": "99", "author": { "id": "8", "username": "bob" },
+                  "timestamp": "2025-01-31T12:05:00.000000+00:00",
+                  "content": "<@7> <@&3> see <#20> & <b>", "message_reference": { "message_id": "98" },
+                  "attachments": [{ "id": "5", "filename": "log.txt", "url": "https://cdn.example/log.txt" }] },
+                { "id": "98", "author": alice, "timestamp": "2025-01-31T12:00:00.000000+00:00",
+                  "content": "hello", "embeds": [{ "title": "Docs", "url": "https://example.com" }] },
+            ]}],
+        });
+
+        let (markdown, count) = render(&document, "20", Style::Markdown).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            markdown,
+            "# #general (Guild)\n\n\
+             **Alice** · 2025-01-31 12:00 UTC\n\nhello\n\n> **[Docs](https://example.com)**\n\n---\n\n\
+             > ↪ replying to Alice: hello\n\n\
+             **bob** · 2025-01-31 12:05 UTC\n\n@Alice @mods see #general & <b>\n\n\
+             📎 [log.txt](https://cdn.example/log.txt)\n\n---\n\n"
+        );
+
+        let (html, _) = render(&document, "20", Style::Html).unwrap();
+        assert!(html.contains("@Alice @mods see #general &amp; &lt;b&gt;"));
+        assert!(matches!(
+            render(&document, "21", Style::Html),
+            Err(CliError::NotFound(_))
+        ));
+    }
+}
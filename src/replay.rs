@@ -0,0 +1,209 @@
+//! Message replay for `discord import --replay-messages`.
+//!
+//! A bot cannot post as other users, so each channel's archived messages are re-sent through a
+//! temporary webhook under their original author's name and avatar. Only channels the import
+//! creates are replayed into: channels that already exist keep their own history, so running an
+//! import again never posts it twice.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::discord::{Client, list, text};
+use crate::error::CliError;
+use crate::import::{Kind, Op, Step};
+
+/// Channel types that carry messages and accept webhooks: text, voice, announcement, and stage.
+const WEBHOOK_CHANNEL_TYPES: &[u64] = &[0, 2, 5, 13];
+
+/// Message types that are replayed: plain messages and replies. Joins, pins, boosts, and other
+/// system notices are not.
+const REPLAYED_TYPES: &[u64] = &[0, 19];
+
+/// Name of the temporary webhooks.
+const WEBHOOK_NAME: &str = "guildsync replay";
+
+/// Longest webhook username Discord accepts.
+const MAX_USERNAME: usize = 80;
+
+/// Messages to replay into one channel.
+#[derive(Debug)]
+pub struct Pending<'a> {
+    /// Channel id in the import file.
+    pub channel: String,
+    /// Oldest first.
+    pub messages: Vec<&'a Value>,
+}
+
+/// Counts reported after [`replay`].
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub channels: usize,
+    pub messages: usize,
+}
+
+/// The messages of `document` that replaying would post: those in channels that `steps` create,
+/// skipping system messages and messages with nothing to show.
+pub fn pending<'a>(document: &'a Value, steps: &[Step]) -> Vec<Pending<'a>> {
+    let created = |id: &str| {
+        steps
+            .iter()
+            .any(|s| s.op == Op::Create && s.kind == Kind::Channel && s.id == id)
+    };
+    document["channels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|channel| {
+            channel["type"]
+                .as_u64()
+                .is_some_and(|t| WEBHOOK_CHANNEL_TYPES.contains(&t))
+        })
+        .map(|channel| Pending {
+            channel: text(&channel["id"]),
+            messages: list(&channel["messages"])
+                .filter(|m| {
+                    m["type"]
+                        .as_u64()
+                        .is_none_or(|t| REPLAYED_TYPES.contains(&t))
+                })
+                .filter(|m| !body(m, "").is_null())
+                .collect(),
+        })
+        .filter(|pending| !pending.messages.is_empty() && created(&pending.channel))
+        .collect()
+}
+
+/// Post `pending` through one temporary webhook per channel, waiting `interval` between
+/// messages, and delete the webhook afterwards (also when a message fails).
+///
+/// `ids` maps the file's channel ids to the ones Discord assigned on import; `cdn` is the CDN
+/// base for author avatars. `progress` advances once per message.
+pub async fn replay(
+    client: &Client,
+    pending: &[Pending<'_>],
+    ids: &HashMap<String, String>,
+    cdn: &str,
+    interval: Duration,
+    progress: &indicatif::ProgressBar,
+) -> Result<Stats, CliError> {
+    let cdn = cdn.trim_end_matches('/');
+    let mut stats = Stats::default();
+    for channel in pending {
+        let live = ids.get(&channel.channel).unwrap_or(&channel.channel);
+        progress.set_message(format!("channel {live}"));
+        let webhook = client
+            .post(
+                &format!("/channels/{live}/webhooks"),
+                &serde_json::json!({ "name": WEBHOOK_NAME }),
+            )
+            .await?;
+        let (id, token) = (text(&webhook["id"]), text(&webhook["token"]));
+        let mut posted = Ok(());
+        for (i, message) in channel.messages.iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(interval).await;
+            }
+            let path = format!("/webhooks/{id}/{token}?wait=true");
+            if let Err(err) = client.post(&path, &body(message, cdn)).await {
+                tracing::error!(
+                    "channel {live}: message {} failed after {i} of {}",
+                    text(&message["id"]),
+                    channel.messages.len()
+                );
+                posted = Err(err);
+                break;
+            }
+            stats.messages += 1;
+            progress.inc(1);
+        }
+        // The webhook (and its token, which error messages may show) is gone either way.
+        if let Err(err) = client.delete(&format!("/webhooks/{id}")).await {
+            tracing::warn!("channel {live}: webhook {id} not deleted: {err}");
+        }
+        posted?;
+        stats.channels += 1;
+    }
+    Ok(stats)
+}
+
+/// Webhook execution body re-sending `message` as its author, or `null` if it has no content,
+/// attachments, or rich embeds. Attachments are linked by URL, and mentions do not ping.
+fn body(message: &Value, cdn: &str) -> Value {
+    let mut content = message["content"].as_str().unwrap_or_default().to_string();
+    for url in list(&message["attachments"]).filter_map(|a| a["url"].as_str()) {
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(url);
+    }
+    // Link previews are regenerated by Discord; only bot-made rich embeds are sent again.
+    let embeds: Vec<&Value> = list(&message["embeds"])
+        .filter(|embed| embed["type"] == "rich")
+        .collect();
+    if content.is_empty() && embeds.is_empty() {
+        return Value::Null;
+    }
+
+    let author = &message["author"];
+    let name = author["global_name"]
+        .as_str()
+        .or(author["username"].as_str())
+        .unwrap_or("unknown");
+    let mut body = serde_json::json!({
+        "username": name.chars().take(MAX_USERNAME).collect::<String>(),
+        "content": content,
+        "allowed_mentions": { "parse": [] },
+    });
+    if !embeds.is_empty() {
+        body["embeds"] = embeds.into_iter().cloned().collect();
+    }
+    if let Some(avatar) = author["avatar"].as_str() {
+        body["avatar_url"] = format!("{cdn}/avatars/{}/{avatar}.png", text(&author["id"])).into();
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import;
+
+    #[test]
+    fn replays_as_the_author_into_created_channels_only() {
+        let message = |id: &str, kind: u64, content: &str| {
+            serde_json::json!({
+                "id": id, "type": kind, "content": content,
+                "author": { "id": "7", "username": "alice", "global_name": null, "avatar": "a1" },
+            })
+        };
+        let document = serde_json::json!({ "channels": [
+            { "id": "80", "type": 0, "messages": [
+                message("1", 0, "hi @everyone"),
+                message("2", 7, ""),
+                message("3", 0, ""),
+            ]},
+            { "id": "81", "type": 0, "messages": [message("4", 0, "kept")] },
+        ]});
+        let steps = import::plan(
+            &serde_json::json!({ "guild": { "id": "1" }, "channels": [{ "id": "81", "type": 0 }] }),
+            &document,
+        );
+        let pending = pending(&document, &steps);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].channel, "80");
+        assert_eq!(pending[0].messages.len(), 1);
+
+        assert_eq!(
+            body(pending[0].messages[0], "https://cdn.example"),
+            serde_json::json!({
+                "username": "alice",
+                "content": "hi @everyone",
+                "allowed_mentions": { "parse": [] },
+                "avatar_url": "https://cdn.example/avatars/7/a1.png",
+            })
+        );
+    }
+}
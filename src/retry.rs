@@ -0,0 +1,167 @@
+use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+use crate::error::CliError;
+
+/// Upper bound for a single backoff delay, regardless of attempt count.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Exponential backoff settings for idempotent network operations.
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; `0` disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on each subsequent one.
+    pub base: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt` (0-based), with jitter in `[d/2, d]`.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(MAX_DELAY);
+        let half = delay / 2;
+        half + jitter(half)
+    }
+}
+
+/// Run `op`, retrying retryable failures with exponential backoff.
+///
+/// A `Retry-After` hint carried by the error takes precedence over the computed
+/// backoff. Non-retryable errors (auth, not found, ...) are returned immediately.
+pub async fn with_backoff<T, F, Fut>(
+    policy: &RetryPolicy,
+    action: &str,
+    op: F,
+) -> Result<T, CliError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, CliError>>,
+{
+    with_backoff_if(policy, action, CliError::is_retryable, op).await
+}
+
+/// [`with_backoff`], retrying only the failures `retryable` accepts, e.g. rate limits alone
+/// for a request that is not safe to repeat.
+pub async fn with_backoff_if<T, F, Fut>(
+    policy: &RetryPolicy,
+    action: &str,
+    retryable: impl Fn(&CliError) -> bool,
+    mut op: F,
+) -> Result<T, CliError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, CliError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(err) if retryable(&err) && attempt < policy.max_retries => {
+                let delay = err.retry_after().unwrap_or_else(|| policy.backoff(attempt));
+                attempt += 1;
+                tracing::warn!(
+                    "{action}: {err}; retry {attempt}/{} in {}ms",
+                    policy.max_retries,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Uniformly distributed duration in `[0, max]`.
+pub(crate) fn jitter(max: Duration) -> Duration {
+    let nanos = max.as_nanos() as u64;
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_nanos(RandomState::new().hash_one(nanos) % (nanos + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base: Duration::from_millis(100),
+        };
+        for attempt in 0..5 {
+            let full = Duration::from_millis(100 << attempt);
+            let delay = policy.backoff(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{attempt}: {delay:?}");
+        }
+        for attempt in [10, 31, u32::MAX] {
+            let delay = policy.backoff(attempt);
+            assert!(
+                delay >= MAX_DELAY / 2 && delay <= MAX_DELAY,
+                "{attempt}: {delay:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn non_retryable_errors_fail_at_once() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base: Duration::from_secs(30),
+        };
+        let mut calls = 0;
+        let started = Instant::now();
+        let result: Result<(), CliError> = with_backoff(&policy, "test", || {
+            calls += 1;
+            async { Err(CliError::Auth("bad token".to_string())) }
+        })
+        .await;
+        assert!(matches!(result, Err(CliError::Auth(_))));
+        assert_eq!(calls, 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn retry_after_beats_the_computed_delay() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base: Duration::from_secs(30),
+        };
+        let mut calls = 0;
+        let started = Instant::now();
+        let result = with_backoff(&policy, "test", || {
+            calls += 1;
+            let result = if calls < 3 {
+                Err(CliError::RateLimited {
+                    retry_after: Some(Duration::from_millis(1)),
+                })
+            } else {
+                Ok(calls)
+            };
+            async move { result }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // Past `max_retries` the last error is returned.
+        let mut calls = 0;
+        let result: Result<(), CliError> = with_backoff(&policy, "test", || {
+            calls += 1;
+            async {
+                Err(CliError::RateLimited {
+                    retry_after: Some(Duration::from_millis(1)),
+                })
+            }
+        })
+        .await;
+        assert!(matches!(result, Err(CliError::RateLimited { .. })));
+        assert_eq!(calls, 3);
+    }
+}
@@ -0,0 +1,567 @@
+//! Running code blocks of bridged messages for `terminal bridge`, in channels that opt in.
+//!
+//! A message's fenced code blocks tagged with an allowed language are run one by one, each
+//! with its code on stdin, and the output is posted as a reply to the message. The `process`
+//! backend runs the interpreter in an empty temporary directory with a bare environment, in
+//! its own process group, under `ulimit` CPU, memory, and file size limits; the `docker`
+//! backend runs it in a throwaway container without network access, with memory, CPU, and
+//! process limits and a read-only root. Either way the run is killed at the time limit.
+//!
+//! Only messages of allowed authors are run, and a runner is not built without an allowlist.
+//! The `process` backend does not sandbox: code still reads the host's files and reaches its
+//! network as the bridge's user, so prefer `docker` wherever untrusted users are allowed.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::capture;
+use crate::discord::Client;
+use crate::error::CliError;
+
+/// An interpreter code blocks can be run with.
+pub struct Language {
+    /// Fence tags naming it; the first is its name in `[terminal.runner] languages`.
+    pub tags: &'static [&'static str],
+    /// Command reading the program from stdin.
+    pub command: &'static [&'static str],
+    /// Image for the `docker` backend.
+    pub image: &'static str,
+}
+
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        tags: &["python", "py", "python3"],
+        command: &["python3", "-I", "-"],
+        image: "python:3-alpine",
+    },
+    Language {
+        tags: &["sh", "shell"],
+        command: &["sh", "-s"],
+        image: "alpine:3",
+    },
+    Language {
+        tags: &["bash"],
+        command: &["bash", "-s"],
+        image: "bash:5",
+    },
+    Language {
+        tags: &["javascript", "js", "node"],
+        command: &["node", "-"],
+        image: "node:lts-alpine",
+    },
+    Language {
+        tags: &["ruby", "rb"],
+        command: &["ruby", "-"],
+        image: "ruby:alpine",
+    },
+];
+
+/// The language fence tag `tag` names, ignoring case.
+pub fn language(tag: &str) -> Option<&'static Language> {
+    let tag = tag.to_ascii_lowercase();
+    LANGUAGES.iter().find(|l| l.tags.contains(&tag.as_str()))
+}
+
+/// Where code runs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A local subprocess under `ulimit` limits, with the host's filesystem and network.
+    #[default]
+    Process,
+    /// A `docker run` container without network.
+    Docker,
+}
+
+/// `[terminal.runner]` section: off unless `channels` lists some.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RunnerConfig {
+    /// Channel IDs whose code blocks are run.
+    pub channels: Vec<u64>,
+    /// Languages allowed to run, by name (`python`, `sh`, `bash`, `javascript`, `ruby`).
+    pub languages: Vec<String>,
+    pub backend: Backend,
+    /// Wall-clock limit of a run.
+    pub timeout_secs: u64,
+    /// CPU time limit of a run.
+    pub cpu_secs: u64,
+    pub memory_mb: u64,
+    /// Output kept per stream; the rest is cut.
+    pub max_output_bytes: usize,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            languages: Vec::new(),
+            backend: Backend::Process,
+            timeout_secs: 10,
+            cpu_secs: 5,
+            memory_mb: 256,
+            max_output_bytes: 8000,
+        }
+    }
+}
+
+impl RunnerConfig {
+    /// Check every allowed language is known.
+    pub fn validate(&self) -> Result<(), String> {
+        for name in &self.languages {
+            if !LANGUAGES.iter().any(|l| l.tags[0] == name) {
+                let known: Vec<&str> = LANGUAGES.iter().map(|l| l.tags[0]).collect();
+                return Err(format!(
+                    "terminal.runner.languages: unknown language {name:?}; known: {}",
+                    known.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A fenced code block of a message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    /// The fence's tag, possibly empty.
+    pub tag: String,
+    pub code: String,
+}
+
+/// The fenced (```` ``` ````) code blocks of `content`; an unclosed fence is not a block.
+pub fn code_blocks(content: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let Some(end) = after.find("```") else {
+            break;
+        };
+        let inner = &after[..end];
+        // Discord takes the first line as the language only if more lines follow.
+        let (tag, code) = match inner.split_once('\n') {
+            Some((tag, code)) if !tag.contains(char::is_whitespace) => (tag, code),
+            _ => ("", inner),
+        };
+        blocks.push(Block {
+            tag: tag.to_string(),
+            code: code.trim_end_matches('\n').to_string(),
+        });
+        rest = &after[end + 3..];
+    }
+    blocks
+}
+
+/// How one run went.
+#[derive(Debug, Default)]
+pub struct Run {
+    pub language: &'static str,
+    /// Exit status; `None` if killed by a signal or the time limit.
+    pub status: Option<i32>,
+    /// Signal that killed it, such as `SIGXCPU` at the CPU limit.
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub elapsed: Duration,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether output beyond `max_output_bytes` was cut.
+    pub truncated: bool,
+}
+
+impl Run {
+    /// Reply messages: a summary line, then stdout and stderr as code blocks.
+    pub fn reply(&self, max_messages: usize) -> Vec<String> {
+        let summary = match (self.timed_out, self.status) {
+            (true, _) => format!(
+                "`{}` timed out after {:.1}s",
+                self.language,
+                self.elapsed.as_secs_f64()
+            ),
+            (false, Some(status)) => format!(
+                "`{}` exited {status} in {:.1}s",
+                self.language,
+                self.elapsed.as_secs_f64()
+            ),
+            (false, None) => {
+                let reason = match self.signal {
+                    Some(SIGXCPU) => " at the CPU time limit".to_string(),
+                    Some(SIGXFSZ) => " at the file size limit".to_string(),
+                    Some(signal) => format!(" by signal {signal}"),
+                    None => String::new(),
+                };
+                format!("`{}` was killed{reason}", self.language)
+            }
+        };
+        let summary = if self.truncated {
+            format!("{summary} (output cut)")
+        } else {
+            summary
+        };
+        let mut messages = vec![summary];
+        for (label, output) in [("", &self.stdout), ("stderr", &self.stderr)] {
+            let lines: Vec<String> = output.lines().map(capture::strip_ansi).collect();
+            if lines.is_empty() {
+                continue;
+            }
+            let room = max_messages.saturating_sub(messages.len()).max(1);
+            let (blocks, _) = capture::code_blocks(&lines, "", room);
+            for block in blocks {
+                let last = messages.last_mut().expect("the summary");
+                let block = match label {
+                    "" => block,
+                    label => format!("{label}:\n{block}"),
+                };
+                if last.chars().count() + 1 + block.chars().count() <= capture::MESSAGE_LIMIT {
+                    last.push('\n');
+                    last.push_str(&block);
+                } else if messages.len() < max_messages {
+                    messages.push(block);
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// Runs the code blocks of messages in opted-in channels and replies with their output.
+pub struct Runner {
+    pub config: RunnerConfig,
+    pub client: Arc<Client>,
+    /// Authors (user IDs) whose code is run; nobody's if empty.
+    pub allowed_users: Vec<String>,
+    /// Replies posted per message at most.
+    pub max_messages: usize,
+}
+
+/// Signals sent at the `ulimit -t` and `ulimit -f` limits (Linux and the BSDs agree).
+const SIGXCPU: i32 = 24;
+const SIGXFSZ: i32 = 25;
+
+/// Runs started by this process, naming their directories and containers.
+static RUNS: AtomicU64 = AtomicU64::new(0);
+
+impl Runner {
+    /// The blocks of a message to run: by an allowed author in an opted-in channel, tagged
+    /// with an allowed language.
+    pub fn runnable(
+        &self,
+        channel: &str,
+        author: &str,
+        content: &str,
+    ) -> Vec<(&'static Language, String)> {
+        if !self.allowed_users.iter().any(|user| user == author)
+            || !self
+                .config
+                .channels
+                .iter()
+                .any(|c| c.to_string() == channel)
+        {
+            return Vec::new();
+        }
+        code_blocks(content)
+            .into_iter()
+            .filter_map(|block| {
+                let language = language(&block.tag)?;
+                self.config
+                    .languages
+                    .iter()
+                    .any(|name| name == language.tags[0])
+                    .then_some((language, block.code))
+            })
+            .collect()
+    }
+
+    /// Run `blocks` of message `message` in `channel` and reply with each one's output.
+    pub async fn run_and_reply(
+        &self,
+        channel: &str,
+        message: &str,
+        blocks: Vec<(&'static Language, String)>,
+    ) -> Result<(), CliError> {
+        let path = format!("/channels/{channel}/messages");
+        for (language, code) in blocks {
+            let run = self.run(language, &code).await?;
+            for content in run.reply(self.max_messages) {
+                let body = serde_json::json!({
+                    "content": content,
+                    "message_reference": { "message_id": message, "fail_if_not_exists": false },
+                    "allowed_mentions": { "parse": [], "replied_user": false },
+                });
+                self.client.post(&path, &body).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `code` with `language` under the configured limits.
+    pub async fn run(&self, language: &'static Language, code: &str) -> Result<Run, CliError> {
+        let id = format!(
+            "guildsync-run-{}-{}",
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        );
+        let config = &self.config;
+        let dir = std::env::temp_dir().join(&id);
+        // Removed however the run ends, including the early returns below.
+        let _work_dir = match config.backend {
+            Backend::Process => Some(WorkDir::create(&dir)?),
+            Backend::Docker => None,
+        };
+        let mut command = match config.backend {
+            Backend::Process => {
+                // Limits are set by the shell that then becomes the interpreter, so they hold
+                // for it and everything it starts.
+                let mut command = tokio::process::Command::new("sh");
+                command
+                    .args([
+                        "-c",
+                        r#"ulimit -t "$1" && ulimit -v "$2" && ulimit -f "$3" || exit 125; shift 3; exec "$@""#,
+                        "sh",
+                    ])
+                    .arg(config.cpu_secs.max(1).to_string())
+                    .arg((config.memory_mb.max(1) * 1024).to_string())
+                    // File sizes in 512-byte blocks: 16 MiB.
+                    .arg("32768")
+                    .args(language.command)
+                    .current_dir(&dir)
+                    .env_clear()
+                    .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+                    .env("HOME", &dir)
+                    .env("TMPDIR", &dir)
+                    .env("LANG", "C.UTF-8");
+                #[cfg(unix)]
+                command.process_group(0);
+                command
+            }
+            Backend::Docker => {
+                let memory = format!("{}m", config.memory_mb.max(1));
+                let mut command = tokio::process::Command::new("docker");
+                command
+                    .args(["run", "--rm", "-i", "--name", &id, "--network", "none"])
+                    .args(["--memory", &memory, "--memory-swap", &memory])
+                    .args(["--cpus", "1", "--pids-limit", "64", "--read-only"])
+                    .args(["--tmpfs", "/tmp:size=16m", "--workdir", "/tmp"])
+                    .arg(format!("--ulimit=cpu={}", config.cpu_secs.max(1)))
+                    .arg(language.image)
+                    .args(language.command);
+                command
+            }
+        };
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let started = Instant::now();
+        let mut child = command
+            .spawn()
+            .map_err(|err| spawn_error(config.backend, language, err))?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let code = format!("{code}\n");
+        let limit = config.max_output_bytes;
+        let stdout = read_limited(child.stdout.take().expect("piped stdout"), limit);
+        let stderr = read_limited(child.stderr.take().expect("piped stderr"), limit);
+        let feed = async move {
+            // A program that exits without reading all of its code closes the pipe early.
+            let _ = stdin.write_all(code.as_bytes()).await;
+        };
+        let timeout = Duration::from_secs(config.timeout_secs.max(1));
+        let waited = tokio::time::timeout(timeout, async {
+            let ((), stdout, stderr, status) = tokio::join!(feed, stdout, stderr, child.wait());
+            (stdout, stderr, status)
+        })
+        .await;
+        let run = match waited {
+            Ok((stdout, stderr, status)) => {
+                let (stdout, cut_out) = stdout?;
+                let (stderr, cut_err) = stderr?;
+                let status = status?;
+                #[cfg(unix)]
+                let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+                #[cfg(not(unix))]
+                let signal = None;
+                Run {
+                    language: language.tags[0],
+                    status: status.code(),
+                    signal,
+                    timed_out: false,
+                    elapsed: started.elapsed(),
+                    stdout,
+                    stderr,
+                    truncated: cut_out || cut_err,
+                }
+            }
+            Err(_) => {
+                self.kill(&id, &mut child).await;
+                Run {
+                    language: language.tags[0],
+                    timed_out: true,
+                    elapsed: started.elapsed(),
+                    ..Run::default()
+                }
+            }
+        };
+        Ok(run)
+    }
+
+    /// Stop a run that hit the time limit, with whatever it started.
+    async fn kill(&self, id: &str, child: &mut tokio::process::Child) {
+        let quiet = |program: &str, args: &[&str]| {
+            let mut command = tokio::process::Command::new(program);
+            command
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            command
+        };
+        match self.config.backend {
+            Backend::Process => {
+                if let Some(pid) = child.id() {
+                    let _ = quiet("kill", &["-KILL", "--", &format!("-{pid}")])
+                        .status()
+                        .await;
+                }
+            }
+            Backend::Docker => {
+                let _ = quiet("docker", &["kill", id]).status().await;
+            }
+        }
+        let _ = child.kill().await;
+    }
+}
+
+/// Working directory of a process-backend run, removed with everything in it on drop.
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    fn create(path: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(path)?;
+        Ok(Self(path.to_path_buf()))
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Up to `limit` bytes of `stream` as text, and whether more was cut. Reading goes on to the
+/// end so the program does not block on a full pipe.
+async fn read_limited(
+    mut stream: impl tokio::io::AsyncRead + Unpin,
+    limit: usize,
+) -> Result<(String, bool), CliError> {
+    let mut kept = Vec::new();
+    let mut cut = false;
+    let mut buf = [0; 8192];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let room = limit.saturating_sub(kept.len());
+        kept.extend_from_slice(&buf[..n.min(room)]);
+        cut |= n > room;
+    }
+    Ok((String::from_utf8_lossy(&kept).into_owned(), cut))
+}
+
+fn spawn_error(backend: Backend, language: &Language, err: std::io::Error) -> CliError {
+    let program = match backend {
+        Backend::Process => "sh",
+        Backend::Docker => "docker",
+    };
+    if err.kind() == std::io::ErrorKind::NotFound {
+        CliError::NotFound(format!(
+            "{program} is not installed or not on PATH; needed to run {} code",
+            language.tags[0]
+        ))
+    } else {
+        CliError::Io(std::io::Error::new(err.kind(), format!("{program}: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fenced_blocks_are_found_and_replies_summarize_runs() {
+        let content = "run this:\n```py\nprint(1)\n```\nand ```inline``` and ```sh\necho 2\n```\n```bash\nopen";
+        assert_eq!(
+            code_blocks(content),
+            [
+                Block {
+                    tag: "py".to_string(),
+                    code: "print(1)".to_string()
+                },
+                Block {
+                    tag: String::new(),
+                    code: "inline".to_string()
+                },
+                Block {
+                    tag: "sh".to_string(),
+                    code: "echo 2".to_string()
+                },
+            ]
+        );
+        assert_eq!(language("PY").map(|l| l.tags[0]), Some("python"));
+        assert!(language("cobol").is_none());
+
+        let run = Run {
+            language: "python",
+            status: Some(1),
+            elapsed: Duration::from_millis(300),
+            stdout: "1\n".to_string(),
+            stderr: "Traceback\n".to_string(),
+            ..Run::default()
+        };
+        assert_eq!(
+            run.reply(3),
+            ["`python` exited 1 in 0.3s\n```\n1\n```\nstderr:\n```\nTraceback\n```"]
+        );
+    }
+
+    #[test]
+    fn only_allowed_authors_code_is_run() {
+        let policy = crate::retry::RetryPolicy {
+            max_retries: 0,
+            base: Duration::from_millis(1),
+        };
+        let client = Client::new("http://127.0.0.1:9", String::new(), policy, "test").unwrap();
+        let mut runner = Runner {
+            config: RunnerConfig {
+                channels: vec![20],
+                languages: vec!["python".to_string()],
+                ..RunnerConfig::default()
+            },
+            client: Arc::new(client),
+            allowed_users: vec!["7".to_string()],
+            max_messages: 3,
+        };
+        let content = "```py\nprint(1)\n```";
+        assert_eq!(runner.runnable("20", "7", content).len(), 1);
+        assert!(runner.runnable("20", "8", content).is_empty());
+        assert!(runner.runnable("21", "7", content).is_empty());
+        runner.allowed_users.clear();
+        assert!(runner.runnable("20", "7", content).is_empty());
+    }
+
+    #[test]
+    fn work_dirs_are_removed_on_drop() {
+        let path = std::env::temp_dir().join(format!("guildsync-run-test-{}", std::process::id()));
+        let dir = WorkDir::create(&path).unwrap();
+        std::fs::write(path.join("main.py"), "print(1)").unwrap();
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,324 @@
+//! CronJobs for `kube schedule export`: run `discord export` for a guild on a cron schedule,
+//! keeping the dump on a PersistentVolumeClaim or uploading a dated copy to S3-compatible
+//! object storage.
+//!
+//! Manifests are rendered like those of `kube remote deploy` (see [`crate::deploy`]), from
+//! built-in templates with quoted `{var}` values, and read the bot token from the same Secret.
+
+use std::collections::BTreeMap;
+
+use crate::deploy::{self, Manifest};
+use crate::error::CliError;
+
+/// Where the dumps go on the claim.
+const DUMPS_DIR: &str = "/dumps";
+
+/// Longest CronJob name: the controller appends an 11-character suffix to name its Jobs, which
+/// are limited to 63.
+const MAX_NAME_LEN: usize = 52;
+
+/// Export into one dump per guild on the claim, merging new messages into it each run.
+const CLAIM_CRONJOB: &str = r#"apiVersion: batch/v1
+kind: CronJob
+metadata:
+  name: {name}
+  namespace: {namespace}
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/instance: {name}
+    app.kubernetes.io/component: scheduled-export
+spec:
+  schedule: {cron}
+  timeZone: {time_zone}
+  concurrencyPolicy: Forbid
+  successfulJobsHistoryLimit: 3
+  failedJobsHistoryLimit: 3
+  jobTemplate:
+    spec:
+      backoffLimit: 2
+      template:
+        metadata:
+          labels:
+            app.kubernetes.io/name: guildsync
+            app.kubernetes.io/instance: {name}
+            app.kubernetes.io/component: scheduled-export
+        spec:
+          restartPolicy: Never
+          containers:
+            - name: export
+              image: {image}
+              args: ["discord", "export", "--guild", {guild}, "--out", {out}, "--incremental"]
+              env:
+                - name: DISCORD_TOKEN
+                  valueFrom:
+                    secretKeyRef:
+                      name: {secret}
+                      key: {key}
+              volumeMounts:
+                - name: dumps
+                  mountPath: {dumps_dir}
+          volumes:
+            - name: dumps
+              persistentVolumeClaim:
+                claimName: {claim}
+"#;
+
+const CLAIM: &str = r#"apiVersion: v1
+kind: PersistentVolumeClaim
+metadata:
+  name: {claim}
+  namespace: {namespace}
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/instance: {name}
+spec:
+  accessModes: ["ReadWriteOnce"]
+  resources:
+    requests:
+      storage: {size}
+"#;
+
+/// Export a full dump in an init container, then upload it as `<url>/<guild>/<UTC time>.json`
+/// with the AWS CLI, which reads its credentials (and `AWS_ENDPOINT_URL` for other providers)
+/// from `{s3_secret}`.
+const S3_CRONJOB: &str = r#"apiVersion: batch/v1
+kind: CronJob
+metadata:
+  name: {name}
+  namespace: {namespace}
+  labels:
+    app.kubernetes.io/name: guildsync
+    app.kubernetes.io/instance: {name}
+    app.kubernetes.io/component: scheduled-export
+spec:
+  schedule: {cron}
+  timeZone: {time_zone}
+  concurrencyPolicy: Forbid
+  successfulJobsHistoryLimit: 3
+  failedJobsHistoryLimit: 3
+  jobTemplate:
+    spec:
+      backoffLimit: 2
+      template:
+        metadata:
+          labels:
+            app.kubernetes.io/name: guildsync
+            app.kubernetes.io/instance: {name}
+            app.kubernetes.io/component: scheduled-export
+        spec:
+          restartPolicy: Never
+          initContainers:
+            - name: export
+              image: {image}
+              args: ["discord", "export", "--guild", {guild}, "--out", "/work/dump.json"]
+              env:
+                - name: DISCORD_TOKEN
+                  valueFrom:
+                    secretKeyRef:
+                      name: {secret}
+                      key: {key}
+              volumeMounts:
+                - name: work
+                  mountPath: /work
+          containers:
+            - name: upload
+              image: {uploader_image}
+              command: ["sh", "-c", 'aws s3 cp /work/dump.json "$DESTINATION/$(date -u +%Y%m%dT%H%M%SZ).json"']
+              env:
+                - name: DESTINATION
+                  value: {destination}
+              envFrom:
+                - secretRef:
+                    name: {s3_secret}
+              volumeMounts:
+                - name: work
+                  mountPath: /work
+                  readOnly: true
+          volumes:
+            - name: work
+              emptyDir: {}
+"#;
+
+/// Where a scheduled export keeps its dumps.
+#[derive(Debug)]
+pub enum Destination<'a> {
+    /// `<guild>.json` on this claim, created with `size` if given (else it must exist).
+    Claim {
+        name: &'a str,
+        size: Option<&'a str>,
+    },
+    /// Dated copies under `url` (`s3://bucket/prefix`), uploaded by `image` with the
+    /// credentials in Secret `secret`.
+    S3 {
+        url: &'a str,
+        secret: &'a str,
+        image: &'a str,
+    },
+}
+
+/// A `kube schedule export` CronJob.
+#[derive(Debug)]
+pub struct ScheduledExport<'a> {
+    pub name: &'a str,
+    pub namespace: &'a str,
+    pub image: &'a str,
+    pub guild: u64,
+    pub cron: &'a str,
+    pub time_zone: &'a str,
+    /// Secret and key holding the bot token.
+    pub secret: &'a str,
+    pub key: &'a str,
+    pub destination: Destination<'a>,
+}
+
+impl ScheduledExport<'_> {
+    /// The CronJob, preceded by the claim it creates, if any.
+    pub fn manifests(&self) -> Result<Vec<Manifest>, CliError> {
+        let mut vars: BTreeMap<String, String> = [
+            ("name", self.name.to_string()),
+            ("namespace", self.namespace.to_string()),
+            ("image", self.image.to_string()),
+            ("guild", self.guild.to_string()),
+            ("cron", self.cron.to_string()),
+            ("time_zone", self.time_zone.to_string()),
+            ("secret", self.secret.to_string()),
+            ("key", self.key.to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let mut templates = Vec::new();
+        match &self.destination {
+            Destination::Claim { name, size } => {
+                vars.insert("claim".to_string(), name.to_string());
+                vars.insert("dumps_dir".to_string(), DUMPS_DIR.to_string());
+                vars.insert(
+                    "out".to_string(),
+                    format!("{DUMPS_DIR}/{}.json", self.guild),
+                );
+                if let Some(size) = size {
+                    vars.insert("size".to_string(), size.to_string());
+                    templates.push(("claim.yaml", CLAIM));
+                }
+                templates.push(("cronjob.yaml", CLAIM_CRONJOB));
+            }
+            Destination::S3 { url, secret, image } => {
+                let url = url.trim_end_matches('/');
+                vars.insert("destination".to_string(), format!("{url}/{}", self.guild));
+                vars.insert("s3_secret".to_string(), secret.to_string());
+                vars.insert("uploader_image".to_string(), image.to_string());
+                templates.push(("cronjob.yaml", S3_CRONJOB));
+            }
+        }
+        let mut manifests = Vec::new();
+        for (file, template) in templates {
+            manifests.extend(deploy::parse(file, &deploy::substitute(template, &vars))?);
+        }
+        Ok(manifests)
+    }
+}
+
+/// Check a CronJob name: a DNS label of at most 52 characters.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{name}` is not a valid CronJob name: use 1-{MAX_NAME_LEN} lowercase letters, \
+             digits, and inner '-'"
+        ))
+    }
+}
+
+/// Check the shape of a cron schedule: five fields (minute, hour, day of month, month, day of
+/// week) or a macro like `@daily`. Ranges are left for the API server to check.
+pub fn validate_cron(expr: &str) -> Result<(), String> {
+    const MACROS: &[&str] = &[
+        "@yearly",
+        "@annually",
+        "@monthly",
+        "@weekly",
+        "@daily",
+        "@midnight",
+        "@hourly",
+    ];
+    let fields: Vec<&str> = expr.split_whitespace().collect();
+    let valid = match fields.as_slice() {
+        [macro_name] if macro_name.starts_with('@') => MACROS.contains(macro_name),
+        fields => {
+            fields.len() == 5
+                && fields.iter().all(|field| {
+                    field
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "*/,-?".contains(c))
+                })
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{expr}` is not a cron schedule: expected five fields like \"0 3 * * *\" or a \
+             macro like @daily"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_claim_and_s3_cronjobs() {
+        let mut export = ScheduledExport {
+            name: "guildsync-export-42",
+            namespace: "bots",
+            image: "example/guildsync:1",
+            guild: 42,
+            cron: "0 3 * * *",
+            time_zone: "Etc/UTC",
+            secret: "guildsync-token",
+            key: "BOT_TOKEN",
+            destination: Destination::Claim {
+                name: "dumps",
+                size: Some("1Gi"),
+            },
+        };
+        let manifests = export.manifests().unwrap();
+        let kinds: Vec<_> = manifests.iter().map(|m| m.kind.as_str()).collect();
+        assert_eq!(kinds, ["PersistentVolumeClaim", "CronJob"]);
+        let cronjob: serde_yaml::Value = serde_yaml::from_str(&manifests[1].yaml).unwrap();
+        assert_eq!(cronjob["spec"]["schedule"].as_str(), Some("0 3 * * *"));
+        let pod = &cronjob["spec"]["jobTemplate"]["spec"]["template"]["spec"];
+        assert_eq!(
+            pod["containers"][0]["args"][5].as_str(),
+            Some("/dumps/42.json")
+        );
+
+        export.destination = Destination::S3 {
+            url: "s3://bucket/guilds/",
+            secret: "s3",
+            image: "amazon/aws-cli",
+        };
+        let manifests = export.manifests().unwrap();
+        assert_eq!(manifests.len(), 1);
+        let cronjob: serde_yaml::Value = serde_yaml::from_str(&manifests[0].yaml).unwrap();
+        let upload = &cronjob["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(
+            upload["env"][0]["value"].as_str(),
+            Some("s3://bucket/guilds/42")
+        );
+
+        assert!(validate_cron("*/15 3-5 * * MON-FRI").is_ok());
+        assert!(validate_cron("@daily").is_ok());
+        assert!(validate_cron("0 3 * *").is_err());
+        assert!(validate_name(&"x".repeat(53)).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! JSON Schemas of the dump and upload formats, one per format version, embedded from
+//! `schemas/`.
+//!
+//! Both formats share a schema that leaves `format` open; [`schema`] pins it, so the document
+//! printed by `format schema` describes exactly one format. Checks a schema cannot express, such
+//! as a thread's parent being a channel of the same file, stay in [`crate::format`].
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::format::GuildFormat;
+
+/// Schema text by format version.
+const SCHEMAS: &[(u64, &str)] = &[(1, include_str!("../schemas/guild.v1.json"))];
+
+/// One place where a document breaks its schema.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// JSON Pointer to the offending value (empty for the whole document).
+    pub pointer: String,
+    /// Stable, machine-readable kind of problem: `schema.<keyword>` for the JSON Schema keyword
+    /// that failed, or a name such as `thread-parent` for checks beyond the schema.
+    pub code: String,
+    pub message: String,
+    /// Details some codes carry, such as `expected` and `found` of `version-mismatch`.
+    #[serde(flatten)]
+    pub data: serde_json::Map<String, Value>,
+}
+
+impl Violation {
+    pub fn new(pointer: impl Into<String>, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            pointer: pointer.into(),
+            code: code.to_string(),
+            message: message.into(),
+            data: serde_json::Map::new(),
+        }
+    }
+
+    /// This violation with the detail `key` set to `value`.
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.data.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() {
+            "/"
+        } else {
+            &self.pointer
+        };
+        write!(f, "{pointer}: {} [{}]", self.message, self.code)
+    }
+}
+
+/// Format versions that have a schema, listed for messages (e.g. `1, 2`).
+pub fn versions() -> String {
+    let versions: Vec<String> = SCHEMAS.iter().map(|(v, _)| v.to_string()).collect();
+    versions.join(", ")
+}
+
+/// The schema of `format` at `version`, if there is one.
+pub fn schema(format: GuildFormat, version: u64) -> Option<Value> {
+    let (_, text) = SCHEMAS.iter().find(|(v, _)| *v == version)?;
+    let mut schema: Value = serde_json::from_str(text).expect("embedded schemas are valid JSON");
+    schema["title"] = format!("guildsync {format} file, version {version}").into();
+    schema["properties"]["format"] = serde_json::json!({ "const": format.to_string() });
+    Some(schema)
+}
+
+/// The part of `schema` at `pointer` (e.g. `/properties/roles/items`) as a schema of its own,
+/// for checking the items of a file one at a time.
+pub fn subschema(schema: &Value, pointer: &str) -> Option<Value> {
+    let mut part = schema.pointer(pointer)?.clone();
+    part["$schema"] = schema["$schema"].clone();
+    part["$defs"] = schema["$defs"].clone();
+    Some(part)
+}
+
+/// A compiled schema, for checking many documents against it.
+pub struct Checker(jsonschema::Validator);
+
+impl Checker {
+    pub fn new(schema: &Value) -> Self {
+        Self(jsonschema::validator_for(schema).expect("embedded schemas compile"))
+    }
+
+    /// Every violation in `document`, with pointers prefixed by `at`, the document's place in
+    /// the file it came from.
+    pub fn check(&self, document: &Value, at: &str) -> Vec<Violation> {
+        self.0
+            .iter_errors(document)
+            .map(|error| Violation {
+                pointer: format!("{at}{}", error.instance_path()),
+                code: format!("schema.{}", error.kind().keyword()),
+                message: error.to_string(),
+                data: serde_json::Map::new(),
+            })
+            .collect()
+    }
+}
+
+/// Every violation of `schema` in `document`, in document order.
+pub fn check(schema: &Value, document: &Value) -> Vec<Violation> {
+    let mut violations = Checker::new(schema).check(document, "");
+    violations.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn violations_are_reported_together_with_their_pointers() {
+        let schema = schema(GuildFormat::Upload, 1).unwrap();
+        let document = serde_json::json!({
+            "format": "dump",
+            "version": 1,
+            "guild": { "id": "1" },
+            "channels": "none",
+            "threads": [{ "id": "30", "type": 0 }],
+        });
+        let found: Vec<(String, String)> = check(&schema, &document)
+            .into_iter()
+            .map(|v| (v.pointer, v.code))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("/channels".to_string(), "schema.type".to_string()),
+                ("/format".to_string(), "schema.const".to_string()),
+                ("/threads/0/type".to_string(), "schema.enum".to_string()),
+            ]
+        );
+        assert!(super::schema(GuildFormat::Dump, 2).is_none());
+    }
+}
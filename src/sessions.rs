@@ -0,0 +1,251 @@
+//! Agent sessions managed by `terminal opencode start`, `stop`, `list`, and `status`.
+//!
+//! `start` runs OpenCode, Codex, or another agent in a new tmux session (see [`crate::layout`])
+//! and records the session, its agent pane, and the agent's PID in a local state file. Health
+//! is read back from tmux: the agent pane is kept when its process exits, so a session is
+//! running, exited (with the exit status), or missing (the session or pane was closed outside
+//! guildsync).
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file::write_atomic;
+use crate::config::expand_tilde;
+use crate::error::CliError;
+use crate::layout::{Layout, Programs};
+use crate::timestamp;
+use crate::tmux;
+
+/// An agent session started by guildsync.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// tmux session name.
+    pub name: String,
+    /// Command the agent pane runs.
+    pub agent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    pub dir: PathBuf,
+    /// The agent pane (`%N`).
+    pub pane: String,
+    /// PID of the agent process when it was started.
+    pub pid: u32,
+    /// When it was started (RFC 3339).
+    pub started_at: String,
+}
+
+/// Sessions recorded by `start`, persisted at [`default_path`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+}
+
+impl State {
+    /// Load the state from `path`; a missing file has no sessions.
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| CliError::Config(format!("session state {}: {e}", path.display())))
+    }
+
+    /// Write the state atomically, creating its directory.
+    pub fn save(&self, path: &Path) -> Result<(), CliError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut bytes = serde_json::to_vec_pretty(self)?;
+        bytes.push(b'\n');
+        write_atomic(path, &bytes)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Session> {
+        self.sessions.iter().find(|s| s.name == name)
+    }
+
+    /// Record `session`, replacing one of the same name.
+    pub fn insert(&mut self, session: Session) {
+        self.remove(&session.name);
+        self.sessions.push(session);
+        self.sessions.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Session> {
+        let index = self.sessions.iter().position(|s| s.name == name)?;
+        Some(self.sessions.remove(index))
+    }
+}
+
+/// `$XDG_STATE_HOME/guildsync/sessions.json`, falling back to
+/// `~/.local/state/guildsync/sessions.json`.
+pub fn default_path() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| expand_tilde(Path::new("~/.local/state")))
+        .join("guildsync")
+        .join("sessions.json")
+}
+
+/// How a managed session is doing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// The agent process is alive.
+    Running,
+    /// The agent exited; its pane is kept with the exit status.
+    Exited,
+    /// The tmux session or the agent pane is gone.
+    Missing,
+}
+
+impl std::fmt::Display for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Health::Running => "running",
+            Health::Exited => "exited",
+            Health::Missing => "missing",
+        })
+    }
+}
+
+/// A session with its health.
+#[derive(Debug, Serialize)]
+pub struct Status {
+    #[serde(flatten)]
+    pub session: Session,
+    pub health: Health,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+}
+
+impl Status {
+    /// One line for `list` and `status`.
+    pub fn line(&self) -> String {
+        let health = match self.exit_status {
+            Some(status) => format!("{} ({status})", self.health),
+            None => self.health.to_string(),
+        };
+        format!(
+            "{:<16} {:<12} pid {:<8} {} in {} since {}",
+            self.session.name,
+            health,
+            self.session.pid,
+            self.session.agent,
+            self.session.dir.display(),
+            self.session.started_at
+        )
+    }
+}
+
+/// Start `agent` in a new session `name` laid out as `layout`, whose first `{agent}` pane runs
+/// it; the caller checks the name is free.
+pub async fn start(
+    name: &str,
+    dir: &Path,
+    agent: &str,
+    interpreter: Option<&str>,
+    layout: &Layout,
+) -> Result<Session, CliError> {
+    let mut plan = layout.plan(dir, Programs { agent, interpreter });
+    let Some((w, p)) = plan.iter().enumerate().find_map(|(w, window)| {
+        let p = window.panes.iter().position(|pane| pane.agent)?;
+        Some((w, p))
+    }) else {
+        return Err(CliError::Config(
+            "the layout has no pane running {agent}".to_string(),
+        ));
+    };
+    // The agent pane starts as a shell and is respawned with the agent once it is set to stay
+    // open, so even an agent that exits at once leaves its status behind.
+    let planned = &mut plan[w].panes[p];
+    planned.command = tmux::pane_command(None);
+    let planned = planned.clone();
+    let ids = tmux::create_session(name, &plan).await?;
+    let pane = ids[w][p].clone();
+    tmux::respawn_pane(&pane, &planned.dir, &planned.env, agent).await?;
+    let pid = tmux::panes(name)
+        .await?
+        .into_iter()
+        .find(|p| p.id == pane)
+        .map_or(0, |p| p.pid);
+    Ok(Session {
+        name: name.to_string(),
+        agent: agent.to_string(),
+        interpreter: interpreter.map(str::to_string),
+        dir: dir.to_path_buf(),
+        pane,
+        pid,
+        started_at: timestamp::now_rfc3339(),
+    })
+}
+
+/// Read `session`'s health from tmux.
+pub async fn status(session: &Session) -> Result<Status, CliError> {
+    let pane = if tmux::has_session(&session.name).await? {
+        tmux::panes(&session.name)
+            .await?
+            .into_iter()
+            .find(|p| p.id == session.pane)
+    } else {
+        None
+    };
+    Ok(Status {
+        session: session.clone(),
+        health: health(pane.as_ref()),
+        exit_status: pane.and_then(|p| p.dead_status),
+    })
+}
+
+fn health(pane: Option<&tmux::Pane>) -> Health {
+    match pane {
+        None => Health::Missing,
+        Some(pane) if pane.dead => Health::Exited,
+        Some(_) => Health::Running,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_replaces_sessions_by_name_and_health_follows_the_pane() {
+        let session = |name: &str, pid| Session {
+            name: name.to_string(),
+            agent: "opencode".to_string(),
+            interpreter: None,
+            dir: PathBuf::from("/work"),
+            pane: "%1".to_string(),
+            pid,
+            started_at: "2025-01-31T12:00:00Z".to_string(),
+        };
+        let mut state = State::default();
+        state.insert(session("b", 1));
+        state.insert(session("a", 2));
+        state.insert(session("b", 3));
+        let names: Vec<_> = state
+            .sessions
+            .iter()
+            .map(|s| (s.name.as_str(), s.pid))
+            .collect();
+        assert_eq!(names, [("a", 2), ("b", 3)]);
+        assert_eq!(state.remove("a").map(|s| s.pid), Some(2));
+        assert!(state.get("a").is_none());
+
+        let pane = |dead| tmux::Pane {
+            id: "%1".to_string(),
+            pid: 3,
+            dead,
+            dead_status: dead.then_some(1),
+        };
+        assert_eq!(health(Some(&pane(false))), Health::Running);
+        assert_eq!(health(Some(&pane(true))), Health::Exited);
+        assert_eq!(health(None), Health::Missing);
+    }
+}
@@ -0,0 +1,468 @@
+//! The interactive shell of `guildsync shell`.
+//!
+//! Each line is a guildsync command line without the leading `guildsync`, run in the same
+//! process, so the config and the resolved Discord token are loaded once. A few builtins set
+//! context for later commands: `open` picks a dump that commands taking `--in` read when it is
+//! not given, and `use` a guild for commands taking `--guild`. Tab completes subcommands, flags,
+//! and, once a dump is open, the guild, channel, role, and user IDs it holds.
+
+use std::path::{Path, PathBuf};
+
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::{Helper, Highlighter, Hinter, Validator};
+use serde_json::Value;
+
+use crate::config::expand_tilde;
+use crate::discord::list;
+
+/// Builtins' usage and description, for completion and `help`.
+pub const BUILTINS: &[(&str, &str)] = &[
+    (
+        "open <PATH>",
+        "read a dump; commands taking --in use it by default",
+    ),
+    ("close", "forget the open dump"),
+    (
+        "use <GUILD>",
+        "default --guild for later commands (`use -` clears it)",
+    ),
+    ("context", "show the open dump and guild"),
+    ("help", "this list; `<command> --help` for a command"),
+    ("exit", "leave the shell (also `quit` or Ctrl-D)"),
+];
+
+/// What later commands default to.
+#[derive(Debug, Default)]
+pub struct Context {
+    /// Dump opened with `open`.
+    pub dump: Option<PathBuf>,
+    /// Guild picked with `use`.
+    pub guild: Option<String>,
+}
+
+impl Context {
+    /// Prompt showing the context, e.g. `guildsync (123, guild.json)> `.
+    pub fn prompt(&self) -> String {
+        let dump = self
+            .dump
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned());
+        let parts: Vec<String> = self.guild.iter().cloned().chain(dump).collect();
+        if parts.is_empty() {
+            "guildsync> ".to_string()
+        } else {
+            format!("guildsync ({})> ", parts.join(", "))
+        }
+    }
+
+    /// `words`, the command line for `root`'s subcommand, with `--in` and `--guild` added from
+    /// the context when the subcommand takes them and neither they nor a flag conflicting with
+    /// them were given.
+    pub fn apply(&self, root: &clap::Command, mut words: Vec<String>) -> Vec<String> {
+        let (command, _) = subcommand(root, &words);
+        let given = |long: &str| {
+            words
+                .iter()
+                .take_while(|w| *w != "--")
+                .any(|w| w == &format!("--{long}") || w.starts_with(&format!("--{long}=")))
+        };
+        let mut extra = Vec::new();
+        let defaults = [
+            ("in", self.dump.as_ref().map(|p| p.display().to_string())),
+            ("guild", self.guild.clone()),
+        ];
+        for (long, value) in defaults {
+            let Some(value) = value else { continue };
+            let Some(arg) = command.get_arguments().find(|a| a.get_long() == Some(long)) else {
+                continue;
+            };
+            let conflicting = command
+                .get_arg_conflicts_with(arg)
+                .iter()
+                .filter_map(|a| a.get_long())
+                .any(&given);
+            if !given(long) && !conflicting {
+                extra.extend([format!("--{long}"), value]);
+            }
+        }
+        // Before a `--` that starts a trailing command (`ssh exec -- ...`).
+        let at = words.iter().position(|w| w == "--").unwrap_or(words.len());
+        words.splice(at..at, extra);
+        words
+    }
+}
+
+/// A builtin command line.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Builtin {
+    Open(PathBuf),
+    Close,
+    Use(Option<String>),
+    Context,
+    Help,
+    Exit,
+}
+
+impl Builtin {
+    /// The builtin `words` calls, `None` for guildsync commands.
+    pub fn parse(words: &[String]) -> Option<Result<Self, String>> {
+        let args = &words[1..];
+        let usage = |name: &str| {
+            let (usage, _) = BUILTINS
+                .iter()
+                .find(|(usage, _)| usage.split(' ').next() == Some(name))
+                .expect("a builtin");
+            Err(format!("usage: {usage}"))
+        };
+        Some(match (words.first()?.as_str(), args) {
+            ("open", [path]) => Ok(Builtin::Open(expand_tilde(Path::new(path)))),
+            ("open", _) => usage("open"),
+            ("close", []) => Ok(Builtin::Close),
+            ("use", [guild]) if guild == "-" => Ok(Builtin::Use(None)),
+            ("use", [guild]) if guild.parse::<u64>().is_ok() => {
+                Ok(Builtin::Use(Some(guild.clone())))
+            }
+            ("use", _) => usage("use"),
+            ("context", []) => Ok(Builtin::Context),
+            ("help", []) => Ok(Builtin::Help),
+            ("exit" | "quit", []) => Ok(Builtin::Exit),
+            ("close" | "context" | "help" | "exit" | "quit", _) => {
+                Err(format!("{} takes no arguments", words[0]))
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Split `line` into words like a POSIX shell: whitespace separates words, single quotes keep
+/// text as is, and double quotes and backslashes escape. No expansions happen.
+pub fn split(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => return Err("unclosed double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_default().push(c),
+                None => return Err("line ends with a backslash".to_string()),
+            },
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The deepest subcommand of `root` that `words` name, and how many words named it.
+fn subcommand<'a>(root: &'a clap::Command, words: &[String]) -> (&'a clap::Command, usize) {
+    let mut command = root;
+    let mut depth = 0;
+    for word in words {
+        match command.find_subcommand(word) {
+            Some(sub) => {
+                command = sub;
+                depth += 1;
+            }
+            None if word.starts_with('-') => {}
+            // A flag's value or a positional argument: no further subcommands follow.
+            None => break,
+        }
+    }
+    (command, depth)
+}
+
+/// What kind of thing an ID names, for completing the right ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Guild,
+    Channel,
+    Role,
+    User,
+}
+
+/// An ID found in the open dump.
+#[derive(Clone, Debug)]
+pub struct Id {
+    pub id: String,
+    pub kind: Kind,
+    pub name: String,
+}
+
+/// The guild, channel and thread, role, and message author IDs of `document`, a dump.
+pub fn ids(document: &Value) -> Vec<Id> {
+    let mut ids = Vec::new();
+    let mut push = |item: &Value, kind, name: &str| {
+        let id = match &item["id"] {
+            Value::String(id) => id.clone(),
+            Value::Number(id) => id.to_string(),
+            _ => return,
+        };
+        if !ids.iter().any(|i: &Id| i.id == id) {
+            let name = item[name].as_str().unwrap_or_default().to_string();
+            ids.push(Id { id, kind, name });
+        }
+    };
+    push(&document["guild"], Kind::Guild, "name");
+    for channel in list(&document["channels"]).chain(list(&document["threads"])) {
+        push(channel, Kind::Channel, "name");
+    }
+    for role in list(&document["roles"]) {
+        push(role, Kind::Role, "name");
+    }
+    let channels = list(&document["channels"]).chain(list(&document["threads"]));
+    for message in channels.flat_map(|c| list(&c["messages"])) {
+        push(&message["author"], Kind::User, "username");
+    }
+    ids
+}
+
+/// Tab completion for the shell's line editor.
+#[derive(Helper, Hinter, Highlighter, Validator)]
+pub struct Completion {
+    root: clap::Command,
+    /// IDs of the open dump.
+    pub ids: Vec<Id>,
+    files: FilenameCompleter,
+}
+
+impl Completion {
+    pub fn new(root: clap::Command) -> Self {
+        let root = root.disable_help_subcommand(true);
+        Completion {
+            root,
+            ids: Vec::new(),
+            files: FilenameCompleter::new(),
+        }
+    }
+
+    /// Where the word being completed starts in `line[..pos]`, and its candidates.
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| {
+            i + line[i..].chars().next().map_or(1, char::len_utf8)
+        });
+        let word = &line[start..];
+        let before: Vec<String> = line[..start]
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        let (command, depth) = subcommand(&self.root, &before);
+        let pair = |replacement: String, display: String| Pair {
+            display,
+            replacement,
+        };
+
+        // The value of a flag that takes IDs: named for what it identifies, or `<ID>`.
+        if let Some((flag, arg)) = value_of(command, &before) {
+            let kind = [
+                ("guild", Kind::Guild),
+                ("channel", Kind::Channel),
+                ("thread", Kind::Channel),
+                ("parent", Kind::Channel),
+                ("role", Kind::Role),
+                ("user", Kind::User),
+                ("author", Kind::User),
+                ("member", Kind::User),
+            ]
+            .into_iter()
+            .find_map(|(name, kind)| flag.contains(name).then_some(kind));
+            if kind.is_none() && !value_named(arg, |name| name.ends_with("ID")) {
+                return (start, Vec::new());
+            }
+            let matches = self
+                .ids
+                .iter()
+                .filter(|id| kind.is_none_or(|k| id.kind == k) && id.id.starts_with(word))
+                .map(|id| pair(id.id.clone(), format!("{}  {}", id.id, id.name)))
+                .collect();
+            return (start, matches);
+        }
+
+        let mut names: Vec<String> = if word.starts_with('-') {
+            command
+                .get_arguments()
+                .chain(
+                    if depth == 0 {
+                        None
+                    } else {
+                        Some(self.root.get_arguments())
+                    }
+                    .into_iter()
+                    .flatten(),
+                )
+                .filter(|a| !a.is_hide_set())
+                .filter_map(|a| a.get_long())
+                .map(|long| format!("--{long}"))
+                .chain(["--help".to_string()])
+                .collect()
+        } else {
+            let subcommands = command
+                .get_subcommands()
+                .filter(|c| !c.is_hide_set())
+                .map(|c| c.get_name().to_string());
+            if depth == 0 && before.is_empty() {
+                subcommands
+                    .chain(
+                        BUILTINS
+                            .iter()
+                            .filter_map(|(usage, _)| Some(usage.split(' ').next()?.to_string())),
+                    )
+                    .collect()
+            } else {
+                subcommands.collect()
+            }
+        };
+        names.sort();
+        names.dedup();
+        let matches = names
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| pair(format!("{name} "), name))
+            .collect();
+        (start, matches)
+    }
+}
+
+impl Completer for Completion {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        // Paths come from the filesystem: `open`'s argument and `<PATH>` or `<DIR>` values.
+        let before: Vec<String> = line[..pos].split_whitespace().map(str::to_string).collect();
+        let before = match line[..pos].ends_with(char::is_whitespace) {
+            true => &before[..],
+            false => &before[..before.len().saturating_sub(1)],
+        };
+        let (command, _) = subcommand(&self.root, before);
+        let path = before == ["open"]
+            || value_of(command, before)
+                .is_some_and(|(_, arg)| value_named(arg, |name| matches!(name, "PATH" | "DIR")));
+        if path {
+            return self.files.complete_path(line, pos);
+        }
+        Ok(self.candidates(line, pos))
+    }
+}
+
+/// The flag `before` ends with and its argument of `command`, if it takes a value.
+fn value_of<'a>(
+    command: &'a clap::Command,
+    before: &'a [String],
+) -> Option<(&'a str, &'a clap::Arg)> {
+    let flag = before.last()?.strip_prefix("--")?;
+    let arg = command
+        .get_arguments()
+        .find(|a| a.get_long() == Some(flag))?;
+    arg.get_action().takes_values().then_some((flag, arg))
+}
+
+fn value_named(arg: &clap::Arg, test: impl Fn(&str) -> bool) -> bool {
+    arg.get_value_names()
+        .is_some_and(|names| names.iter().any(|name| test(name.as_str())))
+}
+
+/// `$XDG_STATE_HOME/guildsync/shell_history`, falling back to
+/// `~/.local/state/guildsync/shell_history`.
+pub fn history_path() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| expand_tilde(Path::new("~/.local/state")))
+        .join("guildsync")
+        .join("shell_history")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_split_gain_context_and_complete() {
+        assert_eq!(
+            split(r#"format query --in 'my dump.json' "a \"b\"" c\ d"#).unwrap(),
+            ["format", "query", "--in", "my dump.json", r#"a "b""#, "c d"]
+        );
+        assert!(split("echo 'open").is_err());
+        assert_eq!(
+            Builtin::parse(&split("use 42").unwrap()),
+            Some(Ok(Builtin::Use(Some("42".to_string()))))
+        );
+        assert!(
+            Builtin::parse(&split("use general").unwrap())
+                .unwrap()
+                .is_err()
+        );
+        assert_eq!(Builtin::parse(&split("format stats").unwrap()), None);
+
+        let root = clap::Command::new("guildsync").subcommand(
+            clap::Command::new("discord")
+                .subcommand(
+                    clap::Command::new("export")
+                        .arg(clap::arg!(--guild <ID>).conflicts_with("all"))
+                        .arg(clap::arg!(--all))
+                        .arg(clap::arg!(--channel <ID>)),
+                )
+                .subcommand(clap::Command::new("edit")),
+        );
+        let context = Context {
+            dump: Some(PathBuf::from("guild.json")),
+            guild: Some("42".to_string()),
+        };
+        let words = |line: &str| context.apply(&root, split(line).unwrap()).join(" ");
+        assert_eq!(words("discord export"), "discord export --guild 42");
+        assert_eq!(words("discord export --all"), "discord export --all");
+        assert_eq!(
+            words("discord export --guild=7"),
+            "discord export --guild=7"
+        );
+        assert_eq!(context.prompt(), "guildsync (42, guild.json)> ");
+
+        let mut completion = Completion::new(root);
+        completion.ids = ids(&serde_json::json!({
+            "guild": { "id": "42", "name": "Guild" },
+            "channels": [{ "id": "100", "name": "general",
+                           "messages": [{ "author": { "id": "7", "username": "alice" } }] }],
+        }));
+        let replacements = |line: &str| {
+            let (_, pairs) = completion.candidates(line, line.len());
+            pairs.into_iter().map(|p| p.replacement).collect::<Vec<_>>()
+        };
+        assert_eq!(replacements("disc"), ["discord "]);
+        assert_eq!(replacements("discord e"), ["edit ", "export "]);
+        assert_eq!(replacements("discord export --c"), ["--channel "]);
+        assert_eq!(replacements("discord export --channel "), ["100"]);
+        assert_eq!(replacements("discord export --guild 4"), ["42"]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Detached ed25519 signatures for `format sign` and `format verify-signature`.
+//!
+//! A signature file covers a dump's bytes exactly as written (compressed, if it is) and names the
+//! public key that made it. Verifying checks the signature and that the key is one the verifier
+//! trusts (`--public-key` or `[formats] trusted_keys`), so a signature made with any other key,
+//! including one swapped in alongside a modified dump, is rejected.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file::write_atomic;
+use crate::auth;
+use crate::error::CliError;
+use crate::timestamp;
+
+/// `format` of a signature file.
+pub const SIGNATURE_FORMAT: &str = "signature";
+pub const SIGNATURE_VERSION: u64 = 1;
+
+/// Default signature path for a file at `path`: `<path>.sig`.
+pub fn default_path(path: &Path) -> PathBuf {
+    let mut sig = path.as_os_str().to_owned();
+    sig.push(".sig");
+    PathBuf::from(sig)
+}
+
+/// A new random signing key, base64-encoded for the keyring or an environment variable.
+pub fn generate() -> Result<String, CliError> {
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed).map_err(|e| CliError::Io(std::io::Error::other(e.to_string())))?;
+    Ok(STANDARD.encode(seed))
+}
+
+/// The signing key from the `env` variable, else the keyring (`auth keygen`).
+pub fn resolve_key(env: &str) -> Result<SigningKey, CliError> {
+    let encoded = match std::env::var(env).ok().filter(|key| !key.is_empty()) {
+        Some(key) => key,
+        None => auth::stored_signing_key()?.ok_or_else(|| {
+            CliError::Auth(format!(
+                "no signing key; run `guildsync auth keygen` or set {env}"
+            ))
+        })?,
+    };
+    signing_key(&encoded).map_err(|e| CliError::Auth(format!("signing key: {e}")))
+}
+
+/// Decode a base64 signing key (its 32-byte seed).
+pub fn signing_key(encoded: &str) -> Result<SigningKey, String> {
+    let bytes = STANDARD.decode(encoded.trim()).map_err(|e| e.to_string())?;
+    let seed: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "expected 32 bytes".to_string())?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Decode a base64 public key.
+pub fn public_key(encoded: &str) -> Result<VerifyingKey, String> {
+    let bytes = STANDARD.decode(encoded.trim()).map_err(|e| e.to_string())?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "expected 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())
+}
+
+/// The base64 public key of `key`, as shared with verifiers.
+pub fn public_key_of(key: &SigningKey) -> String {
+    STANDARD.encode(key.verifying_key().as_bytes())
+}
+
+/// A detached signature file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignatureFile {
+    pub format: String,
+    pub version: u64,
+    pub algorithm: String,
+    pub public_key: String,
+    pub signed_at: String,
+    pub signature: String,
+}
+
+/// Sign `bytes` with `key` and write the signature to `out`.
+pub fn sign(bytes: &[u8], key: &SigningKey, out: &Path) -> Result<SignatureFile, CliError> {
+    let file = SignatureFile {
+        format: SIGNATURE_FORMAT.to_string(),
+        version: SIGNATURE_VERSION,
+        algorithm: "ed25519".to_string(),
+        public_key: public_key_of(key),
+        signed_at: timestamp::now_rfc3339(),
+        signature: STANDARD.encode(key.sign(bytes).to_bytes()),
+    };
+    let mut json = serde_json::to_vec_pretty(&file)?;
+    json.push(b'\n');
+    write_atomic(out, &json)?;
+    Ok(file)
+}
+
+pub fn read(path: &Path) -> Result<SignatureFile, CliError> {
+    let text = match std::fs::read_to_string(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(CliError::NotFound(format!(
+                "no signature at {}; sign with `format sign`",
+                path.display()
+            )));
+        }
+        text => text?,
+    };
+    let file: SignatureFile = serde_json::from_str(&text)
+        .map_err(|e| CliError::Validation(format!("{}: {e}", path.display())))?;
+    if file.format != SIGNATURE_FORMAT || file.version != SIGNATURE_VERSION {
+        return Err(CliError::Validation(format!(
+            "{}: expected a {SIGNATURE_FORMAT} v{SIGNATURE_VERSION} file, found {} v{}",
+            path.display(),
+            file.format,
+            file.version
+        )));
+    }
+    if file.algorithm != "ed25519" {
+        return Err(CliError::Validation(format!(
+            "{}: unsupported algorithm {}",
+            path.display(),
+            file.algorithm
+        )));
+    }
+    Ok(file)
+}
+
+/// Outcome of checking a signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    /// Made over these bytes by a trusted key.
+    Valid,
+    /// Valid, but by a key not among the trusted ones.
+    Untrusted,
+    /// The bytes or the signature were changed after signing.
+    Invalid,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Valid => "valid signature by a trusted key",
+            Verdict::Untrusted => "valid signature, but by a key that is not trusted",
+            Verdict::Invalid => "signature does not match; the file was modified or not signed",
+        })
+    }
+}
+
+/// Check `file`'s signature over `bytes` against the `trusted` public keys.
+pub fn verify(
+    bytes: &[u8],
+    file: &SignatureFile,
+    trusted: &[VerifyingKey],
+) -> Result<Verdict, CliError> {
+    let invalid = |what: &str, e: String| CliError::Validation(format!("signature {what}: {e}"));
+    let key = public_key(&file.public_key).map_err(|e| invalid("public_key", e))?;
+    let signature = STANDARD
+        .decode(&file.signature)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Signature::from_slice(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| invalid("signature", e))?;
+    Ok(if key.verify(bytes, &signature).is_err() {
+        Verdict::Invalid
+    } else if trusted.contains(&key) {
+        Verdict::Valid
+    } else {
+        Verdict::Untrusted
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_check_bytes_and_signer() {
+        let dir = std::env::temp_dir().join(format!("guildsync-signing-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = signing_key(&generate().unwrap()).unwrap();
+        let other = signing_key(&generate().unwrap()).unwrap();
+        let path = dir.join("guild.json.sig");
+
+        sign(b"dump", &key, &path).unwrap();
+        let file = read(&path).unwrap();
+        let trusted = [public_key(&public_key_of(&key)).unwrap()];
+        assert_eq!(verify(b"dump", &file, &trusted).unwrap(), Verdict::Valid);
+        assert_eq!(verify(b"dump!", &file, &trusted).unwrap(), Verdict::Invalid);
+        let others = [other.verifying_key()];
+        assert_eq!(verify(b"dump", &file, &others).unwrap(), Verdict::Untrusted);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,354 @@
+//! SQLite export of a dump for `format export-sqlite`, so archives can be queried with SQL.
+//!
+//! The schema is normalized: users appear once however many messages they wrote, threads are
+//! rows of `channels` with their parent's id, and member roles, attachments, and mentions get
+//! their own tables. Ids are stored as integers, so ordering by id is chronological, and
+//! timestamps as UTC RFC 3339 text, which SQLite's date functions accept.
+
+use std::path::Path;
+
+use rusqlite::types::{ToSql, ToSqlOutput, Value as SqlValue};
+use rusqlite::{Connection, Transaction, params};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::atomic_file::AtomicFile;
+use crate::discord::list;
+use crate::error::CliError;
+use crate::timestamp;
+
+/// User ids are text in redacted and anonymized dumps, which `INTEGER PRIMARY KEY` (an alias of
+/// the rowid) would reject, so the tables keyed by them are `WITHOUT ROWID`.
+const SCHEMA: &str = "
+CREATE TABLE guild (
+    id INTEGER PRIMARY KEY,
+    name TEXT,
+    exported_at TEXT
+);
+CREATE TABLE roles (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    position INTEGER,
+    color INTEGER,
+    permissions TEXT,
+    hoist INTEGER,
+    mentionable INTEGER,
+    managed INTEGER
+);
+CREATE TABLE channels (
+    id INTEGER PRIMARY KEY,
+    parent_id INTEGER,
+    name TEXT,
+    type INTEGER,
+    position INTEGER,
+    topic TEXT,
+    is_thread INTEGER NOT NULL
+);
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    username TEXT,
+    global_name TEXT,
+    bot INTEGER NOT NULL
+) WITHOUT ROWID;
+CREATE TABLE members (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id),
+    nick TEXT,
+    joined_at TEXT
+) WITHOUT ROWID;
+CREATE TABLE member_roles (
+    user_id INTEGER NOT NULL REFERENCES members (user_id),
+    role_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, role_id)
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY,
+    channel_id INTEGER NOT NULL REFERENCES channels (id),
+    author_id INTEGER REFERENCES users (id),
+    content TEXT,
+    timestamp TEXT,
+    edited_timestamp TEXT,
+    reply_to INTEGER,
+    pinned INTEGER
+);
+CREATE TABLE attachments (
+    id INTEGER PRIMARY KEY,
+    message_id INTEGER NOT NULL REFERENCES messages (id),
+    filename TEXT,
+    content_type TEXT,
+    size INTEGER,
+    url TEXT
+);
+CREATE TABLE mentions (
+    message_id INTEGER NOT NULL REFERENCES messages (id),
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    PRIMARY KEY (message_id, user_id)
+);
+CREATE INDEX channels_parent ON channels (parent_id);
+CREATE INDEX member_roles_role ON member_roles (role_id);
+CREATE INDEX messages_channel_time ON messages (channel_id, timestamp);
+CREATE INDEX messages_author ON messages (author_id);
+CREATE INDEX messages_reply ON messages (reply_to);
+CREATE INDEX attachments_message ON attachments (message_id);
+CREATE INDEX mentions_user ON mentions (user_id);
+";
+
+/// Rows loaded into each table.
+#[derive(Debug, Default, Serialize)]
+pub struct Loaded {
+    pub roles: usize,
+    pub channels: usize,
+    pub users: usize,
+    pub members: usize,
+    pub messages: usize,
+    pub attachments: usize,
+}
+
+/// Write `document` and the `members` of a member list to a new SQLite database at `out`,
+/// replacing any file there once the export is complete.
+pub fn export(document: &Value, members: &[Value], out: &Path) -> Result<Loaded, CliError> {
+    let file = AtomicFile::create(out)?;
+    let mut db = Connection::open(file.tmp()).map_err(db_error)?;
+    let loaded = load(&mut db, document, members)?;
+    db.close().map_err(|(_, e)| db_error(e))?;
+    file.commit()?;
+    Ok(loaded)
+}
+
+fn load(db: &mut Connection, document: &Value, members: &[Value]) -> Result<Loaded, CliError> {
+    db.execute_batch("PRAGMA journal_mode = MEMORY;")
+        .map_err(db_error)?;
+    let tx = db.transaction().map_err(db_error)?;
+    tx.execute_batch(SCHEMA).map_err(db_error)?;
+    let loaded = insert(&tx, document, members).map_err(db_error)?;
+    tx.commit().map_err(db_error)?;
+    Ok(loaded)
+}
+
+fn insert(tx: &Transaction, document: &Value, members: &[Value]) -> rusqlite::Result<Loaded> {
+    let mut loaded = Loaded::default();
+    let guild = &document["guild"];
+    tx.execute(
+        "INSERT INTO guild VALUES (?1, ?2, ?3)",
+        params![
+            Id(&guild["id"]),
+            Text(&guild["name"]),
+            Time(&document["exported_at"])
+        ],
+    )?;
+
+    let mut role =
+        tx.prepare("INSERT OR REPLACE INTO roles VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?;
+    for r in list(&document["roles"]) {
+        role.execute(params![
+            Id(&r["id"]),
+            r["name"].as_str().unwrap_or_default(),
+            r["position"].as_i64(),
+            r["color"].as_i64(),
+            Text(&r["permissions"]),
+            r["hoist"].as_bool(),
+            r["mentionable"].as_bool(),
+            r["managed"].as_bool(),
+        ])?;
+        loaded.roles += 1;
+    }
+
+    // Members first, so their fuller records win over the copies embedded in messages.
+    let mut user = tx.prepare("INSERT OR IGNORE INTO users VALUES (?1, ?2, ?3, ?4)")?;
+    let mut member = tx.prepare("INSERT OR REPLACE INTO members VALUES (?1, ?2, ?3)")?;
+    let mut member_role = tx.prepare("INSERT OR IGNORE INTO member_roles VALUES (?1, ?2)")?;
+    for m in members {
+        user.execute(params![
+            Id(&m["id"]),
+            Text(&m["username"]),
+            Text(&m["global_name"]),
+            m["bot"] == true
+        ])?;
+        member.execute(params![
+            Id(&m["id"]),
+            Text(&m["nick"]),
+            Time(&m["joined_at"])
+        ])?;
+        for role_id in list(&m["roles"]) {
+            member_role.execute(params![Id(&m["id"]), Id(role_id)])?;
+        }
+        loaded.members += 1;
+    }
+
+    let mut channel =
+        tx.prepare("INSERT OR REPLACE INTO channels VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+    let mut message =
+        tx.prepare("INSERT OR REPLACE INTO messages VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?;
+    let mut attachment =
+        tx.prepare("INSERT OR REPLACE INTO attachments VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+    let mut mention = tx.prepare("INSERT OR IGNORE INTO mentions VALUES (?1, ?2)")?;
+    for (section, is_thread) in [("channels", false), ("threads", true)] {
+        for c in list(&document[section]) {
+            channel.execute(params![
+                Id(&c["id"]),
+                Id(&c["parent_id"]),
+                Text(&c["name"]),
+                c["type"].as_i64(),
+                c["position"].as_i64(),
+                Text(&c["topic"]),
+                is_thread,
+            ])?;
+            loaded.channels += 1;
+            for m in list(&c["messages"]) {
+                let author = &m["author"];
+                if !author["id"].is_null() {
+                    user.execute(params![
+                        Id(&author["id"]),
+                        Text(&author["username"]),
+                        Text(&author["global_name"]),
+                        author["bot"] == true
+                    ])?;
+                }
+                message.execute(params![
+                    Id(&m["id"]),
+                    Id(&c["id"]),
+                    Id(&author["id"]),
+                    Text(&m["content"]),
+                    Time(&m["timestamp"]),
+                    Time(&m["edited_timestamp"]),
+                    Id(&m["message_reference"]["message_id"]),
+                    m["pinned"].as_bool(),
+                ])?;
+                loaded.messages += 1;
+                for a in list(&m["attachments"]) {
+                    attachment.execute(params![
+                        Id(&a["id"]),
+                        Id(&m["id"]),
+                        Text(&a["filename"]),
+                        Text(&a["content_type"]),
+                        a["size"].as_i64(),
+                        Text(&a["url"]),
+                    ])?;
+                    loaded.attachments += 1;
+                }
+                for u in list(&m["mentions"]) {
+                    user.execute(params![
+                        Id(&u["id"]),
+                        Text(&u["username"]),
+                        Text(&u["global_name"]),
+                        u["bot"] == true
+                    ])?;
+                    mention.execute(params![Id(&m["id"]), Id(&u["id"])])?;
+                }
+            }
+        }
+    }
+    let users: i64 = tx.query_row("SELECT count(*) FROM users", [], |row| row.get(0))?;
+    loaded.users = users as usize;
+    Ok(loaded)
+}
+
+/// An id column: snowflakes as integers, anything else (such as pseudonyms) as text.
+struct Id<'a>(&'a Value);
+
+impl ToSql for Id<'_> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        let value = match self.0 {
+            Value::Number(n) => n.as_i64().map_or(SqlValue::Null, SqlValue::Integer),
+            Value::String(s) => s
+                .parse::<i64>()
+                .map_or_else(|_| SqlValue::Text(s.clone()), SqlValue::Integer),
+            _ => SqlValue::Null,
+        };
+        Ok(ToSqlOutput::Owned(value))
+    }
+}
+
+/// A text column; non-strings (such as numeric permission bits) as their JSON text.
+struct Text<'a>(&'a Value);
+
+impl ToSql for Text<'_> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self.0 {
+            Value::Null => ToSqlOutput::Owned(SqlValue::Null),
+            Value::String(s) => ToSqlOutput::from(s.as_str()),
+            other => ToSqlOutput::Owned(SqlValue::Text(other.to_string())),
+        })
+    }
+}
+
+/// A timestamp column, normalized to UTC; unparseable strings are kept as written.
+struct Time<'a>(&'a Value);
+
+impl ToSql for Time<'_> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self.0.as_str().and_then(timestamp::normalize) {
+            Some(time) => Ok(ToSqlOutput::Owned(SqlValue::Text(time))),
+            None => Ok(match self.0 {
+                Value::String(s) => ToSqlOutput::from(s.as_str()),
+                _ => ToSqlOutput::Owned(SqlValue::Null),
+            }),
+        }
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> CliError {
+    CliError::Io(std::io::Error::other(format!("sqlite: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_loads_into_normalized_tables() {
+        let author = serde_json::json!({ "id": "7", "username": "alice" });
+        let document = serde_json::json!({
+            "exported_at": "2025-01-31T13:00:00+01:00",
+            "guild": { "id": "1", "name": "Test" },
+            "roles": [{ "id": "2", "name": "mod", "permissions": "8" }],
+            "channels": [{ "id": "20", "name": "general", "type": 0, "messages": [
+                { "id": "98", "author": author, "content": "hi",
+                  "timestamp": "2025-01-31T12:00:00+00:00",
+                  "attachments": [{ "id": "5", "filename": "a.png", "size": 10 }] },
+                { "id": "99", "author": { "id": "8", "username": "bob", "bot": true },
+                  "content": "hey <@7>", "mentions": [author],
+                  "message_reference": { "message_id": "98" } },
+            ]}],
+            "threads": [{ "id": "30", "parent_id": "20", "name": "side", "type": 11 }],
+        });
+        let members = [serde_json::json!({
+            "id": "7", "username": "alice", "global_name": "Alice", "nick": "al",
+            "roles": ["2"], "joined_at": "2024-01-01T00:00:00+00:00",
+        })];
+        let mut db = Connection::open_in_memory().unwrap();
+        let loaded = load(&mut db, &document, &members).unwrap();
+        assert_eq!(
+            (
+                loaded.channels,
+                loaded.users,
+                loaded.messages,
+                loaded.attachments
+            ),
+            (2, 2, 2, 1)
+        );
+
+        let query = "SELECT u.global_name, m.timestamp, r.content
+                     FROM messages r JOIN messages m ON r.reply_to = m.id
+                     JOIN users u ON m.author_id = u.id
+                     JOIN mentions x ON x.message_id = r.id AND x.user_id = u.id";
+        let row: (String, String, String) = db
+            .query_row(query, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                "Alice".into(),
+                "2025-01-31T12:00:00Z".into(),
+                "hey <@7>".into()
+            )
+        );
+        let thread_parent: i64 = db
+            .query_row(
+                "SELECT parent_id FROM channels WHERE is_thread",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(thread_parent, 20);
+    }
+}
@@ -0,0 +1,606 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::config::{KnownHostsMode, SshConfig, expand_tilde};
+use crate::error::CliError;
+use crate::ssh_config::{self, HostConfig};
+
+/// Exit status `ssh` itself uses for connection and authentication failures.
+const SSH_FAILURE: i32 = 255;
+
+/// Remote wrapper for `--script`: stash stdin in a temp file, run it, and always remove it.
+/// `{run}` is replaced with how the file is invoked.
+const SCRIPT_WRAPPER: &str =
+    r#"t=$(mktemp) || exit 1; trap 'rm -f "$t"' EXIT; cat > "$t" && chmod 700 "$t" && {run} "$t""#;
+
+/// Result of a remote command; the output is empty unless it was captured.
+#[derive(Debug, Serialize)]
+pub struct ExecResult {
+    pub host: String,
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// How a command went on one of several hosts.
+#[derive(Debug, Serialize)]
+pub struct HostOutcome {
+    pub host: String,
+    pub ok: bool,
+    /// `None` when the command did not run to its end (no connection, timed out).
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+impl HostOutcome {
+    pub fn new(host: &str, result: Result<ExecResult, CliError>) -> Self {
+        match result {
+            Ok(result) => Self {
+                host: result.host,
+                ok: result.exit_code == 0,
+                exit_code: Some(result.exit_code),
+                stdout: result.stdout,
+                stderr: result.stderr,
+                error: None,
+            },
+            Err(err) => Self {
+                host: host.to_string(),
+                ok: false,
+                exit_code: None,
+                stdout: String::new(),
+                stderr: String::new(),
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+/// `--host` values followed by the members of each `--group` from `[ssh.groups]`, each host
+/// once, in the order given.
+pub fn resolve_hosts(
+    config: &SshConfig,
+    hosts: &[String],
+    groups: &[String],
+) -> Result<Vec<String>, CliError> {
+    let mut resolved = hosts.to_vec();
+    for group in groups {
+        let Some(members) = config.groups.get(group) else {
+            let known: Vec<&str> = config.groups.keys().map(String::as_str).collect();
+            return Err(CliError::Usage(format!(
+                "unknown host group `{group}`; [ssh.groups] defines {}",
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )));
+        };
+        resolved.extend(members.iter().cloned());
+    }
+    let mut seen = std::collections::HashSet::new();
+    resolved.retain(|host| seen.insert(host.clone()));
+    Ok(resolved)
+}
+
+/// One row per host: whether it succeeded, its exit code, and the error or last line of stderr
+/// for those that did not.
+pub fn outcome_table(outcomes: &[HostOutcome]) -> String {
+    let width = outcomes.iter().map(|o| o.host.len()).max().unwrap_or(0);
+    let mut table = String::new();
+    for o in outcomes {
+        let exit = o
+            .exit_code
+            .map_or_else(|| "-".to_string(), |code| code.to_string());
+        let mut row = format!(
+            "{:<width$}  {:<6}  {exit:>3}",
+            o.host,
+            if o.ok { "ok" } else { "failed" }
+        );
+        let detail = o
+            .error
+            .clone()
+            .or_else(|| (!o.ok).then(|| o.stderr.trim().lines().last().map(str::to_string))?);
+        if let Some(detail) = detail {
+            row.push_str(&format!("  {detail}"));
+        }
+        table.push_str(&row);
+        table.push('\n');
+    }
+    table
+}
+
+/// Parse a `KEY=VALUE` pair for `--env`; keys must be valid shell variable names.
+pub fn parse_env(pair: &str) -> Result<(String, String), String> {
+    let (key, value) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{pair}`"))?;
+    let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("`{key}` is not a valid environment variable name"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Single-quote `value` for a POSIX shell.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// `export K='v' && ` for each variable, so the remote shell sees them before the command.
+fn env_prefix(env: &[(String, String)]) -> String {
+    env.iter()
+        .map(|(key, value)| format!("export {key}={} && ", shell_quote(value)))
+        .collect()
+}
+
+/// Where the output of a remote command goes.
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// Kept in the [`ExecResult`].
+    Capture,
+    /// Written to the local stdout and stderr line by line as it arrives, after `prefix` if
+    /// given, with `bar` (if any) hidden while a line is written.
+    Stream {
+        prefix: Option<String>,
+        bar: Option<ProgressBar>,
+    },
+}
+
+/// How [`exec`] and [`exec_script`] run a command.
+#[derive(Debug)]
+pub struct ExecOptions {
+    pub output: Output,
+    /// Forward the local stdin to the command (not available for scripts, which arrive on it).
+    pub stdin: bool,
+    /// Close the connection, failing with [`CliError::Timeout`], once the command has run this
+    /// long.
+    pub timeout: Option<Duration>,
+}
+
+/// Run `cmd` on `host` with `env` exported, returning its exit code and, with
+/// [`Output::Capture`], its output.
+pub async fn exec(
+    config: &SshConfig,
+    host: &str,
+    env: &[(String, String)],
+    cmd: &[String],
+    options: &ExecOptions,
+) -> Result<ExecResult, CliError> {
+    let remote = format!("{}{}", env_prefix(env), cmd.join(" "));
+    let input = if options.stdin {
+        Input::Stdin
+    } else {
+        Input::None
+    };
+    run(config, host, &remote, input, options).await
+}
+
+/// Upload the local `script` to a temp path on `host`, run it, and clean it up.
+///
+/// Scripts with a `#!` line run under that interpreter; others run under `sh`.
+pub async fn exec_script(
+    config: &SshConfig,
+    host: &str,
+    env: &[(String, String)],
+    script: &Path,
+    options: &ExecOptions,
+) -> Result<ExecResult, CliError> {
+    let body = std::fs::read(script)
+        .map_err(|e| CliError::Usage(format!("cannot read script {}: {e}", script.display())))?;
+    let run_as = if body.starts_with(b"#!") { "" } else { "sh" };
+    let remote = env_prefix(env) + &SCRIPT_WRAPPER.replace("{run}", run_as);
+    run(config, host, &remote, Input::Bytes(body), options).await
+}
+
+/// What the remote command reads on its stdin.
+enum Input {
+    None,
+    Bytes(Vec<u8>),
+    Stdin,
+}
+
+async fn run(
+    config: &SshConfig,
+    host: &str,
+    remote: &str,
+    input: Input,
+    options: &ExecOptions,
+) -> Result<ExecResult, CliError> {
+    let mut command = tokio::process::Command::new("ssh");
+    command.args(client_options(config, host)?);
+    let spawn_err = |e: std::io::Error| CliError::Network(format!("ssh {host}: {e}"));
+    let mut child = command
+        .arg("--")
+        .arg(host)
+        .arg(remote)
+        .stdin(match input {
+            Input::None => Stdio::null(),
+            Input::Bytes(_) | Input::Stdin => Stdio::piped(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_err)?;
+
+    // Fed from a task of its own: the command may exit before the local stdin ends, and a
+    // pending read of it must not keep us waiting.
+    let feeder = child.stdin.take().map(|mut stdin| {
+        tokio::spawn(async move {
+            let _ = match input {
+                Input::Bytes(body) => stdin.write_all(&body).await,
+                Input::Stdin => {
+                    let mut chunks = read_stdin();
+                    while let Some(chunk) = chunks.recv().await {
+                        if stdin.write_all(&chunk).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(())
+                }
+                Input::None => Ok(()),
+            };
+        })
+    });
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let finished = async {
+        let (stdout, stderr) = tokio::join!(
+            pump(stdout, Stream::Stdout, &options.output),
+            pump(stderr, Stream::Stderr, &options.output),
+        );
+        let status = child.wait().await.map_err(spawn_err)?;
+        Ok::<_, CliError>((
+            stdout.map_err(spawn_err)?,
+            stderr.map_err(spawn_err)?,
+            status,
+        ))
+    };
+    let result = match options.timeout {
+        Some(limit) => tokio::time::timeout(limit, finished).await,
+        None => Ok(finished.await),
+    };
+    if let Some(feeder) = feeder {
+        feeder.abort();
+    }
+    let Ok(result) = result else {
+        let _ = child.kill().await;
+        return Err(CliError::Timeout(format!(
+            "ssh {host}: command still running after --timeout {}s; connection closed",
+            options.timeout.unwrap_or_default().as_secs()
+        )));
+    };
+    let ((stdout, _), (stderr, last_error), status) = result?;
+
+    let stderr = String::from_utf8_lossy(&stderr).into_owned();
+    let exit_code = status.code().unwrap_or(SSH_FAILURE);
+    if exit_code == SSH_FAILURE {
+        let reason = match stderr.trim() {
+            "" => last_error,
+            captured => captured.to_string(),
+        };
+        return Err(CliError::Network(format!("ssh {host}: {reason}")));
+    }
+    Ok(ExecResult {
+        host: host.to_string(),
+        exit_code,
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr,
+    })
+}
+
+/// Which way [`transfer`] copies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Local to remote (`ssh push`).
+    Push,
+    /// Remote to local (`ssh pull`).
+    Pull,
+}
+
+/// A copy between this machine and `host` for `ssh push` and `ssh pull`.
+#[derive(Debug)]
+pub struct Transfer<'a> {
+    pub host: &'a str,
+    pub direction: Direction,
+    pub local: &'a Path,
+    pub remote: &'a str,
+    /// Copy directories and their contents.
+    pub recursive: bool,
+    /// Continue partial files where they end instead of copying them again.
+    pub resume: bool,
+    /// Let `sftp` draw its progress meter on the terminal.
+    pub progress: bool,
+}
+
+/// Copy with the system `sftp` client in batch mode, with the same `[ssh]` settings as
+/// [`exec`].
+pub async fn transfer(config: &SshConfig, transfer: &Transfer<'_>) -> Result<(), CliError> {
+    let host = transfer.host;
+    let spawn_err = |e: std::io::Error| CliError::Network(format!("sftp {host}: {e}"));
+    let mut child = tokio::process::Command::new("sftp")
+        .args(client_options(config, host)?)
+        .args(["-b", "-", "--", host])
+        .stdin(Stdio::piped())
+        .stdout(if transfer.progress {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_err)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // `sftp` exits without reading it when it cannot connect; its status tells why.
+        let _ = stdin.write_all(sftp_batch(transfer).as_bytes()).await;
+    }
+    let output = child.wait_with_output().await.map_err(spawn_err)?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // Without a connection `ssh` says why first and `sftp` adds "Connection closed"; a failed
+    // copy is reported last.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut lines = stderr.trim().lines();
+    if output.status.code() == Some(SSH_FAILURE) {
+        let reason = lines.next().unwrap_or("connection failed");
+        return Err(CliError::Network(format!("sftp {host}: {reason}")));
+    }
+    let reason = lines.last().unwrap_or("copy failed");
+    Err(
+        if reason.contains("No such file") || reason.contains("not found") {
+            CliError::NotFound(format!("sftp {host}: {reason}"))
+        } else {
+            CliError::Network(format!("sftp {host}: {reason}"))
+        },
+    )
+}
+
+/// Batch file for `sftp -b`: `@` keeps it from echoing each command.
+fn sftp_batch(transfer: &Transfer<'_>) -> String {
+    let mut flags = String::new();
+    if transfer.resume {
+        flags.push_str(" -a");
+    }
+    if transfer.recursive {
+        flags.push_str(" -R");
+    }
+    let local = sftp_quote(&transfer.local.to_string_lossy());
+    let remote = sftp_quote(transfer.remote);
+    let copy = match transfer.direction {
+        Direction::Push => format!("@put -p{flags} {local} {remote}"),
+        Direction::Pull => format!("@get -p{flags} {remote} {local}"),
+    };
+    // Batch mode starts with the meter off; `progress` toggles it on.
+    let progress = if transfer.progress { "@progress\n" } else { "" };
+    format!("{progress}{copy}\n")
+}
+
+/// Double-quote `path` for an `sftp` command line, which unescapes `\\` and `\"` inside quotes.
+fn sftp_quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Files under `path` (itself, if it is a file) and their total size.
+pub fn local_size(path: &Path) -> std::io::Result<(u64, u64)> {
+    let meta = std::fs::metadata(path)?;
+    if !meta.is_dir() {
+        return Ok((1, meta.len()));
+    }
+    let (mut files, mut bytes) = (0, 0);
+    for entry in std::fs::read_dir(path)? {
+        let (f, b) = local_size(&entry?.path())?;
+        files += f;
+        bytes += b;
+    }
+    Ok((files, bytes))
+}
+
+/// What `host` stands for in the OpenSSH config file `ssh` reads (`[ssh] config_file`, else
+/// `~/.ssh/config`).
+pub fn resolve_host(config: &SshConfig, host: &str) -> Result<HostConfig, CliError> {
+    let path = match &config.config_file {
+        Some(file) => expand_tilde(file),
+        None => ssh_config::default_path(),
+    };
+    ssh_config::resolve(&path, host)
+}
+
+/// Options `ssh` and `sftp` share: batch mode, the host-key policy, the config file, and the
+/// identity and user from `[ssh]` for hosts the OpenSSH config does not give their own.
+fn client_options(config: &SshConfig, host: &str) -> Result<Vec<OsString>, CliError> {
+    let mut options: Vec<OsString> = vec!["-o".into(), "BatchMode=yes".into()];
+    options.push("-o".into());
+    options.push(
+        match config.known_hosts_mode {
+            KnownHostsMode::Strict => "StrictHostKeyChecking=yes",
+            KnownHostsMode::AcceptNew => "StrictHostKeyChecking=accept-new",
+            KnownHostsMode::Off => "StrictHostKeyChecking=no",
+        }
+        .into(),
+    );
+    if let Some(file) = &config.config_file {
+        options.push("-F".into());
+        options.push(expand_tilde(file).into());
+    }
+    // Options on the command line beat the config file, so these would override an alias.
+    let resolved = resolve_host(config, host)?;
+    if resolved.identity_files.is_empty()
+        && let Some(identity) = &config.identity_file
+    {
+        options.push("-i".into());
+        options.push(expand_tilde(identity).into());
+    }
+    if resolved.user.is_none()
+        && let Some(user) = &config.user
+    {
+        options.push("-o".into());
+        options.push(format!("User={user}").into());
+    }
+    Ok(options)
+}
+
+/// The local stdin in chunks until it ends, read on a detached thread: tokio's own stdin reads
+/// on a blocking-pool thread the runtime waits for at exit, which would hang on a stdin that
+/// stays open after the command is done.
+fn read_stdin() -> tokio::sync::mpsc::Receiver<Vec<u8>> {
+    use std::io::Read;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = vec![0; 8192];
+        while let Ok(n @ 1..) = stdin.read(&mut buf) {
+            if tx.blocking_send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Read `from` to the end, keeping it with [`Output::Capture`] or passing each line on to the
+/// local `to` with [`Output::Stream`]. Also returns the last non-empty line, for the reason
+/// `ssh` gives when it cannot connect.
+async fn pump(
+    from: Option<impl tokio::io::AsyncRead + Unpin>,
+    to: Stream,
+    output: &Output,
+) -> std::io::Result<(Vec<u8>, String)> {
+    let (mut kept, mut last) = (Vec::new(), String::new());
+    let Some(from) = from else {
+        return Ok((kept, last));
+    };
+    let mut reader = tokio::io::BufReader::new(from);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        if !text.trim().is_empty() {
+            last = text.trim().to_string();
+        }
+        match output {
+            Output::Capture => kept.extend_from_slice(&line),
+            Output::Stream { prefix, bar } => {
+                let mut chunk = prefix.as_deref().unwrap_or_default().as_bytes().to_vec();
+                chunk.extend_from_slice(&line);
+                if prefix.is_some() && !line.ends_with(b"\n") {
+                    chunk.push(b'\n');
+                }
+                match (to, bar) {
+                    // Written synchronously so the bar is redrawn below the whole line.
+                    (Stream::Stdout, Some(bar)) => {
+                        bar.suspend(|| write_blocking(std::io::stdout().lock(), &chunk))?
+                    }
+                    (Stream::Stderr, Some(bar)) => {
+                        bar.suspend(|| write_blocking(std::io::stderr().lock(), &chunk))?
+                    }
+                    (Stream::Stdout, None) => write_flushed(tokio::io::stdout(), &chunk).await?,
+                    (Stream::Stderr, None) => write_flushed(tokio::io::stderr(), &chunk).await?,
+                }
+            }
+        }
+        line.clear();
+    }
+    Ok((kept, last))
+}
+
+fn write_blocking(mut to: impl std::io::Write, chunk: &[u8]) -> std::io::Result<()> {
+    to.write_all(chunk)?;
+    to.flush()
+}
+
+async fn write_flushed(
+    mut to: impl tokio::io::AsyncWrite + Unpin,
+    chunk: &[u8],
+) -> std::io::Result<()> {
+    to.write_all(chunk).await?;
+    to.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_values_are_quoted_and_keys_checked() {
+        assert_eq!(
+            parse_env("GREETING=it's a \"test\""),
+            Ok(("GREETING".to_string(), "it's a \"test\"".to_string()))
+        );
+        assert_eq!(
+            parse_env("EMPTY="),
+            Ok(("EMPTY".to_string(), String::new()))
+        );
+        assert!(parse_env("NOEQUALS").is_err());
+        assert!(parse_env("A;rm -rf /=x").is_err());
+        assert_eq!(shell_quote("it's a b"), r"'it'\''s a b'");
+    }
+
+    #[test]
+    fn resolves_groups_and_tabulates_outcomes() {
+        let config = SshConfig {
+            groups: [("web".to_string(), vec!["a".to_string(), "b".to_string()])].into(),
+            ..SshConfig::default()
+        };
+        let hosts = resolve_hosts(&config, &["b".to_string()], &["web".to_string()]).unwrap();
+        assert_eq!(hosts, ["b", "a"]);
+        assert!(resolve_hosts(&config, &[], &["db".to_string()]).is_err());
+
+        let outcomes = [
+            HostOutcome::new(
+                "a",
+                Ok(ExecResult {
+                    host: "a".to_string(),
+                    exit_code: 3,
+                    stdout: String::new(),
+                    stderr: "warn\nbroke\n".to_string(),
+                }),
+            ),
+            HostOutcome::new("bb", Err(CliError::Network("ssh bb: refused".to_string()))),
+        ];
+        assert_eq!(
+            outcome_table(&outcomes),
+            "a   failed    3  broke\nbb  failed    -  network: ssh bb: refused\n"
+        );
+    }
+
+    #[test]
+    fn builds_sftp_batches() {
+        let mut transfer = Transfer {
+            host: "box",
+            direction: Direction::Push,
+            local: Path::new("dumps/my \"guild\""),
+            remote: "backup",
+            recursive: true,
+            resume: true,
+            progress: false,
+        };
+        assert_eq!(
+            sftp_batch(&transfer),
+            "@put -p -a -R \"dumps/my \\\"guild\\\"\" \"backup\"\n"
+        );
+        transfer.direction = Direction::Pull;
+        transfer.local = Path::new(".");
+        (transfer.recursive, transfer.resume, transfer.progress) = (false, false, true);
+        assert_eq!(
+            sftp_batch(&transfer),
+            "@progress\n@get -p \"backup\" \".\"\n"
+        );
+    }
+}
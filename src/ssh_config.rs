@@ -0,0 +1,312 @@
+//! Reading OpenSSH client config (`~/.ssh/config`) the way `ssh` does, so `ssh exec`, `push`, and
+//! `pull` know what an alias stands for and leave its settings alone.
+//!
+//! Only what guildsync needs is resolved: `HostName`, `User`, `Port`, `IdentityFile`, and
+//! `ProxyJump`, from `Host` blocks and `Include`d files. As in `ssh`, the first value found for a
+//! keyword wins, except `IdentityFile`, which accumulates. `Match` blocks other than
+//! `Match all` depend on things only `ssh` knows at connect time and are skipped.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::expand_tilde;
+use crate::error::CliError;
+
+/// Nesting limit for `Include`, as in `ssh`.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// What a `--host` value connects to.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct HostConfig {
+    /// The name as given, without a `user@` part.
+    pub alias: String,
+    pub host_name: String,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<PathBuf>,
+    pub proxy_jump: Option<String>,
+}
+
+impl HostConfig {
+    /// One line: `alias -> user@host_name:port`, with identities and jump host.
+    pub fn describe(&self) -> String {
+        let mut line = format!("{} -> ", self.alias);
+        if let Some(user) = &self.user {
+            line.push_str(&format!("{user}@"));
+        }
+        line.push_str(&self.host_name);
+        if let Some(port) = self.port {
+            line.push_str(&format!(":{port}"));
+        }
+        if !self.identity_files.is_empty() {
+            let files: Vec<String> = self
+                .identity_files
+                .iter()
+                .map(|f| f.display().to_string())
+                .collect();
+            line.push_str(&format!(" (identity {})", files.join(", ")));
+        }
+        if let Some(jump) = &self.proxy_jump {
+            line.push_str(&format!(" via {jump}"));
+        }
+        line
+    }
+}
+
+/// `~/.ssh/config`, where `ssh` reads it from without `-F`.
+pub fn default_path() -> PathBuf {
+    expand_tilde(Path::new("~/.ssh/config"))
+}
+
+/// Resolve `host` (optionally `user@host`) through the config file at `path`. A missing file
+/// resolves every host to itself.
+pub fn resolve(path: &Path, host: &str) -> Result<HostConfig, CliError> {
+    let (user, alias) = match host.split_once('@') {
+        Some((user, alias)) => (Some(user.to_string()), alias),
+        None => (None, host),
+    };
+    let mut resolved = HostConfig {
+        alias: alias.to_string(),
+        user,
+        ..HostConfig::default()
+    };
+    let mut host_name = None;
+    if path.exists() {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CliError::Config(format!("{}: {e}", path.display())))?;
+        let mut reader = Reader {
+            alias,
+            include_dir: expand_tilde(Path::new("~/.ssh")),
+            host_name: &mut host_name,
+            resolved: &mut resolved,
+        };
+        reader.read(&text, path, 0)?;
+    }
+    resolved.host_name = match host_name {
+        Some(name) => name.replace("%h", alias).replace("%%", "%"),
+        None => alias.to_string(),
+    };
+    Ok(resolved)
+}
+
+struct Reader<'a> {
+    alias: &'a str,
+    /// Where relative `Include` paths are looked up.
+    include_dir: PathBuf,
+    host_name: &'a mut Option<String>,
+    resolved: &'a mut HostConfig,
+}
+
+impl Reader<'_> {
+    fn read(&mut self, text: &str, file: &Path, depth: usize) -> Result<(), CliError> {
+        // Settings before the first `Host` apply to every host.
+        let mut active = true;
+        for (number, line) in text.lines().enumerate() {
+            let invalid = |reason: String| {
+                CliError::Config(format!("{}:{}: {reason}", file.display(), number + 1))
+            };
+            let Some((keyword, args)) = split_line(line) else {
+                continue;
+            };
+            match keyword.to_ascii_lowercase().as_str() {
+                "host" => active = host_matches(&args, self.alias),
+                "match" => active = args.len() == 1 && args[0].eq_ignore_ascii_case("all"),
+                _ if !active => {}
+                "include" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(invalid("Include nested too deeply".to_string()));
+                    }
+                    for pattern in &args {
+                        for included in self.include_paths(pattern) {
+                            let Ok(text) = std::fs::read_to_string(&included) else {
+                                continue;
+                            };
+                            self.read(&text, &included, depth + 1)?;
+                        }
+                    }
+                }
+                "hostname" => set(self.host_name, &args),
+                "user" => set(&mut self.resolved.user, &args),
+                "port" if self.resolved.port.is_none() => {
+                    let port = args.first().and_then(|p| p.parse().ok());
+                    self.resolved.port =
+                        Some(port.ok_or_else(|| invalid(format!("bad Port {args:?}")))?);
+                }
+                "identityfile" => {
+                    for file in &args {
+                        let file = expand_tilde(Path::new(file));
+                        if !self.resolved.identity_files.contains(&file) {
+                            self.resolved.identity_files.push(file);
+                        }
+                    }
+                }
+                "proxyjump" => set(&mut self.resolved.proxy_jump, &args),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Files an `Include` argument names, in name order; `*` and `?` match within the last
+    /// path component.
+    fn include_paths(&self, pattern: &str) -> Vec<PathBuf> {
+        let pattern = expand_tilde(Path::new(pattern));
+        let pattern = if pattern.is_relative() {
+            self.include_dir.join(pattern)
+        } else {
+            pattern
+        };
+        let name = pattern
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !name.contains(['*', '?']) {
+            return vec![pattern];
+        }
+        let dir = pattern.parent().unwrap_or(Path::new("."));
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| wildcard(&name, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect();
+        paths.sort();
+        paths
+    }
+}
+
+/// First value wins.
+fn set(slot: &mut Option<String>, args: &[String]) {
+    if slot.is_none()
+        && let Some(value) = args.first()
+    {
+        *slot = Some(value.clone());
+    }
+}
+
+/// Keyword and arguments of a config line, or `None` for blanks and comments. Arguments are
+/// split on whitespace, with double quotes grouping, and the keyword may be followed by `=`.
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let (keyword, rest) = line.split_at(end);
+    let rest = rest.trim_start().strip_prefix('=').unwrap_or(rest);
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let (mut quoted, mut started) = (false, false);
+    for c in rest.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    args.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        args.push(current);
+    }
+    Some((keyword.to_string(), args))
+}
+
+/// Whether a `Host` line's patterns select `alias`: one matches and no `!` pattern does.
+fn host_matches(patterns: &[String], alias: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard(negated, alias) => return false,
+            Some(_) => {}
+            None => matched |= wildcard(pattern, alias),
+        }
+    }
+    matched
+}
+
+/// `*` matches any run of characters and `?` any one; hostnames compare case-insensitively.
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, at)) => {
+                    p = star + 1;
+                    t = at + 1;
+                    backtrack = Some((star, at + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_aliases_through_includes() {
+        let dir = std::env::temp_dir().join(format!("guildsync-ssh-config-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("config.d")).unwrap();
+        std::fs::write(
+            dir.join("config.d/10-work"),
+            "Host box\n  HostName %h.example.com\n  Port 2222\n  ProxyJump bastion\n",
+        )
+        .unwrap();
+        let config = dir.join("config");
+        std::fs::write(
+            &config,
+            format!(
+                "Include {}/config.d/*\n\n\
+                 Host box !other\n  User = deploy\n  IdentityFile \"~/keys/box key\"\n\n\
+                 Match exec \"true\"\n  User nobody\n\n\
+                 Host *\n  User fallback\n  Port 22\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+
+        let box_ = resolve(&config, "box").unwrap();
+        assert_eq!(box_.host_name, "box.example.com");
+        assert_eq!(box_.user.as_deref(), Some("deploy"));
+        assert_eq!(box_.port, Some(2222));
+        assert_eq!(box_.proxy_jump.as_deref(), Some("bastion"));
+        assert!(box_.identity_files[0].ends_with("keys/box key"));
+
+        let other = resolve(&config, "root@other").unwrap();
+        assert_eq!(
+            (other.host_name.as_str(), other.user.as_deref(), other.port),
+            ("other", Some("root"), Some(22))
+        );
+        assert_eq!(resolve(&dir.join("missing"), "x").unwrap().host_name, "x");
+        assert!(wildcard("web-?.*", "WEB-1.example"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+//! Summary statistics of a dump's messages for `format stats`: who wrote how much where, what
+//! was attached, which emoji got used, and how activity grew over time.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::FormatsConfig;
+use crate::discord::{list, text};
+use crate::error::CliError;
+use crate::format::{self, GuildFormat};
+use crate::timestamp;
+
+/// Arguments of `format stats`, shared by the CLI and the MCP tool.
+#[derive(clap::Args, Debug, Deserialize)]
+pub struct StatsArgs {
+    /// Dump file path.
+    #[arg(long, value_name = "PATH")]
+    pub r#in: PathBuf,
+
+    /// Period to count growth by.
+    #[arg(long, value_enum, default_value_t = Period::Month)]
+    #[serde(default)]
+    pub by: Period,
+}
+
+/// Length of the periods message counts are grouped by.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    #[default]
+    Month,
+    Year,
+}
+
+impl Period {
+    /// Characters of a UTC RFC 3339 time that name its period (`2025-01` for months).
+    fn prefix(self) -> usize {
+        match self {
+            Period::Day => 10,
+            Period::Month => 7,
+            Period::Year => 4,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Month => "month",
+            Period::Year => "year",
+        }
+    }
+}
+
+/// A channel, author, or emoji with its count.
+#[derive(Debug, Serialize)]
+pub struct Count {
+    /// Channel or user id; absent for unicode emoji.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Attachments {
+    pub count: usize,
+    pub bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub largest: Option<Largest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Largest {
+    pub filename: String,
+    pub bytes: u64,
+}
+
+/// Messages sent in one period.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Growth {
+    /// `2025-01-31`, `2025-01`, or `2025`.
+    pub period: String,
+    pub messages: usize,
+}
+
+/// Statistics of one dump. Lists are sorted by count, largest first.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub messages: usize,
+    /// Time of the oldest and newest message, in UTC.
+    pub first: Option<String>,
+    pub last: Option<String>,
+    /// Channels and threads with their message counts.
+    pub channels: Vec<Count>,
+    pub authors: Vec<Count>,
+    pub attachments: Attachments,
+    /// Reactions and custom emoji in message content.
+    pub emoji: Vec<Count>,
+    /// Messages per period, oldest first.
+    pub growth: Vec<Growth>,
+    #[serde(skip)]
+    period: Period,
+}
+
+/// Read and validate the dump of `args` and summarize it.
+pub fn stats_file(args: &StatsArgs, formats: &FormatsConfig) -> Result<Stats, CliError> {
+    let document = format::read_document(&args.r#in)?;
+    format::validate_value(&document, Some(GuildFormat::Dump), formats)?;
+    Ok(stats(&document, args.by))
+}
+
+/// Compute the statistics of `document`, grouping growth by `period`.
+pub fn stats(document: &Value, period: Period) -> Stats {
+    let custom_emoji = Regex::new(r"<a?:(\w+):(\d+)>").expect("valid pattern");
+    let mut channels = Vec::new();
+    let mut authors: HashMap<String, Count> = HashMap::new();
+    let mut emoji: HashMap<(Option<String>, String), usize> = HashMap::new();
+    let mut growth: BTreeMap<String, usize> = BTreeMap::new();
+    let mut attachments = Attachments::default();
+    let mut times: Vec<String> = Vec::new();
+
+    for channel in ["channels", "threads"]
+        .iter()
+        .flat_map(|section| list(&document[*section]))
+    {
+        let messages: Vec<&Value> = list(&channel["messages"]).collect();
+        if messages.is_empty() {
+            continue;
+        }
+        channels.push(Count {
+            id: Some(text(&channel["id"])),
+            name: format!("#{}", text(&channel["name"])),
+            count: messages.len(),
+        });
+        for message in messages {
+            let author = &message["author"];
+            let id = text(&author["id"]);
+            authors
+                .entry(id.clone())
+                .or_insert_with(|| Count {
+                    id: Some(id),
+                    name: ["global_name", "username"]
+                        .iter()
+                        .find_map(|key| author[*key].as_str())
+                        .unwrap_or("unknown")
+                        .to_string(),
+                    count: 0,
+                })
+                .count += 1;
+
+            if let Some(time) = message["timestamp"].as_str().and_then(timestamp::normalize) {
+                let key = time.get(..period.prefix()).unwrap_or(&time).to_string();
+                *growth.entry(key).or_default() += 1;
+                times.push(time);
+            }
+            for attachment in list(&message["attachments"]) {
+                let size = attachment["size"].as_u64().unwrap_or(0);
+                attachments.count += 1;
+                attachments.bytes += size;
+                if attachments
+                    .largest
+                    .as_ref()
+                    .is_none_or(|largest| size > largest.bytes)
+                {
+                    attachments.largest = Some(Largest {
+                        filename: text(&attachment["filename"]),
+                        bytes: size,
+                    });
+                }
+            }
+            for reaction in list(&message["reactions"]) {
+                let id = reaction["emoji"]["id"].as_str().map(str::to_string);
+                let name = text(&reaction["emoji"]["name"]);
+                let uses = reaction["count"].as_u64().unwrap_or(1) as usize;
+                *emoji.entry((id, name)).or_default() += uses;
+            }
+            let content = message["content"].as_str().unwrap_or_default();
+            for caps in custom_emoji.captures_iter(content) {
+                *emoji
+                    .entry((Some(caps[2].to_string()), caps[1].to_string()))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    // RFC 3339 strings compare by time only at equal precision; compare parsed instants.
+    times.sort_by_key(|time| timestamp::instant(time));
+    let mut authors: Vec<Count> = authors.into_values().collect();
+    let mut emoji: Vec<Count> = emoji
+        .into_iter()
+        .map(|((id, name), count)| Count {
+            name: if id.is_some() {
+                format!(":{name}:")
+            } else {
+                name
+            },
+            id,
+            count,
+        })
+        .collect();
+    for counts in [&mut channels, &mut authors, &mut emoji] {
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    }
+    Stats {
+        messages: channels.iter().map(|c| c.count).sum(),
+        first: times.first().cloned(),
+        last: times.last().cloned(),
+        channels,
+        authors,
+        attachments,
+        emoji,
+        growth: growth
+            .into_iter()
+            .map(|(period, messages)| Growth { period, messages })
+            .collect(),
+        period,
+    }
+}
+
+/// Width of the longest growth bar.
+const BAR_WIDTH: usize = 30;
+
+impl Stats {
+    /// Compact tables of the statistics, listing at most `top` channels, authors, and emoji.
+    pub fn table(&self, top: usize) -> String {
+        let mut out = String::new();
+        let range = match (&self.first, &self.last) {
+            (Some(first), Some(last)) => format!(", {} to {}", &first[..10], &last[..10]),
+            _ => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "{} message(s) in {} channel(s) by {} author(s){range}",
+            self.messages,
+            self.channels.len(),
+            self.authors.len()
+        );
+        let _ = write!(
+            out,
+            "{} attachment(s), {}",
+            self.attachments.count,
+            human_size(self.attachments.bytes)
+        );
+        if let Some(largest) = &self.attachments.largest {
+            let _ = write!(
+                out,
+                " (largest {}, {})",
+                largest.filename,
+                human_size(largest.bytes)
+            );
+        }
+        out.push('\n');
+
+        for (title, counts) in [
+            ("channel", &self.channels),
+            ("author", &self.authors),
+            ("emoji", &self.emoji),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "\n{title:<24} {:>8}", "count");
+            for count in counts.iter().take(top) {
+                let _ = writeln!(out, "{:<24} {:>8}", truncate(&count.name), count.count);
+            }
+            if counts.len() > top {
+                let _ = writeln!(out, "… {} more", counts.len() - top);
+            }
+        }
+
+        let max = self.growth.iter().map(|g| g.messages).max().unwrap_or(0);
+        if max > 0 {
+            let _ = writeln!(out, "\n{:<24} {:>8}", self.period.name(), "messages");
+            for Growth { period, messages } in &self.growth {
+                let bar = "█".repeat((messages * BAR_WIDTH).div_ceil(max));
+                let _ = writeln!(out, "{period:<24} {messages:>8} {bar}");
+            }
+        }
+        out
+    }
+}
+
+fn truncate(name: &str) -> String {
+    if name.chars().count() <= 24 {
+        return name.to_string();
+    }
+    let mut short: String = name.chars().take(23).collect();
+    short.push('…');
+    short
+}
+
+/// `bytes` in B, KiB, MiB, or GiB.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_messages_authors_attachments_and_emoji() {
+        let alice = serde_json::json!({ "id": "7", "username": "alice", "global_name": "Alice" });
+        let document = serde_json::json!({
+            "channels": [
+                { "id": "20", "name": "general", "messages": [
+                    { "id": "1", "author": alice, "timestamp": "2025-01-31T23:30:00-01:00",
+                      "content": "hi <:party:55>",
+                      "reactions": [{ "emoji": { "id": null, "name": "👍" }, "count": 3 }] },
+                    { "id": "2", "author": alice, "timestamp": "2025-01-15T12:00:00.5Z",
+                      "attachments": [{ "filename": "a.png", "size": 2048 },
+                                      { "filename": "b.zip", "size": 1048576 }] },
+                ]},
+                { "id": "21", "name": "empty" },
+            ],
+            "threads": [{ "id": "30", "name": "side", "messages": [
+                { "id": "3", "author": { "id": "8", "username": "bob" },
+                  "timestamp": "2025-02-01T00:00:00Z", "content": "<:party:55> <a:party:55>" },
+            ]}],
+        });
+        let stats = stats(&document, Period::Month);
+
+        assert_eq!(stats.messages, 3);
+        assert_eq!(stats.first.as_deref(), Some("2025-01-15T12:00:00.5Z"));
+        assert_eq!(stats.last.as_deref(), Some("2025-02-01T00:30:00Z"));
+        assert_eq!(stats.channels.len(), 2);
+        assert_eq!(
+            (stats.authors[0].name.as_str(), stats.authors[0].count),
+            ("Alice", 2)
+        );
+        assert_eq!(stats.attachments.bytes, 1050624);
+        let emoji: Vec<(&str, usize)> = stats
+            .emoji
+            .iter()
+            .map(|e| (e.name.as_str(), e.count))
+            .collect();
+        assert_eq!(emoji, [(":party:", 3), ("👍", 3)]);
+        let growth: Vec<(&str, usize)> = stats
+            .growth
+            .iter()
+            .map(|g| (g.period.as_str(), g.messages))
+            .collect();
+        assert_eq!(growth, [("2025-01", 1), ("2025-02", 2)]);
+        assert!(stats.table(1).contains("… 1 more"));
+    }
+}
@@ -0,0 +1,172 @@
+//! UTC timestamps without a date-time dependency.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The current time as RFC 3339 UTC, e.g. `2025-01-31T12:00:00Z`.
+pub fn now_rfc3339() -> String {
+    rfc3339(now())
+}
+
+/// Seconds since the Unix epoch as RFC 3339 UTC.
+pub fn rfc3339(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parse `YYYY-MM-DD` (midnight UTC) or RFC 3339 UTC `YYYY-MM-DDTHH:MM:SSZ` into seconds since
+/// the Unix epoch.
+pub fn parse(text: &str) -> Option<u64> {
+    let (date, time) = match text.split_once('T') {
+        Some((date, time)) => (date, Some(time.strip_suffix('Z')?)),
+        None => (text, None),
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (y, m, d) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    let secs = match time {
+        Some(time) => {
+            let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+            let (h, min, sec) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+            if h > 23 || min > 59 || sec > 60 {
+                return None;
+            }
+            h * 3600 + min * 60 + sec
+        }
+        None => 0,
+    };
+    let days = u64::try_from(days_from_civil(i64::from(y), m, d)).ok()?;
+    Some(days * 86_400 + secs)
+}
+
+/// Rewrite an RFC 3339 time with any UTC offset (as Discord writes them, e.g.
+/// `2025-01-31T14:00:00.120000+02:00`) in UTC with `Z` and without trailing zeros in the
+/// fraction: `2025-01-31T12:00:00.12Z`. `None` if `text` is not such a time.
+pub fn normalize(text: &str) -> Option<String> {
+    let (secs, fraction) = parse_offset(text)?;
+    let utc = rfc3339(secs);
+    match fraction.trim_end_matches('0') {
+        "" => Some(utc),
+        fraction => Some(format!("{}.{fraction}Z", utc.trim_end_matches('Z'))),
+    }
+}
+
+/// An RFC 3339 time with any UTC offset as seconds and nanoseconds since the Unix epoch, for
+/// ordering times written with different offsets or precision.
+pub fn instant(text: &str) -> Option<(u64, u32)> {
+    let (secs, fraction) = parse_offset(text)?;
+    let digits: String = fraction
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(9)
+        .collect();
+    Some((secs, digits.parse().ok()?))
+}
+
+/// Seconds since the Unix epoch and the digits of the fraction of an RFC 3339 time.
+fn parse_offset(text: &str) -> Option<(u64, &str)> {
+    let (date, time) = text.split_once('T')?;
+    let (clock, offset) = match time.strip_suffix('Z') {
+        Some(clock) => (clock, 0),
+        None => {
+            let at = time.rfind(['+', '-'])?;
+            let (clock, offset) = time.split_at(at);
+            let (h, m) = offset[1..].split_once(':')?;
+            let (h, m): (i64, i64) = (h.parse().ok()?, m.parse().ok()?);
+            if h > 23 || m > 59 {
+                return None;
+            }
+            let minutes = h * 60 + m;
+            (
+                clock,
+                if offset.starts_with('-') {
+                    -minutes
+                } else {
+                    minutes
+                },
+            )
+        }
+    };
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let local = parse(&format!("{date}T{clock}Z"))?;
+    let secs = u64::try_from(i64::try_from(local).ok()? - offset * 60).ok()?;
+    Some((secs, fraction))
+}
+
+/// Proleptic Gregorian date to days since 1970-01-01 (inverse of [`civil_from_days`]).
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = i64::from(if m > 2 { m - 3 } else { m + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_what_it_formats() {
+        for secs in [0, 951_782_400, 1_738_324_800, 1_738_325_799] {
+            assert_eq!(parse(&rfc3339(secs)), Some(secs));
+        }
+        assert_eq!(parse("2025-01-31"), Some(1_738_281_600));
+        assert_eq!(parse("2025-13-01"), None);
+        assert_eq!(parse("2025-01-31T12:00:00+02:00"), None);
+        assert_eq!(parse("1969-12-31"), None);
+    }
+
+    #[test]
+    fn normalizes_offsets_and_fractions() {
+        assert_eq!(
+            normalize("2025-01-31T14:00:00.120000+02:00").as_deref(),
+            Some("2025-01-31T12:00:00.12Z")
+        );
+        assert_eq!(
+            normalize("2025-01-01T00:30:00.000000+00:00").as_deref(),
+            Some("2025-01-01T00:30:00Z")
+        );
+        assert_eq!(
+            normalize("2024-12-31T23:00:00-01:30").as_deref(),
+            Some("2025-01-01T00:30:00Z")
+        );
+        assert!(instant("2025-01-31T12:00:00Z") < instant("2025-01-31T14:00:00.12+02:00"));
+        assert_eq!(normalize("2025-01-31"), None);
+        assert_eq!(normalize("soon"), None);
+    }
+}
@@ -0,0 +1,328 @@
+//! tmux sessions for `terminal opencode`, typing into panes for `terminal send`, and
+//! reading them for `terminal capture`.
+//!
+//! Sessions are created from a [`crate::layout`]; the built-in one holds an `agent` window
+//! split in two: the coding agent (OpenCode, Codex, ...) on the left and an interpreter or shell
+//! on the right. Sessions made by `attach` drop each pane to the user's shell when its program
+//! exits, so quitting the agent does not close the pane; sessions made by `start` keep the agent
+//! pane dead instead, so its exit status can be reported. An existing session is attached to as
+//! it is.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+
+use crate::error::CliError;
+use crate::layout::{Split, WindowPlan};
+
+/// Name of the window holding the agent and interpreter panes in the built-in `opencode`
+/// layout.
+pub const WINDOW: &str = "agent";
+
+/// Check `name` can name a tmux session: tmux rewrites `.` and `:` (its target separators).
+pub fn validate_session_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("tmux session name must not be empty".to_string());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| matches!(c, '.' | ':') || c.is_control())
+    {
+        return Err(format!("tmux session name {name:?} must not contain {c:?}"));
+    }
+    Ok(())
+}
+
+/// Shell command for a pane running `program`, then the user's shell once it exits; just the
+/// shell if there is no program.
+pub fn pane_command(program: Option<&str>) -> String {
+    let shell = r#"exec "${SHELL:-/bin/sh}""#;
+    match program.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(program) => format!("{program}; {shell}"),
+        None => shell.to_string(),
+    }
+}
+
+/// Whether the first word of `command` is a program on `PATH` (or an existing path).
+pub fn program_exists(command: &str) -> bool {
+    let Some(program) = command.split_whitespace().next() else {
+        return false;
+    };
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Whether this process runs inside a tmux client.
+pub fn inside_tmux() -> bool {
+    std::env::var_os("TMUX").is_some_and(|v| !v.is_empty())
+}
+
+/// Whether a session named exactly `name` exists.
+pub async fn has_session(name: &str) -> Result<bool, CliError> {
+    status(&["has-session", "-t", &format!("={name}")]).await
+}
+
+/// Create session `name` with the windows of `plan` (see [`layout::Layout::plan`]), selecting
+/// the focused pane. Returns the pane ids, window by window.
+pub async fn create_session(name: &str, plan: &[WindowPlan]) -> Result<Vec<Vec<String>>, CliError> {
+    let session = format!("={name}:");
+    let mut ids = Vec::new();
+    let mut focus = None;
+    for (w, window) in plan.iter().enumerate() {
+        let mut panes: Vec<String> = Vec::new();
+        for pane in &window.panes {
+            let dir = pane.dir.to_string_lossy();
+            let env = env_args(&pane.env);
+            let mut args: Vec<&str> = match panes.last() {
+                None if w == 0 => vec!["new-session", "-d", "-s", name, "-n", &window.name],
+                None => vec!["new-window", "-d", "-t", &session, "-n", &window.name],
+                Some(previous) => {
+                    let side = match pane.split {
+                        Split::Right => "-h",
+                        Split::Below => "-v",
+                    };
+                    vec!["split-window", "-d", side, "-t", previous]
+                }
+            };
+            if let Some(size) = &pane.size
+                && !panes.is_empty()
+            {
+                args.extend(["-l", size]);
+            }
+            args.extend(["-c", &dir, "-P", "-F", "#{pane_id}"]);
+            // `new-session -e` would set the variables for the whole session, so its pane is
+            // respawned with them instead.
+            let respawn = args[0] == "new-session" && !env.is_empty();
+            if !respawn {
+                args.extend(env.iter().flat_map(|var| ["-e", var]));
+                args.push(&pane.command);
+            }
+            let id = tmux(&args).await?.trim().to_string();
+            if respawn {
+                let mut args = vec!["respawn-pane", "-k", "-t", &id, "-c", &dir];
+                args.extend(env.iter().flat_map(|var| ["-e", var]));
+                args.push(&pane.command);
+                tmux(&args).await?;
+            }
+            if pane.focus {
+                focus = Some(id.clone());
+            }
+            panes.push(id);
+        }
+        if let (Some(arrange), Some(first)) = (&window.arrange, panes.first()) {
+            tmux(&["select-layout", "-t", first, arrange]).await?;
+        }
+        ids.push(panes);
+    }
+    if let Some(pane) = focus {
+        tmux(&["select-window", "-t", &pane]).await?;
+        tmux(&["select-pane", "-t", &pane]).await?;
+    }
+    Ok(ids)
+}
+
+/// Replace what pane `target` runs with `command`, started in `dir` with `env`. The pane stays
+/// open when the command exits, so its exit status can still be read (see [`panes`]).
+pub async fn respawn_pane(
+    target: &str,
+    dir: &Path,
+    env: &BTreeMap<String, String>,
+    command: &str,
+) -> Result<(), CliError> {
+    tmux(&["set-option", "-p", "-t", target, "remain-on-exit", "on"]).await?;
+    let dir = dir.to_string_lossy();
+    let env = env_args(env);
+    let mut args = vec!["respawn-pane", "-k", "-t", target, "-c", &dir];
+    args.extend(env.iter().flat_map(|var| ["-e", var]));
+    args.push(command);
+    tmux(&args).await?;
+    Ok(())
+}
+
+/// `KEY=VALUE` arguments of tmux's `-e`.
+fn env_args(env: &BTreeMap<String, String>) -> Vec<String> {
+    env.iter().map(|(k, v)| format!("{k}={v}")).collect()
+}
+
+/// A pane as `list-panes` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pane {
+    /// `%N`, stable for the pane's lifetime.
+    pub id: String,
+    /// Process the pane runs.
+    pub pid: u32,
+    /// Whether that process exited (kept by `remain-on-exit`).
+    pub dead: bool,
+    /// Its exit status, once dead.
+    pub dead_status: Option<i32>,
+}
+
+/// Panes of every window of session `name`.
+pub async fn panes(name: &str) -> Result<Vec<Pane>, CliError> {
+    let out = tmux(&[
+        "list-panes",
+        "-s",
+        "-t",
+        &format!("={name}"),
+        "-F",
+        "#{pane_id} #{pane_pid} #{pane_dead} #{pane_dead_status}",
+    ])
+    .await?;
+    Ok(out.lines().filter_map(parse_pane).collect())
+}
+
+fn parse_pane(line: &str) -> Option<Pane> {
+    let mut fields = line.split(' ');
+    Some(Pane {
+        id: fields.next()?.to_string(),
+        pid: fields.next()?.parse().ok()?,
+        dead: fields.next()? == "1",
+        dead_status: fields.next().and_then(|s| s.parse().ok()),
+    })
+}
+
+/// Kill session `name` and the processes of its panes.
+pub async fn kill_session(name: &str) -> Result<(), CliError> {
+    tmux(&["kill-session", "-t", &format!("={name}")]).await?;
+    Ok(())
+}
+
+/// Attach the terminal to session `name`, replacing this process with `tmux attach`, or switch
+/// the current client to it when already inside tmux.
+pub async fn attach(name: &str) -> Result<(), CliError> {
+    let exact = format!("={name}");
+    if inside_tmux() {
+        tmux(&["switch-client", "-t", &exact]).await?;
+        return Ok(());
+    }
+    let mut command = std::process::Command::new("tmux");
+    command.args(["attach-session", "-t", &exact]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Only returns on failure.
+        Err(spawn_error(command.exec()))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status().map_err(spawn_error)?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(CliError::Io(std::io::Error::other(format!(
+                "tmux attach-session: {status}"
+            ))))
+        }
+    }
+}
+
+/// Whether `target` names an existing pane.
+pub async fn pane_exists(target: &str) -> Result<bool, CliError> {
+    // `display-message` falls back to the current pane for unknown targets; `list-panes` fails.
+    status(&["list-panes", "-t", target]).await
+}
+
+/// Type `text` literally into pane `target`, then press Enter if `enter`.
+pub async fn send_keys(target: &str, text: &str, enter: bool) -> Result<(), CliError> {
+    tmux(&["send-keys", "-t", target, "-l", "--", text]).await?;
+    if enter {
+        tmux(&["send-keys", "-t", target, "Enter"]).await?;
+    }
+    Ok(())
+}
+
+/// Lines `start` to `end` of pane `target`, as tmux counts them (`-` is the start of the
+/// history, `0` the top of the visible area; an empty `end` is the bottom), with wrapped lines
+/// joined and colors kept as SGR escape sequences.
+pub async fn capture_pane(target: &str, start: &str, end: &str) -> Result<Vec<String>, CliError> {
+    let mut args = vec!["capture-pane", "-p", "-e", "-J", "-t", target, "-S", start];
+    if !end.is_empty() {
+        args.extend(["-E", end]);
+    }
+    Ok(tmux(&args).await?.lines().map(str::to_string).collect())
+}
+
+/// Line of pane `target` its cursor is on, from the top of the visible area.
+pub async fn cursor_line(target: &str) -> Result<i64, CliError> {
+    let line = tmux(&["display-message", "-p", "-t", target, "#{cursor_y}"]).await?;
+    line.trim().parse().map_err(|_| {
+        CliError::Io(std::io::Error::other(format!(
+            "tmux display-message: no cursor position for pane {target}"
+        )))
+    })
+}
+
+/// Run `tmux <args>` and return its stdout.
+async fn tmux(args: &[&str]) -> Result<String, CliError> {
+    let output = tokio::process::Command::new("tmux")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(spawn_error)?;
+    if !output.status.success() {
+        return Err(CliError::Io(std::io::Error::other(format!(
+            "tmux {}: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether `tmux <args>` succeeds, for queries such as `has-session`.
+async fn status(args: &[&str]) -> Result<bool, CliError> {
+    let status = tokio::process::Command::new("tmux")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(spawn_error)?;
+    Ok(status.success())
+}
+
+fn spawn_error(err: std::io::Error) -> CliError {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        CliError::NotFound(
+            "tmux is not installed or not on PATH; install it (e.g. `apt install tmux` or \
+             `brew install tmux`) and check with `guildsync doctor`"
+                .to_string(),
+        )
+    } else {
+        CliError::Io(std::io::Error::new(err.kind(), format!("tmux: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panes_fall_back_to_the_shell_and_names_are_checked() {
+        assert_eq!(
+            pane_command(Some("opencode")),
+            r#"opencode; exec "${SHELL:-/bin/sh}""#
+        );
+        assert_eq!(pane_command(Some(" ")), r#"exec "${SHELL:-/bin/sh}""#);
+        assert!(validate_session_name("opencode").is_ok());
+        assert!(validate_session_name("guild.sync").is_err());
+        assert!(validate_session_name("").is_err());
+        assert!(program_exists("sh -c true"));
+        assert!(!program_exists("guildsync-no-such-program"));
+        assert_eq!(
+            parse_pane("%3 4242 1 127"),
+            Some(Pane {
+                id: "%3".to_string(),
+                pid: 4242,
+                dead: true,
+                dead_status: Some(127),
+            })
+        );
+        assert_eq!(parse_pane("%4 4243 0 ").map(|p| p.dead_status), Some(None));
+    }
+}
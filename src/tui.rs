@@ -0,0 +1,493 @@
+//! Interactive dump browser for `guildsync tui`.
+//!
+//! The left pane lists the dump's categories, channels, and threads as a tree; the right pane
+//! shows the selected channel's messages, oldest first, starting at the message under the cursor.
+//! `/` searches message text and authors across every channel, `d` jumps to the first message at
+//! or after a date, and `q` quits.
+
+use std::cmp::Ordering;
+
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use serde_json::Value;
+
+use crate::discord::{self, CATEGORY_TYPE, list, text};
+use crate::error::CliError;
+use crate::timestamp;
+
+/// Messages moved by PageUp and PageDown.
+const PAGE: usize = 10;
+
+const HELP: &str = "↑↓ move  Tab switch pane  / search  n/N next/previous  d date  q quit";
+
+/// A category, channel, or thread in the tree.
+#[derive(Debug)]
+pub struct Node {
+    pub id: String,
+    pub label: String,
+    pub depth: usize,
+    pub messages: Vec<Message>,
+}
+
+/// A message as the browser shows it.
+#[derive(Debug)]
+pub struct Message {
+    pub id: u64,
+    /// `YYYY-MM-DD HH:MM` in UTC.
+    pub time: String,
+    pub author: String,
+    pub content: String,
+    pub attachments: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Focus {
+    Tree,
+    Messages,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Prompt {
+    Search,
+    Date,
+}
+
+/// Browser state: the tree, the selected channel, and the message under the cursor.
+pub struct Browser {
+    pub nodes: Vec<Node>,
+    pub selected: usize,
+    pub cursor: usize,
+    focus: Focus,
+    prompt: Option<(Prompt, String)>,
+    query: String,
+    status: String,
+}
+
+impl Browser {
+    /// Build the tree of `document`, a dump: channels without a category first, then each
+    /// category with its channels, each channel followed by its threads.
+    pub fn new(document: &Value) -> Self {
+        let mut channels: Vec<&Value> = list(&document["channels"]).collect();
+        channels.sort_by(|a, b| by_position(a, b));
+        let mut threads: Vec<&Value> = list(&document["threads"]).collect();
+        threads.sort_by_key(|thread| discord::snowflake(thread));
+        let is_category = |c: &Value| c["type"].as_u64() == Some(CATEGORY_TYPE);
+        let categories: Vec<String> = channels
+            .iter()
+            .filter(|c| is_category(c))
+            .map(|c| text(&c["id"]))
+            .collect();
+
+        let mut nodes = Vec::new();
+        let mut placed = std::collections::HashSet::new();
+        let mut push = |nodes: &mut Vec<Node>, channel: &Value, depth: usize| {
+            placed.insert(text(&channel["id"]));
+            nodes.push(node(channel, depth));
+            for thread in threads
+                .iter()
+                .filter(|t| text(&t["parent_id"]) == text(&channel["id"]))
+            {
+                placed.insert(text(&thread["id"]));
+                nodes.push(node(thread, depth + 1));
+            }
+        };
+        let parent = |c: &Value| Some(text(&c["parent_id"])).filter(|p| categories.contains(p));
+        for channel in channels
+            .iter()
+            .filter(|c| !is_category(c) && parent(c).is_none())
+        {
+            push(&mut nodes, channel, 0);
+        }
+        for category in channels.iter().filter(|c| is_category(c)) {
+            push(&mut nodes, category, 0);
+            let id = text(&category["id"]);
+            for channel in channels
+                .iter()
+                .filter(|c| parent(c).as_deref() == Some(id.as_str()))
+            {
+                push(&mut nodes, channel, 1);
+            }
+        }
+        // Threads whose parent channel is not in the file.
+        for thread in threads {
+            if !placed.contains(&text(&thread["id"])) {
+                nodes.push(node(thread, 0));
+            }
+        }
+        Browser {
+            nodes,
+            selected: 0,
+            cursor: 0,
+            focus: Focus::Tree,
+            prompt: None,
+            query: String::new(),
+            status: HELP.to_string(),
+        }
+    }
+
+    /// Select the channel or thread `id`, if the tree has it.
+    pub fn select(&mut self, id: &str) -> bool {
+        match self.nodes.iter().position(|node| node.id == id) {
+            Some(index) => {
+                (self.selected, self.cursor) = (index, 0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move to the next (or previous) message whose content or author contains `query`,
+    /// ignoring case, across all channels and wrapping around. Returns whether one matched.
+    pub fn search(&mut self, query: &str, forward: bool) -> bool {
+        let query = query.to_lowercase();
+        let positions: Vec<(usize, usize)> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(n, node)| (0..node.messages.len()).map(move |m| (n, m)))
+            .collect();
+        let current = positions
+            .iter()
+            .position(|p| *p == (self.selected, self.cursor));
+        let count = positions.len();
+        let order = (1..=count).map(|step| match (current, forward) {
+            (Some(at), true) => (at + step) % count,
+            (Some(at), false) => (at + count - step) % count,
+            (None, true) => step - 1,
+            (None, false) => count - step,
+        });
+        for index in order {
+            let (n, m) = positions[index];
+            let message = &self.nodes[n].messages[m];
+            if message.content.to_lowercase().contains(&query)
+                || message.author.to_lowercase().contains(&query)
+            {
+                (self.selected, self.cursor) = (n, m);
+                self.status = format!("match in {}", self.nodes[n].label);
+                return true;
+            }
+        }
+        self.status = format!("no message matches {query:?}");
+        false
+    }
+
+    /// Move to the first message of the selected channel at or after `when` (a `YYYY-MM-DD`
+    /// date, RFC 3339 time, or message ID); the last one if all are earlier.
+    pub fn jump_to(&mut self, when: &str) -> Result<(), String> {
+        let bound = discord::parse_bound(when.trim())?;
+        let messages = &self.nodes[self.selected].messages;
+        if messages.is_empty() {
+            return Err("this channel has no messages".to_string());
+        }
+        self.cursor = match messages.iter().position(|m| m.id >= bound) {
+            Some(index) => index,
+            None => {
+                self.status = format!("no messages after {when}; showing the last one");
+                messages.len() - 1
+            }
+        };
+        Ok(())
+    }
+
+    /// Apply `key`; returns whether to quit.
+    fn handle(&mut self, key: KeyEvent) -> bool {
+        if let Some((prompt, input)) = &mut self.prompt {
+            match key.code {
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => {
+                    let (prompt, input) = (*prompt, std::mem::take(input));
+                    self.prompt = None;
+                    self.focus = Focus::Messages;
+                    match prompt {
+                        Prompt::Search => {
+                            self.search(&input, true);
+                            self.query = input;
+                        }
+                        Prompt::Date => {
+                            if let Err(err) = self.jump_to(&input) {
+                                self.status = err;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+            return false;
+        }
+        let count = self.nodes[self.selected].messages.len();
+        match (key.code, self.focus) {
+            (KeyCode::Char('q') | KeyCode::Esc, _) => return true,
+            (KeyCode::Char('c'), _) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return true;
+            }
+            (KeyCode::Tab | KeyCode::Left | KeyCode::Right | KeyCode::Char('h' | 'l'), _) => {
+                self.focus = match self.focus {
+                    Focus::Tree => Focus::Messages,
+                    Focus::Messages => Focus::Tree,
+                };
+            }
+            (KeyCode::Char('/'), _) => self.prompt = Some((Prompt::Search, String::new())),
+            (KeyCode::Char('d'), _) => self.prompt = Some((Prompt::Date, String::new())),
+            (KeyCode::Char('n'), _) if !self.query.is_empty() => {
+                let query = self.query.clone();
+                self.search(&query, true);
+            }
+            (KeyCode::Char('N'), _) if !self.query.is_empty() => {
+                let query = self.query.clone();
+                self.search(&query, false);
+            }
+            (KeyCode::Up | KeyCode::Char('k'), Focus::Tree) => {
+                self.selected = self.selected.saturating_sub(1);
+                self.cursor = 0;
+            }
+            (KeyCode::Down | KeyCode::Char('j'), Focus::Tree) => {
+                self.selected = (self.selected + 1).min(self.nodes.len() - 1);
+                self.cursor = 0;
+            }
+            (KeyCode::Home | KeyCode::Char('g'), Focus::Tree) => {
+                (self.selected, self.cursor) = (0, 0)
+            }
+            (KeyCode::End | KeyCode::Char('G'), Focus::Tree) => {
+                (self.selected, self.cursor) = (self.nodes.len() - 1, 0);
+            }
+            (KeyCode::Up | KeyCode::Char('k'), Focus::Messages) => {
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            (KeyCode::Down | KeyCode::Char('j'), Focus::Messages) => {
+                self.cursor = (self.cursor + 1).min(count.saturating_sub(1));
+            }
+            (KeyCode::PageUp, _) => self.cursor = self.cursor.saturating_sub(PAGE),
+            (KeyCode::PageDown, _) => {
+                self.cursor = (self.cursor + PAGE).min(count.saturating_sub(1))
+            }
+            (KeyCode::Home | KeyCode::Char('g'), Focus::Messages) => self.cursor = 0,
+            (KeyCode::End | KeyCode::Char('G'), Focus::Messages) => {
+                self.cursor = count.saturating_sub(1);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [tree, messages] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        let border = |focus| {
+            Style::default().fg(if self.focus == focus {
+                Color::Cyan
+            } else {
+                Color::DarkGray
+            })
+        };
+
+        let items: Vec<ListItem> = self
+            .nodes
+            .iter()
+            .map(|node| ListItem::new(format!("{}{}", "  ".repeat(node.depth), node.label)))
+            .collect();
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(" Channels ")
+                        .border_style(border(Focus::Tree)),
+                )
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            tree,
+            &mut state,
+        );
+
+        let node = &self.nodes[self.selected];
+        let mut lines = Vec::new();
+        for (i, message) in node.messages.iter().enumerate().skip(self.cursor) {
+            if lines.len() > usize::from(messages.height) {
+                break;
+            }
+            let mut header = Style::default().fg(Color::Yellow);
+            if i == self.cursor && self.focus == Focus::Messages {
+                header = header.add_modifier(Modifier::REVERSED);
+            }
+            let mut spans = Vec::new();
+            if !message.time.is_empty() {
+                spans.push(Span::styled(
+                    format!("{} ", message.time),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            spans.push(Span::styled(
+                message.author.clone(),
+                header.add_modifier(Modifier::BOLD),
+            ));
+            lines.push(Line::from(spans));
+            lines.extend(message.content.lines().map(|l| Line::from(l.to_string())));
+            if message.attachments > 0 {
+                lines.push(Line::styled(
+                    format!("[{} attachment(s)]", message.attachments),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            lines.push(Line::default());
+        }
+        if node.messages.is_empty() {
+            lines.push(Line::styled(
+                "no messages in this file",
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let position = match node.messages.len() {
+            0 => String::new(),
+            count => format!(" {}/{count} ", self.cursor + 1),
+        };
+        frame.render_widget(
+            Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", node.label))
+                    .title_bottom(Line::from(position).right_aligned())
+                    .border_style(border(Focus::Messages)),
+            ),
+            messages,
+        );
+
+        let line = match &self.prompt {
+            Some((Prompt::Search, input)) => format!("/{input}"),
+            Some((Prompt::Date, input)) => format!("date (YYYY-MM-DD): {input}"),
+            None => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+}
+
+/// Browse `browser` until the user quits, restoring the terminal afterwards.
+pub fn run(mut browser: Browser) -> Result<(), CliError> {
+    let mut terminal = ratatui::try_init()?;
+    let result = (|| -> Result<(), CliError> {
+        loop {
+            terminal.draw(|frame| browser.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && browser.handle(key)
+            {
+                return Ok(());
+            }
+        }
+    })();
+    ratatui::try_restore()?;
+    result
+}
+
+fn node(channel: &Value, depth: usize) -> Node {
+    let mut messages: Vec<Message> = list(&channel["messages"])
+        .map(|message| {
+            let author = &message["author"];
+            Message {
+                id: discord::snowflake(message),
+                time: message["timestamp"]
+                    .as_str()
+                    .and_then(timestamp::normalize)
+                    .map(|t| t.get(..16).unwrap_or(&t).replace('T', " "))
+                    .unwrap_or_default(),
+                author: ["global_name", "username"]
+                    .iter()
+                    .find_map(|key| author[*key].as_str())
+                    .unwrap_or("unknown")
+                    .to_string(),
+                content: text(&message["content"]),
+                attachments: list(&message["attachments"]).count(),
+            }
+        })
+        .collect();
+    messages.sort_by_key(|message| message.id);
+    let name = text(&channel["name"]);
+    let label = match channel["type"].as_u64() {
+        Some(CATEGORY_TYPE) => name.to_uppercase(),
+        Some(10..=12) => format!("↳ {name}"),
+        _ => format!("#{name}"),
+    };
+    let label = match messages.len() {
+        0 => label,
+        count => format!("{label} ({count})"),
+    };
+    Node {
+        id: text(&channel["id"]),
+        label,
+        depth,
+        messages,
+    }
+}
+
+fn by_position(a: &Value, b: &Value) -> Ordering {
+    let key = |c: &Value| (c["position"].as_i64().unwrap_or(0), discord::snowflake(c));
+    key(a).cmp(&key(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_search_and_date_jumps() {
+        let message = |id: u64, content: &str| {
+            serde_json::json!({
+                "id": id.to_string(), "content": content,
+                "author": { "username": "alice" },
+                "timestamp": "2025-01-31T12:00:00Z",
+            })
+        };
+        // Snowflakes of 2025-01-01 and 2025-02-01.
+        let (jan, feb) = (1_323_825_246_208_000_000u64, 1_335_058_612_224_000_000u64);
+        let document = serde_json::json!({
+            "channels": [
+                { "id": "2", "type": 0, "name": "rules", "parent_id": "1", "position": 1 },
+                { "id": "1", "type": 4, "name": "Info", "position": 0 },
+                { "id": "3", "type": 0, "name": "general", "position": 5,
+                  "messages": [message(jan + 1, "hello"), message(feb + 1, "Deploy done")] },
+            ],
+            "threads": [
+                { "id": "4", "type": 11, "name": "help", "parent_id": "3",
+                  "messages": [message(jan + 2, "deploy failed")] },
+            ],
+        });
+        let mut browser = Browser::new(&document);
+        let labels: Vec<_> = browser
+            .nodes
+            .iter()
+            .map(|n| (n.depth, n.label.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (0, "#general (2)"),
+                (1, "↳ help (1)"),
+                (0, "INFO"),
+                (1, "#rules")
+            ]
+        );
+
+        assert!(browser.search("DEPLOY", true));
+        assert_eq!((browser.selected, browser.cursor), (0, 1));
+        assert!(browser.search("deploy", true));
+        assert_eq!((browser.selected, browser.cursor), (1, 0));
+        assert!(browser.search("deploy", false));
+        assert_eq!((browser.selected, browser.cursor), (0, 1));
+        assert!(!browser.search("nothing", true));
+
+        assert!(browser.select("3"));
+        browser.jump_to("2025-01-15").unwrap();
+        assert_eq!(browser.cursor, 1);
+        assert!(browser.jump_to("yesterday").is_err());
+    }
+}
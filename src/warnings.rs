@@ -0,0 +1,26 @@
+use std::sync::Mutex;
+
+/// Non-fatal caveats collected while an action runs, reported alongside its result.
+///
+/// Shared by reference so handlers (and helpers they call) can add to it without threading
+/// `&mut` through every layer.
+#[derive(Debug, Default)]
+pub struct Warnings(Mutex<Vec<String>>);
+
+impl Warnings {
+    pub fn push(&self, warning: impl Into<String>) {
+        let warning = warning.into();
+        tracing::debug!("warning: {warning}");
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(warning);
+    }
+
+    /// The collected warnings, in the order they were added.
+    pub fn into_vec(self) -> Vec<String> {
+        self.0
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}